@@ -1,11 +1,20 @@
 //! Block Cache Layer
 //! Implements about the disk block cache functionality
-use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
 
 use lazy_static::*;
 use spin::Mutex;
 
-use super::{block_dev::BlockDevice, BLOCK_SZ};
+use super::{block_dev::BlockDevice, elevator::Elevator, BLOCK_SZ};
+use crate::{
+    sysctl,
+    task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock},
+};
 /// BlockCache is a cache for a block in disk.
 pub struct BlockCache {
     cache:        Vec<u8>,
@@ -16,10 +25,21 @@ pub struct BlockCache {
 
 impl BlockCache {
     /// Load a new BlockCache from disk.
+    ///
+    /// 读盘期间当前任务可能被阻塞，调用者不应持有其他任务会争用的锁
     pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
         // for alignment and move effciency
         let mut cache = vec![0u8; BLOCK_SZ];
-        block_device.read_block(block_id, &mut cache);
+        block_device.read_block_wait(block_id, &mut cache);
+        Self {
+            cache,
+            block_id,
+            block_device,
+            modified: false,
+        }
+    }
+    /// Create a BlockCache from data already read from disk.
+    pub fn from_data(block_id: usize, block_device: Arc<dyn BlockDevice>, cache: Vec<u8>) -> Self {
         Self {
             cache,
             block_id,
@@ -71,12 +91,12 @@ impl Drop for BlockCache {
     }
 }
 
-const BLOCK_CACHE_SIZE: usize = 16;
-
 /// BlockCacheManager is a manager for BlockCache.
 pub struct BlockCacheManager {
-    /// (block_id, block_cache)
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    /// ((block_id, device_id), block_cache)
+    queue:   VecDeque<((usize, usize), Arc<Mutex<BlockCache>>)>,
+    /// 正在从设备读入、还没放进 queue 的块，以及等它读完的任务
+    loading: BTreeMap<(usize, usize), Vec<Arc<TaskControlBlock>>>,
 }
 
 impl Default for BlockCacheManager {
@@ -89,58 +109,353 @@ impl BlockCacheManager {
     /// Create a new BlockCacheManager with an empty queue (block_id, block_cache)
     pub fn new() -> Self {
         Self {
-            queue: VecDeque::new(),
-        }
-    }
-    /// Get a block cache from the queue. according to the block_id.
-    pub fn get_block_cache(
-        &mut self, block_id: usize, block_device: Arc<dyn BlockDevice>,
-    ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
-        } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
+            queue:   VecDeque::new(),
+            loading: BTreeMap::new(),
+        }
+    }
+    /// 块正在读入时把 task 加入它的等待队列，返回 false 表示块没有在读入
+    fn wait_loading(&mut self, key: (usize, usize), task: Arc<TaskControlBlock>) -> bool {
+        match self.loading.get_mut(&key) {
+            Some(waiters) => {
+                waiters.push(task);
+                true
             }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+            None => false,
+        }
+    }
+    /// 块读入完成，从 loading 中移除并返回等它的任务
+    fn finish_loading(&mut self, key: (usize, usize)) -> Vec<Arc<TaskControlBlock>> {
+        self.loading.remove(&key).unwrap_or_default()
+    }
+    /// Find a block cache in the queue. according to the key.
+    fn find(&self, key: (usize, usize)) -> Option<Arc<Mutex<BlockCache>>> {
+        self.queue
+            .iter()
+            .find(|pair| pair.0 == key)
+            .map(|pair| Arc::clone(&pair.1))
+    }
+    /// Push a loaded block cache to the queue, substitute an unused one when full.
+    fn insert(&mut self, key: (usize, usize), block_cache: Arc<Mutex<BlockCache>>) {
+        // substitute
+        // 缓存大小可以通过 sysctl 在运行时调小，所以这里要一直淘汰到有空位为止
+        let capacity = shard_capacity();
+        while self.queue.len() >= capacity {
+            // from front to tail
+            if let Some((idx, _)) = self
+                .queue
+                .iter()
+                .enumerate()
+                .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
+            {
+                self.queue.drain(idx..=idx);
+            } else if self.queue.len() > capacity {
+                // 缓存刚被调小且全部在使用中，暂时允许超出上限
+                break;
+            } else {
+                panic!("Run out of BlockCache!");
+            }
+        }
+        self.queue.push_back((key, block_cache));
+    }
+}
+
+/// 用设备对象的地址区分不同的块设备
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(block_device) as *const () as usize
+}
+
+/// 块缓存索引的分片数，按块号分到不同分片，访问不同块的任务不会争用同一把锁
+const CACHE_SHARDS: usize = 4;
+
+/// 每个分片最多缓存的块数，总数仍由 sysctl 的 block_cache_size 决定
+fn shard_capacity() -> usize {
+    (sysctl::block_cache_size() + CACHE_SHARDS - 1) / CACHE_SHARDS
+}
+
+impl BlockCacheManager {
+    /// 把块移出缓存 (drop 时会写回)，块正在被使用时保留并返回 false
+    pub fn evict(&mut self, block_id: usize, block_device: &Arc<dyn BlockDevice>) -> bool {
+        let key = (block_id, device_id(block_device));
+        match self.queue.iter().position(|pair| pair.0 == key) {
+            Some(idx) if Arc::strong_count(&self.queue[idx].1) == 1 => {
+                self.queue.remove(idx);
+                true
+            }
+            Some(_) => false,
+            None => true,
         }
     }
 }
 
 lazy_static! {
-    /// BLOCK_CACHE_MANAGER: Glocal instance of BlockCacheManager.
-    pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> =
-        Mutex::new(BlockCacheManager::new());
+    /// BLOCK_CACHE_MANAGER: Glocal instance of BlockCacheManager, one per shard.
+    pub static ref BLOCK_CACHE_MANAGER: [Mutex<BlockCacheManager>; CACHE_SHARDS] =
+        core::array::from_fn(|_| Mutex::new(BlockCacheManager::new()));
 }
 /// Get a block cache from the queue. according to the block_id.
+///
+/// 分片的锁只在查找和插入时持有，返回后对块的读写只锁住这一个块。
+/// 未命中时先把块记为正在读入再放开分片的锁，读盘期间当前任务被阻塞，
+/// 其他任务仍可访问缓存；访问同一个块的任务在这个块的等待队列中睡眠，读入完成后被唤醒
 pub fn get_block_cache(
     block_id: usize, block_device: Arc<dyn BlockDevice>,
 ) -> Arc<Mutex<BlockCache>> {
-    BLOCK_CACHE_MANAGER
+    let shard = &BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS];
+    // 可能同时存在多个块设备 (如 loop 设备)，需要同时比较块号和设备
+    let key = (block_id, device_id(&block_device));
+    loop {
+        let mut manager = shard.lock();
+        if let Some(block_cache) = manager.find(key) {
+            return block_cache;
+        }
+        if !manager.loading.contains_key(&key) {
+            manager.loading.insert(key, Vec::new());
+            break;
+        }
+        // 还没有任务在运行时 (启动阶段) 只能忙等
+        if let Some(task) = current_task() {
+            manager.wait_loading(key, task);
+            drop(manager);
+            block_current_and_run_next();
+        }
+    }
+    // load block into mem and push back
+    let block_cache = Arc::new(Mutex::new(BlockCache::new(block_id, block_device)));
+    let mut manager = shard.lock();
+    let waiters = manager.finish_loading(key);
+    manager.insert(key, Arc::clone(&block_cache));
+    drop(manager);
+    waiters.into_iter().for_each(wakeup_task);
+    block_cache
+}
+/// 把一批块读进缓存：已缓存或正在读入的块跳过，其余交给电梯排序合并，
+/// 用尽量少的设备请求读入
+pub fn prefetch_block_caches(
+    block_ids: impl IntoIterator<Item = usize>, block_device: &Arc<dyn BlockDevice>,
+) {
+    let dev_id = device_id(block_device);
+    let mut elevator = Elevator::new();
+    for block_id in block_ids {
+        let key = (block_id, dev_id);
+        let mut manager = BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS].lock();
+        if manager.find(key).is_none() && !manager.loading.contains_key(&key) {
+            manager.loading.insert(key, Vec::new());
+            elevator.submit(block_id);
+        }
+    }
+    for (block_id, data) in elevator.dispatch_reads(block_device.as_ref()) {
+        let key = (block_id, dev_id);
+        let block_cache = Arc::new(Mutex::new(BlockCache::from_data(
+            block_id,
+            Arc::clone(block_device),
+            data,
+        )));
+        let mut manager = BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS].lock();
+        let waiters = manager.finish_loading(key);
+        manager.insert(key, block_cache);
+        drop(manager);
+        waiters.into_iter().for_each(wakeup_task);
+    }
+}
+/// Evict a block from the cache, return false if it is still in use.
+pub fn evict_block_cache(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> bool {
+    BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS]
+        .lock()
+        .evict(block_id, block_device)
+}
+/// 块是否在缓存中
+pub fn block_cached(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> bool {
+    let key = (block_id, device_id(block_device));
+    BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS]
+        .lock()
+        .queue
+        .iter()
+        .any(|pair| pair.0 == key)
+}
+/// 块在缓存中且被修改过时写回设备，不在缓存中时什么也不做
+pub fn sync_block_cache(block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+    let key = (block_id, device_id(block_device));
+    // 写盘时可能睡眠，先放开分片的锁
+    let cache = BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS]
         .lock()
-        .get_block_cache(block_id, block_device)
+        .queue
+        .iter()
+        .find(|pair| pair.0 == key)
+        .map(|pair| Arc::clone(&pair.1));
+    if let Some(cache) = cache {
+        cache.lock().sync();
+    }
+}
+/// 内存不足时回收块缓存：丢掉没有被使用、也没有被修改的块，返回丢掉的块数。
+///
+/// 由堆分配器在分配失败时调用，此时可能正持有某个分片或块的锁，所以只用 try_lock，
+/// 拿不到锁的分片直接跳过；只丢弃干净的块，drop 时不会写盘
+pub fn shrink_block_cache() -> usize {
+    let mut freed = 0;
+    for shard in BLOCK_CACHE_MANAGER.iter() {
+        let Some(mut manager) = shard.try_lock() else {
+            continue;
+        };
+        manager.queue.retain(|(_, cache)| {
+            let clean = Arc::strong_count(cache) == 1
+                && cache.try_lock().map_or(false, |cache| !cache.modified);
+            if clean {
+                freed += 1;
+            }
+            !clean
+        });
+    }
+    freed
 }
 /// Sync(write) all the block cache to disk.
 pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
+    for shard in BLOCK_CACHE_MANAGER.iter() {
+        let manager = shard.lock();
+        for (_, cache) in manager.queue.iter() {
+            cache.lock().sync();
+        }
+    }
+}
+
+/// 模拟一次在途的读请求：设备在请求完成之前让另一个任务访问同一分片中的其他块，
+/// 确认读盘期间分片的锁已经放开，另一个任务不会被挡住
+#[allow(unused)]
+pub fn block_cache_async_test() {
+    use alloc::sync::Weak;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    struct SlowDevice {
+        this:      Weak<SlowDevice>,
+        other_ran: AtomicBool,
+    }
+    impl BlockDevice for SlowDevice {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.fill(block_id as u8);
+        }
+        fn write_block(&self, _block_id: usize, _buf: &[u8]) {}
+        fn read_block_wait(&self, block_id: usize, buf: &mut [u8]) {
+            if block_id == 0 {
+                // 请求在途时调度到的另一个任务
+                let dev: Arc<dyn BlockDevice> = self.this.upgrade().unwrap();
+                let key = (0, device_id(&dev));
+                assert!(BLOCK_CACHE_MANAGER[0].lock().loading.contains_key(&key));
+                let other = get_block_cache(CACHE_SHARDS, dev);
+                assert_eq!(other.lock().read(0, |v: &u8| *v), CACHE_SHARDS as u8);
+                self.other_ran.store(true, Ordering::Release);
+            }
+            self.read_block(block_id, buf);
+        }
+    }
+
+    let slow = Arc::new_cyclic(|this| SlowDevice {
+        this:      this.clone(),
+        other_ran: AtomicBool::new(false),
+    });
+    let dev: Arc<dyn BlockDevice> = slow.clone();
+    let block_cache = get_block_cache(0, Arc::clone(&dev));
+    assert!(slow.other_ran.load(Ordering::Acquire));
+    assert_eq!(block_cache.lock().read(0, |v: &u8| *v), 0);
+    assert!(!BLOCK_CACHE_MANAGER[0]
+        .lock()
+        .loading
+        .contains_key(&(0, device_id(&dev))));
+    drop(block_cache);
+    evict_block_cache(0, &dev);
+    evict_block_cache(CACHE_SHARDS, &dev);
+    info!("block_cache_async_test passed!");
+}
+
+/// 设备在请求完成之前，另一个任务来读同一个块：它进入这个块的等待队列并保持阻塞，
+/// 不会拿到还没读完的块；读入完成后它被唤醒，等待队列随之清空
+#[allow(unused)]
+pub fn block_cache_wait_test() {
+    use alloc::sync::Weak;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::task::{remove_task, TaskStatus, INITPROC};
+
+    struct DelayedDevice {
+        this:   Weak<DelayedDevice>,
+        waited: AtomicBool,
+    }
+    impl BlockDevice for DelayedDevice {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.fill(block_id as u8 + 1);
+        }
+        fn write_block(&self, _block_id: usize, _buf: &[u8]) {}
+        fn read_block_wait(&self, block_id: usize, buf: &mut [u8]) {
+            let dev: Arc<dyn BlockDevice> = self.this.upgrade().unwrap();
+            let key = (block_id, device_id(&dev));
+            let mut manager = BLOCK_CACHE_MANAGER[block_id % CACHE_SHARDS].lock();
+            assert!(manager.find(key).is_none());
+            // 请求在途时来读同一个块的任务
+            let waiter = INITPROC.clone();
+            waiter.inner_exclusive_access(file!(), line!()).task_status = TaskStatus::Blocked;
+            assert!(manager.wait_loading(key, waiter.clone()));
+            drop(manager);
+            // 设备稍后才完成请求，在此之前等待者一直阻塞，块也不在缓存中
+            assert!(
+                waiter.inner_exclusive_access(file!(), line!()).task_status == TaskStatus::Blocked
+            );
+            assert!(!block_cached(block_id, &dev));
+            self.waited.store(true, Ordering::Release);
+            self.read_block(block_id, buf);
+        }
+    }
+
+    let delayed = Arc::new_cyclic(|this| DelayedDevice {
+        this:   this.clone(),
+        waited: AtomicBool::new(false),
+    });
+    let dev: Arc<dyn BlockDevice> = delayed.clone();
+    let block_cache = get_block_cache(1, Arc::clone(&dev));
+    assert!(delayed.waited.load(Ordering::Acquire));
+    assert_eq!(block_cache.lock().read(0, |v: &u8| *v), 2);
+    let waiter = INITPROC.clone();
+    assert!(waiter.inner_exclusive_access(file!(), line!()).task_status == TaskStatus::Ready);
+    let mut manager = BLOCK_CACHE_MANAGER[1].lock();
+    assert!(!manager.wait_loading((1, device_id(&dev)), waiter.clone()));
+    assert!(manager.loading.is_empty());
+    drop(manager);
+    // initproc 创建时已经在就绪队列中，去掉唤醒时多加入的一次
+    remove_task(waiter);
+    drop(block_cache);
+    evict_block_cache(1, &dev);
+    info!("block_cache_wait_test passed!");
+}
+
+/// 持有一个分片的锁和一个块的锁时，仍能访问其他分片中的块 (自旋锁下若共用一把锁会死锁)；
+/// 块按块号落在各自的分片中，各分片的上限加起来不少于 block_cache_size
+#[allow(unused)]
+pub fn block_cache_shard_test() {
+    struct PatternDevice;
+    impl BlockDevice for PatternDevice {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.fill(block_id as u8);
+        }
+        fn write_block(&self, _block_id: usize, _buf: &[u8]) {}
+    }
+
+    assert!(shard_capacity() * CACHE_SHARDS >= sysctl::block_cache_size());
+    let dev: Arc<dyn BlockDevice> = Arc::new(PatternDevice);
+    let held = get_block_cache(0, Arc::clone(&dev));
+    let held_block = held.lock();
+    let held_shard = BLOCK_CACHE_MANAGER[0].lock();
+    for block_id in 1..CACHE_SHARDS {
+        let cache = get_block_cache(block_id, Arc::clone(&dev));
+        assert_eq!(cache.lock().read(0, |v: &u8| *v), block_id as u8);
+        let key = (block_id, device_id(&dev));
+        for (shard, manager) in BLOCK_CACHE_MANAGER.iter().enumerate().skip(1) {
+            assert_eq!(manager.lock().find(key).is_some(), shard == block_id);
+        }
+    }
+    assert!(held_shard.find((0, device_id(&dev))).is_some());
+    drop(held_shard);
+    drop(held_block);
+    drop(held);
+    for block_id in 0..CACHE_SHARDS {
+        assert!(evict_block_cache(block_id, &dev));
+        assert!(!block_cached(block_id, &dev));
     }
+    info!("block_cache_shard_test passed!");
 }
@@ -4,10 +4,25 @@
 
 use core::any::Any;
 
+use super::BLOCK_SZ;
+
 /// Block device interface.
 pub trait BlockDevice: Send + Sync + Any {
     /// Read a block from the block device.
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     /// Write a block to the block device.
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// 提交读请求后让出 CPU，等设备完成后再返回；不支持中断的设备直接同步读取
+    fn read_block_wait(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_block(block_id, buf);
+    }
+    /// 从 `block_id` 开始连续读 `buf.len() / BLOCK_SZ` 个块，能一次读多块的设备应当重写
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        for (i, block) in buf.chunks_mut(BLOCK_SZ).enumerate() {
+            self.read_block_wait(block_id + i, block);
+        }
+    }
+    /// Tell the device that `count` blocks starting at `block_id` are no longer in use,
+    /// devices without discard (TRIM) support just ignore it.
+    fn discard(&self, _block_id: usize, _count: usize) {}
 }
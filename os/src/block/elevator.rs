@@ -0,0 +1,109 @@
+//! 简单的块 I/O 调度器 (电梯)
+//!
+//! 请求先攒在调度器里，下发前按块号排序，并把块号相邻的请求合并成一次设备读，
+//! 减少设备往返的次数，主要服务于预读这类一次提交多个块的场景。
+
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::ops::Range;
+
+use super::{block_dev::BlockDevice, BLOCK_SZ};
+
+/// 一次合并的最大块数，避免单个请求过大
+pub const MAX_MERGE_BLOCKS: usize = 64;
+
+/// 按块号排序并合并相邻请求的 I/O 调度器
+pub struct Elevator {
+    pending: BTreeSet<usize>,
+}
+
+impl Default for Elevator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Elevator {
+    pub fn new() -> Self {
+        Self {
+            pending: BTreeSet::new(),
+        }
+    }
+
+    /// 提交一个读请求，重复的块只读一次
+    pub fn submit(&mut self, block_id: usize) {
+        self.pending.insert(block_id);
+    }
+
+    /// 是否还有没下发的请求
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// 取出全部请求，按块号升序合并成若干段连续的块
+    pub fn drain(&mut self) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for block_id in core::mem::take(&mut self.pending) {
+            match runs.last_mut() {
+                Some(run) if run.end == block_id && run.len() < MAX_MERGE_BLOCKS => {
+                    run.end += 1;
+                }
+                _ => runs.push(block_id..block_id + 1),
+            }
+        }
+        runs
+    }
+
+    /// 下发全部读请求，每段连续的块只做一次设备读，按块号升序返回 (块号, 块数据)
+    pub fn dispatch_reads(&mut self, block_device: &dyn BlockDevice) -> Vec<(usize, Vec<u8>)> {
+        let mut blocks = Vec::new();
+        for run in self.drain() {
+            let mut buf = vec![0u8; run.len() * BLOCK_SZ];
+            block_device.read_blocks(run.start, &mut buf);
+            for (block_id, data) in run.zip(buf.chunks(BLOCK_SZ)) {
+                blocks.push((block_id, data.to_vec()));
+            }
+        }
+        blocks
+    }
+}
+
+/// 乱序提交几段相邻的块，确认它们被合并成更少的设备请求且数据按块号对应
+#[allow(unused)]
+pub fn elevator_test() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 记录设备请求次数，每个块的内容都是它的块号
+    struct CountingDevice {
+        ops: AtomicUsize,
+    }
+    impl BlockDevice for CountingDevice {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            self.read_blocks(block_id, buf);
+        }
+        fn write_block(&self, _block_id: usize, _buf: &[u8]) {}
+        fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+            self.ops.fetch_add(1, Ordering::Relaxed);
+            for (i, block) in buf.chunks_mut(BLOCK_SZ).enumerate() {
+                block.fill((block_id + i) as u8);
+            }
+        }
+    }
+
+    let dev = CountingDevice {
+        ops: AtomicUsize::new(0),
+    };
+    let mut elevator = Elevator::new();
+    for block_id in [5, 3, 4, 11, 10, 20, 4] {
+        elevator.submit(block_id);
+    }
+    let blocks = elevator.dispatch_reads(&dev);
+    assert!(elevator.is_empty());
+    // 3..6、10..12、20..21 三段
+    assert_eq!(dev.ops.load(Ordering::Relaxed), 3);
+    let ids: Vec<usize> = blocks.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, [3, 4, 5, 10, 11, 20]);
+    for (block_id, data) in blocks.iter() {
+        assert!(data.iter().all(|b| *b == *block_id as u8));
+    }
+    info!("elevator_test passed!");
+}
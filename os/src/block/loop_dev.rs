@@ -0,0 +1,57 @@
+//! Loop device
+//!
+//! 把一个普通文件当作块设备使用，便于把存放在文件中的文件系统镜像挂载起来
+
+use alloc::{sync::Arc, vec::Vec};
+
+use lazy_static::*;
+use spin::Mutex;
+
+use super::{block_dev::BlockDevice, BLOCK_SZ};
+use crate::fs::inode::Inode;
+
+/// Linux 中 loop 设备的主设备号
+pub const LOOP_MAJOR: u64 = 7;
+
+/// 以文件为后端的块设备，第 i 块对应文件中 [i * BLOCK_SZ, (i + 1) * BLOCK_SZ) 的内容
+pub struct LoopDevice {
+    inode: Arc<dyn Inode>,
+}
+
+impl LoopDevice {
+    pub fn new(inode: Arc<dyn Inode>) -> Self {
+        Self { inode }
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let len = self.inode.read_at(block_id * BLOCK_SZ, buf);
+        // 超出文件末尾的部分按 0 处理
+        buf[len..].fill(0);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.inode.write_at(block_id * BLOCK_SZ, buf);
+    }
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        // 连续的块在文件中也是连续的，一次读完
+        self.read_block(block_id, buf);
+    }
+}
+
+lazy_static! {
+    /// 已绑定的 loop 设备，下标即设备编号 (/dev/loopN 中的 N)
+    static ref LOOP_DEVICES: Mutex<Vec<Arc<LoopDevice>>> = Mutex::new(Vec::new());
+}
+
+/// 把 inode 绑定到一个新的 loop 设备上，返回设备编号
+pub fn loop_setup(inode: Arc<dyn Inode>) -> usize {
+    let mut devices = LOOP_DEVICES.lock();
+    devices.push(Arc::new(LoopDevice::new(inode)));
+    devices.len() - 1
+}
+
+/// 根据设备编号找到 loop 设备
+pub fn loop_device(index: usize) -> Option<Arc<LoopDevice>> {
+    LOOP_DEVICES.lock().get(index).cloned()
+}
@@ -0,0 +1,99 @@
+//! In-memory block device
+//!
+//! 用内存模拟的块设备，可以设置写入若干次之后“掉电”，之后的写入全部丢弃，
+//! 用来模拟写到一半断电的情况，再用同一份数据重新挂载检查文件系统是否一致
+
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use super::{block_dev::BlockDevice, BLOCK_SZ};
+
+/// 不会掉电
+const NEVER_CRASH: usize = usize::MAX;
+
+pub struct MemBlockDevice {
+    data:        Mutex<Vec<u8>>,
+    /// 已经成功写入的次数
+    writes:      AtomicUsize,
+    /// 读取的次数
+    reads:       AtomicUsize,
+    /// 写入这么多次之后掉电
+    crash_after: AtomicUsize,
+    /// 收到的 discard 请求 (block_id, count)
+    discards:    Mutex<Vec<(usize, usize)>>,
+}
+
+impl MemBlockDevice {
+    /// 创建一个有 block_count 个块、内容全为 0 的设备
+    pub fn new(block_count: usize) -> Self {
+        Self::from_image(vec![0u8; block_count * BLOCK_SZ])
+    }
+
+    /// 用已有的镜像创建设备，长度不足一个块的部分补 0
+    pub fn from_image(mut image: Vec<u8>) -> Self {
+        let len = (image.len() + BLOCK_SZ - 1) / BLOCK_SZ * BLOCK_SZ;
+        image.resize(len, 0);
+        Self {
+            data:        Mutex::new(image),
+            writes:      AtomicUsize::new(0),
+            reads:       AtomicUsize::new(0),
+            crash_after: AtomicUsize::new(NEVER_CRASH),
+            discards:    Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 从现在起再成功写入 n 次之后掉电
+    pub fn crash_after(&self, n: usize) {
+        let writes = self.writes.load(Ordering::Relaxed);
+        self.crash_after
+            .store(writes.saturating_add(n), Ordering::Relaxed);
+    }
+
+    /// 是否已经掉电
+    pub fn crashed(&self) -> bool {
+        self.writes.load(Ordering::Relaxed) >= self.crash_after.load(Ordering::Relaxed)
+    }
+
+    /// 当前设备内容，可以用 from_image 构造新设备来模拟重新挂载
+    pub fn image(&self) -> Vec<u8> {
+        self.data.lock().clone()
+    }
+
+    /// 目前为止读取的次数
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// 目前为止收到的 discard 请求
+    pub fn discards(&self) -> Vec<(usize, usize)> {
+        self.discards.lock().clone()
+    }
+}
+
+impl BlockDevice for MemBlockDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let data = self.data.lock();
+        let start = block_id * BLOCK_SZ;
+        let len = buf.len().min(data.len().saturating_sub(start));
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        buf[len..].fill(0);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        if self.crashed() {
+            return;
+        }
+        let mut data = self.data.lock();
+        let start = block_id * BLOCK_SZ;
+        let len = buf.len().min(data.len().saturating_sub(start));
+        data[start..start + len].copy_from_slice(&buf[..len]);
+        self.writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn discard(&self, block_id: usize, count: usize) {
+        self.discards.lock().push((block_id, count));
+    }
+}
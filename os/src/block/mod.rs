@@ -1,6 +1,10 @@
 //! Block device and block cache module
 pub mod block_cache;
 pub mod block_dev;
+pub mod cached_dev;
+pub mod elevator;
+pub mod loop_dev;
+pub mod mem_dev;
 
 /// Block size in bytes
 pub const BLOCK_SZ: usize = 512;
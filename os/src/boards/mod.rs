@@ -1,5 +1,7 @@
+#[cfg(feature = "qemu")]
 mod qemu;
 
+#[cfg(feature = "visionfive2")]
 mod visionfive2;
 
 #[cfg(feature = "qemu")]
@@ -21,10 +21,48 @@ pub const MMIO: &[(usize, usize, MapPermission)] = &[
 
 pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
 
+/// PLIC 的物理地址
+pub const VIRT_PLIC: usize = 0x0C00_0000;
+/// virtio-blk 的中断源编号
+pub const VIRTIO0_IRQ: usize = 1;
+
+/// 全局的 PLIC
+pub static PLIC: Plic = Plic::new(VIRT_PLIC);
+
+/// 配置 PLIC，把块设备的中断送到启动 hart 的 S 态，并打开 S 态外部中断
+pub fn device_init() {
+    let hart = hart_id();
+    PLIC.set_priority(VIRTIO0_IRQ, 1);
+    PLIC.enable(hart, VIRTIO0_IRQ);
+    PLIC.set_threshold(hart, 0);
+    unsafe {
+        sie::set_sext();
+    }
+    BLOCK_DEVICE_IMPL.enable_irq();
+}
+
+/// S 态外部中断处理：从 PLIC 领取中断，分发给设备驱动后通知处理完毕
+pub fn irq_handler() {
+    let hart = hart_id();
+    let irq = PLIC.claim(hart);
+    match irq {
+        0 => return,
+        VIRTIO0_IRQ => BLOCK_DEVICE_IMPL.handle_irq(),
+        _ => warn!("unsupported external interrupt {}", irq),
+    }
+    PLIC.complete(hart, irq);
+}
+
 //ref:: https://github.com/andre-richter/qemu-exit
 use core::arch::asm;
 
-use crate::mm::MapPermission;
+use riscv::register::sie;
+
+use crate::{
+    drivers::{block::BLOCK_DEVICE_IMPL, plic::Plic},
+    mm::MapPermission,
+    task::hart_id,
+};
 
 const EXIT_SUCCESS: u32 = 0x5555; // Equals `exit(0)`. qemu successful exit
 
@@ -16,6 +16,12 @@ pub const MMIO: &[(usize, usize, MapPermission)] = &[
 
 pub type BlockDeviceImpl = crate::drivers::block::SDCard;
 
+/// SD 卡驱动只支持轮询，不打开外部中断
+pub fn device_init() {}
+
+/// 没有打开任何外部中断源
+pub fn irq_handler() {}
+
 pub fn shutdown() -> ! {
     // 直接死循环
     loop {}
@@ -4,10 +4,49 @@
 
 /// user app's stack size
 pub const USER_STACK_SIZE: usize = 4096 * 20;
+/// 主线程的用户栈在缺页时最多向下增长到的大小，初始只映射 USER_STACK_SIZE
+pub const USER_STACK_MAX_SIZE: usize = USER_STACK_SIZE * 4;
+/// 缺页地址低于当前栈底不超过这么多页时才当作栈增长
+pub const USER_STACK_GROW_GAP: usize = 8;
 /// kernel stack size
 pub const KERNEL_STACK_SIZE: usize = 4096 * 8;
 /// kernel heap size
-pub const KERNEL_HEAP_SIZE: usize = PAGE_SIZE * 0x500;
+///
+/// 页数可以在构建时用环境变量 `KERNEL_HEAP_PAGES` 指定 (十进制或 0x 开头的十六进制)，
+/// 未指定时为 0x500 页
+pub const KERNEL_HEAP_SIZE: usize =
+    PAGE_SIZE * parse_pages(option_env!("KERNEL_HEAP_PAGES"), 0x500);
+
+/// 在编译期解析页数，格式不对时编译失败
+const fn parse_pages(value: Option<&str>, default: usize) -> usize {
+    let bytes = match value {
+        Some(value) if !value.is_empty() => value.as_bytes(),
+        _ => return default,
+    };
+    let (radix, mut i) =
+        if bytes.len() > 2 && bytes[0] == b'0' && (bytes[1] == b'x' || bytes[1] == b'X') {
+            (16, 2)
+        } else {
+            (10, 0)
+        };
+    let mut pages = 0;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b'0'..=b'9' => bytes[i] - b'0',
+            b'a'..=b'f' if radix == 16 => bytes[i] - b'a' + 10,
+            b'A'..=b'F' if radix == 16 => bytes[i] - b'A' + 10,
+            b'_' => {
+                i += 1;
+                continue;
+            }
+            _ => panic!("KERNEL_HEAP_PAGES is not a number"),
+        };
+        pages = pages * radix + digit as usize;
+        i += 1;
+    }
+    assert!(pages > 0, "KERNEL_HEAP_PAGES must be positive");
+    pages
+}
 /// physical memory end address
 #[cfg(feature = "qemu")]
 pub const MEMORY_END: usize = 0xffff_ffc0_88000000;
@@ -33,14 +72,27 @@ pub const TRAP_CONTEXT_BASE: usize = USER_SPACE_END - PAGE_SIZE * 2 + 1;
 pub use crate::boards::{CLOCK_FREQ, MMIO};
 /// Big stride (lcm of 2..20)
 pub const BIG_STRIDE: usize = 232792560;
+/// 任务的默认优先级
+pub const DEFAULT_PRIORITY: usize = 16;
 /// system name
 pub const SYS_NAME: &str = "Chaos";
 /// system nodename
 pub const SYS_NODENAME: &str = "None";
+/// system domainname
+pub const SYS_DOMAINNAME: &str = "None";
+/// max length of the fields in utsname (hostname, domainname...), see __NEW_UTS_LEN
+pub const UTS_LEN: usize = 64;
+/// 附属用户组列表的最大长度, see NGROUPS_MAX
+pub const NGROUPS_MAX: usize = 65536;
 /// system release
-pub const SYS_RELEASE: &str = "0.0.1";
-/// system version
-pub const SYS_VERSION: &str = "#1-Chaos RISC-V 64bit Version 0.0.1";
+///
+/// 用户程序 (如 glibc、busybox) 会把 release 当作 Linux 内核版本号 `x.y.z` 解析，
+/// 这里填一个与当前实现的系统调用接口大致相当的版本
+pub const SYS_RELEASE: &str = "5.10.0";
+/// system version, chaos 自身的版本号放在这里
+pub const SYS_VERSION: &str = "#1 Chaos 0.0.1 RISC-V 64bit";
+/// hardware identifier
+pub const SYS_MACHINE: &str = "riscv64";
 ///
 pub const STACK_TOP: usize = 0x1_0000_0000;
 ///
@@ -49,6 +101,12 @@ pub const MMAP_BASE: usize = 0x2000_0000;
 pub const PAGE_TABLE_LEVEL: usize = 3;
 /// kernel space offset
 pub const KERNEL_SPACE_OFFSET: usize = 0xffff_ffc0_0000_0;
+/// 支持的最大 hart 数，entry.S 按这个数目预留启动栈
+pub const MAX_HARTS: usize = 4;
+/// 每个进程最多能打开的 fd 个数
+pub const MAX_FD: usize = 1024;
+/// 启用 fixed-seed feature 时伪随机数发生器使用的种子
+pub const FIXED_RANDOM_SEED: u64 = 0x5eed_c4a0_5eed_c4a0;
 
 pub const TRAP_CONTEXT_TRAMPOLINE: usize = 0xFFFF_FFFF_FFFF_E000;
 
@@ -4,8 +4,13 @@ use core::{
     fmt::{self, Write},
 };
 
+use spin::Mutex;
+
 use crate::sbi::console_putchar;
 
+/// 普通输出持有的锁，保证一次 print 的内容不会与其他输出交错
+static CONSOLE_LOCK: Mutex<()> = Mutex::new(());
+
 struct Stdout;
 
 impl Write for Stdout {
@@ -19,9 +24,29 @@ impl Write for Stdout {
 }
 /// print to the host console using the format string and arguments.
 pub fn print(args: fmt::Arguments) {
+    let _guard = CONSOLE_LOCK.lock();
     Stdout.write_fmt(args).unwrap();
 }
 
+/// 不加锁、不分配内存的紧急输出，供 panic 和中断上下文使用。
+/// 此时 [`CONSOLE_LOCK`] 可能正被被打断的代码持有，等锁会死锁，
+/// 代价是输出可能与正在进行的 print 交错
+pub fn emergency_print(args: fmt::Arguments) {
+    let _ = Stdout.write_fmt(args);
+}
+
+/// 持有控制台锁时进行紧急输出，能返回即说明没有死锁
+#[allow(unused)]
+pub fn emergency_print_test() {
+    let guard = CONSOLE_LOCK.lock();
+    assert!(CONSOLE_LOCK.try_lock().is_none());
+    emergency_print(format_args!(
+        "[kernel] emergency output while console is locked\n"
+    ));
+    drop(guard);
+    info!("emergency_print_test passed!");
+}
+
 /// Print! macro to the host console using the format string and arguments.
 #[macro_export]
 macro_rules! print {
@@ -30,6 +55,22 @@ macro_rules! print {
     }
 }
 
+/// 紧急输出，不获取控制台锁
+#[macro_export]
+macro_rules! eprint {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::emergency_print(format_args!($fmt $(, $($arg)+)?))
+    }
+}
+
+/// 紧急输出并换行，不获取控制台锁
+#[macro_export]
+macro_rules! eprintln {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::emergency_print(format_args!(concat!($fmt, "\n") $(, $($arg)+)?))
+    }
+}
+
 /// Println! macro to the host console using the format string and arguments.
 #[macro_export]
 macro_rules! println {
@@ -7,13 +7,30 @@ use alloc::sync::Arc;
 
 use lazy_static::*;
 pub use vf2_sd::SDCard;
+#[cfg(feature = "qemu")]
+pub use virtio_blk::virtio_blk_irq_test;
 pub use virtio_blk::VirtIOBlock;
 
+#[cfg(feature = "qemu")]
+use crate::block::cached_dev::CachedDevice;
 use crate::{block::block_dev::BlockDevice, boards::BlockDeviceImpl};
 
 lazy_static! {
+    /// 具体类型的块设备驱动实例，供中断处理等需要驱动自身接口的地方使用
+    pub static ref BLOCK_DEVICE_IMPL: Arc<BlockDeviceImpl> = Arc::new(BlockDeviceImpl::new());
     /// The global block device driver instance: BLOCK_DEVICE with BlockDevice trait
-    pub static ref BLOCK_DEVICE: Arc<dyn ext4_rs::BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    pub static ref BLOCK_DEVICE: Arc<dyn ext4_rs::BlockDevice> = root_device();
+}
+
+/// virtio-blk 经过块缓存读写，读盘时可以等待设备中断
+#[cfg(feature = "qemu")]
+fn root_device() -> Arc<dyn ext4_rs::BlockDevice> {
+    Arc::new(CachedDevice::new(BLOCK_DEVICE_IMPL.clone()))
+}
+
+#[cfg(not(feature = "qemu"))]
+fn root_device() -> Arc<dyn ext4_rs::BlockDevice> {
+    BLOCK_DEVICE_IMPL.clone()
 }
 
 #[allow(unused)]
@@ -1,11 +1,13 @@
-use alloc::vec::Vec;
-use core::ptr::NonNull;
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use ext4_rs::BLOCK_SIZE;
 use lazy_static::*;
 use spin::Mutex;
 use virtio_drivers::{
-    device::blk::VirtIOBlk,
+    device::blk::{BlkReq, BlkResp, VirtIOBlk},
     transport::mmio::{MmioTransport, VirtIOHeader},
     BufferDirection,
     Hal,
@@ -14,7 +16,6 @@ use virtio_drivers::{
 // use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
 use super::BlockDevice;
 use crate::{
-    block::BLOCK_SZ,
     config::{KERNEL_SPACE_OFFSET, PAGE_SIZE},
     mm::{
         frame_alloc_contiguous,
@@ -26,17 +27,30 @@ use crate::{
         VirtAddr,
         KERNEL_SPACE,
     },
-    sync::UPSafeCell,
+    task::{
+        block_current_and_run_next,
+        current_task,
+        has_ready_task,
+        suspend_current_and_run_next,
+        wakeup_task,
+        TaskControlBlock,
+    },
 };
 
 #[allow(unused)]
 const VIRTIO0: usize = 0x10001000 + KERNEL_SPACE_OFFSET * PAGE_SIZE;
 /// VirtIOBlock device driver strcuture for virtio_blk device
-pub struct VirtIOBlock(Mutex<VirtIOBlk<VirtioHal, MmioTransport>>);
+pub struct VirtIOBlock {
+    blk:         Mutex<VirtIOBlk<VirtioHal, MmioTransport>>,
+    /// PLIC 是否已经打开了设备中断，打开之前只能轮询
+    irq_enabled: AtomicBool,
+    /// 等待请求完成的任务，由设备中断唤醒
+    waiters:     Mutex<Vec<Arc<TaskControlBlock>>>,
+}
 
 lazy_static! {
     /// The global io data queue for virtio_blk device
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
+    static ref QUEUE_FRAMES: Mutex<Vec<FrameTracker>> = Mutex::new(Vec::new());
 }
 
 unsafe impl Send for VirtIOBlock {}
@@ -45,13 +59,13 @@ unsafe impl Sync for VirtIOBlock {}
 impl BlockDevice for VirtIOBlock {
     /// Read a block from the virtio_blk device
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        let mut res = self.0.lock().read_blocks(block_id, buf);
+        let mut res = self.blk.lock().read_blocks(block_id, buf);
         if res.is_err() {
             error!("Error when reading VirtIOBlk, block_id {}", block_id);
             let mut times = 0 as usize;
             while res.is_err() {
                 warn!("read_block: retrying block_id: {:}", block_id);
-                res = self.0.lock().read_blocks(block_id, buf);
+                res = self.blk.lock().read_blocks(block_id, buf);
                 times += 1;
                 if times > 10 {
                     panic!("read_block {}: failed after 10 retries", block_id);
@@ -67,48 +81,23 @@ impl BlockDevice for VirtIOBlock {
             res.unwrap()
         }
     }
-    ///
+    /// 块缓存淘汰脏块时在持有分片锁的情况下写回，不能睡眠，所以写请求总是轮询完成
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         debug!("write_block: block_id: {:}", block_id);
-        self.0
+        self.blk
             .lock()
             .write_blocks(block_id, buf)
             .expect("Error when writing VirtIOBlk");
     }
-}
-
-impl ext4_rs::BlockDevice for VirtIOBlock {
-    fn read_offset(&self, offset: usize) -> Vec<u8> {
-        // debug!("read_offset: offset = {:#x}", offset);
-        let mut buf = [0u8; BLOCK_SIZE];
-        self.0
-            .lock()
-            .read_blocks(offset / BLOCK_SZ, &mut buf)
+    /// 等待设备中断完成读请求，期间其他任务可以运行
+    fn read_block_wait(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_blocks_irq(block_id, buf)
+            .expect("Error when reading VirtIOBlk");
+    }
+    /// 一个 virtio 请求可以携带多个扇区
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_blocks_irq(block_id, buf)
             .expect("Error when reading VirtIOBlk");
-        // debug!("read_offset = {:#x}, buf = {:x?}", offset, buf);
-        buf[offset % BLOCK_SZ..].to_vec()
-    }
-    fn write_offset(&self, offset: usize, data: &[u8]) {
-        debug!("write_offset: offset = {:#x}", offset);
-        //     debug!("data len = {:#x}", data.len());
-        let mut write_size = 0;
-        while write_size < data.len() {
-            let block_id = (offset + write_size) / BLOCK_SZ;
-            let block_offset = (offset + write_size) % BLOCK_SZ;
-            let mut buf = [0u8; BLOCK_SZ];
-            let copy_size = core::cmp::min(data.len() - write_size, BLOCK_SZ - block_offset);
-            self.0
-                .lock()
-                .read_blocks(block_id, &mut buf)
-                .expect("Error when reading VirtIOBlk");
-            buf[block_offset..block_offset + copy_size]
-                .copy_from_slice(&data[write_size..write_size + copy_size]);
-            self.0
-                .lock()
-                .write_blocks(block_id, &buf)
-                .expect("Error when writing VirtIOBlk");
-            write_size += copy_size;
-        }
     }
 }
 
@@ -125,16 +114,80 @@ impl VirtIOBlock {
         debug!("VirtIOBlock::new()");
         unsafe {
             let header = &mut *(VIRTIO0 as *mut VirtIOHeader);
-            let blk = Self(Mutex::new(
-                VirtIOBlk::<VirtioHal, MmioTransport>::new(
-                    MmioTransport::new(header.into()).unwrap(),
-                )
-                .unwrap(),
-            ));
+            let blk = Self {
+                blk:         Mutex::new(
+                    VirtIOBlk::<VirtioHal, MmioTransport>::new(
+                        MmioTransport::new(header.into()).unwrap(),
+                    )
+                    .unwrap(),
+                ),
+                irq_enabled: AtomicBool::new(false),
+                waiters:     Mutex::new(Vec::new()),
+            };
             debug!("VirtIOBlock created");
             blk
         }
     }
+
+    /// PLIC 已为设备打开中断，此后请求可以等待中断完成
+    pub fn enable_irq(&self) {
+        self.irq_enabled.store(true, Ordering::Release);
+    }
+
+    /// 设备中断处理：应答中断并唤醒所有等待的任务，由它们各自检查请求是否完成
+    pub fn handle_irq(&self) {
+        self.blk.lock().ack_interrupt();
+        let waiters: Vec<_> = self.waiters.lock().drain(..).collect();
+        for task in waiters {
+            wakeup_task(task);
+        }
+    }
+
+    /// 提交读请求后阻塞当前任务，直到设备中断报告请求完成
+    pub fn read_blocks_irq(&self, block_id: usize, buf: &mut [u8]) -> virtio_drivers::Result {
+        if !self.irq_enabled.load(Ordering::Acquire) || current_task().is_none() {
+            return self.blk.lock().read_blocks(block_id, buf);
+        }
+        let mut req = BlkReq::default();
+        let mut resp = BlkResp::default();
+        unsafe {
+            let token = self
+                .blk
+                .lock()
+                .read_blocks_nb(block_id, &mut req, buf, &mut resp)?;
+            self.wait_for(token);
+            self.blk
+                .lock()
+                .complete_read_blocks(token, &req, buf, &mut resp)
+        }
+    }
+
+    /// 等待 `token` 对应的请求出现在 used ring 的队首。
+    /// 内核态不响应中断，所以没有其他可运行的任务时只能轮询，
+    /// 否则阻塞当前任务，让出 CPU 直到设备中断把它唤醒
+    fn wait_for(&self, token: u16) {
+        loop {
+            match self.blk.lock().peek_used() {
+                Some(used) if used == token => return,
+                Some(_) => {
+                    // 队首是别人的请求，叫醒它的主人先取走，自己稍后再看
+                    let waiters: Vec<_> = self.waiters.lock().drain(..).collect();
+                    for task in waiters {
+                        wakeup_task(task);
+                    }
+                    if has_ready_task() {
+                        suspend_current_and_run_next();
+                    }
+                }
+                None => {
+                    if has_ready_task() {
+                        self.waiters.lock().push(current_task().unwrap());
+                        block_current_and_run_next();
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub struct VirtioHal;
@@ -172,7 +225,7 @@ unsafe impl Hal for VirtioHal {
     unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> virtio_drivers::PhysAddr {
         unsafe {
             KERNEL_SPACE
-                .exclusive_access(file!(), line!())
+                .lock()
                 .page_table
                 .translate_va(VirtAddr::from(buffer.as_ptr() as *const usize as usize))
                 .unwrap()
@@ -187,3 +240,46 @@ unsafe impl Hal for VirtioHal {
         //todo!();
     }
 }
+
+/// 提交一个非阻塞读请求，只通过 PLIC 报告的外部中断得知其完成，
+/// 确认中断路径能完成请求，且读到的数据与同步读取一致
+#[cfg(feature = "qemu")]
+#[allow(unused)]
+pub fn virtio_blk_irq_test(dev: &VirtIOBlock) {
+    use crate::{
+        block::BLOCK_SZ,
+        boards::{PLIC, VIRTIO0_IRQ},
+    };
+
+    let mut expected = [0u8; BLOCK_SZ];
+    dev.blk.lock().read_blocks(0, &mut expected).unwrap();
+    let mut buf = [0u8; BLOCK_SZ];
+    let mut req = BlkReq::default();
+    let mut resp = BlkResp::default();
+    unsafe {
+        let token = dev
+            .blk
+            .lock()
+            .read_blocks_nb(0, &mut req, &mut buf, &mut resp)
+            .unwrap();
+        // 内核态屏蔽了中断，直接向 PLIC 领取；之前的同步请求也可能留下中断，所以要等到自己的请求完成
+        loop {
+            let irq = PLIC.claim(0);
+            if irq == 0 {
+                continue;
+            }
+            assert_eq!(irq, VIRTIO0_IRQ);
+            dev.handle_irq();
+            PLIC.complete(0, irq);
+            if dev.blk.lock().peek_used() == Some(token) {
+                break;
+            }
+        }
+        dev.blk
+            .lock()
+            .complete_read_blocks(token, &req, &mut buf, &mut resp)
+            .unwrap();
+    }
+    assert_eq!(buf, expected);
+    info!("virtio_blk_irq_test passed!");
+}
@@ -1,5 +1,6 @@
 //! block device driver
 
 pub mod block;
+pub mod plic;
 
 pub use block::BLOCK_DEVICE;
@@ -0,0 +1,80 @@
+//! RISC-V PLIC (Platform-Level Interrupt Controller) 驱动
+//!
+//! 每个 hart 有 M 态和 S 态两个 context，内核只使用 S 态 context：
+//! 设置中断源优先级、在该 context 上打开中断源，之后通过 claim/complete 处理外部中断。
+
+use crate::config::{KERNEL_SPACE_OFFSET, PAGE_SIZE};
+
+/// 优先级寄存器相对基址的偏移
+const PRIORITY_BASE: usize = 0x0;
+/// 使能寄存器相对基址的偏移，每个 context 占 0x80 字节
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+/// 阈值与 claim/complete 寄存器相对基址的偏移，每个 context 占 0x1000 字节
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+
+/// PLIC 控制器
+pub struct Plic {
+    base_addr: usize,
+}
+
+impl Plic {
+    /// `base_addr` 为 PLIC 的物理地址，通过内核的线性映射访问
+    pub const fn new(base_addr: usize) -> Self {
+        Self {
+            base_addr: base_addr + KERNEL_SPACE_OFFSET * PAGE_SIZE,
+        }
+    }
+
+    /// hart 的 S 态 context 编号
+    fn supervisor_context(hart_id: usize) -> usize {
+        hart_id * 2 + 1
+    }
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base_addr + offset) as *mut u32
+    }
+
+    /// 设置中断源的优先级，0 表示屏蔽
+    pub fn set_priority(&self, source: usize, priority: u32) {
+        unsafe {
+            self.reg(PRIORITY_BASE + source * 4)
+                .write_volatile(priority)
+        }
+    }
+
+    /// 在 hart 的 S 态 context 上打开中断源
+    pub fn enable(&self, hart_id: usize, source: usize) {
+        let context = Self::supervisor_context(hart_id);
+        let reg = self.reg(ENABLE_BASE + context * ENABLE_STRIDE + source / 32 * 4);
+        unsafe { reg.write_volatile(reg.read_volatile() | 1 << (source % 32)) }
+    }
+
+    /// 设置 hart 的 S 态 context 的优先级阈值，只有优先级高于阈值的中断会被送达
+    pub fn set_threshold(&self, hart_id: usize, threshold: u32) {
+        let context = Self::supervisor_context(hart_id);
+        unsafe {
+            self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE)
+                .write_volatile(threshold)
+        }
+    }
+
+    /// 领取一个待处理的中断，返回中断源编号，没有待处理的中断时返回 0
+    pub fn claim(&self, hart_id: usize) -> usize {
+        let context = Self::supervisor_context(hart_id);
+        unsafe {
+            self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE + 4)
+                .read_volatile() as usize
+        }
+    }
+
+    /// 通知 PLIC 中断源已处理完毕
+    pub fn complete(&self, hart_id: usize, source: usize) {
+        let context = Self::supervisor_context(hart_id);
+        unsafe {
+            self.reg(CONTEXT_BASE + context * CONTEXT_STRIDE + 4)
+                .write_volatile(source as u32)
+        }
+    }
+}
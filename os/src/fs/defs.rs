@@ -28,6 +28,53 @@ bitflags! {
     }
 }
 
+impl OpenFlags {
+    /// 根据访问模式返回 (readable, writable)
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.contains(Self::O_RDWR) {
+            (true, true)
+        } else if self.contains(Self::O_WRONLY) {
+            (false, true)
+        } else {
+            (true, false)
+        }
+    }
+
+    /// F_SETFL 能够修改的文件状态标志
+    pub fn status_flags(&self) -> Self {
+        *self & (Self::O_APPEND | Self::O_NONBLOCK)
+    }
+}
+
+/// F_GETFD/F_SETFD 中的 close-on-exec 位
+pub const FD_CLOEXEC: usize = 1;
+
+/// fd_table 中每一项自己的标志，与 fd_table 按下标一一对应
+#[derive(Clone, Copy)]
+pub struct FdFlags {
+    /// exec 时关闭这个 fd
+    pub cloexec: bool,
+    /// 文件状态标志，只记录 O_APPEND 和 O_NONBLOCK，访问模式由打开的文件本身决定
+    pub status:  OpenFlags,
+}
+
+impl FdFlags {
+    pub const fn empty() -> Self {
+        Self {
+            cloexec: false,
+            status:  OpenFlags::empty(),
+        }
+    }
+
+    /// open 时传入的标志
+    pub fn from_open(flags: OpenFlags) -> Self {
+        Self {
+            cloexec: flags.contains(OpenFlags::O_CLOEXEC),
+            status:  flags.status_flags(),
+        }
+    }
+}
+
 bitflags! {
     pub struct FileMode: u32 {
         const S_IRWXU = 0o700;  // 用户（所有者）读、写、执行权限
@@ -50,3 +97,17 @@ bitflags! {
         const S_ISVTX = 0o1000; // 粘滞位
     }
 }
+
+/* fadvise advice */
+
+/// lseek 的 whence
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+pub const POSIX_FADV_NORMAL: usize = 0;
+pub const POSIX_FADV_RANDOM: usize = 1;
+pub const POSIX_FADV_SEQUENTIAL: usize = 2;
+pub const POSIX_FADV_WILLNEED: usize = 3;
+pub const POSIX_FADV_DONTNEED: usize = 4;
+pub const POSIX_FADV_NOREUSE: usize = 5;
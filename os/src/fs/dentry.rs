@@ -3,11 +3,13 @@ use alloc::{
     sync::Arc,
 };
 
-use super::inode::Inode;
+use super::{fs::MountRef, inode::Inode};
 
 pub struct Dentry {
     name:  String,
     inode: Arc<dyn Inode>,
+    /// 经由挂载点打开时持有的挂载引用
+    mount: Option<MountRef>,
 }
 
 impl Dentry {
@@ -15,6 +17,22 @@ impl Dentry {
         Self {
             name: name.to_string(),
             inode,
+            mount: None,
+        }
+    }
+
+    /// 让这个目录项在存在期间一直持有挂载的引用
+    pub fn pinned(mut self, mount: MountRef) -> Self {
+        self.mount = Some(mount);
+        self
+    }
+
+    /// 换一个名字的同一个目录项，挂载的引用也一并复制
+    pub fn renamed(&self, name: &str) -> Self {
+        Self {
+            name:  name.to_string(),
+            inode: self.inode(),
+            mount: self.mount.clone(),
         }
     }
 
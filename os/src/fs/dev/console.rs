@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+use riscv::register::sstatus;
+
+use super::{char_device_stat, impl_device_inode, makedev};
+use crate::{
+    fs::{file::File, inode::Stat},
+    sbi::{console_getchar, console_putchar},
+    task::suspend_current_and_run_next,
+};
+
+/// /dev/console: 直接读写 UART 的字符设备，与 fd 0/1/2 上的 Stdin/Stdout 相互独立
+pub struct Console;
+
+impl_device_inode!(Console);
+
+impl File for Console {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        // 与 Stdin 相同，一次只读一个字符，没有输入时让出 CPU
+        let c = loop {
+            let c = console_getchar();
+            if c == 0 || c == usize::MAX {
+                suspend_current_and_run_next();
+                continue;
+            }
+            break c;
+        };
+        unsafe {
+            sstatus::set_sum();
+            buf[0] = c as u8;
+            sstatus::clear_sum();
+        }
+        1
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        unsafe {
+            sstatus::set_sum();
+            for &c in buf {
+                console_putchar(c as usize);
+            }
+            sstatus::clear_sum();
+        }
+        buf.len()
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(char_device_stat(makedev(5, 1)))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
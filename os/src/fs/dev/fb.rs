@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+use riscv::register::sstatus;
+
+use super::{char_device_stat, impl_device_inode, makedev};
+use crate::{
+    config::PAGE_SIZE,
+    fs::{file::File, inode::Stat},
+    mm::{frame_alloc_contiguous, FrameTracker, MemorySet, PhysPageNum, VirtAddr},
+    task::process::Flags,
+};
+
+/// 帧缓冲的页数
+pub const FB_PAGES: usize = 4;
+/// 帧缓冲的字节数
+pub const FB_SIZE: usize = FB_PAGES * PAGE_SIZE;
+
+/// /dev/fb0: 占位用的帧缓冲，背后是一段固定的连续物理页，
+/// read/write 总是从缓冲区开头开始，mmap 直接映射这些物理页
+pub struct FrameBuffer {
+    frames: Vec<FrameTracker>,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        let (frames, _) = frame_alloc_contiguous(FB_PAGES);
+        Self { frames }
+    }
+
+    /// 按页拷贝缓冲区的 [0, len) 部分，`f` 的参数为 (页内的字节, 该页在缓冲区中的偏移)
+    fn for_each_page(&self, len: usize, mut f: impl FnMut(&'static mut [u8], usize)) {
+        let len = len.min(FB_SIZE);
+        let mut pos = 0;
+        for frame in self.frames.iter() {
+            if pos >= len {
+                break;
+            }
+            let n = (len - pos).min(PAGE_SIZE);
+            f(&mut frame.ppn.get_bytes_array()[..n], pos);
+            pos += n;
+        }
+    }
+}
+
+impl_device_inode!(FrameBuffer);
+
+impl File for FrameBuffer {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(FB_SIZE);
+        unsafe {
+            sstatus::set_sum();
+            self.for_each_page(len, |page, pos| {
+                buf[pos..pos + page.len()].copy_from_slice(page)
+            });
+            sstatus::clear_sum();
+        }
+        len
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        let len = buf.len().min(FB_SIZE);
+        unsafe {
+            sstatus::set_sum();
+            self.for_each_page(len, |page, pos| {
+                page.copy_from_slice(&buf[pos..pos + page.len()])
+            });
+            sstatus::clear_sum();
+        }
+        len
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(char_device_stat(makedev(29, 0)))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+    fn mmap_frames(&self, offset: usize, len: usize) -> Option<Vec<PhysPageNum>> {
+        if offset % PAGE_SIZE != 0 || len == 0 || offset + len > FB_SIZE {
+            return None;
+        }
+        let first = offset / PAGE_SIZE;
+        let last = (offset + len).div_ceil(PAGE_SIZE);
+        Some(self.frames[first..last].iter().map(|f| f.ppn).collect())
+    }
+}
+
+/// 把帧缓冲映射进一个新地址空间，确认经映射的读写落在设备的物理页上
+#[allow(unused)]
+pub fn fb_mmap_test() {
+    let fb = FrameBuffer::new();
+    let mut memory_set = MemorySet::new_bare();
+    let frames = fb.mmap_frames(PAGE_SIZE, PAGE_SIZE * 2).unwrap();
+    let start = memory_set.mmap_device(0, PAGE_SIZE * 2, frames, Flags::MAP_SHARED);
+    assert!(start > 0);
+    let vpn = VirtAddr::from(start as usize).floor();
+
+    // 通过映射写入，从设备读出
+    let mapped = memory_set.translate(vpn).unwrap().ppn();
+    assert_eq!(mapped, fb.frames[1].ppn);
+    mapped.get_bytes_array()[..4].copy_from_slice(b"ChaO");
+    let mut buf = [0u8; PAGE_SIZE + 4];
+    fb.read(&mut buf);
+    assert_eq!(&buf[PAGE_SIZE..], b"ChaO");
+
+    // 写入设备，从映射读出
+    buf[PAGE_SIZE..].copy_from_slice(b"fb0!");
+    fb.write(&buf);
+    assert_eq!(&mapped.get_bytes_array()[..4], b"fb0!");
+
+    // munmap 只解除映射，不会释放设备的物理页
+    memory_set.munmap(start as usize, PAGE_SIZE * 2);
+    assert!(memory_set
+        .translate(vpn)
+        .map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(&fb.frames[1].ppn.get_bytes_array()[..4], b"fb0!");
+    info!("fb_mmap_test passed!");
+}
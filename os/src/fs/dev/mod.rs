@@ -0,0 +1,316 @@
+//! devfs: 内存中的 /dev 目录
+//!
+//! 启动时挂载到 `/dev`，并注册 null、zero、urandom、console、tty、fb0 等设备节点，
+//! 之后对 `/dev/xxx` 的访问走正常的挂载点 + inode 路径解析。
+
+pub mod console;
+pub mod fb;
+pub mod node;
+pub mod null;
+pub mod tty;
+pub mod urandom;
+pub mod zero;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use self::{
+    console::Console,
+    fb::FrameBuffer,
+    node::DeviceNode,
+    null::Null,
+    tty::Tty,
+    urandom::URandom,
+    zero::Zero,
+};
+use super::{
+    dentry::Dentry,
+    file::File,
+    fs::{FileSystem, FileSystemType},
+    inode::{Inode, InodeType, Stat, StatMode},
+};
+
+/// 从 st_rdev 中取出主设备号
+pub const fn major(rdev: u64) -> u64 {
+    ((rdev >> 32) & 0xffff_f000) | ((rdev >> 8) & 0xfff)
+}
+
+/// 从 st_rdev 中取出次设备号
+pub const fn minor(rdev: u64) -> u64 {
+    ((rdev >> 12) & 0xffff_ff00) | (rdev & 0xff)
+}
+
+lazy_static! {
+    /// 设备号 (major, minor) 到驱动的映射
+    static ref DEVICE_DRIVERS: Mutex<BTreeMap<(u64, u64), Arc<dyn File>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// 注册设备驱动，设备号已被占用时返回 false
+pub fn register_device(major: u64, minor: u64, driver: Arc<dyn File>) -> bool {
+    let mut drivers = DEVICE_DRIVERS.lock();
+    if drivers.contains_key(&(major, minor)) {
+        return false;
+    }
+    drivers.insert((major, minor), driver);
+    true
+}
+
+/// 注销设备驱动
+pub fn unregister_device(major: u64, minor: u64) -> Option<Arc<dyn File>> {
+    DEVICE_DRIVERS.lock().remove(&(major, minor))
+}
+
+/// 根据设备号找到注册的驱动
+pub fn device_driver(rdev: u64) -> Option<Arc<dyn File>> {
+    DEVICE_DRIVERS
+        .lock()
+        .get(&(major(rdev), minor(rdev)))
+        .cloned()
+}
+
+/// 把 (major, minor) 编码为 st_rdev
+pub const fn makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0xfff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0xff)
+}
+
+/// 为字符设备实现 [`Inode`]：设备没有目录结构，读写直接转发给 [`File`] 的实现
+macro_rules! impl_device_inode {
+    ($device:ty) => {
+        impl $crate::fs::inode::Inode for $device {
+            fn fstype(&self) -> $crate::fs::fs::FileSystemType {
+                $crate::fs::fs::FileSystemType::DEVFS
+            }
+            fn lookup(
+                self: alloc::sync::Arc<Self>, _name: &str,
+            ) -> Option<alloc::sync::Arc<$crate::fs::dentry::Dentry>> {
+                None
+            }
+            fn create(
+                self: alloc::sync::Arc<Self>, _name: &str, _type_: $crate::fs::inode::InodeType,
+            ) -> Option<alloc::sync::Arc<$crate::fs::dentry::Dentry>> {
+                None
+            }
+            fn unlink(self: alloc::sync::Arc<Self>, _name: &str) -> bool {
+                false
+            }
+            fn link(
+                self: alloc::sync::Arc<Self>, _name: &str,
+                _target: alloc::sync::Arc<$crate::fs::dentry::Dentry>,
+            ) -> bool {
+                false
+            }
+            fn rename(self: alloc::sync::Arc<Self>, _old_name: &str, _new_name: &str) -> bool {
+                false
+            }
+            fn mkdir(self: alloc::sync::Arc<Self>, _name: &str) -> bool {
+                false
+            }
+            fn rmdir(self: alloc::sync::Arc<Self>, _name: &str) -> bool {
+                false
+            }
+            fn ls(&self) -> alloc::vec::Vec<alloc::string::String> {
+                alloc::vec::Vec::new()
+            }
+            fn clear(&self) {}
+            fn read_at(&self, _offset: usize, buf: &mut [u8]) -> usize {
+                $crate::fs::file::File::read(self, buf)
+            }
+            fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+                $crate::fs::file::File::write(self, buf)
+            }
+            fn read_all(&self) -> alloc::vec::Vec<u8> {
+                alloc::vec::Vec::new()
+            }
+        }
+    };
+}
+pub(crate) use impl_device_inode;
+
+/// 字符设备的 stat
+pub fn char_device_stat(rdev: u64) -> Stat {
+    Stat::new(0, 0, StatMode::CHAR.bits(), 1, rdev, 0, 0, 0, 0)
+}
+
+/// devfs 的根目录，保存设备名到设备 inode 的映射
+pub struct DevDir {
+    entries: Mutex<BTreeMap<String, Arc<dyn Inode>>>,
+}
+
+impl DevDir {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 注册一个设备节点
+    pub fn insert(&self, name: &str, inode: Arc<dyn Inode>) {
+        self.entries.lock().insert(name.to_string(), inode);
+    }
+}
+
+impl Inode for DevDir {
+    fn fstype(&self) -> FileSystemType {
+        FileSystemType::DEVFS
+    }
+    fn lookup(self: Arc<Self>, name: &str) -> Option<Arc<Dentry>> {
+        let name = name.trim_start_matches("./");
+        let inode = self.entries.lock().get(name)?.clone();
+        Some(Arc::new(Dentry::new(name, inode)))
+    }
+    fn create(self: Arc<Self>, _name: &str, _type_: InodeType) -> Option<Arc<Dentry>> {
+        None
+    }
+    fn unlink(self: Arc<Self>, name: &str) -> bool {
+        self.entries.lock().remove(name).is_some()
+    }
+    fn link(self: Arc<Self>, name: &str, target: Arc<Dentry>) -> bool {
+        let mut entries = self.entries.lock();
+        if entries.contains_key(name) {
+            return false;
+        }
+        entries.insert(name.to_string(), target.inode());
+        true
+    }
+    fn rename(self: Arc<Self>, old_name: &str, new_name: &str) -> bool {
+        let mut entries = self.entries.lock();
+        if let Some(inode) = entries.remove(old_name) {
+            entries.insert(new_name.to_string(), inode);
+            true
+        } else {
+            false
+        }
+    }
+    fn mkdir(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn rmdir(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn mknod(self: Arc<Self>, name: &str, type_: InodeType, rdev: u64) -> Option<Arc<Dentry>> {
+        if !matches!(type_, InodeType::CharDevice | InodeType::BlockDevice) {
+            return None;
+        }
+        let mut entries = self.entries.lock();
+        if entries.contains_key(name) {
+            return None;
+        }
+        let inode: Arc<dyn Inode> = Arc::new(DeviceNode::new(type_, rdev));
+        entries.insert(name.to_string(), inode.clone());
+        Some(Arc::new(Dentry::new(name, inode)))
+    }
+    fn ls(&self) -> Vec<String> {
+        self.entries.lock().keys().cloned().collect()
+    }
+    fn clear(&self) {}
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+}
+
+impl File for DevDir {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, _buf: &[u8]) -> usize {
+        0
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(Stat::new(0, 0, StatMode::DIR.bits(), 2, 0, 0, 0, 0, 0))
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
+
+/// 设备文件系统
+pub struct DevFS {
+    root: Arc<DevDir>,
+}
+
+impl DevFS {
+    /// 创建 devfs，按 Linux 惯用的设备号注册默认驱动并创建对应的设备节点
+    pub fn new() -> Self {
+        let root = Arc::new(DevDir::new());
+        let devices: [(&str, u64, u64, Arc<dyn File>); 6] = [
+            ("null", 1, 3, Arc::new(Null)),
+            ("zero", 1, 5, Arc::new(Zero)),
+            ("urandom", 1, 9, Arc::new(URandom)),
+            ("tty", 5, 0, Arc::new(Tty)),
+            ("console", 5, 1, Arc::new(Console)),
+            ("fb0", 29, 0, Arc::new(FrameBuffer::new())),
+        ];
+        for (name, major, minor, driver) in devices {
+            register_device(major, minor, driver);
+            root.insert(
+                name,
+                Arc::new(DeviceNode::new(
+                    InodeType::CharDevice,
+                    makedev(major, minor),
+                )),
+            );
+        }
+        Self { root }
+    }
+}
+
+impl FileSystem for DevFS {
+    fn fs_type(&self) -> FileSystemType {
+        FileSystemType::DEVFS
+    }
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// 设备节点按设备号找到驱动：号码被占用时注册失败，注销之后同一个节点的读写不再到达驱动
+#[allow(unused)]
+pub fn device_registry_test() {
+    assert!(device_driver(makedev(1, 3)).is_some());
+    assert!(!register_device(1, 3, Arc::new(Zero)));
+    assert_eq!(
+        (major(makedev(4095, 1)), minor(makedev(4095, 1))),
+        (4095, 1)
+    );
+    assert_eq!(
+        (major(makedev(4096, 256)), minor(makedev(4096, 256))),
+        (4096, 256)
+    );
+
+    let node = DeviceNode::new(InodeType::CharDevice, makedev(240, 0));
+    let mut buf = [0xffu8; 8];
+    assert_eq!(node.read(&mut buf), 0);
+    assert!(register_device(240, 0, Arc::new(Zero)));
+    assert!(!register_device(240, 0, Arc::new(Null)));
+    assert_eq!(node.read(&mut buf), 8);
+    assert_eq!(buf, [0; 8]);
+    assert!(unregister_device(240, 0).is_some());
+    assert!(unregister_device(240, 0).is_none());
+    buf.fill(0xff);
+    assert_eq!(node.read(&mut buf), 0);
+    assert_eq!(buf, [0xff; 8]);
+    info!("device_registry_test passed!");
+}
@@ -0,0 +1,58 @@
+use alloc::vec::Vec;
+
+use super::{device_driver, impl_device_inode};
+use crate::{
+    fs::{
+        file::File,
+        inode::{InodeType, Stat, StatMode},
+    },
+    mm::PhysPageNum,
+};
+
+/// devfs 中的设备节点，读写按设备号转发给注册的驱动，没有对应驱动时读写均返回 0
+pub struct DeviceNode {
+    type_: InodeType,
+    rdev:  u64,
+}
+
+impl DeviceNode {
+    pub fn new(type_: InodeType, rdev: u64) -> Self {
+        Self { type_, rdev }
+    }
+}
+
+impl_device_inode!(DeviceNode);
+
+impl File for DeviceNode {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        device_driver(self.rdev).map_or(0, |driver| driver.read(buf))
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        device_driver(self.rdev).map_or(0, |driver| driver.write(buf))
+    }
+    fn fstat(&self) -> Option<Stat> {
+        let mode = match self.type_ {
+            InodeType::BlockDevice => StatMode::BLOCK,
+            _ => StatMode::CHAR,
+        };
+        Some(Stat::new(0, 0, mode.bits(), 1, self.rdev, 0, 0, 0, 0))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+    fn mmap_frames(&self, offset: usize, len: usize) -> Option<Vec<PhysPageNum>> {
+        device_driver(self.rdev)?.mmap_frames(offset, len)
+    }
+}
@@ -0,0 +1,36 @@
+use alloc::vec::Vec;
+
+use super::{char_device_stat, impl_device_inode, makedev};
+use crate::fs::{file::File, inode::Stat};
+
+/// /dev/null: 读总是返回 EOF，写入的数据全部丢弃
+pub struct Null;
+
+impl_device_inode!(Null);
+
+impl File for Null {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        buf.len()
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(char_device_stat(makedev(1, 3)))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
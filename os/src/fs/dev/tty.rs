@@ -0,0 +1,68 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use super::{char_device_stat, impl_device_inode, makedev};
+use crate::{
+    fs::{
+        file::{cast_inode_to_file, File},
+        fs::FileSystemType,
+        inode::{Inode, Stat},
+    },
+    task::current_task,
+};
+
+/// /dev/tty 的设备号
+pub const TTY_RDEV: u64 = makedev(5, 0);
+
+/// inode 是否是 /dev/tty 设备节点。按设备号判断，与打开时使用的路径无关；
+/// 设备节点只存在于 devfs 中
+pub fn is_tty(inode: Arc<dyn Inode>) -> bool {
+    if !matches!(inode.fstype(), FileSystemType::DEVFS) {
+        return false;
+    }
+    cast_inode_to_file(inode)
+        .and_then(|file| file.fstat())
+        .is_some_and(|stat| stat.is_char_device() && stat.rdev() == TTY_RDEV)
+}
+
+/// /dev/tty: 当前进程的控制终端，读写转发给 `ctty`
+///
+/// 打开时由 open 检查进程是否有控制终端，没有时返回 ENXIO
+pub struct Tty;
+
+impl Tty {
+    fn ctty() -> Option<Arc<dyn File>> {
+        current_task()?
+            .inner_exclusive_access(file!(), line!())
+            .ctty
+            .clone()
+    }
+}
+
+impl_device_inode!(Tty);
+
+impl File for Tty {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        Self::ctty().map_or(0, |tty| tty.read(buf))
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        Self::ctty().map_or(0, |tty| tty.write(buf))
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(char_device_stat(TTY_RDEV))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,46 @@
+use alloc::vec::Vec;
+
+use riscv::register::sstatus;
+
+use super::{char_device_stat, impl_device_inode, makedev};
+use crate::{
+    fs::{file::File, inode::Stat},
+    utils::random::fill_bytes,
+};
+
+/// /dev/urandom: 由内核伪随机数发生器生成，不能用于密码学用途
+pub struct URandom;
+
+impl_device_inode!(URandom);
+
+impl File for URandom {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        unsafe {
+            sstatus::set_sum();
+            fill_bytes(buf);
+            sstatus::clear_sum();
+        }
+        buf.len()
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        buf.len()
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(char_device_stat(makedev(1, 9)))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,43 @@
+use alloc::vec::Vec;
+
+use riscv::register::sstatus;
+
+use super::{char_device_stat, impl_device_inode, makedev};
+use crate::fs::{file::File, inode::Stat};
+
+/// /dev/zero: 读出全 0，写入的数据全部丢弃
+pub struct Zero;
+
+impl_device_inode!(Zero);
+
+impl File for Zero {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        unsafe {
+            sstatus::set_sum();
+            buf.fill(0);
+            sstatus::clear_sum();
+        }
+        buf.len()
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        buf.len()
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(char_device_stat(makedev(1, 5)))
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
@@ -11,7 +11,7 @@ use crate::{
         fs::{FileSystem, FileSystemType},
         inode::Inode,
     },
-    sync::UPSafeCell,
+    sync::SpSafeCell,
 };
 
 pub struct Ext4FS {
@@ -33,7 +33,7 @@ impl FileSystem for Ext4FS {
         let inode = Ext4Inode {
             fs:    self.clone(),
             ino:   ROOT_INO,
-            inner: unsafe { UPSafeCell::new(Ext4InodeInner { fpos: 0 }) },
+            inner: SpSafeCell::new(Ext4InodeInner { fpos: 0 }),
         };
         Arc::new(inode)
     }
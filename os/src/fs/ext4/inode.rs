@@ -10,13 +10,13 @@ use crate::{
         fs::FileSystemType,
         inode::{Inode, InodeType, Stat},
     },
-    sync::UPSafeCell,
+    sync::SpSafeCell,
 };
 
 pub struct Ext4Inode {
     pub fs:    Arc<Ext4FS>,
     pub ino:   u32,
-    pub inner: UPSafeCell<Ext4InodeInner>,
+    pub inner: SpSafeCell<Ext4InodeInner>,
 }
 
 pub struct Ext4InodeInner {
@@ -27,11 +27,42 @@ impl Inode for Ext4Inode {
     fn fstype(&self) -> FileSystemType {
         FileSystemType::EXT4
     }
-    fn clear(&self) {
-        todo!()
+    fn ino(&self) -> usize {
+        self.ino as usize
     }
-    fn create(self: Arc<Self>, _name: &str, _type_: InodeType) -> Option<Arc<Dentry>> {
-        todo!()
+    fn mode(&self) -> u32 {
+        let inode_ref = Ext4InodeRef::get_inode_ref(Arc::downgrade(&self.fs.ext4), self.ino);
+        inode_ref.inner.inode.mode as u32 & 0o7777
+    }
+    fn chmod(&self, mode: u32) -> bool {
+        let mut inode_ref = Ext4InodeRef::get_inode_ref(Arc::downgrade(&self.fs.ext4), self.ino);
+        let inode = &mut inode_ref.inner.inode;
+        inode.mode = (inode.mode & 0o170000) | (mode & 0o7777) as u16;
+        inode_ref.write_back_inode();
+        true
+    }
+    fn clear(&self) {
+        self.truncate(0);
+    }
+    /// 只支持缩短，由 ext4_rs 释放多余的块。ext4_rs 的读写不支持空洞，也不能写不满一块的数据，
+    /// 无法把文件变长，这时返回 false
+    fn truncate(&self, size: usize) -> bool {
+        let mut inode_ref = Ext4InodeRef::get_inode_ref(Arc::downgrade(&self.fs.ext4), self.ino);
+        if size as u64 > inode_ref.inner.inode.inode_get_size() {
+            return false;
+        }
+        inode_ref.truncate_inode(size as u64).is_ok()
+    }
+    fn create(self: Arc<Self>, name: &str, type_: InodeType) -> Option<Arc<Dentry>> {
+        match type_ {
+            InodeType::Directory => {
+                if self.clone().lookup(name).is_some() || !self.clone().mkdir(name) {
+                    return None;
+                }
+                self.lookup(name)
+            }
+            _ => todo!(),
+        }
     }
 
     fn lookup(self: Arc<Self>, name: &str) -> Option<Arc<Dentry>> {
@@ -43,7 +74,7 @@ impl Inode for Ext4Inode {
         let inode = Ext4Inode {
             fs:    self.fs.clone(),
             ino:   file.inode,
-            inner: unsafe { UPSafeCell::new(Ext4InodeInner { fpos: 0 }) },
+            inner: SpSafeCell::new(Ext4InodeInner { fpos: 0 }),
         };
         let dentry = Dentry::new(name, Arc::new(inode));
         Some(Arc::new(dentry))
@@ -106,7 +137,7 @@ impl File for Ext4Inode {
         todo!()
     }
     fn is_dir(&self) -> bool {
-        todo!()
+        Ext4InodeRef::get_inode_ref(Arc::downgrade(&self.fs.ext4), self.ino).is_dir()
     }
     fn read(&self, buf: &mut [u8]) -> usize {
         // TODO: 暂时不考虑 pos
@@ -0,0 +1,98 @@
+//! 目录项缓存
+//!
+//! 记录 (目录起始簇号, 文件名) 对应的目录项位置，重复查找同一个名字时不必再逐个扫描目录项。
+//! 查找不到的名字也会记录下来 (负缓存)，反复打开不存在的文件时不必每次都扫描整个目录。
+//! 目录项的位置和起始簇号只会在创建、删除和重命名时改变，这些操作通过
+//! insert_dentry / remove_dentry 完成，由它们负责让对应的缓存项失效。
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+};
+
+use super::inode::Fat32InodeType;
+
+/// 最多缓存的目录项数，超过时淘汰最早加入的
+const DCACHE_CAPACITY: usize = 256;
+
+/// 一个缓存的目录项
+#[derive(Clone, Copy)]
+pub struct CachedDentry {
+    /// 目录项 (包括长文件名项) 开始的位置
+    pub sector_id:     usize,
+    pub offset:        usize,
+    pub start_cluster: usize,
+    pub type_:         Fat32InodeType,
+}
+
+pub struct DentryCache {
+    /// None 表示目录中没有这个名字
+    entries: BTreeMap<(usize, String), Option<CachedDentry>>,
+    /// 加入的先后顺序
+    order:   VecDeque<(usize, String)>,
+    hits:    usize,
+    misses:  usize,
+}
+
+impl DentryCache {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            order:   VecDeque::new(),
+            hits:    0,
+            misses:  0,
+        }
+    }
+
+    /// 没有缓存时返回 None，缓存了名字不存在时返回 Some(None)
+    pub fn get(&mut self, dir: usize, name: &str) -> Option<Option<CachedDentry>> {
+        let entry = self.entries.get(&(dir, name.to_string())).copied();
+        match entry {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        entry
+    }
+
+    /// entry 为 None 时记录目录中没有这个名字
+    pub fn insert(&mut self, dir: usize, name: &str, entry: Option<CachedDentry>) {
+        let key = (dir, name.to_string());
+        if self.entries.insert(key.clone(), entry).is_some() {
+            return;
+        }
+        self.order.push_back(key);
+        if self.order.len() > DCACHE_CAPACITY {
+            let oldest = self.order.pop_front().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// 目录 dir 中的 name 被创建或删除
+    pub fn invalidate(&mut self, dir: usize, name: &str) {
+        let key = (dir, name.to_string());
+        if self.entries.remove(&key).is_some() {
+            self.order.retain(|k| *k != key);
+        }
+    }
+
+    /// 目录 dir 中位于 (sector_id, offset) 的目录项被删除
+    pub fn invalidate_at(&mut self, dir: usize, sector_id: usize, offset: usize) {
+        self.entries.retain(|(d, _), entry| {
+            *d != dir || entry.map_or(true, |e| (e.sector_id, e.offset) != (sector_id, offset))
+        });
+        let entries = &self.entries;
+        self.order.retain(|key| entries.contains_key(key));
+    }
+
+    /// 目录 dir 被删除，它的簇之后可能分配给别的目录
+    pub fn invalidate_dir(&mut self, dir: usize) {
+        self.entries.retain(|(d, _), _| *d != dir);
+        let entries = &self.entries;
+        self.order.retain(|key| entries.contains_key(key));
+    }
+
+    /// (命中次数, 未命中次数)，命中包括负缓存的命中
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+}
@@ -1,7 +1,10 @@
-use alloc::{string::String, sync::Arc};
+use alloc::{string::String, sync::Arc, vec::Vec};
 
 use super::fat::FAT;
-use crate::block::{block_cache::get_block_cache, block_dev::BlockDevice};
+use crate::{
+    block::{block_cache::get_block_cache, block_dev::BlockDevice},
+    fs::writeback::DirtyMetadata,
+};
 
 pub struct Fat32Dentry {
     pub sector_id:     usize,
@@ -11,6 +14,22 @@ pub struct Fat32Dentry {
     pub fat:           Arc<FAT>,
 }
 
+/// 一个长文件名项存放的 UCS-2 字符数 (5 + 6 + 2)
+pub const LFN_CHARS: usize = 13;
+/// 长文件名最多 255 个字符，对应的长文件名项个数
+const MAX_LFN_ENTRIES: usize = 20;
+
+/// 目录项保存在块缓存中，写回就是把短目录项所在的块写到磁盘
+impl DirtyMetadata for Fat32Dentry {
+    fn write_back(&self) {
+        if self.deleted {
+            return;
+        }
+        let (sector_id, _) = self.to_end();
+        get_block_cache(sector_id, self.bdev.clone()).lock().sync();
+    }
+}
+
 bitflags! {
     pub struct FileAttributes: u8 {
         const READ_ONLY  = 0b00000001;
@@ -46,15 +65,15 @@ impl Fat32Dentry {
     }
 
     pub fn is_system(&self) -> bool {
-        self.read_dentry().attr() == FileAttributes::SYSTEM
+        self.attr() == FileAttributes::SYSTEM
     }
 
     pub fn is_dir(&self) -> bool {
-        self.read_dentry().attr() == FileAttributes::DIRECTORY
+        self.attr() == FileAttributes::DIRECTORY
     }
 
     pub fn is_volume_id(&self) -> bool {
-        self.read_dentry().attr() == FileAttributes::VOLUME_ID
+        self.attr() == FileAttributes::VOLUME_ID
     }
 
     pub fn is_file(&self) -> bool {
@@ -88,29 +107,30 @@ impl Fat32Dentry {
         self.read_dentry().is_long()
     }
 
+    pub fn attr(&self) -> FileAttributes {
+        let (sector_id, offset) = self.to_end();
+        get_block_cache(sector_id, self.bdev.clone())
+            .lock()
+            .read(offset, |layout: &Fat32DentryLayout| layout.attr())
+    }
+
+    pub fn set_attr(&self, attr: FileAttributes) {
+        let (sector_id, offset) = self.to_end();
+        get_block_cache(sector_id, self.bdev.clone()).lock().modify(
+            offset,
+            |layout: &mut Fat32DentryLayout| {
+                layout.attr = attr.bits();
+            },
+        );
+    }
+
+    /// 有长文件名且与短目录项的校验和一致时返回长文件名，否则返回 8.3 短文件名
     pub fn name(&self) -> String {
-        if self.is_long() {
-            let mut name = String::new();
-            let mut sector_id = self.sector_id;
-            let mut offset = self.sector_offset;
-            loop {
-                let layout = get_block_cache(sector_id, self.bdev.clone())
-                    .lock()
-                    .read(offset, |layout: &Fat32LDentryLayout| *layout);
-                name.insert_str(0, &layout.name());
-                if layout.is_end() {
-                    break;
-                }
-                (sector_id, offset) = self.fat.next_dentry_id(sector_id, offset).unwrap();
-            }
-            name
-        } else {
-            get_block_cache(self.sector_id, self.bdev.clone())
-                .lock()
-                .read(self.sector_offset, |layout: &Fat32DentryLayout| {
-                    layout.name()
-                })
-        }
+        let (long_entries, (sector_id, offset)) = self.long_entries();
+        let short = get_block_cache(sector_id, self.bdev.clone())
+            .lock()
+            .read(offset, |layout: &Fat32DentryLayout| *layout);
+        decode_long_name(&long_entries, short.checksum()).unwrap_or_else(|| short.name())
     }
 
     pub fn start_cluster_id(&self) -> usize {
@@ -122,22 +142,27 @@ impl Fat32Dentry {
             })
     }
 
+    /// 短目录项的位置
     fn to_end(&self) -> (usize, usize) {
-        if !self.is_long() {
-            return (self.sector_id, self.sector_offset);
-        }
+        self.long_entries().1
+    }
+
+    /// 按在磁盘上的顺序读出短目录项之前的所有长文件名项，同时返回短目录项的位置
+    fn long_entries(&self) -> (Vec<Fat32LDentryLayout>, (usize, usize)) {
+        let mut entries = Vec::new();
         let mut sector_id = self.sector_id;
         let mut offset = self.sector_offset;
-        loop {
+        while entries.len() <= MAX_LFN_ENTRIES {
             let layout = get_block_cache(sector_id, self.bdev.clone())
                 .lock()
                 .read(offset, |layout: &Fat32LDentryLayout| *layout);
-            (sector_id, offset) = self.fat.next_dentry_id(sector_id, offset).unwrap();
-            if layout.is_end() {
+            if layout.attr & 0x0F != 0x0F {
                 break;
             }
+            entries.push(layout);
+            (sector_id, offset) = self.fat.next_dentry_id(sector_id, offset).unwrap();
         }
-        (sector_id, offset)
+        (entries, (sector_id, offset))
     }
 
     fn read_dentry(&self) -> Fat32DentryLayout {
@@ -177,26 +202,24 @@ impl Fat32DentryLayout {
     pub fn new(
         file_name: &str, attr: FileAttributes, start_cluster: usize, file_size: u32,
     ) -> Self {
-        let mut name = [0u8; 8];
-        let mut ext = [0u8; 3];
-        let mut name_capital = false;
-        let mut ext_capital = false;
+        // 短文件名只是长文件名的替身：转成大写，不足的部分填空格
+        let mut name = [0x20u8; 8];
+        let mut ext = [0x20u8; 3];
         let mut i = 0;
         for c in file_name.chars() {
             if c == '.' {
                 i = 8;
                 continue;
             }
+            let c = if c.is_ascii() {
+                c.to_ascii_uppercase() as u8
+            } else {
+                b'_'
+            };
             if i < 8 {
-                name[i] = c as u8;
-                if c.is_ascii_uppercase() {
-                    name_capital = true;
-                }
+                name[i] = c;
             } else if i < 11 {
-                ext[i - 8] = c as u8;
-                if c.is_ascii_uppercase() {
-                    ext_capital = true;
-                }
+                ext[i - 8] = c;
             } else {
                 break;
             }
@@ -206,8 +229,7 @@ impl Fat32DentryLayout {
             name,
             ext,
             attr: attr.bits(),
-            reserved: if name_capital { 0x08 } else { 0x00 }
-                | if ext_capital { 0x10 } else { 0x00 },
+            reserved: 0,
             create_time_ms: 0,
             create_time: 0,
             create_date: 0,
@@ -220,6 +242,13 @@ impl Fat32DentryLayout {
         }
     }
 
+    /// 子目录开头的 "." (`dots` 为 1) 或 ".." (`dots` 为 2) 目录项
+    pub fn new_dot(dots: usize, start_cluster: usize) -> Self {
+        let mut layout = Self::new("", FileAttributes::DIRECTORY, start_cluster, 0);
+        layout.name[..dots].fill(b'.');
+        layout
+    }
+
     pub fn is_long(&self) -> bool {
         self.attr & 0x0F == 0x0F
     }
@@ -248,25 +277,58 @@ impl Fat32DentryLayout {
         self.name[0] = 0xE5;
     }
 
+    /// 长文件名项中记录的短文件名校验和
+    pub fn checksum(&self) -> u8 {
+        self.name
+            .iter()
+            .chain(self.ext.iter())
+            .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+    }
+
+    /// 8.3 短文件名，扩展名为空时不带 '.'
     pub fn name(&self) -> String {
-        let mut name = String::new();
-        for i in self.name.iter() {
-            if *i == 0x20 {
-                break;
-            }
-            name.push(char::from_u32(*i as u32).unwrap());
-        }
-        name.push('.');
-        for i in self.ext.iter() {
-            if *i == 0x20 {
-                break;
-            }
-            name.push(char::from_u32(*i as u32).unwrap());
+        let part = |bytes: &[u8]| -> String {
+            bytes
+                .iter()
+                .take_while(|&&c| c != 0x20 && c != 0)
+                .map(|&c| char::from_u32(c as u32).unwrap())
+                .collect()
+        };
+        let mut name = part(&self.name);
+        let ext = part(&self.ext);
+        if !ext.is_empty() {
+            name.push('.');
+            name.push_str(&ext);
         }
         name
     }
 }
 
+/// 把磁盘上按逆序存放的长文件名项 (第一项带 0x40 标记、序号为 N，最后一项序号为 1)
+/// 拼成长文件名。序号不连续或者校验和与短目录项不一致时返回 None
+fn decode_long_name(entries: &[Fat32LDentryLayout], checksum: u8) -> Option<String> {
+    let count = entries.len();
+    if count == 0 || !entries[0].is_end() {
+        return None;
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        if (entry.order & 0x1F) as usize != count - i || entry.checksum != checksum {
+            return None;
+        }
+    }
+    let units: Vec<u16> = entries
+        .iter()
+        .rev()
+        .flat_map(|entry| entry.units())
+        .take_while(|&unit| unit != 0x0000)
+        .collect();
+    Some(
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+    )
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
 /// the layout of a fat32 long dentry
@@ -282,35 +344,38 @@ pub struct Fat32LDentryLayout {
 }
 
 impl Fat32LDentryLayout {
-    pub fn new(mut order: u8, name: &str, is_end: bool) -> Self {
-        let mut name1 = [0u16; 5];
-        let mut name2 = [0u16; 6];
-        let mut name3 = [0u16; 2];
-        let mut i = 0;
-        for c in name.chars() {
-            if i < 5 {
-                name1[i] = c as u16;
-            } else if i < 11 {
-                name2[i - 5] = c as u16;
-            } else {
-                name3[i - 11] = c as u16;
-            }
-            i += 1;
+    /// `units` 是长文件名中第 order 段的 UTF-16 编码，不满 13 个时以 0x0000 结尾、其余填 0xFFFF。
+    /// `is_end` 表示这是最后一段 (在磁盘上排在最前面)
+    pub fn new(mut order: u8, units: &[u16], is_end: bool, checksum: u8) -> Self {
+        let mut chars = [0xFFFFu16; LFN_CHARS];
+        chars[..units.len()].copy_from_slice(units);
+        if units.len() < LFN_CHARS {
+            chars[units.len()] = 0x0000;
         }
         if is_end {
             order |= 0x40;
         }
         Self {
             order,
-            name1,
+            name1: chars[..5].try_into().unwrap(),
             attr: 0x0F,
             reserved: 0,
-            checksum: 0,
-            name2,
+            checksum,
+            name2: chars[5..11].try_into().unwrap(),
             start_cluster: 0,
-            name3,
+            name3: chars[11..].try_into().unwrap(),
         }
     }
+
+    /// 这一项中的 13 个 UTF-16 编码单元
+    pub fn units(&self) -> [u16; LFN_CHARS] {
+        let (name1, name2, name3) = (self.name1, self.name2, self.name3);
+        let mut units = [0u16; LFN_CHARS];
+        units[..5].copy_from_slice(&name1);
+        units[5..11].copy_from_slice(&name2);
+        units[11..].copy_from_slice(&name3);
+        units
+    }
     pub fn from_short_layout(layout: &Fat32DentryLayout) -> Option<Self> {
         if layout.attr & 0x0F != 0x0F {
             return None;
@@ -355,36 +420,4 @@ impl Fat32LDentryLayout {
     pub fn is_valid(&self) -> bool {
         !self.is_end() && !self.is_deleted() && !self.is_empty()
     }
-
-    pub fn name(&self) -> String {
-        let mut name = String::new();
-        let mut finished = false;
-        for i in self.name1 {
-            if i == 0x0000 {
-                finished = true;
-                break;
-            }
-            name.push(char::from_u32(i as u32).unwrap());
-        }
-        if finished {
-            return name;
-        }
-        for i in self.name2 {
-            if i == 0x0000 {
-                finished = true;
-                break;
-            }
-            name.push(char::from_u32(i as u32).unwrap());
-        }
-        if finished {
-            return name;
-        }
-        for i in self.name3 {
-            if i == 0x0000 {
-                break;
-            }
-            name.push(char::from_u32(i as u32).unwrap());
-        }
-        name
-    }
 }
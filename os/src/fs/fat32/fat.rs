@@ -18,11 +18,15 @@ impl FAT {
         }
     }
 
-    /// allocate a new cluster
+    /// allocate a new cluster, return None if there is no free cluster
     pub fn alloc_new_cluster(&self) -> Option<usize> {
         let mut offset = self.start_sector * BLOCK_SZ + 3 * 4;
+        let end = self.start_sector * BLOCK_SZ + (self.sb.cluster_count() + 2) * 4;
         let mut cluster_id = 0;
         loop {
+            if offset >= end {
+                return None;
+            }
             let sector_id = offset / BLOCK_SZ;
             let sector_offset = offset % BLOCK_SZ;
             get_block_cache(sector_id, Arc::clone(&self.bdev))
@@ -60,6 +64,30 @@ impl FAT {
         Some(new_cluster_id)
     }
 
+    /// set the FAT entry of a cluster
+    pub fn set_entry(&self, cluster_id: usize, value: u32) {
+        let fat_offset = self.start_sector * BLOCK_SZ + cluster_id * 4;
+        get_block_cache(fat_offset / BLOCK_SZ, Arc::clone(&self.bdev))
+            .lock()
+            .modify(fat_offset % BLOCK_SZ, |num: &mut u32| {
+                *num = value;
+            });
+    }
+
+    /// mark a cluster as the end of its chain
+    pub fn set_eoc(&self, cluster_id: usize) {
+        self.set_entry(cluster_id, 0x0FFFFFFF);
+    }
+
+    /// free a cluster and tell the block device its sectors can be discarded
+    pub fn free_cluster(&self, cluster_id: usize) {
+        self.set_entry(cluster_id, 0);
+        if let Some(sector_id) = self.cluster_id_to_sector_id(cluster_id) {
+            self.bdev
+                .discard(sector_id, self.sb.sectors_per_cluster as usize);
+        }
+    }
+
     /// get next cluster number
     pub fn next_cluster_id(&self, cluster: usize) -> Option<usize> {
         let fat_offset = self.start_sector * BLOCK_SZ + cluster * 4;
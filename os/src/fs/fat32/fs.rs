@@ -1,9 +1,19 @@
-use alloc::{string::String, sync::Arc, vec::Vec};
-use core::cmp::min;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{cmp::min, fmt, ops::Range};
+
+use spin::Mutex;
 
 use super::{
-    dentry::{Fat32Dentry, Fat32DentryLayout, Fat32LDentryLayout, FileAttributes},
+    dcache::DentryCache,
+    dentry::{Fat32Dentry, Fat32DentryLayout, Fat32LDentryLayout, FileAttributes, LFN_CHARS},
     fat::FAT,
+    icache::InodeCache,
     inode::{Fat32Inode, Fat32InodeType},
     super_block::{Fat32SB, Fat32SBLayout},
 };
@@ -12,13 +22,25 @@ use crate::{
     fs::{
         fs::{FileSystem, FileSystemType},
         inode::Inode,
+        writeback::{self, DirtyMetadata},
     },
+    sysctl,
 };
 
 pub struct Fat32FS {
-    pub sb:   Fat32SB,
-    pub fat:  Arc<FAT>,
-    pub bdev: Arc<dyn BlockDevice>,
+    pub sb:             Fat32SB,
+    pub fat:            Arc<FAT>,
+    pub bdev:           Arc<dyn BlockDevice>,
+    /// 目录起始簇号 -> 该目录中第一个空闲目录项的位置 (sector_id, offset)
+    pub free_slot_hint: Mutex<BTreeMap<usize, (usize, usize)>>,
+    /// 保证 create 中的查找和插入是原子的
+    pub create_lock:    Mutex<()>,
+    /// 通过 fadvise(SEQUENTIAL) 开启了积极预读的文件 (起始簇号)
+    pub sequential:     Mutex<BTreeSet<usize>>,
+    /// 目录项缓存
+    pub dcache:         Mutex<DentryCache>,
+    /// inode 缓存
+    pub icache:         Mutex<InodeCache>,
 }
 
 impl FileSystem for Fat32FS {
@@ -40,12 +62,14 @@ impl FileSystem for Fat32FS {
 }
 
 impl Fat32FS {
-    /// load a exist fat32 file system from block device
-    pub fn load(bdev: Arc<dyn BlockDevice>) -> Arc<Self> {
+    /// load a exist fat32 file system from block device, return None if it is not FAT32
+    pub fn load(bdev: Arc<dyn BlockDevice>) -> Option<Arc<Self>> {
         get_block_cache(0, Arc::clone(&bdev))
             .lock()
             .read(0, |sb_layout: &Fat32SBLayout| {
-                assert!(sb_layout.is_valid(), "Error loading FAT32!");
+                if !sb_layout.is_valid() {
+                    return None;
+                }
                 let fat32fs = Self {
                     sb: Fat32SB::from_layout(sb_layout),
                     fat: Arc::new(FAT::from_sb(
@@ -53,11 +77,39 @@ impl Fat32FS {
                         &bdev,
                     )),
                     bdev,
+                    free_slot_hint: Mutex::new(BTreeMap::new()),
+                    create_lock: Mutex::new(()),
+                    sequential: Mutex::new(BTreeSet::new()),
+                    dcache: Mutex::new(DentryCache::new()),
+                    icache: Mutex::new(InodeCache::new()),
                 };
-                Arc::new(fat32fs)
+                Some(Arc::new(fat32fs))
             })
     }
 
+    /// 目录项位于 key = (sector_id, offset) 的文件的 inode，不在缓存中时用 `make` 创建并加入缓存
+    pub fn cached_inode(
+        &self, key: (usize, usize), make: impl FnOnce() -> Fat32Inode,
+    ) -> Arc<Fat32Inode> {
+        let mut icache = self.icache.lock();
+        if let Some(inode) = icache.get(key) {
+            return inode;
+        }
+        let inode = Arc::new(make());
+        let evicted = icache.insert(key, Arc::clone(&inode), sysctl::inode_cache_size());
+        drop(icache);
+        // 写回要访问设备，放开缓存的锁再做；和 fsync 一样先写数据再写元数据
+        for inode in evicted {
+            let dirty = inode.dentry.as_ref().is_some_and(|dentry| {
+                writeback::take_dirty(&(Arc::clone(dentry) as Arc<dyn DirtyMetadata>))
+            });
+            if dirty {
+                inode.fsync();
+            }
+        }
+        inode
+    }
+
     /// get cluster chain
     pub fn cluster_chain(&self, start_cluster: usize) -> Vec<usize> {
         let mut cluster_chain = Vec::new();
@@ -73,6 +125,34 @@ impl Fat32FS {
         cluster_chain
     }
 
+    /// 把从 start_cluster 开始的整条簇链归还给 FAT，返回释放的簇数。
+    /// 空文件的起始簇号为 0，没有簇需要释放；损坏的 FAT 中簇链可能成环，
+    /// 最多走簇的总数那么多步
+    pub fn free_cluster_chain(&self, start_cluster: usize) -> usize {
+        // 簇之后可能分配给新的目录，旧目录记录的空闲位置不能留给它用
+        self.free_slot_hint.lock().remove(&start_cluster);
+        let max_cluster = self.sb.cluster_count() + 2;
+        let mut cluster = start_cluster;
+        let mut freed = 0;
+        while (2..max_cluster).contains(&cluster) && freed < max_cluster - 2 {
+            let next = self.fat.next_cluster_id(cluster);
+            self.fat.free_cluster(cluster);
+            freed += 1;
+            match next {
+                Some(next) => cluster = next,
+                None => break,
+            }
+        }
+        freed
+    }
+
+    /// sectors of a cluster
+    pub fn cluster_sectors(&self, cluster: usize) -> Range<usize> {
+        let spc = self.sb.sectors_per_cluster as usize;
+        let start = self.sb.root_sector() + (cluster - 2) * spc;
+        start..start + spc
+    }
+
     /// read a cluster
     pub fn read_cluster(&self, cluster: usize, buf: &mut [u8; 4096]) {
         let cluster_offset =
@@ -148,31 +228,35 @@ impl Fat32FS {
                 Some(Fat32Dentry::new(*sector_id, *offset, &self.bdev, &self.fat))
             });
         if is_long_entry {
-            let mut is_end = false;
-            loop {
-                get_block_cache(*sector_id, Arc::clone(&self.bdev))
-                    .lock()
-                    .read(*offset, |layout: &Fat32LDentryLayout| {
-                        if layout.is_end() {
-                            is_end = true;
-                        }
-                    });
+            // 跳过所有长文件名项，停在短目录项上
+            while self.is_long_slot(*sector_id, *offset) {
                 (*sector_id, *offset) = self.next_dentry_id(*sector_id, *offset).unwrap();
-                if is_end {
-                    break;
-                }
             }
         }
         (*sector_id, *offset) = self.next_dentry_id(*sector_id, *offset).unwrap();
         dentry
     }
 
+    /// 这个位置上是否是一个长文件名项
+    fn is_long_slot(&self, sector_id: usize, offset: usize) -> bool {
+        get_block_cache(sector_id, Arc::clone(&self.bdev))
+            .lock()
+            .read(offset, |layout: &Fat32DentryLayout| {
+                layout.is_long() && !layout.is_deleted() && !layout.is_empty()
+            })
+    }
+
     pub fn insert_dentry(
         &self, cluster_id: usize, name: String, attr: FileAttributes, file_size: u32,
         start_cluster: usize,
     ) -> Option<Fat32Dentry> {
-        let mut sector_id = self.fat.cluster_id_to_sector_id(cluster_id).unwrap();
-        let mut offset = 0;
+        self.dcache.lock().invalidate(cluster_id, &name);
+        // 从上次记录的第一个空闲位置开始找，避免每次都从目录开头扫描
+        let mut hints = self.free_slot_hint.lock();
+        let (mut sector_id, mut offset) = match hints.get(&cluster_id) {
+            Some(&hint) => hint,
+            None => (self.fat.cluster_id_to_sector_id(cluster_id).unwrap(), 0),
+        };
         loop {
             let found = get_block_cache(sector_id, self.bdev.clone())
                 .lock()
@@ -182,57 +266,239 @@ impl Fat32FS {
             }
             (sector_id, offset) = self.next_dentry_id(sector_id, offset).unwrap();
         }
-        let mut order = 1;
-        let mut pos = 0;
-        while pos < name.len() {
-            let copy_len = min(13, name.len() - pos);
+        // 长文件名按 13 个 UTF-16 编码单元一段，和 Linux 一样逆序存放在短目录项之前：
+        // 最前面是带 0x40 标记的最后一段，紧挨着短目录项的是第 1 段，每一段都记录短文件名的校验和
+        let short = Fat32DentryLayout::new(name.as_str(), attr, start_cluster, file_size);
+        let checksum = short.checksum();
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let count = (units.len() + LFN_CHARS - 1) / LFN_CHARS;
+        let first = (sector_id, offset);
+        for order in (1..=count).rev() {
+            let part = &units[(order - 1) * LFN_CHARS..min(order * LFN_CHARS, units.len())];
             get_block_cache(sector_id, Arc::clone(&self.bdev))
                 .lock()
                 .modify(offset, |layout: &mut Fat32LDentryLayout| {
-                    *layout = Fat32LDentryLayout::new(
-                        order,
-                        &name[pos..pos + copy_len],
-                        pos + copy_len == name.len(),
-                    );
+                    *layout = Fat32LDentryLayout::new(order as u8, part, order == count, checksum);
                 });
-            order += 1;
-            pos += copy_len;
             (sector_id, offset) = self.next_dentry_id(sector_id, offset).unwrap();
         }
         get_block_cache(sector_id, self.bdev.clone()).lock().modify(
             offset,
             |layout: &mut Fat32DentryLayout| {
-                *layout = Fat32DentryLayout::new(name.as_str(), attr, start_cluster, file_size);
+                *layout = short;
             },
         );
-        Some(Fat32Dentry::new(sector_id, offset, &self.bdev, &self.fat))
+        match self.next_dentry_id(sector_id, offset) {
+            Some(next) => hints.insert(cluster_id, next),
+            None => hints.remove(&cluster_id),
+        };
+        Some(Fat32Dentry::new(first.0, first.1, &self.bdev, &self.fat))
     }
 
-    pub fn remove_dentry(&self, dentry: &Fat32Dentry) {
+    /// remove the dentry (with its long name entries) from the directory starting at `cluster_id`
+    pub fn remove_dentry(&self, cluster_id: usize, dentry: &Fat32Dentry) {
+        self.dcache
+            .lock()
+            .invalidate_at(cluster_id, dentry.sector_id, dentry.sector_offset);
+        self.icache
+            .lock()
+            .remove((dentry.sector_id, dentry.sector_offset));
+        // 先收集该目录项占用的所有位置 (长文件名项 + 短目录项)
+        let mut slots = Vec::new();
         let mut sector_id = dentry.sector_id;
         let mut offset = dentry.sector_offset;
-        if dentry.is_long() {
-            let mut is_end = false;
-            loop {
-                get_block_cache(sector_id, Arc::clone(&self.bdev))
-                    .lock()
-                    .modify(offset, |layout: &mut Fat32LDentryLayout| {
-                        layout.order = 0xE5;
-                        if layout.is_end() {
-                            is_end = true;
-                        }
+        while self.is_long_slot(sector_id, offset) {
+            slots.push((sector_id, offset));
+            (sector_id, offset) = self.next_dentry_id(sector_id, offset).unwrap();
+        }
+        slots.push((sector_id, offset));
+        // 被删除的是目录中的最后一项时，直接把这些位置标记为空闲并把提示回退到开头，
+        // 否则只标记为已删除 (insert_dentry 不会复用已删除的位置，提示保持不变)
+        let mut hints = self.free_slot_hint.lock();
+        let is_last = hints.get(&cluster_id).copied() == self.next_dentry_id(sector_id, offset);
+        let marker = if is_last { 0x00 } else { 0xE5 };
+        for &(sector_id, offset) in slots.iter() {
+            get_block_cache(sector_id, Arc::clone(&self.bdev))
+                .lock()
+                .modify(offset, |first_byte: &mut u8| *first_byte = marker);
+        }
+        if is_last {
+            hints.insert(cluster_id, slots[0]);
+        }
+    }
+
+    /// 轻量的一致性检查：簇链无环、没有簇被多个文件共用、目录项指向合法的簇、
+    /// 目录树中没有环、FAT 中没有不属于任何文件的簇。发现的问题都会打印出来
+    pub fn check(&self) -> Vec<FsckProblem> {
+        let max_cluster = self.sb.cluster_count() + 2;
+        let is_valid = |cluster: usize| (2..max_cluster).contains(&cluster);
+        let mut problems = Vec::new();
+        // 簇号 -> 占用它的文件
+        let mut owners: BTreeMap<usize, String> = BTreeMap::new();
+        // 已经检查过的目录的起始簇号
+        let mut seen_dirs = BTreeSet::new();
+        // 待检查的目录 (路径, 起始簇号)
+        let mut dirs = Vec::from([(String::from("/"), self.sb.root_cluster as usize)]);
+        while let Some((path, dir_cluster)) = dirs.pop() {
+            if !is_valid(dir_cluster) {
+                problems.push(FsckProblem::InvalidStart {
+                    path,
+                    cluster: dir_cluster,
+                });
+                continue;
+            }
+            if !seen_dirs.insert(dir_cluster) {
+                problems.push(FsckProblem::DirCycle {
+                    path,
+                    cluster: dir_cluster,
+                });
+                continue;
+            }
+            self.check_chain(&path, dir_cluster, max_cluster, &mut owners, &mut problems);
+            let mut sector_id = self.fat.cluster_id_to_sector_id(dir_cluster).unwrap();
+            let mut offset = 0;
+            while let Some(dentry) = self.get_dentry(&mut sector_id, &mut offset) {
+                if dentry.is_deleted() || dentry.is_volume_id() {
+                    continue;
+                }
+                let name = dentry.name();
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let child = format!("{}{}", path, name);
+                let start_cluster = dentry.start_cluster_id();
+                if dentry.is_dir() {
+                    dirs.push((format!("{}/", child), start_cluster));
+                } else if start_cluster == 0 {
+                    // 空文件没有分配簇
+                    continue;
+                } else if !is_valid(start_cluster) {
+                    problems.push(FsckProblem::InvalidStart {
+                        path:    child,
+                        cluster: start_cluster,
                     });
-                (sector_id, offset) = self.next_dentry_id(sector_id, offset).unwrap();
-                if is_end {
-                    break;
+                } else {
+                    self.check_chain(
+                        &child,
+                        start_cluster,
+                        max_cluster,
+                        &mut owners,
+                        &mut problems,
+                    );
                 }
             }
         }
-        get_block_cache(sector_id, self.bdev.clone()).lock().modify(
-            offset,
-            |layout: &mut Fat32DentryLayout| {
-                layout.set_deleted();
-            },
-        );
+        // 已分配却没有被任何文件的簇链走到的簇
+        for cluster in 2..max_cluster {
+            if self.fat.next_cluster_id(cluster) != Some(0) && !owners.contains_key(&cluster) {
+                problems.push(FsckProblem::Lost { cluster });
+            }
+        }
+        for problem in &problems {
+            warn!("[fsck] {}", problem);
+        }
+        if problems.is_empty() {
+            info!("[fsck] FAT32 check passed");
+        } else {
+            warn!("[fsck] FAT32 check found {} problem(s)", problems.len());
+        }
+        problems
+    }
+
+    /// 检查 path 的簇链，并把链上的簇记到 owners 中，发现的问题追加到 problems
+    fn check_chain(
+        &self, path: &str, start_cluster: usize, max_cluster: usize,
+        owners: &mut BTreeMap<usize, String>, problems: &mut Vec<FsckProblem>,
+    ) {
+        let mut visited = BTreeSet::new();
+        let mut cluster = start_cluster;
+        loop {
+            if !visited.insert(cluster) {
+                problems.push(FsckProblem::ChainLoop {
+                    path: path.to_string(),
+                    cluster,
+                });
+                return;
+            }
+            if let Some(owner) = owners.get(&cluster) {
+                problems.push(FsckProblem::CrossLinked {
+                    path: path.to_string(),
+                    cluster,
+                    owner: owner.clone(),
+                });
+            } else {
+                owners.insert(cluster, path.to_string());
+            }
+            match self.fat.next_cluster_id(cluster) {
+                Some(next) if (2..max_cluster).contains(&next) => cluster = next,
+                Some(next) => {
+                    problems.push(FsckProblem::InvalidNext {
+                        path: path.to_string(),
+                        cluster,
+                        next,
+                    });
+                    return;
+                }
+                None => return,
+            }
+        }
+    }
+}
+
+/// [`Fat32FS::check`] 发现的问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckProblem {
+    /// 目录项指向不合法的起始簇
+    InvalidStart { path: String, cluster: usize },
+    /// 簇链绕回到链上已经出现过的簇
+    ChainLoop { path: String, cluster: usize },
+    /// 簇同时出现在 owner 的簇链中
+    CrossLinked {
+        path:    String,
+        cluster: usize,
+        owner:   String,
+    },
+    /// 簇链指向不合法的簇
+    InvalidNext {
+        path:    String,
+        cluster: usize,
+        next:    usize,
+    },
+    /// 目录的起始簇已经作为另一个目录检查过，目录树中有环
+    DirCycle { path: String, cluster: usize },
+    /// FAT 中已分配但不属于任何文件的簇
+    Lost { cluster: usize },
+}
+
+impl fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidStart { path, cluster } => {
+                write!(f, "{}: invalid start cluster {}", path, cluster)
+            }
+            Self::ChainLoop { path, cluster } => {
+                write!(f, "{}: cluster chain loops back to {}", path, cluster)
+            }
+            Self::CrossLinked {
+                path,
+                cluster,
+                owner,
+            } => write!(f, "{}: cluster {} is also used by {}", path, cluster, owner),
+            Self::InvalidNext {
+                path,
+                cluster,
+                next,
+            } => write!(
+                f,
+                "{}: cluster {} points to invalid cluster {}",
+                path, cluster, next
+            ),
+            Self::DirCycle { path, cluster } => write!(
+                f,
+                "{}: directory cluster {} is already part of the tree",
+                path, cluster
+            ),
+            Self::Lost { cluster } => write!(f, "cluster {} is allocated but unused", cluster),
+        }
     }
 }
@@ -0,0 +1,77 @@
+//! inode 缓存
+//!
+//! 同一个文件的多次查找共用一个 Fat32Inode，文件以目录项的位置 (sector_id, offset) 区分。
+//! 缓存的 inode 数超过 sysctl 的 inode_cache_size 时，按最近最少使用的顺序淘汰
+//! 没有被打开的 inode (只有缓存自己持有引用)，被打开的 inode 一直保留。
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+
+use super::inode::Fat32Inode;
+
+pub struct InodeCache {
+    inodes:    BTreeMap<(usize, usize), Arc<Fat32Inode>>,
+    /// 使用的先后顺序，越靠后越是最近使用的
+    lru:       VecDeque<(usize, usize)>,
+    evictions: usize,
+}
+
+impl InodeCache {
+    pub fn new() -> Self {
+        Self {
+            inodes:    BTreeMap::new(),
+            lru:       VecDeque::new(),
+            evictions: 0,
+        }
+    }
+
+    /// 命中时把它移到最近使用的位置
+    pub fn get(&mut self, key: (usize, usize)) -> Option<Arc<Fat32Inode>> {
+        let inode = self.inodes.get(&key)?.clone();
+        self.lru.retain(|k| *k != key);
+        self.lru.push_back(key);
+        Some(inode)
+    }
+
+    /// 加入新的 inode，超过 capacity 时返回被淘汰的 inode，由调用者写回它们的脏元数据
+    pub fn insert(
+        &mut self, key: (usize, usize), inode: Arc<Fat32Inode>, capacity: usize,
+    ) -> Vec<Arc<Fat32Inode>> {
+        if self.inodes.insert(key, inode).is_some() {
+            self.lru.retain(|k| *k != key);
+        }
+        self.lru.push_back(key);
+        let mut evicted = Vec::new();
+        let mut i = 0;
+        while self.inodes.len() > capacity && i < self.lru.len() {
+            let key = self.lru[i];
+            if Arc::strong_count(&self.inodes[&key]) == 1 {
+                self.lru.remove(i);
+                evicted.push(self.inodes.remove(&key).unwrap());
+            } else {
+                i += 1;
+            }
+        }
+        self.evictions += evicted.len();
+        evicted
+    }
+
+    /// 位于 key 的目录项被删除
+    pub fn remove(&mut self, key: (usize, usize)) {
+        if self.inodes.remove(&key).is_some() {
+            self.lru.retain(|k| *k != key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inodes.len()
+    }
+
+    /// 目前为止淘汰的 inode 数
+    pub fn evictions(&self) -> usize {
+        self.evictions
+    }
+}
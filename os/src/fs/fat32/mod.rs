@@ -1,6 +1,8 @@
+mod dcache;
 mod dentry;
 mod fat;
 pub mod fs;
+mod icache;
 pub mod inode;
 mod super_block;
 
@@ -30,6 +30,12 @@ impl Fat32SB {
     pub fn root_sector(&self) -> usize {
         self.reserved_sectors_cnt as usize + self.fat_cnt as usize * self.fat_size_32 as usize
     }
+
+    /// number of data clusters, valid cluster ids are 2..cluster_count() + 2
+    pub fn cluster_count(&self) -> usize {
+        (self.total_sectors_32 as usize).saturating_sub(self.root_sector())
+            / self.sectors_per_cluster as usize
+    }
 }
 
 impl From<Fat32SBLayout> for Fat32SB {
@@ -2,11 +2,26 @@ use alloc::{sync::Arc, vec::Vec};
 use core::any::Any;
 
 use super::{
+    dev::{
+        console::Console,
+        node::DeviceNode,
+        null::Null,
+        tty::Tty,
+        urandom::URandom,
+        zero::Zero,
+        DevDir,
+    },
     ext4::inode::Ext4Inode,
     fat32::inode::Fat32Inode,
+    fscontext::{FsContext, MountFd},
     inode::{Inode, Stat},
+    os_inode::OSInode,
+    overlay::OverlayInode,
+    pidfd::PidFd,
+    procfs::{MountInfo, ProcDir},
+    tmpfs::TmpInode,
 };
-use crate::mm::UserBuffer;
+use crate::mm::{PhysPageNum, UserBuffer};
 
 /// trait File for all file types
 pub trait File: Any + Send + Sync {
@@ -37,6 +52,24 @@ pub trait File: Any + Send + Sync {
     fn w_ready(&self) -> bool {
         true
     }
+    /// 设备文件提供 mmap 时要映射的物理页，`offset` 与 `len` 均以字节为单位；
+    /// 返回 None 表示按普通文件的方式读入内容
+    fn mmap_frames(&self, _offset: usize, _len: usize) -> Option<Vec<PhysPageNum>> {
+        None
+    }
+}
+
+/// 依次尝试把 `Arc<dyn Any>` 形式的指针转换为列出的具体类型
+macro_rules! cast_arc {
+    ($ptr:expr, $any:expr, $target:ty, [$($ty:ty),* $(,)?]) => {{
+        $(
+            if $any.is::<$ty>() {
+                let ptr = $ptr as *const $ty;
+                let arc: Arc<$target> = Arc::from_raw(ptr);
+                return Some(arc);
+            }
+        )*
+    }};
 }
 
 // TODO: 优化这个函数
@@ -44,15 +77,86 @@ pub fn cast_file_to_inode(file: Arc<dyn File>) -> Option<Arc<dyn Inode>> {
     unsafe {
         let file_ptr = Arc::into_raw(file);
         let file_ref = &*(file_ptr as *const dyn Any);
-        if file_ref.is::<Fat32Inode>() {
-            let inode_ptr = file_ptr as *const Fat32Inode;
-            let inode = Arc::from_raw(inode_ptr);
-            Some(inode)
-        } else {
-            // 如果转换失败，我们需要重新创建原始的 Arc 以避免内存泄漏
-            let _ = Arc::from_raw(file_ptr);
-            None
+        // 打开的文件外面包了一层 OSInode，直接取出底层的 inode
+        if file_ref.is::<OSInode>() {
+            let os_inode = Arc::from_raw(file_ptr as *const OSInode);
+            return Some(os_inode.inode());
+        }
+        cast_arc!(
+            file_ptr,
+            file_ref,
+            dyn Inode,
+            [
+                Fat32Inode,
+                Ext4Inode,
+                TmpInode,
+                OverlayInode,
+                ProcDir,
+                MountInfo,
+                DevDir,
+                DeviceNode,
+                Console,
+                Null,
+                Zero,
+                URandom,
+                Tty
+            ]
+        );
+        // 如果转换失败，我们需要重新创建原始的 Arc 以避免内存泄漏
+        let _ = Arc::from_raw(file_ptr);
+        None
+    }
+}
+
+/// 如果是 open 得到的文件，取出对应的 OSInode
+pub fn cast_file_to_os_inode(file: Arc<dyn File>) -> Option<Arc<OSInode>> {
+    unsafe {
+        let file_ptr = Arc::into_raw(file);
+        let file_ref = &*(file_ptr as *const dyn Any);
+        if file_ref.is::<OSInode>() {
+            return Some(Arc::from_raw(file_ptr as *const OSInode));
+        }
+        let _ = Arc::from_raw(file_ptr);
+        None
+    }
+}
+
+/// 如果是 pidfd，取出对应的 PidFd
+pub fn cast_file_to_pidfd(file: Arc<dyn File>) -> Option<Arc<PidFd>> {
+    unsafe {
+        let file_ptr = Arc::into_raw(file);
+        let file_ref = &*(file_ptr as *const dyn Any);
+        if file_ref.is::<PidFd>() {
+            return Some(Arc::from_raw(file_ptr as *const PidFd));
         }
+        let _ = Arc::from_raw(file_ptr);
+        None
+    }
+}
+
+/// 如果是 fsopen 得到的 fd，取出对应的 FsContext
+pub fn cast_file_to_fs_context(file: Arc<dyn File>) -> Option<Arc<FsContext>> {
+    unsafe {
+        let file_ptr = Arc::into_raw(file);
+        let file_ref = &*(file_ptr as *const dyn Any);
+        if file_ref.is::<FsContext>() {
+            return Some(Arc::from_raw(file_ptr as *const FsContext));
+        }
+        let _ = Arc::from_raw(file_ptr);
+        None
+    }
+}
+
+/// 如果是 fsmount 得到的 fd，取出对应的 MountFd
+pub fn cast_file_to_mount_fd(file: Arc<dyn File>) -> Option<Arc<MountFd>> {
+    unsafe {
+        let file_ptr = Arc::into_raw(file);
+        let file_ref = &*(file_ptr as *const dyn Any);
+        if file_ref.is::<MountFd>() {
+            return Some(Arc::from_raw(file_ptr as *const MountFd));
+        }
+        let _ = Arc::from_raw(file_ptr);
+        None
     }
 }
 
@@ -60,18 +164,28 @@ pub fn cast_inode_to_file(inode: Arc<dyn Inode>) -> Option<Arc<dyn File>> {
     unsafe {
         let inode_ptr = Arc::into_raw(inode);
         let inode_ref = &*(inode_ptr as *const dyn Any);
-        if inode_ref.is::<Fat32Inode>() {
-            let file_ptr = inode_ptr as *const Fat32Inode;
-            let file = Arc::from_raw(file_ptr);
-            Some(file)
-        } else if inode_ref.is::<Ext4Inode>() {
-            let file_ptr = inode_ptr as *const Ext4Inode;
-            let file = Arc::from_raw(file_ptr);
-            Some(file)
-        } else {
-            // 如果转换失败，我们需要重新创建原始的 Arc 以避免内存泄漏
-            let _ = Arc::from_raw(inode_ptr);
-            None
-        }
+        cast_arc!(
+            inode_ptr,
+            inode_ref,
+            dyn File,
+            [
+                Fat32Inode,
+                Ext4Inode,
+                TmpInode,
+                OverlayInode,
+                ProcDir,
+                MountInfo,
+                DevDir,
+                DeviceNode,
+                Console,
+                Null,
+                Zero,
+                URandom,
+                Tty
+            ]
+        );
+        // 如果转换失败，我们需要重新创建原始的 Arc 以避免内存泄漏
+        let _ = Arc::from_raw(inode_ptr);
+        None
     }
 }
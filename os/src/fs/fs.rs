@@ -1,4 +1,12 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use super::{inode::Inode, path::Path};
 
@@ -13,6 +21,10 @@ pub trait FileSystem: Send + Sync {
 pub enum FileSystemType {
     VFAT,
     EXT4,
+    DEVFS,
+    TMPFS,
+    OVERLAY,
+    PROC,
 }
 
 impl FileSystemType {
@@ -20,6 +32,10 @@ impl FileSystemType {
         match name {
             "vfat" => Some(Self::VFAT),
             "ext4" => Some(Self::EXT4),
+            "devfs" => Some(Self::DEVFS),
+            "tmpfs" => Some(Self::TMPFS),
+            "overlay" => Some(Self::OVERLAY),
+            "proc" => Some(Self::PROC),
             _ => panic!("[FileSystemType] unknown file system type"),
         }
     }
@@ -28,14 +44,73 @@ impl FileSystemType {
         match self {
             Self::VFAT => "vfat",
             Self::EXT4 => "ext4",
+            Self::DEVFS => "devfs",
+            Self::TMPFS => "tmpfs",
+            Self::OVERLAY => "overlay",
+            Self::PROC => "proc",
         }
     }
 }
 
 /* File System Manager */
 
+/// 挂载编号，从 1 开始递增，卸载后不回收
+static NEXT_MOUNT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// 挂载表中的一项：挂载点所在的文件系统，以及挂载点解析到的目录。
+/// 普通挂载的 root 是文件系统的根目录，bind mount 的 root 是源路径对应的已有 inode
+#[derive(Clone)]
+pub struct Mount {
+    pub fs:     Arc<dyn FileSystem>,
+    pub root:   Arc<dyn Inode>,
+    /// 挂载的来源，如块设备路径、bind mount 的源路径或文件系统名
+    pub source: String,
+    pub id:     usize,
+    /// 经由这个挂载打开的文件和工作目录的个数
+    refs:       Arc<AtomicUsize>,
+}
+
+impl Mount {
+    fn new(fs: Arc<dyn FileSystem>, root: Arc<dyn Inode>, source: &str) -> Self {
+        Self {
+            fs,
+            root,
+            source: source.to_string(),
+            id: NEXT_MOUNT_ID.fetch_add(1, Ordering::Relaxed),
+            refs: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// 取得挂载的一个引用，引用 drop 时计数减少
+    pub fn pin(&self) -> MountRef {
+        self.refs.fetch_add(1, Ordering::Relaxed);
+        MountRef(self.refs.clone())
+    }
+
+    /// 还有打开的文件或工作目录在这个挂载中
+    pub fn busy(&self) -> bool {
+        self.refs.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// 挂载的一个引用，由经由挂载点打开的目录项持有
+pub struct MountRef(Arc<AtomicUsize>);
+
+impl Clone for MountRef {
+    fn clone(&self) -> Self {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for MountRef {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 pub struct FileSystemManager {
-    pub mounted_fs: BTreeMap<Path, Arc<dyn FileSystem>>,
+    pub mounted_fs: BTreeMap<Path, Mount>,
 }
 
 impl Default for FileSystemManager {
@@ -51,9 +126,18 @@ impl FileSystemManager {
         }
     }
 
-    pub fn mount(&mut self, fs: Arc<dyn FileSystem>, path: &str) {
-        let path = Path::new(path);
-        self.mounted_fs.insert(path, fs);
+    pub fn mount(&mut self, fs: Arc<dyn FileSystem>, source: &str, path: &str) {
+        let root = fs.clone().root_inode();
+        self.mounted_fs
+            .insert(Path::new(path), Mount::new(fs, root, source));
+    }
+
+    /// bind mount：`path` 解析到 `fs` 中已有的 inode `root`，而不是文件系统的根目录
+    pub fn bind(
+        &mut self, fs: Arc<dyn FileSystem>, root: Arc<dyn Inode>, source: &str, path: &str,
+    ) {
+        self.mounted_fs
+            .insert(Path::new(path), Mount::new(fs, root, source));
     }
 
     pub fn unmount(&mut self, path: &str) {
@@ -61,7 +145,71 @@ impl FileSystemManager {
         self.mounted_fs.remove(&path);
     }
 
+    /// 挂载在 `path` 上的一项，`path` 不是挂载点时返回 None
+    pub fn get(&self, path: &str) -> Option<&Mount> {
+        self.mounted_fs.get(&Path::new(path))
+    }
+
+    /// 复制一份挂载表作为新的挂载命名空间。每个挂载都是新的一项，编号和忙碌计数与原来的无关
+    pub fn copy(&self) -> Self {
+        let mounted_fs = self
+            .mounted_fs
+            .iter()
+            .map(|(path, mount)| {
+                let copy = Mount::new(mount.fs.clone(), mount.root.clone(), &mount.source);
+                (path.clone(), copy)
+            })
+            .collect();
+        Self { mounted_fs }
+    }
+
     pub fn rootfs(&self) -> Arc<dyn FileSystem> {
-        self.mounted_fs.get(&Path::new("/")).unwrap().clone()
+        self.mounted_fs.get(&Path::new("/")).unwrap().fs.clone()
+    }
+
+    /// 找到绝对路径 `path` 所在的挂载点 (最长前缀匹配)，返回挂载点、挂载表中的这一项
+    /// 以及路径在挂载点之下的剩余部分 (不以 '/' 开头，可能为空)
+    pub fn find_mount(&self, path: &str) -> Option<(Path, Mount, String)> {
+        self.mounted_fs
+            .iter()
+            .filter_map(|(mount_point, mount)| {
+                let prefix = mount_point.as_str().trim_end_matches('/');
+                let rest = path.strip_prefix(prefix)?;
+                if rest.is_empty() || rest.starts_with('/') {
+                    Some((mount_point, mount, rest.trim_start_matches('/')))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(mount_point, _, _)| mount_point.as_str().len())
+            .map(|(mount_point, mount, rest)| {
+                (mount_point.clone(), mount.clone(), rest.to_string())
+            })
+    }
+
+    /// 按 `/proc/self/mountinfo` 的格式列出挂载表，每个挂载一行：
+    /// `挂载编号 父挂载编号 设备号 根 挂载点 选项 - 文件系统类型 来源 超级块选项`。
+    /// 父挂载是挂载点所在目录的挂载，根文件系统的父挂载是它自己
+    pub fn mountinfo(&self) -> String {
+        let mut info = String::new();
+        for (mount_point, mount) in self.mounted_fs.iter() {
+            let mount_point = mount_point.as_str();
+            let parent = match mount_point.rfind('/') {
+                Some(i) if mount_point != "/" => self
+                    .find_mount(&mount_point[..i.max(1)])
+                    .map_or(mount.id, |(_, parent, _)| parent.id),
+                _ => mount.id,
+            };
+            let _ = writeln!(
+                info,
+                "{} {} 0:0 / {} rw - {} {} rw",
+                mount.id,
+                parent,
+                mount_point,
+                mount.fs.fs_type().to_str(),
+                mount.source
+            );
+        }
+        info
     }
 }
@@ -0,0 +1,152 @@
+//! 新的挂载 API 使用的 fd
+//!
+//! fsopen 返回指向文件系统上下文 ([`FsContext`]) 的 fd，通过 fsconfig 设置参数并创建文件系统；
+//! fsmount 把创建好的文件系统包装成还没有挂到任何路径上的挂载 ([`MountFd`])，
+//! 最后由 move_mount 把它加入挂载表。
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use super::{
+    file::File,
+    fs::{FileSystem, FileSystemType},
+    inode::Stat,
+    tmpfs::TmpFS,
+};
+
+/// tmpfs 接受的参数，目前只检查名字，值不生效
+const TMPFS_PARAMS: &[&str] = &["size", "nr_inodes", "mode", "uid", "gid"];
+
+/// fsopen 得到的文件系统上下文
+pub struct FsContext {
+    fs_type: FileSystemType,
+    inner:   Mutex<FsContextInner>,
+}
+
+struct FsContextInner {
+    /// fsconfig 设置的参数，标志参数没有值
+    params:  BTreeMap<String, Option<String>>,
+    /// FSCONFIG_CMD_CREATE 创建的文件系统
+    fs:      Option<Arc<dyn FileSystem>>,
+    /// 已经由 fsmount 取走
+    mounted: bool,
+}
+
+impl FsContext {
+    /// 目前只支持 tmpfs，其余文件系统返回 None
+    pub fn new(fs_type: &str) -> Option<Self> {
+        let fs_type = match fs_type {
+            "tmpfs" => FileSystemType::TMPFS,
+            _ => return None,
+        };
+        Some(Self {
+            fs_type,
+            inner: Mutex::new(FsContextInner {
+                params:  BTreeMap::new(),
+                fs:      None,
+                mounted: false,
+            }),
+        })
+    }
+
+    /// 设置参数，文件系统不认识这个参数或已经创建时返回 false
+    pub fn set_param(&self, key: &str, value: Option<String>) -> bool {
+        let mut inner = self.inner.lock();
+        let known = match self.fs_type {
+            FileSystemType::TMPFS => TMPFS_PARAMS.contains(&key),
+            _ => false,
+        };
+        if !known || inner.fs.is_some() {
+            return false;
+        }
+        inner.params.insert(key.to_string(), value);
+        true
+    }
+
+    /// 按设置的参数创建文件系统，只能创建一次
+    pub fn create(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.fs.is_some() {
+            return false;
+        }
+        let fs: Arc<dyn FileSystem> = match self.fs_type {
+            FileSystemType::TMPFS => Arc::new(TmpFS::new()),
+            _ => return false,
+        };
+        inner.fs = Some(fs);
+        true
+    }
+
+    /// fsmount 取出创建好的文件系统，还没有创建或已经取走过时返回 None
+    pub fn take_fs(&self) -> Option<Arc<dyn FileSystem>> {
+        let mut inner = self.inner.lock();
+        if inner.mounted {
+            return None;
+        }
+        let fs = inner.fs.clone()?;
+        inner.mounted = true;
+        Some(fs)
+    }
+}
+
+/// fsmount 得到的挂载，move_mount 之前不在任何路径上
+pub struct MountFd {
+    fs:       Arc<dyn FileSystem>,
+    attached: AtomicBool,
+}
+
+impl MountFd {
+    pub fn new(fs: Arc<dyn FileSystem>) -> Self {
+        Self {
+            fs,
+            attached: AtomicBool::new(false),
+        }
+    }
+
+    /// 第一次调用时返回要挂载的文件系统，之后返回 None
+    pub fn attach(&self) -> Option<Arc<dyn FileSystem>> {
+        (!self.attached.swap(true, Ordering::Relaxed)).then(|| Arc::clone(&self.fs))
+    }
+}
+
+/// 这两种 fd 都不能读写
+macro_rules! impl_mount_api_file {
+    ($ty:ty) => {
+        impl File for $ty {
+            fn readable(&self) -> bool {
+                false
+            }
+            fn writable(&self) -> bool {
+                false
+            }
+            fn read(&self, _buf: &mut [u8]) -> usize {
+                0
+            }
+            fn read_all(&self) -> Vec<u8> {
+                Vec::new()
+            }
+            fn write(&self, _buf: &[u8]) -> usize {
+                0
+            }
+            fn fstat(&self) -> Option<Stat> {
+                None
+            }
+            fn is_dir(&self) -> bool {
+                false
+            }
+            fn hang_up(&self) -> bool {
+                false
+            }
+        }
+    };
+}
+
+impl_mount_api_file!(FsContext);
+impl_mount_api_file!(MountFd);
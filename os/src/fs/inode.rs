@@ -2,7 +2,7 @@ use alloc::{string::String, sync::Arc, vec::Vec};
 use core::any::Any;
 
 use super::{dentry::Dentry, file::File, fs::FileSystemType};
-use crate::{block::BLOCK_SZ, mm::UserBuffer, timer::TimeSpec};
+use crate::{block::BLOCK_SZ, mm::UserBuffer, syscall::errno::ENOTTY, timer::TimeSpec};
 
 /* Inode Operators */
 
@@ -24,12 +24,47 @@ pub trait Inode: Any + Send + Sync {
     fn rmdir(self: Arc<Self>, name: &str) -> bool;
     /// list all inodes in the directory
     fn ls(&self) -> Vec<String>;
+    /// list all inodes in the directory with their types
+    fn ls_typed(&self) -> Vec<(String, InodeType)> {
+        self.ls()
+            .into_iter()
+            .map(|name| (name, InodeType::Regular))
+            .collect()
+    }
     /// clear the inode
     fn clear(&self);
+    /// permission bits of the inode
+    fn mode(&self) -> u32 {
+        0o777
+    }
+    /// set the permission bits of the inode; false if unsupported
+    fn chmod(&self, _mode: u32) -> bool {
+        false
+    }
+    /// device or file system specific control operation
+    fn ioctl(&self, _request: usize, _arg: usize) -> isize {
+        ENOTTY
+    }
+    /// hint the expected access pattern of [offset, offset + len), len 0 means to the end
+    fn fadvise(&self, _offset: usize, _len: usize, _advice: usize) {}
+    /// set the size of the inode, the extended part reads as zeros; false if unsupported
+    fn truncate(&self, _size: usize) -> bool {
+        false
+    }
+    /// write the data and metadata of the inode back to the device
+    fn fsync(&self) {}
     /// read at the offset of the inode
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
     /// write at the offset of the inode
     fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    /// inode number, unique within the file system; defaults to the address of the inode
+    fn ino(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+    /// create a device node in the directory with the name, type and device number
+    fn mknod(self: Arc<Self>, _name: &str, _type_: InodeType, _rdev: u64) -> Option<Arc<Dentry>> {
+        None
+    }
     /// read all data from the inode in memory
     fn read_all(&self) -> Vec<u8> {
         trace!("kernel: OSInode::read_all");
@@ -177,16 +212,22 @@ impl Stat {
     }
     /// check whether the inode is a directory
     pub fn is_dir(&self) -> bool {
-        StatMode::from_bits(self.st_mode)
-            .unwrap()
-            .contains(StatMode::DIR)
+        self.st_mode & StatMode::TYPE_MASK.bits() == StatMode::DIR.bits()
     }
 
     /// check whether the inode is a file
     pub fn is_file(&self) -> bool {
-        StatMode::from_bits(self.st_mode)
-            .unwrap()
-            .contains(StatMode::FILE)
+        self.st_mode & StatMode::TYPE_MASK.bits() == StatMode::FILE.bits()
+    }
+
+    /// check whether the inode is a character device
+    pub fn is_char_device(&self) -> bool {
+        self.st_mode & StatMode::TYPE_MASK.bits() == StatMode::CHAR.bits()
+    }
+
+    /// device number of a special file
+    pub fn rdev(&self) -> u64 {
+        self.st_rdev
     }
 }
 
@@ -198,6 +239,12 @@ bitflags! {
         const NULL  = 0;
         /// directory
         const DIR   = 0o040000;
+        /// character device
+        const CHAR  = 0o020000;
+        /// block device
+        const BLOCK = 0o060000;
+        /// mask of the file type bits
+        const TYPE_MASK = 0o170000;
         /// ordinary regular file
         const FILE  = 0o100000;
     }
@@ -0,0 +1,267 @@
+//! Advisory file locks
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use super::inode::Inode;
+use crate::{
+    syscall::errno::{EAGAIN, EINVAL, EWOULDBLOCK},
+    task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock},
+};
+
+pub const LOCK_SH: i32 = 1;
+pub const LOCK_EX: i32 = 2;
+pub const LOCK_NB: i32 = 4;
+pub const LOCK_UN: i32 = 8;
+
+/// 锁挂在 inode 上，用 (文件系统类型, inode 编号) 来标识同一个文件
+pub type LockKey = (&'static str, usize);
+
+pub fn lock_key(inode: &Arc<dyn Inode>) -> LockKey {
+    (inode.fstype().to_str(), inode.ino())
+}
+
+/// 一个 inode 上的 flock 状态
+#[derive(Default)]
+struct FlockState {
+    /// 持有锁的打开文件 (owner) 及是否为排他锁
+    holders:    Vec<(usize, bool)>,
+    /// 等待锁释放的任务
+    wait_queue: Vec<Arc<TaskControlBlock>>,
+}
+
+impl FlockState {
+    /// owner 以 exclusive 方式加锁是否与其他持有者冲突
+    fn conflicts(&self, owner: usize, exclusive: bool) -> bool {
+        self.holders
+            .iter()
+            .any(|&(other, other_ex)| other != owner && (exclusive || other_ex))
+    }
+
+    /// 移除 owner 持有的锁，返回被唤醒的等待者
+    fn release(&mut self, owner: usize) -> Vec<Arc<TaskControlBlock>> {
+        let len = self.holders.len();
+        self.holders.retain(|&(other, _)| other != owner);
+        if self.holders.len() == len {
+            return Vec::new();
+        }
+        core::mem::take(&mut self.wait_queue)
+    }
+}
+
+lazy_static! {
+    static ref FLOCKS: Mutex<BTreeMap<LockKey, FlockState>> = Mutex::new(BTreeMap::new());
+}
+
+/// 对 key 对应的文件执行 flock 操作，owner 标识打开的文件 (dup 出来的 fd 共享同一把锁)
+pub fn flock(key: LockKey, owner: usize, operation: i32) -> isize {
+    let nonblock = operation & LOCK_NB != 0;
+    let exclusive = match operation & !LOCK_NB {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN => {
+            let waiters = match FLOCKS.lock().get_mut(&key) {
+                Some(state) => state.release(owner),
+                None => Vec::new(),
+            };
+            waiters.into_iter().for_each(wakeup_task);
+            return 0;
+        }
+        _ => return EINVAL,
+    };
+    loop {
+        let mut flocks = FLOCKS.lock();
+        let state = flocks.entry(key).or_default();
+        if !state.conflicts(owner, exclusive) {
+            // 已经持有锁时直接转换锁的类型，从排他锁降级时唤醒等待者重新竞争
+            let downgraded = state.holders.contains(&(owner, true)) && !exclusive;
+            state.holders.retain(|&(other, _)| other != owner);
+            state.holders.push((owner, exclusive));
+            let waiters = if downgraded {
+                core::mem::take(&mut state.wait_queue)
+            } else {
+                Vec::new()
+            };
+            drop(flocks);
+            waiters.into_iter().for_each(wakeup_task);
+            return 0;
+        }
+        if nonblock {
+            return EWOULDBLOCK;
+        }
+        state.wait_queue.push(current_task().unwrap());
+        drop(flocks);
+        block_current_and_run_next();
+    }
+}
+
+/// 打开的文件被关闭时释放它持有的所有 flock
+pub fn release_flocks(owner: usize) {
+    let mut waiters = Vec::new();
+    let mut flocks = FLOCKS.lock();
+    for state in flocks.values_mut() {
+        waiters.append(&mut state.release(owner));
+    }
+    flocks.retain(|_, state| !state.holders.is_empty() || !state.wait_queue.is_empty());
+    drop(flocks);
+    waiters.into_iter().for_each(wakeup_task);
+}
+
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+/// fcntl F_GETLK/F_SETLK/F_SETLKW 使用的 struct flock
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Flock {
+    pub l_type:   i16,
+    pub l_whence: i16,
+    pub l_start:  i64,
+    pub l_len:    i64,
+    pub l_pid:    i32,
+}
+
+/// 进程持有的一段字节范围锁 [start, end)，end 为 usize::MAX 表示一直到文件末尾
+#[derive(Debug, Clone, Copy)]
+pub struct RecordLock {
+    pub pid:       usize,
+    pub start:     usize,
+    pub end:       usize,
+    pub exclusive: bool,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// 一个 inode 上的记录锁状态
+#[derive(Default)]
+struct RecordLockState {
+    locks:      Vec<RecordLock>,
+    wait_queue: Vec<Arc<TaskControlBlock>>,
+}
+
+impl RecordLockState {
+    /// 找到与 pid 在 [start, end) 上加锁冲突的其他进程的锁
+    fn conflict(
+        &self, pid: usize, start: usize, end: usize, exclusive: bool,
+    ) -> Option<RecordLock> {
+        self.locks
+            .iter()
+            .find(|lock| {
+                lock.pid != pid && lock.overlaps(start, end) && (exclusive || lock.exclusive)
+            })
+            .copied()
+    }
+
+    /// 去掉 pid 在 [start, end) 上的锁，跨越边界的锁被拆成两段
+    fn remove_range(&mut self, pid: usize, start: usize, end: usize) {
+        let mut locks = Vec::new();
+        for lock in self.locks.drain(..) {
+            if lock.pid != pid || !lock.overlaps(start, end) {
+                locks.push(lock);
+                continue;
+            }
+            if lock.start < start {
+                locks.push(RecordLock { end: start, ..lock });
+            }
+            if end < lock.end {
+                locks.push(RecordLock { start: end, ..lock });
+            }
+        }
+        self.locks = locks;
+    }
+}
+
+lazy_static! {
+    static ref RECORD_LOCKS: Mutex<BTreeMap<LockKey, RecordLockState>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// F_GETLK：返回会阻止 pid 在 [start, end) 上加锁的锁
+pub fn get_record_lock(
+    key: LockKey, pid: usize, start: usize, end: usize, exclusive: bool,
+) -> Option<RecordLock> {
+    RECORD_LOCKS
+        .lock()
+        .get(&key)
+        .and_then(|state| state.conflict(pid, start, end, exclusive))
+}
+
+/// F_SETLK/F_SETLKW：在 [start, end) 上加锁或解锁，wait 为 true 时冲突会阻塞到锁被释放
+pub fn set_record_lock(
+    key: LockKey, pid: usize, start: usize, end: usize, l_type: i16, wait: bool,
+) -> isize {
+    let exclusive = match l_type {
+        F_RDLCK => false,
+        F_WRLCK => true,
+        F_UNLCK => {
+            let mut locks = RECORD_LOCKS.lock();
+            let waiters = match locks.get_mut(&key) {
+                Some(state) => {
+                    state.remove_range(pid, start, end);
+                    core::mem::take(&mut state.wait_queue)
+                }
+                None => Vec::new(),
+            };
+            drop(locks);
+            waiters.into_iter().for_each(wakeup_task);
+            return 0;
+        }
+        _ => return EINVAL,
+    };
+    loop {
+        let mut locks = RECORD_LOCKS.lock();
+        let state = locks.entry(key).or_default();
+        if state.conflict(pid, start, end, exclusive).is_none() {
+            // 新锁替换掉自己在这段范围上原有的锁，写锁降级为读锁时等待者可能可以继续
+            state.remove_range(pid, start, end);
+            state.locks.push(RecordLock {
+                pid,
+                start,
+                end,
+                exclusive,
+            });
+            let waiters = core::mem::take(&mut state.wait_queue);
+            drop(locks);
+            waiters.into_iter().for_each(wakeup_task);
+            return 0;
+        }
+        if !wait {
+            return EAGAIN;
+        }
+        state.wait_queue.push(current_task().unwrap());
+        drop(locks);
+        block_current_and_run_next();
+    }
+}
+
+/// 进程退出时释放它持有的所有记录锁
+pub fn release_record_locks(pid: usize) {
+    release_record_locks_matching(pid, |_| true);
+}
+
+/// 进程关闭指向某个文件的任意一个 fd 时，释放它在这个文件上的所有记录锁 (与 POSIX 一致)
+pub fn release_file_record_locks(key: LockKey, pid: usize) {
+    release_record_locks_matching(pid, |other| *other == key);
+}
+
+fn release_record_locks_matching(pid: usize, matches: impl Fn(&LockKey) -> bool) {
+    let mut waiters = Vec::new();
+    let mut locks = RECORD_LOCKS.lock();
+    for (_, state) in locks.iter_mut().filter(|(key, _)| matches(key)) {
+        let len = state.locks.len();
+        state.locks.retain(|lock| lock.pid != pid);
+        if state.locks.len() != len {
+            waiters.append(&mut state.wait_queue);
+        }
+    }
+    locks.retain(|_, state| !state.locks.is_empty() || !state.wait_queue.is_empty());
+    drop(locks);
+    waiters.into_iter().for_each(wakeup_task);
+}
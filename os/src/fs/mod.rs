@@ -1,71 +1,277 @@
-use alloc::sync::Arc;
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
 
 use defs::OpenFlags;
 use dentry::Dentry;
+use dev::DevFS;
 use ext4::fs::Ext4FS;
-use fs::FileSystemManager;
+use fat32::fs::Fat32FS;
+pub use fs::FileSystemManager;
 use inode::{Inode, InodeType};
 use lazy_static::lazy_static;
+use overlay::OverlayFS;
+use path::Path;
+use procfs::ProcFS;
 use spin::Mutex;
 
-use crate::drivers::BLOCK_DEVICE;
+use crate::{
+    block::block_dev::BlockDevice,
+    drivers::BLOCK_DEVICE,
+    syscall::errno::{EACCES, EEXIST, ENOENT},
+    task::current_task,
+};
 
 pub mod defs;
 pub mod dentry;
+pub mod dev;
 pub mod ext4;
 mod fat32;
 pub mod file;
 mod fs;
+pub mod fscontext;
 pub mod inode;
+pub mod lock;
+pub mod os_inode;
+mod overlay;
 mod path;
+pub mod pidfd;
 pub mod pipe;
+mod procfs;
 pub mod stdio;
+mod tmpfs;
+pub mod writeback;
+
+pub use fat32::inode::{
+    fat32_append_test,
+    fat32_chmod_open_test,
+    fat32_crash_test,
+    fat32_create_cost_test,
+    fat32_dcache_test,
+    fat32_discard_test,
+    fat32_fadvise_test,
+    fat32_file_size_test,
+    fat32_fsck_test,
+    fat32_fsync_on_close_test,
+    fat32_icache_lru_test,
+    fat32_lfn_test,
+    fat32_loop_test,
+    fat32_mkdir_test,
+    fat32_negative_dentry_test,
+    fat32_partial_write_test,
+    fat32_read_at_test,
+    fat32_truncate_test,
+    fat32_unlink_test,
+    fat32_write_grow_test,
+    fat32_writeback_test,
+    Fat32Inode,
+};
+pub use overlay::overlay_test;
+
+/// 挂载命名空间：一张挂载表。fork 出的进程和父进程共享同一个，
+/// 带 CLONE_NEWNS 的 clone 或 unshare 之后得到一份私有的副本
+pub type MountNamespace = Arc<Mutex<FileSystemManager>>;
 
 lazy_static! {
-    pub static ref FS_MANAGER: Mutex<FileSystemManager> = Mutex::new(FileSystemManager::new());
+    /// 初始的挂载命名空间
+    pub static ref FS_MANAGER: MountNamespace = Arc::new(Mutex::new(FileSystemManager::new()));
+}
+
+/// 当前任务所在的挂载命名空间，还没有任务运行时是初始的命名空间
+pub fn mounts() -> MountNamespace {
+    current_task().map_or_else(|| FS_MANAGER.clone(), |task| task.mounts())
 }
 
 lazy_static! {
     pub static ref ROOT_INODE: Arc<dyn Inode> = {
         let ext4fs = Arc::new(Ext4FS::new(BLOCK_DEVICE.clone()));
-        FS_MANAGER.lock().mount(ext4fs, "/");
+        FS_MANAGER.lock().mount(ext4fs, "/dev/root", "/");
         FS_MANAGER.lock().rootfs().root_inode()
     };
 }
 
 pub fn init() {
     let _root = ROOT_INODE.clone();
+    FS_MANAGER
+        .lock()
+        .mount(Arc::new(DevFS::new()), "devfs", "/dev");
+    FS_MANAGER
+        .lock()
+        .mount(Arc::new(ProcFS::new()), "proc", "/proc");
+}
+
+/// 把块设备上的 FAT32 文件系统挂载到绝对路径 `target`，`source` 是块设备的路径，
+/// 设备上不是 FAT32 时返回 false
+pub fn mount_vfat(bdev: Arc<dyn BlockDevice>, source: &str, target: &str) -> bool {
+    match Fat32FS::load(bdev) {
+        Some(fs) => {
+            #[cfg(feature = "fsck")]
+            fs.check();
+            mounts().lock().mount(fs, source, target);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 把绝对路径 `source` 处已有的文件或目录绑定到绝对路径 `target` 上，之后 target 之下的路径
+/// 解析到 source 的子树中，两边看到的是同一组 inode。source 不存在时返回 false
+pub fn bind_mount(source: &str, target: &str) -> bool {
+    let Some(dentry) = open_file(ROOT_INODE.clone(), source, OpenFlags::O_RDONLY) else {
+        return false;
+    };
+    let mounts = mounts();
+    let mut manager = mounts.lock();
+    // 根文件系统总是挂载着，source 一定落在某个挂载点之下
+    let (_, mount, _) = manager.find_mount(source).unwrap();
+    manager.bind(mount.fs, dentry.inode(), source, target);
+    true
+}
+
+/// 把绝对路径 `lower` 和 `upper` 处的两个目录合并挂载到绝对路径 `target`，
+/// 之后的修改都写到 upper 中。两者有一个不存在或不是目录时返回 false
+pub fn mount_overlay(lower: &str, upper: &str, target: &str) -> bool {
+    let dir = |path: &str| {
+        let inode = open_file(ROOT_INODE.clone(), path, OpenFlags::O_RDONLY)?.inode();
+        file::cast_inode_to_file(inode.clone())?
+            .is_dir()
+            .then_some(inode)
+    };
+    let (Some(lower), Some(upper)) = (dir(lower), dir(upper)) else {
+        return false;
+    };
+    mounts()
+        .lock()
+        .mount(Arc::new(OverlayFS::new(lower, upper)), "overlay", target);
+    true
+}
+
+/// 进程看到的路径 `path` 对应的绝对路径，相对路径接在工作目录 `cwd` 之后。
+/// 工作目录的名字不是绝对路径时 (如经由 fchdir 进入的挂载中的目录) 返回 None
+pub fn absolute_path(cwd: &str, path: &str) -> Option<String> {
+    if !path.starts_with('/') && !cwd.starts_with('/') {
+        return None;
+    }
+    Some(Path::resolve(cwd, path).as_str().to_string())
+}
+
+/// 根目录为 `root` 的进程看到的绝对路径 `path` 在全局目录树中的路径，".." 不会越过 root
+pub fn chroot_path(root: &str, path: &str) -> String {
+    match Path::resolve("/", path).as_str() {
+        "/" => root.to_string(),
+        path => root.trim_end_matches('/').to_string() + path,
+    }
+}
+
+/// 根目录为 `root` 的进程在目录 `dir` 中打开 `path` 时使用的路径。chroot 之后相对路径先换成
+/// 进程看到的绝对路径，再由 [`try_open_file`] 限制在根目录之内；`dir` 的名字不是绝对路径时
+/// 返回 None，此时不能再按相对路径打开，否则 ".." 会越过根目录
+pub fn jailed_path(root: &str, dir: &str, path: String) -> Option<String> {
+    match root {
+        "/" => Some(path),
+        _ => absolute_path(dir, &path),
+    }
 }
 
 /// Open a file
 pub fn open_file(inode: Arc<dyn Inode>, name: &str, flags: OpenFlags) -> Option<Arc<Dentry>> {
-    // TODO: read_write
-    // let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::O_CREAT) {
-        if let Some(dentry) = inode.clone().lookup(name) {
-            // clear size
-            dentry.inode().clear();
-            Some(dentry)
-        } else {
-            // create file
+    try_open_file(inode, name, flags).ok()
+}
+
+/// 与 [`open_file`] 相同，失败时返回错误码：O_CREAT | O_EXCL 而文件已存在 (包括和其他任务
+/// 竞争创建失败) 时为 EEXIST，路径不存在时为 ENOENT
+pub fn try_open_file(
+    inode: Arc<dyn Inode>, name: &str, flags: OpenFlags,
+) -> Result<Arc<Dentry>, isize> {
+    // chroot 之后绝对路径从进程的根目录开始解析，返回的目录项仍使用进程看到的路径
+    let root = current_task().map(|task| task.root_path());
+    if let Some(root) = root.filter(|root| root != "/") {
+        if name.starts_with('/') {
+            let dentry = open_global(inode, &chroot_path(&root, name), flags)?;
+            return Ok(Arc::new(dentry.renamed(name)));
+        }
+        // 相对路径中的 ".." 可能越过根目录，调用者应当先用 jailed_path 换成绝对路径
+        if name.split('/').any(|part| part == "..") {
+            return Err(ENOENT);
+        }
+    }
+    open_global(inode, name, flags)
+}
+
+/// 在全局目录树中打开文件，绝对路径先按当前挂载命名空间的挂载表找到所在的文件系统
+fn open_global(inode: Arc<dyn Inode>, name: &str, flags: OpenFlags) -> Result<Arc<Dentry>, isize> {
+    // 绝对路径先按挂载点找到对应文件系统的根目录，根文件系统上的路径仍交给 ext4 自己解析
+    let mount = if name.starts_with('/') {
+        mounts()
+            .lock()
+            .find_mount(name)
+            .filter(|(mount_point, _, _)| mount_point.as_str() != "/")
+    } else {
+        None
+    };
+    let Some((_, mount, rest)) = mount else {
+        return open_in_dir(inode, name, flags);
+    };
+    // 经由挂载点打开的文件和目录持有挂载的引用，还有引用时不能卸载。
+    // 相对路径不经过挂载表，只由工作目录本身保持挂载
+    let pin = mount.pin();
+    if rest.is_empty() {
+        return Ok(Arc::new(Dentry::new(name, mount.root).pinned(pin)));
+    }
+    // 挂载的文件系统的 lookup 只接受单个文件名，先逐级找到最后一级所在的目录
+    let (dirs, last) = rest.rsplit_once('/').unwrap_or(("", rest.as_str()));
+    let mut dir = mount.root;
+    for part in dirs.split('/').filter(|part| !part.is_empty()) {
+        dir = dir.lookup(part).ok_or(ENOENT)?.inode();
+    }
+    let dentry = open_in_dir(dir, last, flags)?;
+    Ok(Arc::new(
+        Dentry::new(dentry.name(), dentry.inode()).pinned(pin),
+    ))
+}
+
+/// 在目录 inode 中打开名为 name 的文件，按 flags 创建或截断，失败时返回错误码
+fn open_in_dir(inode: Arc<dyn Inode>, name: &str, flags: OpenFlags) -> Result<Arc<Dentry>, isize> {
+    let dentry = match inode.clone().lookup(name) {
+        // O_CREAT | O_EXCL 要求文件原本不存在
+        Some(_) if flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL) => return Err(EEXIST),
+        Some(dentry) => dentry,
+        None if flags.contains(OpenFlags::O_CREAT) => {
             let type_ = if flags.contains(OpenFlags::O_DIRECTORY) {
                 InodeType::Directory
             } else {
                 InodeType::Regular
             };
-            let dentry = inode.create(name, type_)?;
-            Some(dentry)
-        }
-    } else if let Some(dentry) = inode.lookup(name) {
-        if flags.contains(OpenFlags::O_TRUNC) {
-            dentry.inode().clear();
+            // 文件系统的 create 在同一把锁下检查并插入，并发创建同一个文件时只有一个会成功；
+            // 新建的文件本来就是空的，不需要再截断
+            if let Some(dentry) = inode.clone().create(name, type_) {
+                return Ok(dentry);
+            }
+            // 创建失败而文件已存在，说明和其他任务竞争创建时输了，没有 O_EXCL 时打开它
+            return match inode.lookup(name) {
+                Some(_) if flags.contains(OpenFlags::O_EXCL) => Err(EEXIST),
+                Some(dentry) => Ok(dentry),
+                None => Err(ENOENT),
+            };
         }
-        Some(dentry)
-    } else {
-        None
+        None => return Err(ENOENT),
+    };
+    // 写权限只在打开时检查，之后 chmod 不影响已经打开的 fd；必须在截断之前检查
+    let (_, writable) = flags.read_write();
+    if writable && dentry.inode().mode() & 0o222 == 0 {
+        return Err(EACCES);
+    }
+    // 只有以可写方式打开已有的普通文件时才截断，单独的 O_CREAT 不会清空文件
+    if flags.contains(OpenFlags::O_TRUNC) && writable && !flags.contains(OpenFlags::O_DIRECTORY) {
+        dentry.inode().clear();
     }
+    Ok(dentry)
 }
 
+/// readv/writev 使用的用户缓冲区描述，与 Linux 的 struct iovec 布局相同
+#[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Iovec {
     pub iov_base: usize,
     pub iov_len:  usize,
@@ -0,0 +1,190 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use super::{
+    defs::{SEEK_CUR, SEEK_END, SEEK_SET},
+    dentry::Dentry,
+    file::{cast_inode_to_file, File},
+    inode::{Inode, Stat},
+    lock::release_flocks,
+};
+use crate::{
+    mm::PhysPageNum,
+    sync::mutex::{Mutex as _, MutexBlocking},
+    sysctl,
+};
+
+/// 打开的文件，记录 open 时的读写权限，其余操作都转发给底层的 inode
+///
+/// 普通文件的读写位置保存在这里，fork 和 dup 得到的 fd 共享同一个位置。
+/// read、write 和 lseek 在整个操作期间持有 `pos_lock`，共享位置的 fd 并发读写时不会读到同一段数据
+pub struct OSInode {
+    readable: bool,
+    writable: bool,
+    /// 是否是普通文件，只有普通文件按 offset 读写，设备等其他文件直接转发
+    seekable: bool,
+    offset:   Mutex<usize>,
+    /// 读写位置的睡眠锁，读写盘时可能睡眠，所以不能只靠 `offset` 的自旋锁
+    pos_lock: MutexBlocking,
+    /// O_APPEND：每次 write 前先移动到文件末尾
+    append:   AtomicBool,
+    /// 打开以来是否通过它写入过数据
+    written:  AtomicBool,
+    dentry:   Arc<Dentry>,
+    file:     Arc<dyn File>,
+}
+
+impl OSInode {
+    pub fn new(readable: bool, writable: bool, dentry: Arc<Dentry>) -> Self {
+        let file = cast_inode_to_file(dentry.inode()).unwrap();
+        let seekable = file.fstat().map_or(false, |stat| stat.is_file());
+        Self {
+            readable,
+            writable,
+            seekable,
+            offset: Mutex::new(0),
+            pos_lock: MutexBlocking::new(),
+            append: AtomicBool::new(false),
+            written: AtomicBool::new(false),
+            dentry,
+            file,
+        }
+    }
+
+    /// 设置 O_APPEND，在 open 和 fcntl(F_SETFL) 时调用
+    pub fn set_append(&self, append: bool) {
+        self.append.store(append, Ordering::Relaxed);
+    }
+
+    /// 当前的读写位置
+    pub fn offset(&self) -> usize {
+        *self.offset.lock()
+    }
+
+    /// 按 whence 移动读写位置，返回新的位置；whence 未知或结果为负时返回 None。
+    /// 允许移动到文件末尾之后，下次写入时中间留下空洞
+    pub fn lseek(&self, offset: isize, whence: usize) -> Option<usize> {
+        self.pos_lock.lock();
+        let new_pos = self.seek_locked(offset, whence);
+        self.pos_lock.unlock();
+        new_pos
+    }
+
+    /// 调用者需要持有 `pos_lock`
+    fn seek_locked(&self, offset: isize, whence: usize) -> Option<usize> {
+        let mut pos = self.offset.lock();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => *pos as isize,
+            SEEK_END => self.file.fstat()?.st_size as isize,
+            _ => return None,
+        };
+        let new_pos = base.checked_add(offset).filter(|pos| *pos >= 0)?;
+        *pos = new_pos as usize;
+        Some(*pos)
+    }
+
+    /// 在指定位置读取，不改变读写位置。不能按位置读写的文件返回 None
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Option<usize> {
+        self.seekable.then(|| self.inode().read_at(offset, buf))
+    }
+
+    /// 在指定位置写入，不改变读写位置。不能按位置读写的文件返回 None
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Option<usize> {
+        let write_size = self.seekable.then(|| self.inode().write_at(offset, buf))?;
+        self.mark_written(write_size);
+        Some(write_size)
+    }
+
+    fn mark_written(&self, write_size: usize) {
+        if write_size > 0 {
+            self.written.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// fd 关闭时调用：开启了 sysctl 的 fsync_on_close 且写过数据时，
+    /// 返回前把文件的数据和元数据写回设备
+    pub fn close(&self) {
+        if sysctl::fsync_on_close() && self.written.swap(false, Ordering::Relaxed) {
+            self.inode().fsync();
+        }
+    }
+
+    /// 打开时的目录项
+    pub fn dentry(&self) -> Arc<Dentry> {
+        Arc::clone(&self.dentry)
+    }
+
+    /// 底层的 inode
+    pub fn inode(&self) -> Arc<dyn Inode> {
+        self.dentry.inode()
+    }
+}
+
+impl Drop for OSInode {
+    /// 最后一个引用这个打开文件的 fd 关闭时释放它持有的 flock
+    fn drop(&mut self) {
+        release_flocks(self as *const Self as usize);
+    }
+}
+
+impl File for OSInode {
+    fn readable(&self) -> bool {
+        self.readable && self.file.readable()
+    }
+    fn writable(&self) -> bool {
+        self.writable && self.file.writable()
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        if !self.seekable {
+            return self.file.read(buf);
+        }
+        self.pos_lock.lock();
+        let pos = self.offset();
+        let read_size = self.inode().read_at(pos, buf);
+        *self.offset.lock() = pos + read_size;
+        self.pos_lock.unlock();
+        read_size
+    }
+    fn read_all(&self) -> Vec<u8> {
+        self.file.read_all()
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        if !self.seekable {
+            return self.file.write(buf);
+        }
+        self.pos_lock.lock();
+        // 移动到末尾和写入都在 pos_lock 内，其他共享位置的 fd 不会插在中间
+        let pos = match self.append.load(Ordering::Relaxed) {
+            true => self
+                .seek_locked(0, SEEK_END)
+                .unwrap_or_else(|| self.offset()),
+            false => self.offset(),
+        };
+        let write_size = self.inode().write_at(pos, buf);
+        *self.offset.lock() = pos + write_size;
+        self.pos_lock.unlock();
+        self.mark_written(write_size);
+        write_size
+    }
+    fn fstat(&self) -> Option<Stat> {
+        self.file.fstat()
+    }
+    fn is_dir(&self) -> bool {
+        self.file.is_dir()
+    }
+    fn hang_up(&self) -> bool {
+        self.file.hang_up()
+    }
+    fn r_ready(&self) -> bool {
+        self.file.r_ready()
+    }
+    fn w_ready(&self) -> bool {
+        self.file.w_ready()
+    }
+    fn mmap_frames(&self, offset: usize, len: usize) -> Option<Vec<PhysPageNum>> {
+        self.file.mmap_frames(offset, len)
+    }
+}
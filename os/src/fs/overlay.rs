@@ -0,0 +1,409 @@
+//! overlay: 把只读的下层目录和可写的上层目录合并成一个视图
+//!
+//! 查找时先找上层，上层没有时落到下层，上下两层的同名目录合并在一起。写操作只发生在上层：
+//! 第一次修改下层中的文件时，先把它连同所在的各级目录复制到上层 (copy-up)。
+//! 删除下层中也有的名字时，在上层的目录中留下 `.wh.<name>` 形式的 whiteout 标记，
+//! 合并视图中不再出现这个名字；删除后重新创建的目录中放一个 opaque 标记，下层同名目录的内容不再透出。
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use spin::Mutex;
+
+use super::{
+    dentry::Dentry,
+    file::{cast_inode_to_file, File},
+    fs::{FileSystem, FileSystemType},
+    inode::{Inode, InodeType, Stat},
+    tmpfs::inode_type,
+};
+
+/// whiteout 标记的名字前缀
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// 目录中有这个标记时，下层同名目录的内容被完全遮住
+const OPAQUE: &str = ".wh..wh..opq";
+
+fn whiteout_name(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+/// 合并视图中的文件或目录
+pub struct OverlayInode {
+    /// 合并视图中的父目录和自己的名字，根目录为 None
+    parent: Option<(Arc<OverlayInode>, String)>,
+    /// 上层中的 inode，只在下层的文件还没有 copy-up 时为 None
+    upper:  Mutex<Option<Arc<dyn Inode>>>,
+    /// 下层中的 inode
+    lower:  Option<Arc<dyn Inode>>,
+}
+
+impl OverlayInode {
+    fn new(
+        parent: Option<(Arc<OverlayInode>, String)>, upper: Option<Arc<dyn Inode>>,
+        lower: Option<Arc<dyn Inode>>,
+    ) -> Self {
+        Self {
+            parent,
+            upper: Mutex::new(upper),
+            lower,
+        }
+    }
+
+    /// 上层中的 inode。同一个文件可以被查找多次，经由别的 OverlayInode 完成的 copy-up
+    /// 这里也要能看到，所以没有记录时再到上层的父目录中找一次
+    fn upper(&self) -> Option<Arc<dyn Inode>> {
+        let mut upper = self.upper.lock();
+        if upper.is_none() {
+            if let Some((parent, name)) = &self.parent {
+                *upper = parent
+                    .upper()
+                    .and_then(|dir| dir.lookup(name))
+                    .map(|dentry| dentry.inode());
+            }
+        }
+        upper.clone()
+    }
+
+    /// 读操作使用的 inode：有上层时用上层，否则用下层
+    fn active(&self) -> Arc<dyn Inode> {
+        self.upper().or_else(|| self.lower.clone()).unwrap()
+    }
+
+    fn is_dir(&self) -> bool {
+        inode_type(&self.active()) == InodeType::Directory
+    }
+
+    /// 确保上层中有这个文件或目录，需要时逐级复制父目录以及文件的内容，返回上层的 inode
+    fn copy_up(&self) -> Option<Arc<dyn Inode>> {
+        if let Some(upper) = self.upper() {
+            return Some(upper);
+        }
+        let (parent, name) = self.parent.as_ref()?;
+        let dir = parent.copy_up()?;
+        let lower = self.lower.as_ref()?;
+        let upper = if inode_type(lower) == InodeType::Directory {
+            dir.create(name, InodeType::Directory)?.inode()
+        } else {
+            let upper = dir.create(name, InodeType::Regular)?.inode();
+            let data = lower.read_all();
+            if upper.write_at(0, &data) != data.len() {
+                return None;
+            }
+            upper
+        };
+        upper.chmod(lower.mode());
+        *self.upper.lock() = Some(upper.clone());
+        Some(upper)
+    }
+
+    /// 名字在这个目录中被删除过
+    fn whited_out(&self, name: &str) -> bool {
+        self.upper()
+            .is_some_and(|dir| dir.lookup(&whiteout_name(name)).is_some())
+    }
+
+    /// 下层目录的内容被上层完全遮住
+    fn opaque(&self) -> bool {
+        self.upper().is_some_and(|dir| dir.lookup(OPAQUE).is_some())
+    }
+
+    /// 合并视图中这个目录下的名字，被删除过或上下两层都没有时返回 None
+    fn child(self: &Arc<Self>, name: &str) -> Option<Arc<OverlayInode>> {
+        if name.starts_with(WHITEOUT_PREFIX) || self.whited_out(name) {
+            return None;
+        }
+        let upper = self
+            .upper()
+            .and_then(|dir| dir.lookup(name))
+            .map(|dentry| dentry.inode());
+        let lower = if self.opaque() {
+            None
+        } else {
+            self.lower
+                .clone()
+                .and_then(|dir| dir.lookup(name))
+                .map(|dentry| dentry.inode())
+        };
+        // 只有两层都是目录时才合并，否则上层遮住下层
+        let lower = match (&upper, lower) {
+            (Some(upper), Some(lower))
+                if inode_type(upper) != InodeType::Directory
+                    || inode_type(&lower) != InodeType::Directory =>
+            {
+                None
+            }
+            (_, lower) => lower,
+        };
+        if upper.is_none() && lower.is_none() {
+            return None;
+        }
+        let parent = Some((Arc::clone(self), name.to_string()));
+        Some(Arc::new(OverlayInode::new(parent, upper, lower)))
+    }
+
+    /// 从合并视图中删除 child：删除它在上层中的部分，下层中也有时留下 whiteout
+    fn remove_child(&self, name: &str, child: &OverlayInode) -> bool {
+        let Some(dir) = self.copy_up() else {
+            return false;
+        };
+        if let Some(upper) = child.upper() {
+            let removed = if child.is_dir() {
+                // 上层的目录中只剩下标记，先清掉它们才能删除
+                for marker in upper
+                    .ls()
+                    .into_iter()
+                    .filter(|name| name.starts_with(WHITEOUT_PREFIX))
+                {
+                    upper.clone().unlink(&marker);
+                }
+                dir.clone().rmdir(name)
+            } else {
+                dir.clone().unlink(name)
+            };
+            if !removed {
+                return false;
+            }
+        }
+        child.lower.is_none()
+            || dir
+                .create(&whiteout_name(name), InodeType::Regular)
+                .is_some()
+    }
+}
+
+impl Inode for OverlayInode {
+    fn fstype(&self) -> FileSystemType {
+        FileSystemType::OVERLAY
+    }
+    fn lookup(self: Arc<Self>, name: &str) -> Option<Arc<Dentry>> {
+        let name = name.trim_start_matches("./");
+        if name.is_empty() || name == "." {
+            return Some(Arc::new(Dentry::new(name, self)));
+        }
+        if !self.is_dir() {
+            return None;
+        }
+        let child = self.child(name)?;
+        Some(Arc::new(Dentry::new(name, child)))
+    }
+    fn create(self: Arc<Self>, name: &str, type_: InodeType) -> Option<Arc<Dentry>> {
+        if name.starts_with(WHITEOUT_PREFIX) || self.child(name).is_some() {
+            return None;
+        }
+        let dir = self.copy_up()?;
+        let whiteout = whiteout_name(name);
+        let recreated = dir.clone().lookup(&whiteout).is_some();
+        if recreated {
+            dir.clone().unlink(&whiteout);
+        }
+        let inode = dir.create(name, type_)?.inode();
+        if recreated && type_ == InodeType::Directory {
+            inode.clone().create(OPAQUE, InodeType::Regular)?;
+        }
+        let parent = Some((Arc::clone(&self), name.to_string()));
+        let child = OverlayInode::new(parent, Some(inode), None);
+        Some(Arc::new(Dentry::new(name, Arc::new(child))))
+    }
+    fn unlink(self: Arc<Self>, name: &str) -> bool {
+        match self.child(name) {
+            Some(child) if !child.is_dir() => self.remove_child(name, &child),
+            _ => false,
+        }
+    }
+    fn link(self: Arc<Self>, _name: &str, _target: Arc<Dentry>) -> bool {
+        false
+    }
+    /// 只支持重命名普通文件，目标名字不能已经存在
+    fn rename(self: Arc<Self>, old_name: &str, new_name: &str) -> bool {
+        let Some(child) = self.child(old_name) else {
+            return false;
+        };
+        if child.is_dir() || new_name.starts_with(WHITEOUT_PREFIX) || self.child(new_name).is_some()
+        {
+            return false;
+        }
+        let (Some(_), Some(dir)) = (child.copy_up(), self.copy_up()) else {
+            return false;
+        };
+        dir.clone().unlink(&whiteout_name(new_name));
+        if !dir.clone().rename(old_name, new_name) {
+            return false;
+        }
+        child.lower.is_none()
+            || dir
+                .create(&whiteout_name(old_name), InodeType::Regular)
+                .is_some()
+    }
+    fn mkdir(self: Arc<Self>, name: &str) -> bool {
+        self.create(name, InodeType::Directory).is_some()
+    }
+    fn rmdir(self: Arc<Self>, name: &str) -> bool {
+        match self.child(name) {
+            Some(child) if child.is_dir() && child.ls().is_empty() => {
+                self.remove_child(name, &child)
+            }
+            _ => false,
+        }
+    }
+    fn ls(&self) -> Vec<String> {
+        self.ls_typed().into_iter().map(|(name, _)| name).collect()
+    }
+    fn ls_typed(&self) -> Vec<(String, InodeType)> {
+        let mut entries = BTreeMap::new();
+        if let Some(lower) = self.lower.as_ref().filter(|_| !self.opaque()) {
+            entries.extend(lower.ls_typed());
+        }
+        if let Some(upper) = self.upper() {
+            for (name, type_) in upper.ls_typed() {
+                match name.strip_prefix(WHITEOUT_PREFIX) {
+                    Some(hidden) => {
+                        entries.remove(hidden);
+                    }
+                    None => {
+                        entries.insert(name, type_);
+                    }
+                }
+            }
+        }
+        entries.into_iter().collect()
+    }
+    fn clear(&self) {
+        if let Some(upper) = self.copy_up() {
+            upper.clear();
+        }
+    }
+    fn mode(&self) -> u32 {
+        self.active().mode()
+    }
+    fn chmod(&self, mode: u32) -> bool {
+        self.copy_up().is_some_and(|upper| upper.chmod(mode))
+    }
+    fn truncate(&self, size: usize) -> bool {
+        self.copy_up().is_some_and(|upper| upper.truncate(size))
+    }
+    fn fsync(&self) {
+        if let Some(upper) = self.upper() {
+            upper.fsync();
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.active().read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.copy_up()
+            .map_or(0, |upper| upper.write_at(offset, buf))
+    }
+    fn ino(&self) -> usize {
+        self.active().ino()
+    }
+    fn read_all(&self) -> Vec<u8> {
+        self.active().read_all()
+    }
+}
+
+impl File for OverlayInode {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        !self.is_dir()
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        self.read_at(0, buf)
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Inode::read_all(self)
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        self.write_at(0, buf)
+    }
+    fn fstat(&self) -> Option<Stat> {
+        cast_inode_to_file(self.active())?.fstat()
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
+
+/// 由下层目录和上层目录合并成的文件系统
+pub struct OverlayFS {
+    root: Arc<OverlayInode>,
+}
+
+impl OverlayFS {
+    /// lower 和 upper 都必须是目录，lower 中的内容不会被修改
+    pub fn new(lower: Arc<dyn Inode>, upper: Arc<dyn Inode>) -> Self {
+        Self {
+            root: Arc::new(OverlayInode::new(None, Some(upper), Some(lower))),
+        }
+    }
+}
+
+impl FileSystem for OverlayFS {
+    fn fs_type(&self) -> FileSystemType {
+        FileSystemType::OVERLAY
+    }
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// 下层是只读使用的 FAT32 目录，上层是 tmpfs：读到下层的文件，修改后 copy-up 到上层，
+/// 下层的内容不变；删除下层的文件后在上层留下 whiteout，合并视图和目录列表中都看不到它
+#[allow(unused)]
+pub fn overlay_test() {
+    use super::{
+        fat32::{fs::Fat32FS, inode::test_image},
+        tmpfs::TmpFS,
+    };
+    use crate::block::{block_dev::BlockDevice, mem_dev::MemBlockDevice};
+
+    let bdev: Arc<dyn BlockDevice> = Arc::new(MemBlockDevice::from_image(test_image(16)));
+    let lower = Fat32FS::load(bdev).unwrap().root_inode();
+    let dir = lower
+        .clone()
+        .create("dir", InodeType::Directory)
+        .unwrap()
+        .inode();
+    let file = dir
+        .clone()
+        .create("file", InodeType::Regular)
+        .unwrap()
+        .inode();
+    file.write_at(0, b"lower");
+    lower.clone().create("gone", InodeType::Regular).unwrap();
+    let upper = Arc::new(TmpFS::new()).root_inode();
+    let root = Arc::new(OverlayFS::new(lower.clone(), upper.clone())).root_inode();
+
+    let merged = root.clone().lookup("dir").unwrap().inode();
+    let merged = merged.lookup("file").unwrap().inode();
+    assert_eq!(merged.read_all(), b"lower");
+    // 另一次查找得到的 inode 也要看到 copy-up 之后的内容
+    let again = root.clone().lookup("dir").unwrap().inode();
+    let again = again.lookup("file").unwrap().inode();
+    assert_eq!(merged.write_at(0, b"UP"), 2);
+    assert_eq!(again.read_all(), b"UPwer");
+    assert_eq!(file.read_all(), b"lower");
+    let copied = upper.clone().lookup("dir").unwrap().inode();
+    assert_eq!(copied.lookup("file").unwrap().inode().read_all(), b"UPwer");
+
+    assert!(root.clone().unlink("gone"));
+    assert!(root.clone().lookup("gone").is_none());
+    assert!(!root.ls().iter().any(|name| name == "gone"));
+    assert!(lower.clone().lookup("gone").is_some());
+    assert!(upper.clone().lookup(".wh.gone").is_some());
+    // 重新创建被删除的名字时去掉 whiteout，得到的是上层中的新文件
+    let recreated = root
+        .clone()
+        .create("gone", InodeType::Regular)
+        .unwrap()
+        .inode();
+    assert!(recreated.read_all().is_empty());
+    assert!(upper.lookup(".wh.gone").is_none());
+    info!("overlay_test passed!");
+}
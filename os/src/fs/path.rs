@@ -1,4 +1,4 @@
-use alloc::{borrow::ToOwned, string::String};
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Path {
@@ -11,12 +11,31 @@ impl Path {
             path: path.to_owned(),
         }
     }
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
     pub fn is_absolute(&self) -> bool {
         self.path.starts_with('/')
     }
     pub fn is_relative(&self) -> bool {
         !self.is_absolute()
     }
+    /// 按字面处理 `path` 中的 "."、".." 和多余的 '/'，得到以 '/' 开头的绝对路径。
+    /// 相对路径接在绝对路径 `cwd` 之后，".." 到达 "/" 后停在 "/"
+    pub fn resolve(cwd: &str, path: &str) -> Self {
+        let base = if path.starts_with('/') { "" } else { cwd };
+        let mut parts = Vec::new();
+        for part in base.split('/').chain(path.split('/')) {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                part => parts.push(part),
+            }
+        }
+        Self::new(&("/".to_owned() + &parts.join("/")))
+    }
 }
 
 impl From<&str> for Path {
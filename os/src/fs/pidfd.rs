@@ -0,0 +1,68 @@
+//! pidfd：指向一个进程的 fd
+//!
+//! 进程退出后 pidfd 变为可读，可以和其他 fd 一起 poll 等待进程结束；
+//! 也可以通过它向进程发送信号，不用担心 pid 已经被回收给别的进程。
+
+use alloc::{sync::Arc, vec::Vec};
+
+use super::{file::File, inode::Stat};
+use crate::task::{pid2process, TaskControlBlock};
+
+pub struct PidFd {
+    task: Arc<TaskControlBlock>,
+}
+
+impl PidFd {
+    pub fn new(task: Arc<TaskControlBlock>) -> Self {
+        Self { task }
+    }
+
+    /// 指向的进程的 pid
+    pub fn pid(&self) -> usize {
+        self.task.pid.0
+    }
+
+    /// 指向的进程，已经退出时返回 None
+    pub fn task(&self) -> Option<Arc<TaskControlBlock>> {
+        (!self.exited()).then(|| Arc::clone(&self.task))
+    }
+
+    /// 进程退出时会从 pid 表中移除，pid 之后可能分配给新的进程，所以要比较是否为同一个进程。
+    /// 不借用进程的 inner，poll 自己的 pidfd 时当前进程的 inner 已经被借用
+    pub fn exited(&self) -> bool {
+        pid2process(self.task.pid.0).map_or(true, |task| !Arc::ptr_eq(&task, &self.task))
+    }
+}
+
+impl File for PidFd {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, _buf: &[u8]) -> usize {
+        0
+    }
+    fn fstat(&self) -> Option<Stat> {
+        None
+    }
+    fn is_dir(&self) -> bool {
+        false
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+    fn r_ready(&self) -> bool {
+        self.exited()
+    }
+    fn w_ready(&self) -> bool {
+        false
+    }
+}
@@ -4,18 +4,18 @@ use alloc::{
 };
 
 use super::{file::File, inode::Stat};
-use crate::{mm::UserBuffer, sync::UPSafeCell, task::suspend_current_and_run_next, trap};
+use crate::{mm::UserBuffer, sync::SpSafeCell, task::suspend_current_and_run_next, trap};
 
 /// IPC pipe
 pub struct Pipe {
     readable: bool,
     writable: bool,
-    buffer:   Arc<UPSafeCell<PipeRingBuffer>>,
+    buffer:   Arc<SpSafeCell<PipeRingBuffer>>,
 }
 
 impl Pipe {
     /// create readable pipe
-    pub fn read_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+    pub fn read_end_with_buffer(buffer: Arc<SpSafeCell<PipeRingBuffer>>) -> Self {
         Self {
             readable: true,
             writable: false,
@@ -23,7 +23,7 @@ impl Pipe {
         }
     }
     /// create writable pipe
-    pub fn write_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+    pub fn write_end_with_buffer(buffer: Arc<SpSafeCell<PipeRingBuffer>>) -> Self {
         Self {
             readable: false,
             writable: true,
@@ -115,7 +115,7 @@ impl PipeRingBuffer {
 /// Return (read_end, write_end)
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     trace!("kernel: make_pipe");
-    let buffer = Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) });
+    let buffer = Arc::new(SpSafeCell::new(PipeRingBuffer::new()));
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     buffer
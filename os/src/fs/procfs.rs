@@ -0,0 +1,219 @@
+//! procfs: 挂载在 `/proc` 上的伪文件系统
+//!
+//! 目前只有 `/proc/self/mountinfo`，内容在每次读取时按读取者所在挂载命名空间的挂载表重新生成。
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use super::{
+    dentry::Dentry,
+    file::File,
+    fs::{FileSystem, FileSystemType},
+    inode::{Inode, InodeType, Stat, StatMode},
+    mounts,
+};
+
+/// procfs 中的目录，内容在创建时确定
+pub struct ProcDir {
+    entries: BTreeMap<&'static str, Arc<dyn Inode>>,
+}
+
+impl ProcDir {
+    fn new(entries: BTreeMap<&'static str, Arc<dyn Inode>>) -> Self {
+        Self { entries }
+    }
+}
+
+impl Inode for ProcDir {
+    fn fstype(&self) -> FileSystemType {
+        FileSystemType::PROC
+    }
+    fn lookup(self: Arc<Self>, name: &str) -> Option<Arc<Dentry>> {
+        let name = name.trim_start_matches("./");
+        if name.is_empty() || name == "." {
+            return Some(Arc::new(Dentry::new(name, self)));
+        }
+        let inode = self.entries.get(name)?.clone();
+        Some(Arc::new(Dentry::new(name, inode)))
+    }
+    fn create(self: Arc<Self>, _name: &str, _type_: InodeType) -> Option<Arc<Dentry>> {
+        None
+    }
+    fn unlink(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn link(self: Arc<Self>, _name: &str, _target: Arc<Dentry>) -> bool {
+        false
+    }
+    fn rename(self: Arc<Self>, _old_name: &str, _new_name: &str) -> bool {
+        false
+    }
+    fn mkdir(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn rmdir(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        self.entries.keys().map(|name| name.to_string()).collect()
+    }
+    fn clear(&self) {}
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+}
+
+impl File for ProcDir {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn write(&self, _buf: &[u8]) -> usize {
+        0
+    }
+    fn fstat(&self) -> Option<Stat> {
+        Some(Stat::new(
+            0,
+            0,
+            StatMode::DIR.bits() | 0o555,
+            2,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ))
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
+
+/// `/proc/self/mountinfo`，每次读取时重新生成
+pub struct MountInfo;
+
+impl Inode for MountInfo {
+    fn fstype(&self) -> FileSystemType {
+        FileSystemType::PROC
+    }
+    fn lookup(self: Arc<Self>, _name: &str) -> Option<Arc<Dentry>> {
+        None
+    }
+    fn create(self: Arc<Self>, _name: &str, _type_: InodeType) -> Option<Arc<Dentry>> {
+        None
+    }
+    fn unlink(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn link(self: Arc<Self>, _name: &str, _target: Arc<Dentry>) -> bool {
+        false
+    }
+    fn rename(self: Arc<Self>, _old_name: &str, _new_name: &str) -> bool {
+        false
+    }
+    fn mkdir(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn rmdir(self: Arc<Self>, _name: &str) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        Vec::new()
+    }
+    fn clear(&self) {}
+    fn mode(&self) -> u32 {
+        0o444
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let info = mounts().lock().mountinfo();
+        let info = info.as_bytes();
+        if offset >= info.len() {
+            return 0;
+        }
+        let len = buf.len().min(info.len() - offset);
+        buf[..len].copy_from_slice(&info[offset..offset + len]);
+        len
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn read_all(&self) -> Vec<u8> {
+        mounts().lock().mountinfo().into_bytes()
+    }
+}
+
+impl File for MountInfo {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        self.read_at(0, buf)
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Inode::read_all(self)
+    }
+    fn write(&self, _buf: &[u8]) -> usize {
+        0
+    }
+    /// 与 Linux 一样，大小总是 0，需要一直读到返回 0 为止
+    fn fstat(&self) -> Option<Stat> {
+        Some(Stat::new(
+            0,
+            0,
+            StatMode::FILE.bits() | 0o444,
+            1,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ))
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
+
+/// 进程信息文件系统
+pub struct ProcFS {
+    root: Arc<ProcDir>,
+}
+
+impl ProcFS {
+    pub fn new() -> Self {
+        let this: Arc<dyn Inode> = Arc::new(ProcDir::new(BTreeMap::from([(
+            "mountinfo",
+            Arc::new(MountInfo) as Arc<dyn Inode>,
+        )])));
+        Self {
+            root: Arc::new(ProcDir::new(BTreeMap::from([("self", this)]))),
+        }
+    }
+}
+
+impl FileSystem for ProcFS {
+    fn fs_type(&self) -> FileSystemType {
+        FileSystemType::PROC
+    }
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
@@ -0,0 +1,253 @@
+//! tmpfs: 数据全部保存在内存中的文件系统
+//!
+//! 目前只能通过 fsopen("tmpfs") / fsconfig / fsmount / move_mount 创建并挂载，
+//! 文件内容保存在 inode 的 Vec 中，文件系统的最后一个引用释放后全部丢失。
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use riscv::register::sstatus;
+use spin::Mutex;
+
+use super::{
+    dentry::Dentry,
+    file::{cast_inode_to_file, File},
+    fs::{FileSystem, FileSystemType},
+    inode::{Inode, InodeType, Stat, StatMode},
+};
+
+/// tmpfs 中的文件或目录
+pub struct TmpInode {
+    type_:    InodeType,
+    mode:     AtomicU32,
+    /// 普通文件的内容
+    data:     Mutex<Vec<u8>>,
+    /// 目录中的文件名到 inode 的映射
+    children: Mutex<BTreeMap<String, Arc<dyn Inode>>>,
+}
+
+impl TmpInode {
+    pub fn new(type_: InodeType) -> Self {
+        Self {
+            type_,
+            mode: AtomicU32::new(0o777),
+            data: Mutex::new(Vec::new()),
+            children: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        self.type_ == InodeType::Directory
+    }
+
+    /// 在目录中加入新的 inode，名字已经存在时返回 None
+    fn insert(&self, name: &str, type_: InodeType) -> Option<Arc<Dentry>> {
+        if !self.is_dir() {
+            return None;
+        }
+        let mut children = self.children.lock();
+        if children.contains_key(name) {
+            return None;
+        }
+        let inode: Arc<dyn Inode> = Arc::new(TmpInode::new(type_));
+        children.insert(name.to_string(), inode.clone());
+        Some(Arc::new(Dentry::new(name, inode)))
+    }
+
+    /// 从目录中删除名字，`dir` 指定被删除的必须是 (或不能是) 目录
+    fn remove(&self, name: &str, dir: bool) -> bool {
+        let mut children = self.children.lock();
+        let removable = match children.get(name) {
+            Some(inode) if dir => {
+                inode_type(inode) == InodeType::Directory && inode.ls().is_empty()
+            }
+            Some(inode) => inode_type(inode) != InodeType::Directory,
+            None => false,
+        };
+        if removable {
+            children.remove(name);
+        }
+        removable
+    }
+}
+
+/// 目录中保存的都是 TmpInode (硬链接的目标也必须来自 tmpfs)，类型从 stat 中取出
+pub(super) fn inode_type(inode: &Arc<dyn Inode>) -> InodeType {
+    match cast_inode_to_file(inode.clone()).and_then(|file| file.fstat()) {
+        Some(stat) if stat.is_dir() => InodeType::Directory,
+        _ => InodeType::Regular,
+    }
+}
+
+impl Inode for TmpInode {
+    fn fstype(&self) -> FileSystemType {
+        FileSystemType::TMPFS
+    }
+    fn lookup(self: Arc<Self>, name: &str) -> Option<Arc<Dentry>> {
+        let name = name.trim_start_matches("./");
+        if name.is_empty() || name == "." {
+            return Some(Arc::new(Dentry::new(name, self)));
+        }
+        let inode = self.children.lock().get(name)?.clone();
+        Some(Arc::new(Dentry::new(name, inode)))
+    }
+    fn create(self: Arc<Self>, name: &str, type_: InodeType) -> Option<Arc<Dentry>> {
+        self.insert(name, type_)
+    }
+    fn unlink(self: Arc<Self>, name: &str) -> bool {
+        self.remove(name, false)
+    }
+    fn link(self: Arc<Self>, name: &str, target: Arc<Dentry>) -> bool {
+        let inode = target.inode();
+        if !matches!(inode.fstype(), FileSystemType::TMPFS) || !self.is_dir() {
+            return false;
+        }
+        let mut children = self.children.lock();
+        if children.contains_key(name) {
+            return false;
+        }
+        children.insert(name.to_string(), inode);
+        true
+    }
+    fn rename(self: Arc<Self>, old_name: &str, new_name: &str) -> bool {
+        let mut children = self.children.lock();
+        match children.remove(old_name) {
+            Some(inode) => {
+                children.insert(new_name.to_string(), inode);
+                true
+            }
+            None => false,
+        }
+    }
+    fn mkdir(self: Arc<Self>, name: &str) -> bool {
+        self.insert(name, InodeType::Directory).is_some()
+    }
+    fn rmdir(self: Arc<Self>, name: &str) -> bool {
+        self.remove(name, true)
+    }
+    fn ls(&self) -> Vec<String> {
+        self.children.lock().keys().cloned().collect()
+    }
+    fn ls_typed(&self) -> Vec<(String, InodeType)> {
+        self.children
+            .lock()
+            .iter()
+            .map(|(name, inode)| (name.clone(), inode_type(inode)))
+            .collect()
+    }
+    fn clear(&self) {
+        self.data.lock().clear();
+    }
+    fn mode(&self) -> u32 {
+        self.mode.load(Ordering::Relaxed)
+    }
+    fn chmod(&self, mode: u32) -> bool {
+        self.mode.store(mode & 0o7777, Ordering::Relaxed);
+        true
+    }
+    fn truncate(&self, size: usize) -> bool {
+        if self.is_dir() {
+            return false;
+        }
+        self.data.lock().resize(size, 0);
+        true
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.data.lock();
+        if offset >= data.len() {
+            return 0;
+        }
+        let len = buf.len().min(data.len() - offset);
+        unsafe {
+            sstatus::set_sum();
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+            sstatus::clear_sum();
+        }
+        len
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        if self.is_dir() {
+            return 0;
+        }
+        let mut data = self.data.lock();
+        let end = offset + buf.len();
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        unsafe {
+            sstatus::set_sum();
+            data[offset..end].copy_from_slice(buf);
+            sstatus::clear_sum();
+        }
+        buf.len()
+    }
+    fn read_all(&self) -> Vec<u8> {
+        self.data.lock().clone()
+    }
+}
+
+impl File for TmpInode {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        !self.is_dir()
+    }
+    fn read(&self, buf: &mut [u8]) -> usize {
+        self.read_at(0, buf)
+    }
+    fn read_all(&self) -> Vec<u8> {
+        Inode::read_all(self)
+    }
+    fn write(&self, buf: &[u8]) -> usize {
+        self.write_at(0, buf)
+    }
+    fn fstat(&self) -> Option<Stat> {
+        let (type_, size) = if self.is_dir() {
+            (StatMode::DIR, 0)
+        } else {
+            (StatMode::FILE, self.data.lock().len())
+        };
+        Some(Stat::new(
+            0,
+            self.ino() as u64,
+            type_.bits() | self.mode(),
+            1,
+            0,
+            size as i64,
+            0,
+            0,
+            0,
+        ))
+    }
+    fn hang_up(&self) -> bool {
+        false
+    }
+}
+
+/// 内存文件系统
+pub struct TmpFS {
+    root: Arc<TmpInode>,
+}
+
+impl TmpFS {
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(TmpInode::new(InodeType::Directory)),
+        }
+    }
+}
+
+impl FileSystem for TmpFS {
+    fn fs_type(&self) -> FileSystemType {
+        FileSystemType::TMPFS
+    }
+    fn root_inode(self: Arc<Self>) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
@@ -0,0 +1,80 @@
+//! 脏元数据的定期写回
+//!
+//! 文件大小、属性等元数据修改后只留在内存中 (对 FAT32 来说是块缓存中目录项所在的块)，
+//! 崩溃时会丢失。修改元数据的文件系统把它登记到脏列表中，调度循环每隔
+//! sysctl 的 dirty_writeback_ms 毫秒做一次写回：先把块缓存中的数据块写到磁盘，
+//! 再写回脏列表中的元数据，保证磁盘上的文件大小不会覆盖到还没写下去的数据。
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::*;
+use spin::Mutex;
+
+use crate::{block::block_cache::block_cache_sync_all, sysctl, timer::get_time_ms};
+
+/// 在内存中被修改、还没写回磁盘的元数据
+pub trait DirtyMetadata: Send + Sync {
+    /// 把元数据写到磁盘
+    fn write_back(&self);
+}
+
+lazy_static! {
+    /// 等待写回的元数据，同一个对象只登记一次
+    static ref DIRTY_METADATA: Mutex<Vec<Arc<dyn DirtyMetadata>>> = Mutex::new(Vec::new());
+}
+
+/// 上一次写回的时间 (毫秒)
+static LAST_WRITEBACK_MS: AtomicUsize = AtomicUsize::new(0);
+/// 完成写回的次数
+static WRITEBACK_CYCLES: AtomicUsize = AtomicUsize::new(0);
+
+/// 登记被修改的元数据，等下一次写回
+pub fn mark_dirty(metadata: Arc<dyn DirtyMetadata>) {
+    let mut dirty = DIRTY_METADATA.lock();
+    if !dirty.iter().any(|item| Arc::ptr_eq(item, &metadata)) {
+        dirty.push(metadata);
+    }
+}
+
+/// 把元数据从脏列表中取出，返回它是否在列表中。调用者负责写回，
+/// 通常是需要立即写回的对象按自己的顺序 (先数据后元数据) 写
+pub fn take_dirty(metadata: &Arc<dyn DirtyMetadata>) -> bool {
+    let mut dirty = DIRTY_METADATA.lock();
+    match dirty.iter().position(|item| Arc::ptr_eq(item, metadata)) {
+        Some(index) => {
+            dirty.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 立即做一次写回：先写数据块，再写元数据
+pub fn writeback() {
+    block_cache_sync_all();
+    let dirty = core::mem::take(&mut *DIRTY_METADATA.lock());
+    dirty.iter().for_each(|metadata| metadata.write_back());
+    WRITEBACK_CYCLES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 距离上次写回超过 dirty_writeback_ms 时做一次写回，由调度循环在没有当前任务时调用。
+/// 多个 hart 同时到期时只有一个执行
+pub fn writeback_if_due() {
+    let now = get_time_ms();
+    let last = LAST_WRITEBACK_MS.load(Ordering::Relaxed);
+    if now < last + sysctl::dirty_writeback_ms() {
+        return;
+    }
+    if LAST_WRITEBACK_MS
+        .compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed)
+        .is_ok()
+    {
+        writeback();
+    }
+}
+
+/// 完成写回的次数
+pub fn writeback_cycles() -> usize {
+    WRITEBACK_CYCLES.load(Ordering::Relaxed)
+}
@@ -8,14 +8,14 @@ use crate::{sbi::shutdown, task::current_kstack_top};
 /// panic handler
 fn panic(info: &PanicInfo) -> ! {
     if let Some(location) = info.location() {
-        println!(
+        eprintln!(
             "[kernel] Panicked at {}:{} {}",
             location.file(),
             location.line(),
             info.message().unwrap()
         );
     } else {
-        println!("[kernel] Panicked: {}", info.message().unwrap());
+        eprintln!("[kernel] Panicked: {}", info.message().unwrap());
     }
     // unsafe {
     //     backtrace();
@@ -28,13 +28,13 @@ unsafe fn backtrace() {
     let mut fp: usize;
     let stop = current_kstack_top();
     asm!("mv {}, s0", out(reg) fp);
-    println!("---START BACKTRACE---");
+    eprintln!("---START BACKTRACE---");
     for i in 0..10 {
         if fp == stop {
             break;
         }
-        println!("#{}:ra={:#x}", i, *((fp - 8) as *const usize));
+        eprintln!("#{}:ra={:#x}", i, *((fp - 8) as *const usize));
         fp = *((fp - 16) as *const usize);
     }
-    println!("---END   BACKTRACE---");
+    eprintln!("---END   BACKTRACE---");
 }
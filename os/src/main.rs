@@ -52,13 +52,15 @@ pub mod mm;
 pub mod sbi;
 pub mod sync;
 pub mod syscall;
+pub mod sysctl;
 pub mod task;
 pub mod timer;
 pub mod trap;
 pub mod utils;
 
 use boards::{shutdown, CLOCK_FREQ};
-use config::{KERNEL_SPACE_OFFSET, MEMORY_END};
+use config::{KERNEL_SPACE_OFFSET, MAX_HARTS, MEMORY_END};
+use mm::KERNEL_SPACE;
 use riscv::register::satp;
 use sbi::console_putchar;
 use timer::{get_time, get_time_ms, sleep_ms};
@@ -109,6 +111,49 @@ pub fn fake_main() {
     }
 }
 
+#[no_mangle]
+pub fn fake_secondary_main() {
+    unsafe {
+        asm!("add sp, sp, {}", in(reg) KERNEL_SPACE_OFFSET << 12);
+        asm!("la t0, rust_main_secondary");
+        asm!("add t0, t0, {}", in(reg) KERNEL_SPACE_OFFSET << 12);
+        asm!("jalr zero, 0(t0)");
+    }
+}
+
+/// 通过 SBI HSM 唤醒其余的 hart，返回成功唤醒的个数
+#[cfg(feature = "qemu")]
+fn start_secondary_harts() -> usize {
+    extern "C" {
+        fn _start_secondary();
+    }
+    // 从 hart 在关闭分页的状态下启动，要给物理地址
+    let entry = _start_secondary as usize - (KERNEL_SPACE_OFFSET << 12);
+    (0..MAX_HARTS)
+        .filter(|&hart| hart != task::hart_id())
+        .filter(|&hart| sbi::hart_start(hart, entry, 0) == 0)
+        .count()
+}
+
+#[no_mangle]
+/// 从 hart 的入口：启动 hart 已经完成全局的初始化，这里只做每个 hart 自己的设置
+pub fn rust_main_secondary() -> ! {
+    KERNEL_SPACE.lock().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_ipi();
+    timer::set_next_trigger();
+    info!("hart {} online", task::hart_id());
+    task::hart_online();
+    mm::tlb::tlb_shootdown_test_secondary();
+    task::cpu::cpu_local_test_secondary();
+    syscall::getcpu_test();
+    loop {
+        task::run_tasks();
+        core::hint::spin_loop();
+    }
+}
+
 #[no_mangle]
 /// the rust entry-point of os
 pub fn rust_main() -> ! {
@@ -121,6 +166,7 @@ pub fn rust_main() -> ! {
     println!("[kernel] Hello, world!");
     logging::init();
     info!("logging init done");
+    console::emergency_print_test();
     let satp = satp::read();
     info!(" satp: {:#x}", satp.bits());
     #[cfg(feature = "visionfive2")]
@@ -128,29 +174,103 @@ pub fn rust_main() -> ! {
     #[cfg(feature = "qemu")]
     init_dtb(None);
     let machine_info = machine_info();
+    utils::random::init(machine_info.bootargs());
     #[cfg(feature = "visionfive2")]
     mm::init(machine_info.memory.end);
     #[cfg(feature = "qemu")]
     mm::init(MEMORY_END);
     info!("mm init done");
     mm::remap_test();
+    mm::cow_fork_test();
+    mm::mprotect_cow_test();
+    mm::lazy_alloc_test();
+    mm::heap_info_test();
+    mm::slab::slab_churn_test();
     info!("mm remap test done");
+    #[cfg(feature = "mm-stress")]
+    mm::stress::mm_stress_test();
+    fs::dev::fb::fb_mmap_test();
     trap::init();
     info!("trap init done");
+    trap::softirq::softirq_test();
+    trap::lazy_fp_test();
+    #[cfg(feature = "kernel-fault-test")]
+    trap::kernel_fault_test();
+    sync::sp::sp_safe_cell_test();
+    boards::device_init();
+    #[cfg(feature = "qemu")]
+    drivers::block::virtio_blk_irq_test(&drivers::block::BLOCK_DEVICE_IMPL);
+    info!("device init done");
+    block::block_cache::block_cache_async_test();
+    block::block_cache::block_cache_shard_test();
+    block::cached_dev::cached_device_test();
+    block::elevator::elevator_test();
+    #[cfg(feature = "heap-oom-test")]
+    mm::heap_oom_test();
+    fs::fat32_read_at_test();
+    fs::fat32_partial_write_test();
+    fs::fat32_file_size_test();
+    fs::fat32_unlink_test();
+    fs::fat32_write_grow_test();
+    fs::fat32_truncate_test();
+    fs::fat32_lfn_test();
+    fs::fat32_mkdir_test();
+    fs::fat32_writeback_test();
+    fs::fat32_fsync_on_close_test();
+    fs::fat32_append_test();
+    fs::fat32_fadvise_test();
+    fs::fat32_chmod_open_test();
+    fs::fat32_discard_test();
+    fs::fat32_fsck_test();
+    fs::fat32_crash_test();
+    fs::fat32_dcache_test();
+    fs::fat32_create_cost_test();
+    fs::fat32_negative_dentry_test();
+    fs::fat32_icache_lru_test();
+    fs::fat32_loop_test();
+    fs::overlay_test();
     trap::enable_timer_interrupt();
+    trap::enable_ipi();
     info!("timer interrupt enabled");
     timer::set_next_trigger();
     info!("timer set next trigger done");
+    task::idle_test();
     // for file in ALL_TASKS.iter() {
     //     task::add_file(file);
     //     task::run_tasks();
     // }
     info!("init file system");
     fs::init();
+    fs::dev::device_registry_test();
+    sync::futex::robust_futex_test();
+    sync::futex::futex_requeue_test();
+    sync::futex::futex_timeout_test();
+    sync::mutex::blocking::mutex_blocking_test();
+    sync::semaphore::semaphore_test();
+    block::block_cache::block_cache_wait_test();
+    task::stride_test();
+    task::sched_boost_test();
     info!("adding initproc");
     task::add_initproc();
+    #[cfg(feature = "qemu")]
+    task::smp_test(start_secondary_harts() + 1);
+    #[cfg(feature = "qemu")]
+    mm::tlb::tlb_shootdown_test();
+    task::cpu::cpu_local_test();
+    syscall::getcpu_test();
+    syscall::random_seed_test();
+    syscall::clone3_args_test();
+    syscall::syscall_table_test();
+    syscall::errno::errno_test();
     info!("running tasks");
     task::run_tasks();
+    info!("tasks ran on harts {:#b}", task::harts_ran_tasks());
+    // 多个 hart 在线时，用户任务应当被分散到不止一个 hart 上运行
+    #[cfg(feature = "smp-run-test")]
+    if task::online_harts() > 1 {
+        assert!(task::harts_ran_tasks().count_ones() > 1);
+        info!("smp_run_tasks_test passed!");
+    }
     println!("[kernel] All tasks finished successfully!");
     println!("[kernel] ChaOS is shutting down...");
     shutdown();
@@ -4,9 +4,10 @@ use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 
 use lazy_static::*;
+use spin::Mutex;
 
 use super::{PhysAddr, PhysPageNum};
-use crate::{config::MEMORY_END, mm::address::KernelAddr, sync::UPSafeCell};
+use crate::{config::MEMORY_END, mm::address::KernelAddr};
 
 /// tracker for physical page frame allocation and deallocation
 pub struct FrameTracker {
@@ -60,6 +61,13 @@ impl StackFrameAllocator {
         // trace!("last {} Physical Frames.", self.end - self.current);
     }
 }
+impl StackFrameAllocator {
+    /// 还能分配的页数，包括回收的页
+    fn free_count(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
+}
+
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
         Self {
@@ -116,8 +124,8 @@ impl FrameAllocator for StackFrameAllocator {
 type FrameAllocatorImpl = StackFrameAllocator;
 
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: Mutex<FrameAllocatorImpl> =
+        Mutex::new(FrameAllocatorImpl::new());
 }
 
 pub fn init_frame_allocator(memory_end: usize) {
@@ -136,7 +144,7 @@ pub fn init_frame_allocator(memory_end: usize) {
         "PhysAddr::from(MEMORY_END)={:?}",
         PhysAddr::from(memory_end)
     );
-    FRAME_ALLOCATOR.exclusive_access(file!(), line!()).init(
+    FRAME_ALLOCATOR.lock().init(
         PhysAddr::from(KernelAddr::from(ekernel as usize)).ceil(),
         PhysAddr::from(KernelAddr::from(memory_end)).floor(),
     );
@@ -144,17 +152,12 @@ pub fn init_frame_allocator(memory_end: usize) {
 
 /// Allocate a physical page frame in FrameTracker style
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access(file!(), line!())
-        .alloc()
-        .map(FrameTracker::new)
+    FRAME_ALLOCATOR.lock().alloc().map(FrameTracker::new)
 }
 
 /// Allocate n contiguous physical page frames in FrameTracker style
 pub fn frame_alloc_contiguous(num: usize) -> (Vec<FrameTracker>, PhysPageNum) {
-    let (frames, root_ppn) = FRAME_ALLOCATOR
-        .exclusive_access(file!(), line!())
-        .alloc_contiguous(num);
+    let (frames, root_ppn) = FRAME_ALLOCATOR.lock().alloc_contiguous(num);
     let frame_trackers: Vec<FrameTracker> = frames.iter().map(|&p| FrameTracker::new(p)).collect();
     (frame_trackers, root_ppn)
 }
@@ -162,9 +165,12 @@ pub fn frame_alloc_contiguous(num: usize) -> (Vec<FrameTracker>, PhysPageNum) {
 /// Deallocate a physical page frame with a given ppn
 pub fn frame_dealloc(ppn: PhysPageNum) {
     // debug!("dealloc a page: ppn={:#x}", ppn.0);
-    FRAME_ALLOCATOR
-        .exclusive_access(file!(), line!())
-        .dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
+}
+
+/// 空闲的物理页数
+pub fn frame_free_count() -> usize {
+    FRAME_ALLOCATOR.lock().free_count()
 }
 
 #[allow(unused)]
@@ -1,15 +1,98 @@
 //! The heap allocator.
 
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use buddy_system_allocator::LockedHeap;
 
-use crate::config::KERNEL_HEAP_SIZE;
+use super::slab;
+use crate::{block::block_cache::shrink_block_cache, config::KERNEL_HEAP_SIZE};
+
+/// 分配失败时先回收缓存再重试的堆分配器
+struct ReclaimingHeap(LockedHeap);
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: ReclaimingHeap = ReclaimingHeap(LockedHeap::empty());
+
+/// 因为分配失败而回收缓存的次数
+static RECLAIM_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+impl ReclaimingHeap {
+    fn alloc_from_heap(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .alloc(layout)
+            .map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+}
+
+unsafe impl GlobalAlloc for ReclaimingHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        loop {
+            // 固定大小的内核对象从 slab 缓存分配，缓存不够时再向堆申请整块 slab
+            let ptr = slab::alloc(layout, |slab| self.alloc_from_heap(slab))
+                .unwrap_or_else(|| self.alloc_from_heap(layout));
+            if !ptr.is_null() {
+                return ptr;
+            }
+            // 回收时会释放内存，必须先放开堆的锁；什么都没回收到才算真的不够
+            if reclaim() == 0 {
+                return null_mut();
+            }
+        }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !slab::dealloc(ptr, layout) {
+            self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+        }
+    }
+}
+
+/// 回收可以丢弃的缓存，返回回收的项数
+fn reclaim() -> usize {
+    RECLAIM_RUNS.fetch_add(1, Ordering::Relaxed);
+    shrink_block_cache()
+}
+
+/// 内核堆的使用情况，heap_info syscall 原样复制给用户
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HeapInfo {
+    /// 堆的总字节数
+    pub total:        usize,
+    /// 已分配的字节数，包括分配器按 2 的幂向上取整多出的部分
+    pub used:         usize,
+    /// 调用者实际请求的字节数
+    pub requested:    usize,
+    /// 空闲字节数
+    pub free:         usize,
+    /// 因为分配失败而回收缓存的次数
+    pub reclaim_runs: usize,
+}
+
+/// 读取内核堆当前的使用情况
+pub fn heap_info() -> HeapInfo {
+    let heap = HEAP_ALLOCATOR.0.lock();
+    HeapInfo {
+        total:        heap.stats_total_bytes(),
+        used:         heap.stats_alloc_actual(),
+        requested:    heap.stats_alloc_user(),
+        free:         heap.stats_total_bytes() - heap.stats_alloc_actual(),
+        reclaim_runs: RECLAIM_RUNS.load(Ordering::Relaxed),
+    }
+}
 
 #[alloc_error_handler]
-pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
-    panic!("Heap allocation error, layout = {:?}", layout);
+pub fn handle_alloc_error(layout: Layout) -> ! {
+    let info = heap_info();
+    panic!(
+        "Heap allocation error, layout = {:?}, heap: {} bytes total, {} allocated ({} requested), \
+         {} reclaim runs",
+        layout, info.total, info.used, info.requested, info.reclaim_runs
+    );
 }
 
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
@@ -17,9 +100,11 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .0
             .lock()
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
+    slab::init();
 }
 
 #[allow(unused)]
@@ -45,3 +130,66 @@ pub fn heap_test() {
     drop(v);
     println!("heap_test passed!");
 }
+
+/// 分配一块大内存后已用字节数增加，释放后回到原来的值
+#[allow(unused)]
+pub fn heap_info_test() {
+    use alloc::vec;
+
+    const SIZE: usize = 64 * 1024;
+    let before = heap_info();
+    assert_eq!(before.used + before.free, before.total);
+    assert_eq!(before.total, KERNEL_HEAP_SIZE);
+    let buffer = vec![0u8; SIZE];
+    let during = heap_info();
+    assert!(during.used >= before.used + SIZE);
+    assert!(during.requested >= before.requested + SIZE);
+    assert_eq!(during.free + during.used, during.total);
+    drop(buffer);
+    let after = heap_info();
+    assert_eq!(after.used, before.used);
+    assert_eq!(after.requested, before.requested);
+    info!("heap_info_test passed!");
+}
+
+/// 填满内核堆，确认分配失败之前会先回收块缓存，回收不到东西时才返回空指针
+#[allow(unused)]
+pub fn heap_oom_test() {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use crate::{
+        block::{
+            block_cache::{block_cached, get_block_cache},
+            block_dev::BlockDevice,
+            mem_dev::MemBlockDevice,
+        },
+        config::PAGE_SIZE,
+    };
+
+    const BLOCKS: usize = 16;
+    let dev: Arc<dyn BlockDevice> = Arc::new(MemBlockDevice::new(BLOCKS));
+    for block_id in 0..BLOCKS {
+        get_block_cache(block_id, Arc::clone(&dev));
+    }
+    let runs = RECLAIM_RUNS.load(Ordering::Relaxed);
+    // 先预留好保存指针的空间，堆满以后不能再让 Vec 扩容
+    let layout = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap();
+    let mut chunks: Vec<*mut u8> = Vec::with_capacity(KERNEL_HEAP_SIZE / PAGE_SIZE);
+    while chunks.len() < chunks.capacity() {
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+        if ptr.is_null() {
+            break;
+        }
+        chunks.push(ptr);
+    }
+    let full = chunks.len() < chunks.capacity();
+    let reclaimed = RECLAIM_RUNS.load(Ordering::Relaxed) > runs;
+    chunks
+        .drain(..)
+        .for_each(|ptr| unsafe { alloc::alloc::dealloc(ptr, layout) });
+    assert!(full);
+    assert!(reclaimed);
+    // 块缓存已经被丢掉
+    assert!((0..BLOCKS).all(|block_id| !block_cached(block_id, &dev)));
+    info!("heap_oom_test passed!");
+}
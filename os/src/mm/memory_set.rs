@@ -10,15 +10,18 @@ use alloc::{
 use core::{
     arch::asm,
     fmt::{Display, Formatter},
+    ops::RangeBounds,
     ptr,
 };
 
 use lazy_static::*;
 use riscv::register::{satp, sstatus};
+use spin::Mutex;
 
 use super::{
     config::*,
     frame_alloc,
+    tlb,
     translated_refmut,
     FrameTracker,
     PTEFlags,
@@ -39,14 +42,15 @@ use crate::{
         MMIO,
         PAGE_SIZE,
         PAGE_SIZE_BITS,
+        USER_STACK_GROW_GAP,
+        USER_STACK_MAX_SIZE,
         USER_STACK_SIZE,
         USER_TRAMPOLINE,
     },
-    fs::{defs::OpenFlags, ROOT_INODE},
+    fs::{defs::OpenFlags, inode::Inode, ROOT_INODE},
     mm::config::AT_PHENT,
-    sync::UPSafeCell,
-    syscall::errno::SUCCESS,
-    task::process::Flags,
+    syscall::errno::{EINVAL, ENOMEM, SUCCESS},
+    task::{current_task, process::Flags},
     utils::string::c_ptr_to_string,
 };
 
@@ -67,44 +71,110 @@ extern "C" {
 
 lazy_static! {
     /// The kernel's initial memory mapping(kernel address space)
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    pub static ref KERNEL_SPACE: Arc<Mutex<MemorySet>> =
+        Arc::new(Mutex::new(MemorySet::new_kernel()));
 }
 
 /// the kernel token
 pub fn kernel_token() -> usize {
-    KERNEL_SPACE.exclusive_access(file!(), line!()).token()
+    KERNEL_SPACE.lock().token()
+}
+
+/// 对当前任务的地址空间做 [`MemorySet::prefault`]，内核直接读写用户内存之前调用。
+///
+/// 内核态访问用户内存时不再处理缺页，懒分配、栈增长和写时复制都要在这里提前完成，
+/// 调用者不能持有当前任务 inner 的借用，持有时应直接调用 [`MemorySet::prefault`]
+pub fn prefault_user(start: usize, len: usize, write: bool) {
+    if let Some(task) = current_task() {
+        task.inner_exclusive_access(file!(), line!())
+            .memory_set
+            .prefault(start, len, write);
+    }
+}
+
+/// 懒分配区域的来源，决定缺页时页帧记在哪里以及映射的权限
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LazyKind {
+    /// brk 扩展的堆
+    Heap,
+    /// 匿名 mmap
+    Mmap,
+}
+
+impl LazyKind {
+    fn flags(self) -> PTEFlags {
+        match self {
+            Self::Heap => PTEFlags::U | PTEFlags::R | PTEFlags::W,
+            Self::Mmap => PTEFlags::U | PTEFlags::R | PTEFlags::W | PTEFlags::X,
+        }
+    }
+}
+
+/// 只登记了虚拟地址范围、第一次访问时才分配页帧的区域，起始页号是 lazy_regions 的键
+#[derive(Clone, Copy)]
+struct LazyRegion {
+    end:  VirtPageNum,
+    kind: LazyKind,
+}
+
+/// 主线程的用户栈：栈区 MapArea 的结束页号，以及栈最多能向下增长到的页号
+#[derive(Clone, Copy)]
+struct UserStack {
+    top:   VirtPageNum,
+    limit: VirtPageNum,
+}
+
+/// MAP_SHARED 映射的一页文件内容
+#[derive(Clone)]
+pub struct SharedFilePage {
+    pub inode:  Arc<dyn Inode>,
+    /// 这一页在文件中的偏移
+    pub offset: usize,
+    /// 映射时这一页中属于文件的字节数，超出文件末尾的部分不写回
+    pub len:    usize,
 }
 
 /// address space
 pub struct MemorySet {
     /// page table
-    pub page_table: PageTable,
+    pub page_table:        PageTable,
     /// areas
-    pub areas:      Vec<MapArea>,
+    pub areas:             Vec<MapArea>,
     /// heap
-    heap_area:      BTreeMap<VirtPageNum, FrameTracker>,
+    heap_area:             BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     // The memory area formed by mmap does not need to be modified
     // we can use MapArea in Vec to hold FramTracker
     // we set a fixed address as the start address for mmap_area
     // the virtual memorySet is big enough to use it that doesnt concern address conflicts
-    pub mmap_area:  BTreeMap<VirtPageNum, FrameTracker>,
+    pub mmap_area:         BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    // 设备 mmap 的页，物理页归设备所有，这里只记录映射关系
+    pub device_area:       BTreeMap<VirtPageNum, PhysPageNum>,
+    // MAP_SHARED 映射的文件页，页帧仍在 mmap_area 中，这里记录写回的位置
+    pub shared_file_pages: BTreeMap<VirtPageNum, SharedFilePage>,
+    // 堆和匿名 mmap 的懒分配区域，其中已经访问过的页的页帧在 heap_area 或 mmap_area 中
+    lazy_regions:          BTreeMap<VirtPageNum, LazyRegion>,
+    // 可以在缺页时向下增长的用户栈
+    user_stack:            Option<UserStack>,
     // mmap_base will never change
-    pub mmap_base:  VirtAddr,
+    pub mmap_base:         VirtAddr,
     // always aligh to PAGE_SIZE
-    pub mmap_end:   VirtAddr,
+    pub mmap_end:          VirtAddr,
 }
 
 impl MemorySet {
     /// Create a new empty `MemorySet`.
     pub fn new_bare() -> Self {
         Self {
-            page_table: PageTable::new(),
-            areas:      Vec::new(),
-            heap_area:  BTreeMap::new(),
-            mmap_area:  BTreeMap::new(),
-            mmap_base:  MMAP_BASE.into(),
-            mmap_end:   MMAP_BASE.into(),
+            page_table:        PageTable::new(),
+            areas:             Vec::new(),
+            heap_area:         BTreeMap::new(),
+            mmap_area:         BTreeMap::new(),
+            device_area:       BTreeMap::new(),
+            shared_file_pages: BTreeMap::new(),
+            lazy_regions:      BTreeMap::new(),
+            user_stack:        None,
+            mmap_base:         MMAP_BASE.into(),
+            mmap_end:          MMAP_BASE.into(),
         }
     }
     /// Create a new `MemorySet` with the same page table as the kernel.
@@ -116,6 +186,10 @@ impl MemorySet {
             areas: Vec::new(),
             heap_area: BTreeMap::new(),
             mmap_area: BTreeMap::new(),
+            device_area: BTreeMap::new(),
+            shared_file_pages: BTreeMap::new(),
+            lazy_regions: BTreeMap::new(),
+            user_stack: None,
             mmap_base: MMAP_BASE.into(),
             mmap_end: MMAP_BASE.into(),
         }
@@ -142,6 +216,20 @@ impl MemorySet {
             Some(data),
         );
     }
+    /// 映射主线程的用户栈 [bottom, top)，之后栈可以在缺页时由 [`MemorySet::handle_stack_fault`]
+    /// 向下增长，总大小不超过 USER_STACK_MAX_SIZE
+    pub fn insert_user_stack(&mut self, bottom: VirtAddr, top: VirtAddr) {
+        self.insert_framed_area(
+            bottom,
+            top,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        let max_grow = (USER_STACK_MAX_SIZE - USER_STACK_SIZE) / PAGE_SIZE;
+        self.user_stack = Some(UserStack {
+            top:   top.ceil(),
+            limit: VirtPageNum(bottom.floor().0 - max_grow),
+        });
+    }
     /// check if exist areas conflict with given virtial address
     pub fn is_conflict_with_va(&self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
         self.areas
@@ -172,9 +260,7 @@ impl MemorySet {
             area.unmap(&mut self.page_table);
             self.areas.remove(idx);
             warn!("remove area with start_vpn: {:#x}", start_vpn.0);
-            unsafe {
-                asm!("sfence.vma");
-            }
+            tlb::shootdown(self.token());
         }
     }
     /// Add a new MapArea into this MemorySet.
@@ -422,9 +508,10 @@ impl MemorySet {
         ));
 
         // map user stack with U flags
+        // 栈底之下留出栈向下增长的空间，最低处与 ELF 之间仍隔着一个保护页
         let max_end_va: VirtAddr = max_end_vpn.into();
         let mut user_stack_bottom: usize = max_end_va.into();
-        user_stack_bottom += PAGE_SIZE;
+        user_stack_bottom += PAGE_SIZE + USER_STACK_MAX_SIZE - USER_STACK_SIZE;
         let user_stack_top: usize = user_stack_bottom + USER_STACK_SIZE;
         debug!("user_stack_bottom: {:#x}", user_stack_bottom);
         let user_heap_base: usize = user_stack_top + PAGE_SIZE;
@@ -438,61 +525,235 @@ impl MemorySet {
         )
     }
     /// Create a new address space by copy code&data from a exited process's address space.
-    pub fn from_existed_user(user_space: &Self) -> Self {
+    ///
+    /// 用户可以访问的页不复制，父子共享同一个页帧，其中可写的页在两边都改为写时复制，
+    /// 第一次写入时由 [`MemorySet::handle_cow_fault`] 复制；trap 上下文等内核使用的页仍然立即复制
+    pub fn from_existed_user(user_space: &mut Self) -> Self {
         let mut memory_set = Self::new_process();
         // map trampoline
         // memory_set.map_trampoline();
         // copy mmap
         memory_set.mmap_end = user_space.mmap_end;
-        // copy data sections/trap_context/user_stack
+        let parent_table = &mut user_space.page_table;
+        // share data sections/user_stack, copy trap_context
         for area in user_space.areas.iter() {
             // skip kernel space, cause it's already mapped
             if area.vpn_range.get_start().0 > KERNEL_SPACE_OFFSET {
                 continue;
             }
+            if area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U) {
+                let mut new_area = MapArea::from_another(area);
+                for (vpn, frame) in area.data_frames.iter() {
+                    share_page(parent_table, &mut memory_set.page_table, *vpn, frame.ppn);
+                    new_area.data_frames.insert(*vpn, Arc::clone(frame));
+                }
+                memory_set.areas.push(new_area);
+                continue;
+            }
             let new_area = MapArea::from_another(area);
             memory_set.push(new_area, None);
             // copy data from another space
             for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                let src_ppn = parent_table.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn
                     .get_bytes_array()
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
         }
-        // copy heap_area
-        for (vpn, src_frame) in user_space.heap_area.iter() {
-            let dst_frame = frame_alloc().unwrap();
-            let dst_ppn = dst_frame.ppn;
-            memory_set
-                .page_table
-                .map(*vpn, dst_ppn, PTEFlags::U | PTEFlags::R | PTEFlags::W);
-            memory_set.heap_area.insert(*vpn, dst_frame);
-
-            let src_ppn = src_frame.ppn;
-            // copy data
-            dst_ppn
-                .get_bytes_array()
-                .copy_from_slice(src_ppn.get_bytes_array());
+        // share heap_area
+        for (vpn, frame) in user_space.heap_area.iter() {
+            share_page(parent_table, &mut memory_set.page_table, *vpn, frame.ppn);
+            memory_set.heap_area.insert(*vpn, Arc::clone(frame));
         }
-        // copy mmap_area
-        for (vpn, src_frame) in user_space.mmap_area.iter() {
-            let dst_frame = frame_alloc().unwrap();
-            let dst_ppn = dst_frame.ppn;
+        // share mmap_area
+        for (vpn, frame) in user_space.mmap_area.iter() {
+            if let Some(page) = user_space.shared_file_pages.get(vpn) {
+                // MAP_SHARED 的文件页父子共用，写入对双方都可见，不做写时复制
+                let flags = user_flags(parent_table.translate(*vpn).unwrap());
+                map_user_page(&mut memory_set.page_table, *vpn, frame.ppn, flags);
+                memory_set.page_table.clear_dirty(*vpn);
+                memory_set.shared_file_pages.insert(*vpn, page.clone());
+                memory_set.mmap_area.insert(*vpn, Arc::clone(frame));
+                continue;
+            }
+            share_page(parent_table, &mut memory_set.page_table, *vpn, frame.ppn);
+            memory_set.mmap_area.insert(*vpn, Arc::clone(frame));
+        }
+        // 还没有访问过的懒分配页在父子两边都是全 0，子进程第一次访问时分配自己的页帧
+        memory_set.lazy_regions = user_space.lazy_regions.clone();
+        memory_set.user_stack = user_space.user_stack;
+        // 设备映射与父进程共享同一组物理页
+        for (vpn, ppn) in user_space.device_area.iter() {
             memory_set
                 .page_table
-                .map(*vpn, dst_ppn, PTEFlags::U | PTEFlags::R | PTEFlags::W);
-            memory_set.mmap_area.insert(*vpn, dst_frame);
-
-            let src_ppn = src_frame.ppn;
-            // copy data
-            dst_ppn
-                .get_bytes_array()
-                .copy_from_slice(src_ppn.get_bytes_array());
+                .map(*vpn, *ppn, PTEFlags::U | PTEFlags::R | PTEFlags::W);
+            memory_set.device_area.insert(*vpn, *ppn);
         }
+        // 父进程的可写页变成了只读，其他 hart 上可能还缓存着可写的表项
+        tlb::shootdown(user_space.token());
         memory_set
     }
+    /// 处理对 `va` 的写入引起的缺页，不是写时复制的页返回 false。
+    ///
+    /// 页帧还被其他地址空间共享时复制一份换上，只剩自己引用时直接恢复写权限。
+    /// 共享的文件页映射时清除了 D 位，硬件不自动置位 D 的平台上第一次写入也会缺页，这里补上 D 位
+    pub fn handle_cow_fault(&mut self, va: VirtAddr) -> bool {
+        let vpn = va.floor();
+        let Some(pte) = self.page_table.translate(vpn).filter(|pte| pte.is_valid()) else {
+            return false;
+        };
+        if !pte.is_cow() {
+            if !pte.writable() || pte.is_dirty() || !self.shared_file_pages.contains_key(&vpn) {
+                return false;
+            }
+            self.page_table.map_allow_cover(vpn, pte.ppn(), pte.flags());
+            tlb::flush_local();
+            return true;
+        }
+        let Some(frame) = self.frame_slot(vpn) else {
+            return false;
+        };
+        if Arc::strong_count(frame) > 1 {
+            let Some(copy) = frame_alloc() else {
+                return false;
+            };
+            copy.ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(copy);
+        }
+        let ppn = frame.ppn;
+        self.page_table
+            .map_allow_cover(vpn, ppn, pte.flags() | PTEFlags::W);
+        tlb::flush_local();
+        true
+    }
+    /// 处理对懒分配区域中还没有分配的页的访问：分配一个清零的页帧并按区域的权限映射。
+    /// 不在任何懒分配区域中或已经映射的页返回 false，由调用者当作段错误处理
+    pub fn handle_lazy_fault(&mut self, va: VirtAddr) -> bool {
+        let vpn = va.floor();
+        if self.translate(vpn).is_some_and(|pte| pte.is_valid()) {
+            return false;
+        }
+        let Some(kind) = self.lazy_region(vpn) else {
+            return false;
+        };
+        let Some(frame) = frame_alloc() else {
+            return false;
+        };
+        self.page_table.map(vpn, frame.ppn, kind.flags());
+        match kind {
+            LazyKind::Heap => self.heap_area.insert(vpn, Arc::new(frame)),
+            LazyKind::Mmap => self.mmap_area.insert(vpn, Arc::new(frame)),
+        };
+        tlb::flush_local();
+        true
+    }
+    /// 处理栈底之下的缺页：低于当前栈底不超过 USER_STACK_GROW_GAP 页、又没有超过栈的增长上限时，
+    /// 把栈区向下扩展到缺页的页，新的页都是清零的。其他地址返回 false，由调用者当作段错误处理
+    pub fn handle_stack_fault(&mut self, va: VirtAddr) -> bool {
+        let Some(stack) = self.user_stack else {
+            return false;
+        };
+        let vpn = va.floor();
+        let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_end() == stack.top)
+        else {
+            return false;
+        };
+        let bottom = area.vpn_range.get_start();
+        if vpn >= bottom || vpn < stack.limit || bottom.0 - vpn.0 > USER_STACK_GROW_GAP {
+            return false;
+        }
+        area.prepend_to(&mut self.page_table, vpn);
+        tlb::flush_local();
+        true
+    }
+    /// 内核要直接读写 [start, start + len) 之前调用：懒分配区域和栈底之下还没有分配的页先分配，
+    /// 要写入时再复制写时复制的页，之后内核访问这段内存不会缺页。处理不了的地址原样保留，
+    /// 由之后的访问报错
+    pub fn prefault(&mut self, start: usize, len: usize, write: bool) {
+        if len == 0 {
+            return;
+        }
+        let mut vpn = VirtAddr::from(start).floor();
+        let end = VirtAddr::from(start.saturating_add(len)).ceil();
+        while vpn < end {
+            if !self.translate(vpn).is_some_and(|pte| pte.is_valid()) {
+                let _ = self.handle_lazy_fault(vpn.into()) || self.handle_stack_fault(vpn.into());
+            }
+            if write {
+                self.handle_cow_fault(vpn.into());
+            }
+            vpn.step();
+        }
+    }
+    /// `vpn` 所在的懒分配区域的来源
+    fn lazy_region(&self, vpn: VirtPageNum) -> Option<LazyKind> {
+        let (_, region) = self.lazy_regions.range(..=vpn).next_back()?;
+        (vpn < region.end).then_some(region.kind)
+    }
+    /// 把 [start, end) 登记为懒分配区域，与已有区域重叠的部分以新的为准，相邻的同类区域合并
+    fn add_lazy_region(&mut self, mut start: VirtPageNum, mut end: VirtPageNum, kind: LazyKind) {
+        if start >= end {
+            return;
+        }
+        self.remove_lazy_range(start, end);
+        if let Some((&prev_start, prev)) = self.lazy_regions.range(..start).next_back() {
+            if prev.end == start && prev.kind == kind {
+                self.lazy_regions.remove(&prev_start);
+                start = prev_start;
+            }
+        }
+        if let Some(next) = self.lazy_regions.get(&end).copied() {
+            if next.kind == kind {
+                self.lazy_regions.remove(&end);
+                end = next.end;
+            }
+        }
+        self.lazy_regions.insert(start, LazyRegion { end, kind });
+    }
+    /// 从懒分配区域中去掉 [start, end)，跨过边界的区域被截短或拆成两段
+    fn remove_lazy_range(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        let overlapping: Vec<(VirtPageNum, LazyRegion)> = self
+            .lazy_regions
+            .range(..end)
+            .filter(|(_, region)| region.end > start)
+            .map(|(region_start, region)| (*region_start, *region))
+            .collect();
+        for (region_start, region) in overlapping {
+            self.lazy_regions.remove(&region_start);
+            if region_start < start {
+                self.lazy_regions.insert(
+                    region_start,
+                    LazyRegion {
+                        end:  start,
+                        kind: region.kind,
+                    },
+                );
+            }
+            if region.end > end {
+                self.lazy_regions.insert(end, region);
+            }
+        }
+    }
+    /// 映射 `vpn` 的页帧
+    fn frame_slot(&mut self, vpn: VirtPageNum) -> Option<&mut Arc<FrameTracker>> {
+        if let Some(area) = self.areas.iter_mut().find(|area| {
+            area.map_type == MapType::Framed
+                && area.vpn_range.get_start() <= vpn
+                && vpn < area.vpn_range.get_end()
+        }) {
+            return area.data_frames.get_mut(&vpn);
+        }
+        if let Some(frame) = self.heap_area.get_mut(&vpn) {
+            return Some(frame);
+        }
+        self.mmap_area.get_mut(&vpn)
+    }
     /// Change page table by writing satp CSR Register.
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -501,6 +762,7 @@ impl MemorySet {
             satp::write(satp);
             asm!("sfence.vma");
         }
+        tlb::set_active_token(satp);
         let satp = satp::read();
         warn!("satp has been reset!! : {:#x}", satp.bits());
     }
@@ -509,6 +771,40 @@ impl MemorySet {
         self.page_table.translate(vpn)
     }
 
+    /// 内核直接写入用户页时使用，写时复制的页先复制出自己的一份，懒分配的页先分配
+    pub fn page_bytes_mut(&mut self, vpn: VirtPageNum) -> Option<&'static mut [u8]> {
+        self.prefault(VirtAddr::from(vpn).0, PAGE_SIZE, true);
+        let pte = self.translate(vpn).filter(|pte| pte.is_valid())?;
+        Some(pte.ppn().get_bytes_array())
+    }
+
+    /// 记录 `vpn` 是 MAP_SHARED 映射的文件页，清除 D 位，之后据此判断是否需要写回
+    pub fn add_shared_file_page(&mut self, vpn: VirtPageNum, page: SharedFilePage) {
+        self.page_table.clear_dirty(vpn);
+        self.shared_file_pages.insert(vpn, page);
+    }
+
+    /// 把 `vpns` 范围内被改写过的 MAP_SHARED 文件页写回文件，MAP_PRIVATE 的页不会出现在这里
+    pub fn writeback_shared_pages(&mut self, vpns: impl RangeBounds<VirtPageNum>) {
+        let mut written = false;
+        for (vpn, page) in self.shared_file_pages.range(vpns) {
+            let Some(pte) = self.page_table.translate(*vpn) else {
+                continue;
+            };
+            if !pte.is_valid() || !pte.is_dirty() {
+                continue;
+            }
+            page.inode
+                .write_at(page.offset, &pte.ppn().get_bytes_array()[..page.len]);
+            self.page_table.clear_dirty(*vpn);
+            written = true;
+        }
+        // 其他 hart 上缓存的表项中 D 位仍然是 1，之后的写入不会再置位
+        if written {
+            tlb::shootdown(self.token());
+        }
+    }
+
     ///Remove all `MapArea`
     pub fn recycle_data_pages(&mut self) {
         self.areas.clear();
@@ -545,30 +841,17 @@ impl MemorySet {
     }
 
     /// map new heap area
-    pub fn map_heap(&mut self, mut current_addr: VirtAddr, aim_addr: VirtAddr) -> isize {
-        // log!("[map_heap] start_addr = {:#x}, end_addr = {:#x}", current_addr.0, aim_addr.0);
-        loop {
-            if current_addr.0 >= aim_addr.0 {
-                break;
-            }
-            // We use BTreeMap to save FrameTracker which makes management quite easy
-            // alloc a new FrameTracker
-            let frame = frame_alloc().unwrap();
-            let ppn = frame.ppn;
-            let vpn: VirtPageNum = current_addr.floor();
-            // log!("[map_heap] map vpn = {:#x}, ppn = {:#x}", vpn.0, ppn.0);
-            self.page_table
-                .map(vpn, ppn, PTEFlags::U | PTEFlags::R | PTEFlags::W);
-            self.heap_area.insert(vpn, frame);
-            current_addr = VirtAddr::from(current_addr.0 + PAGE_SIZE);
-        }
+    ///
+    /// 只登记 [current_addr, aim_addr) 为懒分配区域，页帧在第一次访问时由
+    /// [`MemorySet::handle_lazy_fault`] 分配。current_addr 不对齐时它所在的页已经映射
+    pub fn map_heap(&mut self, current_addr: VirtAddr, aim_addr: VirtAddr) -> isize {
+        self.add_lazy_region(current_addr.ceil(), aim_addr.ceil(), LazyKind::Heap);
         0
     }
 
-    /// mmap
-    pub fn mmap(
-        &mut self, start_addr: usize, len: usize, offset: usize, context: Vec<u8>, flags: Flags,
-    ) -> isize {
+    /// mmap，只登记虚拟地址范围，页帧在第一次访问时分配并清零；
+    /// 文件的内容由调用者通过 [`MemorySet::page_bytes_mut`] 填入
+    pub fn mmap(&mut self, start_addr: usize, len: usize, flags: Flags) -> isize {
         let start_addr_align: usize;
         let end_addr_align: usize;
         if flags.contains(Flags::MAP_FIXED) && start_addr != 0 {
@@ -585,68 +868,40 @@ impl MemorySet {
             VirtAddr::from(start_addr_align).floor(),
             VirtAddr::from(end_addr_align).floor(),
         );
-        if flags.contains(Flags::MAP_FIXED) && start_addr != 0 {
-            // alloc memory
-            for vpn in vpn_range {
-                // let frame = frame_alloc().unwrap();
-                match self.mmap_area.get(&vpn) {
-                    Some(_) => {
-                        debug!("[mmap] vpn = {:#x} has been mapped, skip", vpn.0);
-                    }
-                    None => {
-                        let frame = frame_alloc().unwrap();
-                        let ppn = frame.ppn;
-                        self.mmap_area.insert(vpn, frame);
-                        self.page_table.map(
-                            vpn,
-                            ppn,
-                            PTEFlags::R | PTEFlags::W | PTEFlags::U | PTEFlags::X,
-                        );
-                    }
-                }
-            }
-        } else {
-            // alloc memory
-            for vpn in vpn_range {
-                let frame = frame_alloc().unwrap();
-                let ppn = frame.ppn;
-                self.mmap_area.insert(vpn, frame);
-                self.page_table.map(
-                    vpn,
-                    ppn,
-                    PTEFlags::R | PTEFlags::W | PTEFlags::U | PTEFlags::X,
-                );
-            }
-        }
+        // MAP_FIXED 覆盖的范围中已经映射的页保持不变，不会再缺页
+        self.add_lazy_region(vpn_range.get_start(), vpn_range.get_end(), LazyKind::Mmap);
         debug!(
-            "[mmap] context.len() = {}, offset = {}, len = {}",
-            context.len(),
-            offset,
-            len
+            "[mmap] start_addr_align = {:#x}, end_addr_align = {:#x}",
+            start_addr_align, end_addr_align
         );
+        start_addr_align as isize
+    }
 
-        // MAP_ANONYMOUS标志代表不与文件关联的匿名映射
-        if !flags.contains(Flags::MAP_ANONYMOUS) {
-            let mut start: usize = offset;
-            let mut current_vpn = vpn_range.get_start();
-            loop {
-                let src = &context[start..len.min(start + PAGE_SIZE)];
-                let dst = &mut self
-                    .page_table
-                    .translate(current_vpn)
-                    .unwrap()
-                    .ppn()
-                    .get_bytes_array()[..src.len()];
-                dst.copy_from_slice(src);
-                start += PAGE_SIZE;
-                if start >= len {
-                    break;
-                }
-                current_vpn.step();
-            }
+    /// 把设备提供的物理页映射到用户地址空间，地址的选取与 [`MemorySet::mmap`] 相同
+    pub fn mmap_device(
+        &mut self, start_addr: usize, len: usize, ppns: Vec<PhysPageNum>, flags: Flags,
+    ) -> isize {
+        let start_addr_align = if flags.contains(Flags::MAP_FIXED) && start_addr != 0 {
+            (start_addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+        } else {
+            (self.mmap_end.0 + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+        };
+        let end_addr_align = (start_addr_align + len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        if end_addr_align + PAGE_SIZE > self.mmap_end.0 {
+            self.mmap_end = (end_addr_align + PAGE_SIZE).into();
+        }
+        let mut vpn = VirtAddr::from(start_addr_align).floor();
+        self.remove_lazy_range(vpn, VirtAddr::from(end_addr_align).floor());
+        for ppn in ppns {
+            // MAP_FIXED 可能覆盖已有的 mmap 页
+            self.mmap_area.remove(&vpn);
+            self.page_table
+                .map_allow_cover(vpn, ppn, PTEFlags::R | PTEFlags::W | PTEFlags::U);
+            self.device_area.insert(vpn, ppn);
+            vpn.step();
         }
         debug!(
-            "[mmap] start_addr_align = {:#x}, end_addr_align = {:#x}",
+            "[mmap_device] start_addr_align = {:#x}, end_addr_align = {:#x}",
             start_addr_align, end_addr_align
         );
         start_addr_align as isize
@@ -660,9 +915,70 @@ impl MemorySet {
             VirtAddr::from(start_addr_align).floor(),
             VirtAddr::from(end_addr_align).floor(),
         );
+        self.remove_lazy_range(vpn_range.get_start(), vpn_range.get_end());
         for vpn in vpn_range {
-            self.mmap_area.remove(&vpn);
+            self.shared_file_pages.remove(&vpn);
+            if self.mmap_area.remove(&vpn).is_some() || self.device_area.remove(&vpn).is_some() {
+                self.page_table.unmap(vpn);
+            }
         }
+        // 其他 hart 上同一进程的线程可能还缓存着这些页的映射
+        tlb::shootdown(self.token());
+        SUCCESS
+    }
+
+    /// 把 [start, start + len) 中各页的 R/W/X 权限改为 `perm`，范围中有没有映射的页时返回 ENOMEM，
+    /// 懒分配区域中还没有访问过的页算作已经映射。
+    ///
+    /// 写时复制的页和页帧还被其他地址空间共享的页不直接打开写权限，而是重新标记为写时复制，
+    /// 第一次写入时照常复制；去掉写权限时写时复制标记也一起去掉，之后的写入缺页不会被当作写时复制放行
+    pub fn mprotect(&mut self, start: usize, len: usize, perm: MapPermission) -> isize {
+        if start % PAGE_SIZE != 0 {
+            return EINVAL;
+        }
+        let vpn_range = VPNRange::new(
+            VirtAddr::from(start).floor(),
+            VirtAddr::from(start + len).ceil(),
+        );
+        // 先检查整个范围，不会只改了一部分才失败
+        let mapped = |pte: PageTableEntry| {
+            pte.is_valid() && (pte.flags().contains(PTEFlags::U) || pte.is_prot_none())
+        };
+        if vpn_range
+            .into_iter()
+            .any(|vpn| !self.translate(vpn).is_some_and(mapped) && self.lazy_region(vpn).is_none())
+        {
+            return ENOMEM;
+        }
+        // 懒分配区域中还没有访问过的页先分配，之后与其他页一样修改权限
+        for vpn in vpn_range {
+            if !self.translate(vpn).is_some_and(mapped) && !self.handle_lazy_fault(vpn.into()) {
+                return ENOMEM;
+            }
+        }
+        let perm = PTEFlags::from_bits((perm - MapPermission::U).bits).unwrap();
+        for vpn in vpn_range {
+            let pte = self.translate(vpn).unwrap();
+            let flags = (user_flags(pte) - (PTEFlags::R | PTEFlags::W | PTEFlags::X)) | perm;
+            // MAP_SHARED 的文件页本来就与其他地址空间共用同一个页帧
+            let shared = pte.is_cow()
+                || (!self.shared_file_pages.contains_key(&vpn)
+                    && self
+                        .frame_slot(vpn)
+                        .is_some_and(|frame| Arc::strong_count(frame) > 1));
+            if flags.contains(PTEFlags::W) && shared {
+                self.page_table
+                    .map_allow_cover(vpn, pte.ppn(), flags - PTEFlags::W);
+                self.page_table.set_cow(vpn);
+            } else {
+                map_user_page(&mut self.page_table, vpn, pte.ppn(), flags);
+            }
+            // 保留 D 位，共享的文件页据此判断是否需要写回
+            if !pte.is_dirty() {
+                self.page_table.clear_dirty(vpn);
+            }
+        }
+        tlb::shootdown(self.token());
         SUCCESS
     }
 
@@ -876,9 +1192,43 @@ impl MemorySet {
     }
 }
 
+/// 表项中用户设置的权限 (U/R/W/X)，写时复制的页算作可写
+fn user_flags(pte: PageTableEntry) -> PTEFlags {
+    if pte.is_prot_none() {
+        return PTEFlags::U;
+    }
+    let mut flags = pte.flags() & (PTEFlags::U | PTEFlags::R | PTEFlags::W | PTEFlags::X);
+    if pte.is_cow() {
+        flags |= PTEFlags::W;
+    }
+    flags
+}
+
+/// fork 时让子地址空间共享父地址空间的一页，权限与父地址空间中的表项相同 (可能被 mprotect 改过)，
+/// 可写的页在两边都改为写时复制
+fn share_page(parent: &mut PageTable, child: &mut PageTable, vpn: VirtPageNum, ppn: PhysPageNum) {
+    let flags = user_flags(parent.translate(vpn).unwrap());
+    if flags.contains(PTEFlags::W) {
+        parent.set_cow(vpn);
+        child.map_cow(vpn, ppn, flags);
+    } else {
+        map_user_page(child, vpn, ppn, flags);
+    }
+}
+
+/// 按 `flags` 映射一个用户页，没有 R/W/X 权限时映射为 PROT_NONE 的页
+fn map_user_page(page_table: &mut PageTable, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+    if (flags & (PTEFlags::R | PTEFlags::W | PTEFlags::X)).is_empty() {
+        page_table.map_prot_none(vpn, ppn);
+    } else {
+        page_table.map_allow_cover(vpn, ppn, flags);
+    }
+}
+
 pub struct MapArea {
     pub vpn_range:   VPNRange,
-    pub data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// fork 之后可能与其他地址空间共享，最后一个引用释放时归还页帧
+    pub data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     pub map_type:    MapType,
     pub map_perm:    MapPermission,
 }
@@ -914,7 +1264,7 @@ impl MapArea {
             MapType::Framed => {
                 let frame = frame_alloc().unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
@@ -964,6 +1314,13 @@ impl MapArea {
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
+    /// 向下扩展到 new_start，用于用户栈的增长
+    pub fn prepend_to(&mut self, page_table: &mut PageTable, new_start: VirtPageNum) {
+        for vpn in VPNRange::new(new_start, self.vpn_range.get_start()) {
+            self.map_one(page_table, vpn);
+        }
+        self.vpn_range = VPNRange::new(new_start, self.vpn_range.get_end());
+    }
     #[allow(unused)]
     pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
         for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
@@ -1029,7 +1386,7 @@ bitflags! {
 /// test map function in page table
 #[allow(unused)]
 pub fn remap_test() {
-    let mut kernel_space = KERNEL_SPACE.exclusive_access(file!(), line!());
+    let mut kernel_space = KERNEL_SPACE.lock();
     let mid_text: VirtAddr = (stext as usize + (etext as usize - stext as usize) / 2).into();
     let mid_rodata: VirtAddr =
         (srodata as usize + (erodata as usize - srodata as usize) / 2).into();
@@ -1056,6 +1413,162 @@ pub fn remap_test() {
     info!("remap_test passed!");
 }
 
+/// fork 之后父子共享页帧且都不可写；子进程写入时复制出自己的一页，父进程的内容不变，
+/// 父进程再写入时页帧只剩自己引用，直接恢复写权限。两个地址空间释放后页帧全部归还
+#[allow(unused)]
+pub fn cow_fork_test() {
+    use super::frame_free_count;
+
+    let baseline = frame_free_count();
+    let mut parent = MemorySet::new_process();
+    let flags = Flags::MAP_PRIVATE | Flags::MAP_ANONYMOUS;
+    let va = VirtAddr::from(parent.mmap(0, PAGE_SIZE, flags) as usize);
+    let vpn = va.floor();
+    let bytes = |ms: &MemorySet| ms.translate(vpn).unwrap().ppn().get_bytes_array();
+    parent.page_bytes_mut(vpn).unwrap().fill(1);
+
+    let mut child = MemorySet::from_existed_user(&mut parent);
+    let (parent_pte, child_pte) = (
+        parent.translate(vpn).unwrap(),
+        child.translate(vpn).unwrap(),
+    );
+    assert_eq!(parent_pte.ppn(), child_pte.ppn());
+    assert!(parent_pte.is_cow() && !parent_pte.writable());
+    assert!(child_pte.is_cow() && !child_pte.writable());
+
+    let free = frame_free_count();
+    assert!(child.handle_cow_fault(va));
+    assert_eq!(frame_free_count(), free - 1);
+    let child_pte = child.translate(vpn).unwrap();
+    assert!(!child_pte.is_cow() && child_pte.writable());
+    assert_ne!(child_pte.ppn(), parent_pte.ppn());
+    bytes(&child).fill(2);
+    assert!(bytes(&parent).iter().all(|&byte| byte == 1));
+    // 已经可写的页不再当作写时复制处理
+    assert!(!child.handle_cow_fault(va));
+
+    assert!(parent.handle_cow_fault(va));
+    assert_eq!(frame_free_count(), free - 1);
+    assert_eq!(parent.translate(vpn).unwrap().ppn(), parent_pte.ppn());
+    assert!(parent.translate(vpn).unwrap().writable());
+
+    drop(child);
+    drop(parent);
+    assert_eq!(frame_free_count(), baseline);
+    info!("cow_fork_test passed!");
+}
+
+/// mprotect 去掉写时复制页的写权限后，写入缺页不会被当作写时复制放行；
+/// 重新打开写权限时页帧仍与父地址空间共享，恢复为写时复制而不是直接可写
+#[allow(unused)]
+pub fn mprotect_cow_test() {
+    let mut parent = MemorySet::new_process();
+    let flags = Flags::MAP_PRIVATE | Flags::MAP_ANONYMOUS;
+    let va = VirtAddr::from(parent.mmap(0, 2 * PAGE_SIZE, flags) as usize);
+    let vpn = va.floor();
+    let bytes = |ms: &MemorySet| ms.translate(vpn).unwrap().ppn().get_bytes_array();
+    parent.page_bytes_mut(vpn).unwrap().fill(1);
+    let mut child = MemorySet::from_existed_user(&mut parent);
+
+    let (r, rw) = (
+        MapPermission::U | MapPermission::R,
+        MapPermission::U | MapPermission::R | MapPermission::W,
+    );
+    assert_eq!(child.mprotect(va.0, PAGE_SIZE, r), SUCCESS);
+    let pte = child.translate(vpn).unwrap();
+    assert!(!pte.is_cow() && !pte.writable() && pte.readable());
+    assert!(!child.handle_cow_fault(va));
+
+    assert_eq!(child.mprotect(va.0, PAGE_SIZE, rw), SUCCESS);
+    let pte = child.translate(vpn).unwrap();
+    assert!(pte.is_cow() && !pte.writable());
+    assert!(child.handle_cow_fault(va));
+    bytes(&child).fill(2);
+    assert!(bytes(&parent).iter().all(|&byte| byte == 1));
+
+    // 只剩自己引用的页直接可写；PROT_NONE 的页用户不能访问，fork 之后仍然如此
+    assert_eq!(child.mprotect(va.0, PAGE_SIZE, rw), SUCCESS);
+    assert!(child.translate(vpn).unwrap().writable());
+    assert_eq!(child.mprotect(va.0, PAGE_SIZE, MapPermission::U), SUCCESS);
+    let pte = child.translate(vpn).unwrap();
+    assert!(pte.is_prot_none() && !pte.flags().contains(PTEFlags::U));
+    let grandchild = MemorySet::from_existed_user(&mut child);
+    assert!(grandchild.translate(vpn).unwrap().is_prot_none());
+
+    assert_eq!(child.mprotect(va.0 + 1, PAGE_SIZE, r), EINVAL);
+    assert_eq!(child.mprotect(va.0, 3 * PAGE_SIZE, r), ENOMEM);
+    // 失败时不修改任何一页
+    assert!(child.translate(vpn).unwrap().is_prot_none());
+    info!("mprotect_cow_test passed!");
+}
+
+/// 堆和匿名 mmap 只登记范围：映射时不占用页帧，第一次访问的页分配一个清零的页帧，
+/// 范围之外和 munmap 之后的访问不会被当作懒分配处理；fork 之后子地址空间同样懒分配
+#[allow(unused)]
+pub fn lazy_alloc_test() {
+    use super::frame_free_count;
+
+    const PAGES: usize = 64;
+    let baseline = frame_free_count();
+    let mut ms = MemorySet::new_process();
+    let flags = Flags::MAP_PRIVATE | Flags::MAP_ANONYMOUS;
+    let free = frame_free_count();
+    let start = VirtAddr::from(ms.mmap(0, PAGES * PAGE_SIZE, flags) as usize);
+    // 模拟的堆起始地址，位于 mmap 区域之下
+    let heap = VirtAddr::from(0x1000_0000);
+    ms.map_heap(heap, VirtAddr::from(heap.0 + PAGES * PAGE_SIZE));
+    assert_eq!(frame_free_count(), free);
+    assert!(ms
+        .translate(start.floor())
+        .map_or(true, |pte| !pte.is_valid()));
+
+    let va = VirtAddr::from(start.0 + 3 * PAGE_SIZE + 8);
+    assert!(ms.handle_lazy_fault(va));
+    assert_eq!(frame_free_count(), free - 1);
+    assert!(ms
+        .translate(va.floor())
+        .unwrap()
+        .ppn()
+        .get_bytes_array()
+        .iter()
+        .all(|&b| b == 0));
+    // 已经分配的页和区域之外的地址都不是懒分配缺页
+    assert!(!ms.handle_lazy_fault(va));
+    assert!(!ms.handle_lazy_fault(VirtAddr::from(start.0 + PAGES * PAGE_SIZE)));
+    assert!(ms.handle_lazy_fault(VirtAddr::from(heap.0 + (PAGES - 1) * PAGE_SIZE)));
+    assert!(!ms.handle_lazy_fault(VirtAddr::from(heap.0 + PAGES * PAGE_SIZE)));
+    assert_eq!(frame_free_count(), free - 2);
+
+    // 子地址空间分配自己的页帧，父地址空间中这一页仍然没有分配
+    let second = VirtAddr::from(start.0 + PAGE_SIZE);
+    let mut child = MemorySet::from_existed_user(&mut ms);
+    assert!(child.handle_lazy_fault(second));
+    assert!(ms
+        .translate(second.floor())
+        .map_or(true, |pte| !pte.is_valid()));
+
+    // prefault 一次处理整段范围，读取时只分配懒分配的页，写入时还要复制写时复制的页
+    let free = frame_free_count();
+    ms.prefault(start.0 + 4 * PAGE_SIZE + 8, 2 * PAGE_SIZE, false);
+    assert_eq!(frame_free_count(), free - 3);
+    child.prefault(va.0, 1, true);
+    assert_ne!(
+        child.translate(va.floor()).unwrap().ppn(),
+        ms.translate(va.floor()).unwrap().ppn()
+    );
+
+    // munmap 的部分不再懒分配，区域的其余部分不受影响
+    ms.munmap(start.0, 2 * PAGE_SIZE);
+    assert!(!ms.handle_lazy_fault(second));
+    assert!(ms.handle_lazy_fault(VirtAddr::from(start.0 + 2 * PAGE_SIZE)));
+    assert!(child.handle_lazy_fault(start));
+
+    drop(child);
+    drop(ms);
+    assert_eq!(frame_free_count(), baseline);
+    info!("lazy_alloc_test passed!");
+}
+
 pub struct AuxHeader {
     pub _type: usize,
     pub value: usize,
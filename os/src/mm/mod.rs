@@ -12,13 +12,35 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+pub mod slab;
+#[cfg(feature = "mm-stress")]
+pub mod stress;
+pub mod tlb;
 
 use address::VPNRange;
 pub use address::{KernelAddr, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{frame_alloc, frame_alloc_contiguous, frame_dealloc, FrameTracker};
-pub use heap_allocator::init_heap;
-pub use memory_set::{kernel_token, remap_test, MapPermission, MemorySet, KERNEL_SPACE};
+pub use frame_allocator::{
+    frame_alloc,
+    frame_alloc_contiguous,
+    frame_dealloc,
+    frame_free_count,
+    FrameTracker,
+};
+pub use heap_allocator::{heap_info, heap_info_test, heap_oom_test, init_heap, HeapInfo};
+pub use memory_set::{
+    cow_fork_test,
+    kernel_token,
+    lazy_alloc_test,
+    mprotect_cow_test,
+    prefault_user,
+    remap_test,
+    MapPermission,
+    MemorySet,
+    SharedFilePage,
+    KERNEL_SPACE,
+};
 pub use page_table::{
+    copy_to_user,
     translated_byte_buffer,
     translated_ref,
     translated_refmut,
@@ -37,5 +59,5 @@ pub fn init(memory_end: usize) {
     debug!("frame allocator initialize");
     frame_allocator::init_frame_allocator(memory_end);
     debug!("kernel space initialize");
-    KERNEL_SPACE.exclusive_access(file!(), line!()).activate();
+    KERNEL_SPACE.lock().activate();
 }
@@ -1,10 +1,15 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 use alloc::{string::String, vec, vec::Vec};
+use core::{mem::size_of, slice};
 
 use bitflags::*;
+use riscv::register::satp;
 
 use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-use crate::{config::KERNEL_SPACE_OFFSET, mm::KERNEL_SPACE};
+use crate::{
+    config::{KERNEL_SPACE_OFFSET, PAGE_SIZE},
+    mm::{memory_set::prefault_user, KERNEL_SPACE},
+};
 
 bitflags! {
     /// page table entry flags
@@ -20,6 +25,12 @@ bitflags! {
     }
 }
 
+/// 表项中留给软件使用的 RSW 位之一，标记写时复制的页：写权限被暂时去掉，写入时复制一份
+const PTE_COW: usize = 1 << 8;
+/// RSW 中的另一位，标记 mprotect(PROT_NONE) 的用户页。叶子表项至少要有 R/W/X 中的一位，
+/// 这样的页映射为只有内核可读，用这一位与内核自己的页区分
+const PTE_PROT_NONE: usize = 1 << 9;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 /// page table entry structure
@@ -63,6 +74,18 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// 是写时复制的页？
+    pub fn is_cow(&self) -> bool {
+        self.bits & PTE_COW != 0
+    }
+    /// 是 mprotect(PROT_NONE) 的用户页？
+    pub fn is_prot_none(&self) -> bool {
+        self.bits & PTE_PROT_NONE != 0
+    }
+    /// 映射之后被写过？
+    pub fn is_dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
 }
 
 /// page table structure
@@ -110,11 +133,8 @@ impl PageTable {
 
         //to keep kernel part the same, we only first level of page table
         frame.ppn.get_pte_array()[kernel_root_vpn.indexes()[0]..].copy_from_slice(
-            &KERNEL_SPACE
-                .exclusive_access(file!(), line!())
-                .page_table
-                .root_ppn
-                .get_pte_array()[kernel_root_vpn.indexes()[0]..],
+            &KERNEL_SPACE.lock().page_table.root_ppn.get_pte_array()
+                [kernel_root_vpn.indexes()[0]..],
         );
 
         PageTable {
@@ -182,6 +202,30 @@ impl PageTable {
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V | PTEFlags::D | PTEFlags::A);
     }
 
+    /// 以写时复制的方式映射：去掉写权限并打上 COW 标记，第一次写入时由缺页处理恢复写权限
+    pub fn map_cow(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        self.map(vpn, ppn, flags - PTEFlags::W);
+        self.set_cow(vpn);
+    }
+
+    /// 把已经映射的可写页改为写时复制
+    pub fn set_cow(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte(vpn).unwrap();
+        pte.bits = (pte.bits & !(PTEFlags::W.bits as usize)) | PTE_COW;
+    }
+
+    /// 映射一个用户不能访问的页 (PROT_NONE)，允许覆盖原有的映射
+    pub fn map_prot_none(&mut self, vpn: VirtPageNum, ppn: PhysPageNum) {
+        self.map_allow_cover(vpn, ppn, PTEFlags::R);
+        self.find_pte(vpn).unwrap().bits |= PTE_PROT_NONE;
+    }
+
+    /// 清除 D 位，之后的写入由硬件 (或缺页处理) 重新置位
+    pub fn clear_dirty(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte(vpn).unwrap();
+        pte.bits &= !(PTEFlags::D.bits as usize);
+    }
+
     /// remove the map between virtual page number and physical page number
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
@@ -208,16 +252,33 @@ impl PageTable {
     }
 }
 
+/// 内核要访问 [start, start + len) 之前先处理懒分配、栈增长和写时复制。
+/// 只能处理当前正在使用的地址空间，其他地址空间中的页原样访问
+fn prefault(token: usize, start: usize, len: usize, write: bool) {
+    if token == satp::read().bits() {
+        prefault_user(start, len, write);
+    }
+}
+
+/// 内核要通过物理地址写入 `vpn` 所在的页，写时复制的页已经由 [`prefault`] 复制过。
+/// 和用户自己写入一样置上 D 位，共享的文件页据此判断是否需要写回
+fn writable_ppn(page_table: &PageTable, vpn: VirtPageNum) -> PhysPageNum {
+    let pte = page_table.find_pte(vpn).unwrap();
+    pte.bits |= PTEFlags::D.bits as usize;
+    pte.ppn()
+}
+
 /// Create mutable `Vec<u8>` slice in kernel space from ptr in other address space. NOTICE: the content pointed to by the pointer `ptr` can cross physical pages.
 pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
     let end = start + len;
+    prefault(token, start, len, true);
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = writable_ppn(&page_table, vpn);
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -231,12 +292,26 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
     v
 }
 
+/// 把 `value` 复制到其他地址空间中的 `ptr`，`ptr` 指向的内容可以跨过页的边界
+pub fn copy_to_user<T: Copy>(token: usize, ptr: *mut T, value: &T) {
+    let bytes = unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    let mut copied = 0;
+    for chunk in translated_byte_buffer(token, ptr as *const u8, bytes.len()) {
+        chunk.copy_from_slice(&bytes[copied..copied + chunk.len()]);
+        copied += chunk.len();
+    }
+}
+
 /// Create String in kernel address space from u8 Array(end with 0) in other address space
 pub fn translated_str(token: usize, ptr: *const u8) -> String {
     let page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
+        // 字符串的长度事先不知道，每进入一页先处理这一页的缺页
+        if va == ptr as usize || va % PAGE_SIZE == 0 {
+            prefault(token, va, 1, false);
+        }
         let ch: u8 = *VirtAddr::from(va).get_mut();
         if ch == 0 {
             break;
@@ -250,20 +325,19 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
 /// translate a pointer `ptr` in other address space to a immutable u8 slice in kernel address space. NOTICE: the content pointed to by the pointer `ptr` cannot cross physical pages, otherwise translated_byte_buffer should be used.
 pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
     let page_table = PageTable::from_token(token);
-    page_table
-        .translate_va(VirtAddr::from(ptr as usize))
-        .unwrap()
-        .get_ref()
+    let va = VirtAddr::from(ptr as usize);
+    prefault(token, va.0, size_of::<T>(), false);
+    page_table.translate_va(va).unwrap().get_ref()
 }
 
 /// translate a pointer `ptr` in other address space to a mutable u8 slice in kernel address space. NOTICE: the content pointed to by the pointer `ptr` cannot cross physical pages, otherwise translated_byte_buffer should be used.
 pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    let va = VirtAddr::from(ptr as usize);
+    prefault(token, va.0, size_of::<T>(), true);
+    let aligned_pa: PhysAddr = writable_ppn(&page_table, va.floor()).into();
+    let pa: PhysAddr = (usize::from(aligned_pa) + va.page_offset()).into();
+    pa.get_mut()
 }
 
 /// An abstraction over a buffer passed from user space to kernel space
@@ -0,0 +1,250 @@
+//! 固定大小内核对象的 slab 缓存
+//!
+//! 任务控制块、inode、dentry 等对象频繁地以相同大小分配和释放，直接交给伙伴分配器
+//! 会把堆切得很碎。这里为这些对象的布局各建一个缓存：对象从整块的 slab 中切出，
+//! 释放后挂到缓存的空闲链表上，下一次同样布局的分配直接复用。
+//! 全局分配器按 Layout 查找缓存，`Arc::new` 等调用方不需要改动。
+//! slab 从伙伴分配器申请后不再归还，缓存占用的内存只增不减，但不会超过对象数的峰值。
+
+use alloc::vec::Vec;
+use core::{alloc::Layout, mem::size_of, ptr::null_mut};
+
+use spin::Mutex;
+
+use crate::{
+    config::PAGE_SIZE,
+    fs::{dentry::Dentry, os_inode::OSInode, Fat32Inode},
+    task::TaskControlBlock,
+};
+
+/// 最多的缓存数
+const MAX_CACHES: usize = 8;
+/// 每个 slab 至少能切出的对象数
+const OBJECTS_PER_SLAB: usize = 8;
+/// slab 的最小字节数
+const MIN_SLAB_SIZE: usize = PAGE_SIZE;
+
+/// 与 alloc::sync::ArcInner 相同的布局，`Arc<T>` 实际分配的就是它
+#[repr(C)]
+struct ArcInner<T> {
+    strong: usize,
+    weak:   usize,
+    data:   T,
+}
+
+/// `Arc::new` 分配 T 时使用的布局
+fn arc_layout<T>() -> Layout {
+    Layout::new::<ArcInner<T>>()
+}
+
+/// 空闲对象的前 8 字节用来串成链表
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct SlabCache {
+    name:   &'static str,
+    /// 注册时的对象布局，分配请求的大小等于它且对齐不超过它时由这个缓存负责
+    object: Layout,
+    /// 实际切分时每个对象占用的大小，至少放得下一个 FreeObject
+    slot:   usize,
+    free:   *mut FreeObject,
+    /// 当前 slab 中还没有切出的部分
+    next:   usize,
+    end:    usize,
+    slabs:  usize,
+    total:  usize,
+    in_use: usize,
+}
+
+// 链表中的指针只在持有 CACHES 的锁时访问
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    fn new(name: &'static str, object: Layout) -> Self {
+        let object = object.pad_to_align();
+        let slot = object
+            .align_to(size_of::<FreeObject>())
+            .unwrap()
+            .pad_to_align()
+            .size();
+        Self {
+            name,
+            object,
+            slot,
+            free: null_mut(),
+            next: 0,
+            end: 0,
+            slabs: 0,
+            total: 0,
+            in_use: 0,
+        }
+    }
+
+    fn serves(&self, layout: Layout) -> bool {
+        layout.size() == self.object.size() && layout.align() <= self.object.align()
+    }
+
+    fn slab_layout(&self) -> Layout {
+        let size = (self.slot * OBJECTS_PER_SLAB)
+            .max(MIN_SLAB_SIZE)
+            .next_power_of_two();
+        Layout::from_size_align(size, self.object.align().max(size_of::<FreeObject>())).unwrap()
+    }
+
+    /// 优先复用空闲对象，否则从当前 slab 切一个，slab 用完时通过 `grow` 申请新的
+    fn alloc(&mut self, grow: impl FnOnce(Layout) -> *mut u8) -> *mut u8 {
+        if !self.free.is_null() {
+            let object = self.free;
+            self.free = unsafe { (*object).next };
+            self.in_use += 1;
+            return object as *mut u8;
+        }
+        if self.next + self.slot > self.end {
+            let layout = self.slab_layout();
+            let slab = grow(layout);
+            if slab.is_null() {
+                return null_mut();
+            }
+            self.next = slab as usize;
+            self.end = slab as usize + layout.size();
+            self.slabs += 1;
+        }
+        let object = self.next as *mut u8;
+        self.next += self.slot;
+        self.total += 1;
+        self.in_use += 1;
+        object
+    }
+
+    fn dealloc(&mut self, ptr: *mut u8) {
+        let object = ptr as *mut FreeObject;
+        unsafe { (*object).next = self.free };
+        self.free = object;
+        self.in_use -= 1;
+    }
+}
+
+const NO_CACHE: Option<SlabCache> = None;
+
+static CACHES: Mutex<[Option<SlabCache>; MAX_CACHES]> = Mutex::new([NO_CACHE; MAX_CACHES]);
+
+/// 为 `object` 布局的对象建立缓存，已有缓存负责这个布局时什么也不做
+pub fn register(name: &'static str, object: Layout) {
+    let mut caches = CACHES.lock();
+    if caches.iter().flatten().any(|cache| cache.serves(object)) {
+        return;
+    }
+    let slot = caches
+        .iter_mut()
+        .find(|cache| cache.is_none())
+        .expect("too many slab caches");
+    *slot = Some(SlabCache::new(name, object));
+}
+
+/// 为最常分配的几种内核对象建立缓存，需要在堆初始化之后、这些对象第一次分配之前调用
+pub fn init() {
+    register("task", arc_layout::<TaskControlBlock>());
+    register("fat32_inode", arc_layout::<Fat32Inode>());
+    register("dentry", arc_layout::<Dentry>());
+    register("os_inode", arc_layout::<OSInode>());
+}
+
+/// 由缓存分配，`layout` 不归任何缓存管理时返回 None；返回空指针表示申请新 slab 失败
+pub fn alloc(layout: Layout, grow: impl FnOnce(Layout) -> *mut u8) -> Option<*mut u8> {
+    let mut caches = CACHES.lock();
+    let cache = caches
+        .iter_mut()
+        .flatten()
+        .find(|cache| cache.serves(layout))?;
+    Some(cache.alloc(grow))
+}
+
+/// 把对象还给缓存，`layout` 不归任何缓存管理时返回 false
+pub fn dealloc(ptr: *mut u8, layout: Layout) -> bool {
+    let mut caches = CACHES.lock();
+    match caches
+        .iter_mut()
+        .flatten()
+        .find(|cache| cache.serves(layout))
+    {
+        Some(cache) => {
+            cache.dealloc(ptr);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 一个缓存的使用情况
+#[derive(Clone, Copy, Debug)]
+pub struct SlabStats {
+    pub name:        &'static str,
+    pub object_size: usize,
+    /// 申请的 slab 数
+    pub slabs:       usize,
+    /// 切出过的对象数
+    pub total:       usize,
+    /// 正在使用的对象数
+    pub in_use:      usize,
+}
+
+/// 各个缓存当前的使用情况
+pub fn slab_stats() -> Vec<SlabStats> {
+    // 先复制出来再分配 Vec，分配时不能持有缓存的锁
+    let mut stats = [None; MAX_CACHES];
+    for (stat, cache) in stats.iter_mut().zip(CACHES.lock().iter()) {
+        *stat = cache.as_ref().map(|cache| SlabStats {
+            name:        cache.name,
+            object_size: cache.slot,
+            slabs:       cache.slabs,
+            total:       cache.total,
+            in_use:      cache.in_use,
+        });
+    }
+    stats.into_iter().flatten().collect()
+}
+
+/// 反复创建和释放一批与任务控制块同样大小的对象：释放的对象被复用，
+/// 缓存切出的对象数和堆的已用字节数都不随轮数增长
+#[allow(unused)]
+pub fn slab_churn_test() {
+    use super::heap_info;
+
+    const OBJECTS: usize = 64;
+    const ROUNDS: usize = 16;
+    let layout = arc_layout::<TaskControlBlock>();
+    let task_stats = || {
+        slab_stats()
+            .into_iter()
+            .find(|stats| stats.name == "task")
+            .unwrap()
+    };
+
+    let mut objects: Vec<*mut u8> = Vec::with_capacity(OBJECTS);
+    let churn = |objects: &mut Vec<*mut u8>| {
+        for _ in 0..OBJECTS {
+            let ptr = unsafe { alloc::alloc::alloc(layout) };
+            assert!(!ptr.is_null());
+            objects.push(ptr);
+        }
+        objects
+            .drain(..)
+            .for_each(|ptr| unsafe { alloc::alloc::dealloc(ptr, layout) });
+    };
+    churn(&mut objects);
+    let (before, used) = (task_stats(), heap_info().used);
+    assert!(before.total >= OBJECTS);
+    for _ in 0..ROUNDS {
+        churn(&mut objects);
+    }
+    let after = task_stats();
+    assert_eq!(after.total, before.total);
+    assert_eq!(after.slabs, before.slabs);
+    assert_eq!(after.in_use, before.in_use);
+    assert_eq!(heap_info().used, used);
+    info!(
+        "slab_churn_test passed! task cache: {} objects of {} bytes in {} slabs",
+        after.total, after.object_size, after.slabs
+    );
+}
@@ -0,0 +1,171 @@
+//! 地址空间压力自测
+//!
+//! 按固定种子的伪随机序列反复 mmap、写入、扩展堆、fork (写时复制地址空间) 和 munmap，
+//! 每一步之后检查仍然映射着的页内容正确；每一轮结束释放所有地址空间，
+//! 空闲物理页数必须回到开始时的值，否则说明有页框泄漏。
+//!
+//! 运行时间较长，只在启用 `mm-stress` feature 时由 rust_main 调用。
+
+use alloc::vec::Vec;
+
+use super::{frame_free_count, MemorySet, VirtAddr, VirtPageNum};
+use crate::{config::PAGE_SIZE, task::process::Flags};
+
+/// 测试的轮数。页框分配器目前不会重新分配回收的页，总的分配量不能超过物理内存
+const ITERATIONS: usize = 16;
+/// 每一轮的操作次数
+const OPS_PER_ITERATION: usize = 32;
+/// 一次 mmap 或扩展堆的最大页数
+const MAX_PAGES: usize = 4;
+/// 模拟的堆起始地址，位于 mmap 区域之下
+const HEAP_BASE: usize = 0x1000_0000;
+
+/// xorshift64，固定种子保证每次运行的操作序列相同
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x as usize
+    }
+    fn below(&mut self, n: usize) -> usize {
+        self.next() % n
+    }
+}
+
+/// 一段已经映射的页，每页填充 `tag` 与页号算出的字节
+struct Region {
+    start: VirtPageNum,
+    pages: usize,
+    tag:   u8,
+    /// 属于堆而不是 mmap 得到的
+    heap:  bool,
+}
+
+fn pattern(tag: u8, vpn: VirtPageNum, offset: usize) -> u8 {
+    tag ^ (vpn.0 as u8) ^ (offset as u8).wrapping_mul(31)
+}
+
+/// 与用户态的写入一样，写时复制的页先复制，懒分配的页先分配
+fn fill(ms: &mut MemorySet, region: &Region) {
+    for i in 0..region.pages {
+        let vpn = VirtPageNum(region.start.0 + i);
+        let bytes = ms.page_bytes_mut(vpn).unwrap();
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = pattern(region.tag, vpn, offset);
+        }
+    }
+}
+
+fn check(ms: &MemorySet, region: &Region) {
+    for i in 0..region.pages {
+        let vpn = VirtPageNum(region.start.0 + i);
+        let pte = ms
+            .translate(vpn)
+            .filter(|pte| pte.is_valid())
+            .unwrap_or_else(|| panic!("vpn {:#x} lost its mapping", vpn.0));
+        let bytes = pte.ppn().get_bytes_array();
+        for (offset, byte) in bytes.iter().enumerate() {
+            assert_eq!(
+                *byte,
+                pattern(region.tag, vpn, offset),
+                "vpn {:#x} offset {:#x} corrupted",
+                vpn.0,
+                offset
+            );
+        }
+    }
+}
+
+/// 一轮测试：在新的地址空间上做 OPS_PER_ITERATION 次随机操作
+fn run_iteration(rng: &mut Rng, iteration: usize) {
+    let mut ms = MemorySet::new_process();
+    let mut regions: Vec<Region> = Vec::new();
+    let mut heap_end = VirtAddr::from(HEAP_BASE);
+    for op in 0..OPS_PER_ITERATION {
+        let tag = (iteration * OPS_PER_ITERATION + op) as u8;
+        match rng.below(4) {
+            // mmap 一段匿名内存并写入
+            0 => {
+                let pages = rng.below(MAX_PAGES) + 1;
+                let flags = Flags::MAP_PRIVATE | Flags::MAP_ANONYMOUS;
+                let start = ms.mmap(0, pages * PAGE_SIZE, flags) as usize;
+                let region = Region {
+                    start: VirtAddr::from(start).floor(),
+                    pages,
+                    tag,
+                    heap: false,
+                };
+                fill(&mut ms, &region);
+                regions.push(region);
+            }
+            // 扩展堆 (brk) 并写入新增的部分
+            1 => {
+                let pages = rng.below(MAX_PAGES) + 1;
+                let new_end = VirtAddr::from(heap_end.0 + pages * PAGE_SIZE);
+                ms.map_heap(heap_end, new_end);
+                let region = Region {
+                    start: heap_end.floor(),
+                    pages,
+                    tag,
+                    heap: true,
+                };
+                fill(&mut ms, &region);
+                regions.push(region);
+                heap_end = new_end;
+            }
+            // munmap 一段 mmap 得到的内存
+            2 => {
+                let mmapped: Vec<usize> =
+                    (0..regions.len()).filter(|&i| !regions[i].heap).collect();
+                if mmapped.is_empty() {
+                    continue;
+                }
+                let region = regions.swap_remove(mmapped[rng.below(mmapped.len())]);
+                let start: VirtAddr = region.start.into();
+                ms.munmap(start.0, region.pages * PAGE_SIZE);
+                for i in 0..region.pages {
+                    let vpn = VirtPageNum(region.start.0 + i);
+                    assert!(ms.translate(vpn).map_or(true, |pte| !pte.is_valid()));
+                }
+            }
+            // fork：子地址空间内容相同，改写子地址空间不影响父地址空间
+            _ => {
+                let mut child = MemorySet::from_existed_user(&mut ms);
+                regions.iter().for_each(|region| check(&child, region));
+                for region in regions.iter() {
+                    let child_region = Region {
+                        start: region.start,
+                        pages: region.pages,
+                        tag:   !region.tag,
+                        heap:  region.heap,
+                    };
+                    fill(&mut child, &child_region);
+                }
+                drop(child);
+            }
+        }
+        regions.iter().for_each(|region| check(&ms, region));
+    }
+}
+
+/// 每一轮结束后空闲页数都回到开始时的值
+#[allow(unused)]
+pub fn mm_stress_test() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let baseline = frame_free_count();
+    for iteration in 0..ITERATIONS {
+        run_iteration(&mut rng, iteration);
+        assert_eq!(
+            frame_free_count(),
+            baseline,
+            "frames leaked in iteration {}",
+            iteration
+        );
+    }
+    info!("mm_stress_test passed! {} iterations", ITERATIONS);
+}
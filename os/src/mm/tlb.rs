@@ -0,0 +1,171 @@
+//! 跨 hart 的 TLB shootdown
+//!
+//! 每个 hart 记录自己 satp 中正在使用的页表。修改某个页表的映射后调用 [`shootdown`]：
+//! 先刷新本 hart 的 TLB，再给其他正在使用这个页表的 hart 挂上刷新请求并发送 IPI，
+//! 等它们全部确认后返回，保证返回之后没有 hart 还能通过旧的映射访问已经释放的物理页。
+//!
+//! 目标 hart 在用户态时由 S 态软件中断立即处理；在内核态时中断是关闭的，
+//! 会在返回用户态之前、idle 循环以及自己等待 shootdown 时处理。
+
+use core::{
+    arch::asm,
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{config::MAX_HARTS, sbi::send_ipi, task::hart_id};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_TOKEN: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const NOT_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 每个 hart 正在使用的页表 (satp 的值)
+static ACTIVE_TOKEN: [AtomicUsize; MAX_HARTS] = [NO_TOKEN; MAX_HARTS];
+/// 每个 hart 是否有待处理的刷新请求
+static FLUSH_PENDING: [AtomicBool; MAX_HARTS] = [NOT_PENDING; MAX_HARTS];
+
+/// 刷新本 hart 的全部 TLB
+pub fn flush_local() {
+    unsafe {
+        asm!("sfence.vma");
+    }
+}
+
+/// 记录本 hart 切换到了 `token` 对应的页表，写 satp 时调用
+pub fn set_active_token(token: usize) {
+    ACTIVE_TOKEN[hart_id()].store(token, Ordering::Release);
+    // 切换页表时本来就会刷新 TLB，之前挂上的请求一并完成
+    FLUSH_PENDING[hart_id()].store(false, Ordering::Release);
+}
+
+/// 处理其他 hart 挂在本 hart 上的刷新请求
+pub fn handle_pending_flush() {
+    if FLUSH_PENDING[hart_id()].load(Ordering::Acquire) {
+        flush_local();
+        FLUSH_PENDING[hart_id()].store(false, Ordering::Release);
+    }
+}
+
+/// S 态软件中断：清除 sip.SSIP 后处理刷新请求
+pub fn handle_ipi() {
+    unsafe {
+        asm!("csrc sip, {}", in(reg) 1 << 1);
+    }
+    handle_pending_flush();
+}
+
+/// `token` 对应的页表的映射被修改后，让所有正在使用它的 hart 刷新 TLB
+pub fn shootdown(token: usize) {
+    flush_local();
+    let this = hart_id();
+    let mut mask = 0;
+    for hart in (0..MAX_HARTS).filter(|&hart| hart != this) {
+        if ACTIVE_TOKEN[hart].load(Ordering::Acquire) == token {
+            FLUSH_PENDING[hart].store(true, Ordering::Release);
+            mask |= 1 << hart;
+        }
+    }
+    if mask == 0 {
+        return;
+    }
+    send_ipi(mask);
+    for hart in (0..MAX_HARTS).filter(|hart| mask & (1 << hart) != 0) {
+        while FLUSH_PENDING[hart].load(Ordering::Acquire) {
+            // 两个 hart 互相 shootdown 时不能都只等对方
+            handle_pending_flush();
+            spin_loop();
+        }
+    }
+}
+
+/// 多 hart 测试使用的内核虚拟地址，位于内核地址空间中没有映射的区域
+#[allow(unused)]
+const TEST_VA: usize = (crate::config::KERNEL_SPACE_OFFSET << 12) + 0x4000_0000;
+/// 测试进行到的阶段
+#[allow(unused)]
+static TEST_STAGE: AtomicUsize = AtomicUsize::new(0);
+/// 参与测试的从 hart，usize::MAX 表示还没有
+#[allow(unused)]
+static TEST_HART: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 等待测试进入 `stage`，超时返回 false；等待时处理刷新请求
+#[allow(unused)]
+fn wait_stage(stage: usize) -> bool {
+    let deadline = crate::timer::get_time_ms() + 1000;
+    while TEST_STAGE.load(Ordering::Acquire) < stage {
+        if crate::timer::get_time_ms() > deadline {
+            return false;
+        }
+        handle_pending_flush();
+        spin_loop();
+    }
+    true
+}
+
+/// 启动 hart 一侧：把 TEST_VA 映射到内容为 1 的页，等另一个 hart 读过 (TLB 中缓存了这条映射) 后
+/// 改成映射到内容为 2 的页并 shootdown，确认对方读到的是新的页。
+/// 旧的映射如果还留在对方的 TLB 里，对方会继续读到 1，换成解除映射时就是访问已释放的页而不是缺页
+#[allow(unused)]
+pub fn tlb_shootdown_test() {
+    use super::{frame_alloc, PTEFlags, VirtAddr, KERNEL_SPACE};
+
+    let vpn = VirtAddr::from(TEST_VA).floor();
+    let old = frame_alloc().unwrap();
+    let new = frame_alloc().unwrap();
+    old.ppn.get_bytes_array()[0] = 1;
+    new.ppn.get_bytes_array()[0] = 2;
+    let token = {
+        let mut kernel_space = KERNEL_SPACE.lock();
+        kernel_space
+            .page_table
+            .map(vpn, old.ppn, PTEFlags::R | PTEFlags::W);
+        kernel_space.token()
+    };
+    flush_local();
+    TEST_STAGE.store(1, Ordering::Release);
+
+    if crate::task::online_harts() > 1 && wait_stage(2) {
+        KERNEL_SPACE
+            .lock()
+            .page_table
+            .map_allow_cover(vpn, new.ppn, PTEFlags::R | PTEFlags::W);
+        shootdown(token);
+        TEST_STAGE.store(3, Ordering::Release);
+        assert!(wait_stage(4), "the other hart did not see the new mapping");
+    } else {
+        // 只有一个 hart，只检查本地的刷新
+        KERNEL_SPACE
+            .lock()
+            .page_table
+            .map_allow_cover(vpn, new.ppn, PTEFlags::R | PTEFlags::W);
+        shootdown(token);
+        assert_eq!(unsafe { (TEST_VA as *const u8).read_volatile() }, 2);
+    }
+    KERNEL_SPACE.lock().page_table.unmap(vpn);
+    shootdown(token);
+    info!("tlb_shootdown_test passed!");
+}
+
+/// 从 hart 一侧：第一个调用的从 hart 参与测试，先读一次让 TLB 缓存旧的映射，
+/// 启动 hart shootdown 之后再读，必须看到新的页
+#[allow(unused)]
+pub fn tlb_shootdown_test_secondary() {
+    if TEST_HART
+        .compare_exchange(usize::MAX, hart_id(), Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return;
+    }
+    if !wait_stage(1) {
+        return;
+    }
+    let value = || unsafe { (TEST_VA as *const u8).read_volatile() };
+    assert_eq!(value(), 1);
+    TEST_STAGE.store(2, Ordering::Release);
+    if !wait_stage(3) {
+        return;
+    }
+    assert_eq!(value(), 2);
+    TEST_STAGE.store(4, Ordering::Release);
+}
@@ -11,6 +11,10 @@ const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 /// shutdown sbi call id
 const SBI_SHUTDOWN: usize = 8;
+/// hart state management extension id ("HSM")
+const SBI_EXT_HSM: usize = 0x48534D;
+/// IPI extension id ("sPI")
+const SBI_EXT_IPI: usize = 0x735049;
 
 /// general sbi call
 #[inline(always)]
@@ -44,6 +48,17 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
+/// 通过 HSM 扩展的 hart_start (fid 0) 启动一个 hart，
+/// 它以 S 态从物理地址 `start_addr` 开始执行，a0 = hartid，a1 = `opaque`，返回 0 表示成功
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> usize {
+    sbi_call(SBI_EXT_HSM, hartid, start_addr, opaque)
+}
+
+/// 通过 IPI 扩展的 send_ipi (fid 0) 给 `hart_mask` 中的 hart 发送 S 态软件中断
+pub fn send_ipi(hart_mask: usize) -> usize {
+    sbi_call(SBI_EXT_IPI, hart_mask, 0, 0)
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown() -> ! {
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
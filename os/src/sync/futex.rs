@@ -0,0 +1,386 @@
+//! Futex 等待队列与 robust futex 链表
+//!
+//! 等待队列以 futex 字所在的物理地址为键，共享同一物理页的不同地址空间等待的是同一个 futex。
+//! 线程可以通过 set_robust_list 登记一个 robust futex 链表，线程退出时内核遍历这个链表，
+//! 把仍由它持有的 futex 标记为 FUTEX_OWNER_DIED 并唤醒一个等待者，避免其他线程永远等下去。
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::mem::size_of;
+
+use lazy_static::*;
+use spin::Mutex;
+
+use crate::{
+    mm::{PageTable, VirtAddr},
+    syscall::errno::{EAGAIN, EFAULT, ETIMEDOUT},
+    task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock, TaskStatus},
+    timer::{add_timer, get_time_ms, remove_timer, TimeSpec, NSEC_PER_MSEC},
+};
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+pub const FUTEX_REQUEUE: usize = 3;
+pub const FUTEX_CMP_REQUEUE: usize = 4;
+/// 只在进程内使用的 futex，按物理地址作键时不需要区分
+pub const FUTEX_PRIVATE_FLAG: usize = 128;
+pub const FUTEX_CMD_MASK: usize = !FUTEX_PRIVATE_FLAG;
+
+/// futex 字中有线程在等待
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// 持有者已经退出
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// futex 字中持有者 tid 所在的位
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// 遍历 robust 链表的最大长度，防止用户构造的环让内核一直走下去
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+/// 用户态的 robust 链表头，与 linux 的 `struct robust_list_head` 布局相同
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RobustListHead {
+    /// 链表中第一个节点，链表为空时指向自己
+    pub list:            usize,
+    /// 节点地址加上这个偏移就是对应的 futex 字
+    pub futex_offset:    isize,
+    /// 正在加锁或解锁、可能还没有挂进链表的节点
+    pub list_op_pending: usize,
+}
+
+/// set_robust_list 要求的链表头大小
+pub const ROBUST_LIST_HEAD_SIZE: usize = size_of::<RobustListHead>();
+
+lazy_static! {
+    /// futex 字的物理地址 -> 等待的任务
+    static ref FUTEX_QUEUES: Mutex<BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// 把 `token` 地址空间中的 `addr` 翻译成对它的引用，没有映射时返回 None
+fn user_ref<T>(token: usize, addr: usize) -> Option<&'static mut T> {
+    PageTable::from_token(token)
+        .translate_va(VirtAddr::from(addr))
+        .map(|pa| pa.get_mut())
+}
+
+/// futex 的键：futex 字的物理地址
+pub fn futex_key(token: usize, uaddr: usize) -> Option<usize> {
+    PageTable::from_token(token)
+        .translate_va(VirtAddr::from(uaddr))
+        .map(|pa| pa.0)
+}
+
+/// `*uaddr` 仍等于 `val` 时阻塞当前任务，值已经改变时返回 EAGAIN。
+/// 比较和入队在同一把锁下完成，不会错过比较之后、入队之前的唤醒。
+/// `timeout` 是相对时间，到时仍没有被唤醒时返回 ETIMEDOUT
+pub fn futex_wait(
+    token: usize, uaddr: usize, val: u32, timeout: Option<TimeSpec>,
+) -> Result<(), isize> {
+    let key = futex_key(token, uaddr).ok_or(EFAULT)?;
+    let mut queues = FUTEX_QUEUES.lock();
+    let word: &u32 = user_ref(token, uaddr).unwrap();
+    if unsafe { core::ptr::read_volatile(word) } != val {
+        return Err(EAGAIN);
+    }
+    let task = current_task().unwrap();
+    queues.entry(key).or_default().push_back(Arc::clone(&task));
+    if let Some(timeout) = timeout {
+        add_timer(get_time_ms() + timeout_ms(timeout), Arc::clone(&task));
+    }
+    drop(queues);
+    block_current_and_run_next();
+    if timeout.is_none() {
+        return Ok(());
+    }
+    remove_timer(Arc::clone(&task));
+    // 仍在某个等待队列中 (可能已被 requeue 到别的地址) 说明是定时器唤醒的
+    if remove_waiter(&task) {
+        Err(ETIMEDOUT)
+    } else {
+        Ok(())
+    }
+}
+
+/// 超时时间换算成毫秒，不足一毫秒的部分向上取整，保证至少等够 `timeout`
+fn timeout_ms(timeout: TimeSpec) -> usize {
+    (timeout.to_ns() + NSEC_PER_MSEC - 1) / NSEC_PER_MSEC
+}
+
+/// 把 `task` 从所有等待队列中移除，返回它是否还在等待
+fn remove_waiter(task: &Arc<TaskControlBlock>) -> bool {
+    let mut queues = FUTEX_QUEUES.lock();
+    let found = queues.iter_mut().find_map(|(key, queue)| {
+        let idx = queue.iter().position(|waiter| Arc::ptr_eq(waiter, task))?;
+        queue.remove(idx);
+        Some(*key)
+    });
+    match found {
+        Some(key) => {
+            if queues.get(&key).is_some_and(|queue| queue.is_empty()) {
+                queues.remove(&key);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// 唤醒一个从等待队列取出的任务。带超时的等待者可能已经被定时器唤醒、还没来得及运行，
+/// 这时不能再把它加入就绪队列一次；还没到时的定时器也要取消
+fn wake_waiter(task: Arc<TaskControlBlock>) {
+    remove_timer(Arc::clone(&task));
+    if task.inner_exclusive_access(file!(), line!()).task_status == TaskStatus::Ready {
+        return;
+    }
+    wakeup_task(task);
+}
+
+/// 让 `task` 在 `key` 上等待，不切换任务
+#[allow(unused)]
+fn enqueue_waiter(key: usize, task: Arc<TaskControlBlock>) {
+    FUTEX_QUEUES.lock().entry(key).or_default().push_back(task);
+}
+
+/// 从 `key` 的等待队列头部取出最多 `count` 个任务
+fn take_waiters(
+    queues: &mut BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>, key: usize, count: usize,
+) -> Vec<Arc<TaskControlBlock>> {
+    let queue = match queues.get_mut(&key) {
+        Some(queue) => queue,
+        None => return Vec::new(),
+    };
+    let n = count.min(queue.len());
+    let taken = queue.drain(..n).collect();
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    taken
+}
+
+/// 唤醒最多 `count` 个在 `key` 上等待的任务，返回唤醒的个数
+pub fn futex_wake(key: usize, count: usize) -> usize {
+    let woken = take_waiters(&mut FUTEX_QUEUES.lock(), key, count);
+    let n = woken.len();
+    woken.into_iter().for_each(wake_waiter);
+    n
+}
+
+/// 唤醒 `uaddr` 上最多 `nr_wake` 个任务，再把最多 `nr_requeue` 个剩下的任务移到 `uaddr2` 上等待，
+/// 返回 (唤醒的个数, 移动的个数)。`expected` 不为 None 时 (FUTEX_CMP_REQUEUE)
+/// 先在同一把锁下检查 `*uaddr` 是否仍等于它，不相等时什么也不做并返回 None
+pub fn futex_requeue(
+    token: usize, uaddr: usize, uaddr2: usize, nr_wake: usize, nr_requeue: usize,
+    expected: Option<u32>,
+) -> Option<(usize, usize)> {
+    let key = futex_key(token, uaddr)?;
+    let key2 = futex_key(token, uaddr2)?;
+    let mut queues = FUTEX_QUEUES.lock();
+    if let Some(expected) = expected {
+        let word: &u32 = user_ref(token, uaddr)?;
+        if unsafe { core::ptr::read_volatile(word) } != expected {
+            return None;
+        }
+    }
+    let woken = take_waiters(&mut queues, key, nr_wake);
+    let moved = if key == key2 {
+        0
+    } else {
+        let moved = take_waiters(&mut queues, key, nr_requeue);
+        let n = moved.len();
+        if n > 0 {
+            queues.entry(key2).or_default().extend(moved);
+        }
+        n
+    };
+    drop(queues);
+    let n = woken.len();
+    woken.into_iter().for_each(wake_waiter);
+    Some((n, moved))
+}
+
+/// 在 `key` 上等待的任务数
+#[allow(unused)]
+fn waiter_count(key: usize) -> usize {
+    FUTEX_QUEUES.lock().get(&key).map_or(0, |queue| queue.len())
+}
+
+/// 处理 robust 链表中的一个 futex：仍由 `tid` 持有时标记持有者已退出，有等待者就唤醒一个
+fn handle_futex_death(token: usize, uaddr: usize, tid: usize) {
+    if uaddr % size_of::<u32>() != 0 {
+        return;
+    }
+    let word: &mut u32 = match user_ref(token, uaddr) {
+        Some(word) => word,
+        None => return,
+    };
+    let old = unsafe { core::ptr::read_volatile(word) };
+    if (old & FUTEX_TID_MASK) as usize != tid {
+        return;
+    }
+    unsafe { core::ptr::write_volatile(word, (old & FUTEX_WAITERS) | FUTEX_OWNER_DIED) };
+    if old & FUTEX_WAITERS != 0 {
+        futex_wake(futex_key(token, uaddr).unwrap(), 1);
+    }
+}
+
+/// 线程退出时遍历它登记的 robust 链表 (在 `token` 地址空间中的 `head`)
+pub fn exit_robust_list(token: usize, head: usize, tid: usize) {
+    if head == 0 {
+        return;
+    }
+    let head_ref: RobustListHead = match user_ref::<RobustListHead>(token, head) {
+        Some(head_ref) => *head_ref,
+        None => return,
+    };
+    let futex_of = |entry: usize| entry.wrapping_add(head_ref.futex_offset as usize);
+    let mut entry = head_ref.list;
+    for _ in 0..ROBUST_LIST_LIMIT {
+        if entry == head {
+            break;
+        }
+        // 先取出下一个节点，唤醒的线程可能马上改写这个节点
+        let next = match user_ref::<usize>(token, entry) {
+            Some(next) => *next,
+            None => break,
+        };
+        // list_op_pending 中的节点在最后单独处理，避免处理两次
+        if entry != head_ref.list_op_pending {
+            handle_futex_death(token, futex_of(entry), tid);
+        }
+        entry = next;
+    }
+    if head_ref.list_op_pending != 0 {
+        handle_futex_death(token, futex_of(head_ref.list_op_pending), tid);
+    }
+}
+
+/// 模拟一个持有 robust futex 的线程退出：链表中有两个 futex，一个由它持有且有等待者，
+/// 另一个属于别的线程。退出后前者带上 FUTEX_OWNER_DIED 且等待者被唤醒，后者不受影响。
+/// 等待者借用 initproc，需要在它被加入调度之前、文件系统初始化之后调用
+#[allow(unused)]
+pub fn robust_futex_test() {
+    use alloc::vec;
+
+    use crate::{
+        mm::kernel_token,
+        task::{remove_task, TaskStatus, INITPROC},
+    };
+
+    const TID: usize = 42;
+    // [head.list, head.futex_offset, head.list_op_pending, a.next, a.word, b.next, b.word]
+    let mut buf: Vec<usize> = vec![0; 7];
+    let base = buf.as_mut_ptr() as usize;
+    let addr = |i: usize| base + i * size_of::<usize>();
+    buf[0] = addr(3);
+    buf[1] = size_of::<usize>();
+    buf[3] = addr(5);
+    buf[4] = FUTEX_WAITERS as usize | TID;
+    buf[5] = addr(0);
+    buf[6] = TID + 1;
+
+    let token = kernel_token();
+    let key = futex_key(token, addr(4)).unwrap();
+    let waiter = INITPROC.clone();
+    waiter.inner_exclusive_access(file!(), line!()).task_status = TaskStatus::Blocked;
+    enqueue_waiter(key, waiter.clone());
+
+    exit_robust_list(token, addr(0), TID);
+    assert_eq!(buf[4] as u32, FUTEX_WAITERS | FUTEX_OWNER_DIED);
+    assert_eq!(buf[6], TID + 1);
+    assert!(!FUTEX_QUEUES.lock().contains_key(&key));
+    assert!(waiter.inner_exclusive_access(file!(), line!()).task_status == TaskStatus::Ready);
+    // initproc 创建时已经在就绪队列中，去掉唤醒时多加入的一次
+    remove_task(waiter);
+    info!("robust_futex_test passed!");
+}
+
+/// 三个任务在 a 上等待：FUTEX_REQUEUE 唤醒一个、移动一个到 b，剩下的一个仍在 a 上；
+/// 值不符时 FUTEX_CMP_REQUEUE 不做任何事。之后在 b 上唤醒的正是被移动的任务。
+/// 等待者借用 initproc，调用时机与 [`robust_futex_test`] 相同
+#[allow(unused)]
+pub fn futex_requeue_test() {
+    use crate::{
+        mm::kernel_token,
+        task::{remove_task, TaskStatus, INITPROC},
+    };
+
+    let words = [7u32, 0u32];
+    let (a, b) = (
+        &words[0] as *const u32 as usize,
+        &words[1] as *const u32 as usize,
+    );
+    let token = kernel_token();
+    let (key_a, key_b) = (futex_key(token, a).unwrap(), futex_key(token, b).unwrap());
+    for _ in 0..3 {
+        enqueue_waiter(key_a, INITPROC.clone());
+    }
+    INITPROC
+        .inner_exclusive_access(file!(), line!())
+        .task_status = TaskStatus::Blocked;
+
+    // 已经就绪的任务不会被再次加入就绪队列，每次唤醒前都把 initproc 设回阻塞
+    let block = || {
+        INITPROC
+            .inner_exclusive_access(file!(), line!())
+            .task_status = TaskStatus::Blocked
+    };
+
+    assert_eq!(futex_requeue(token, a, b, 1, 1, Some(8)), None);
+    assert_eq!((waiter_count(key_a), waiter_count(key_b)), (3, 0));
+    assert_eq!(futex_requeue(token, a, b, 1, 1, Some(7)), Some((1, 1)));
+    assert_eq!((waiter_count(key_a), waiter_count(key_b)), (1, 1));
+    assert_eq!(futex_requeue(token, b, b, 0, 1, None), Some((0, 0)));
+
+    // 被移动的任务只会在 b 上被唤醒
+    block();
+    assert_eq!(futex_wake(key_b, usize::MAX), 1);
+    assert_eq!(waiter_count(key_b), 0);
+    block();
+    assert_eq!(futex_wake(key_a, usize::MAX), 1);
+    assert_eq!(waiter_count(key_a), 0);
+    assert!(
+        INITPROC
+            .inner_exclusive_access(file!(), line!())
+            .task_status
+            == TaskStatus::Ready
+    );
+    // 去掉三次唤醒多加入就绪队列的 initproc
+    for _ in 0..3 {
+        remove_task(INITPROC.clone());
+    }
+    info!("futex_requeue_test passed!");
+}
+
+/// 超时换算向上取整；定时器已经唤醒的等待者被 FUTEX_WAKE 取出时不会再次加入就绪队列，
+/// 超时返回前从队列中移除自己时，队列变空就删掉这个键。
+/// 等待者借用 initproc，调用时机与 [`robust_futex_test`] 相同
+#[allow(unused)]
+pub fn futex_timeout_test() {
+    use crate::{mm::kernel_token, task::INITPROC};
+
+    assert_eq!(timeout_ms(TimeSpec::new()), 0);
+    assert_eq!(timeout_ms(TimeSpec::from_ns(1)), 1);
+    assert_eq!(timeout_ms(TimeSpec::from_ms(3)), 3);
+    assert_eq!(timeout_ms(TimeSpec::from_ns(3 * NSEC_PER_MSEC + 1)), 4);
+
+    let word = 0u32;
+    let key = futex_key(kernel_token(), &word as *const u32 as usize).unwrap();
+    // 相当于定时器已经把等待者唤醒
+    let waiter = INITPROC.clone();
+    waiter.inner_exclusive_access(file!(), line!()).task_status = TaskStatus::Ready;
+    enqueue_waiter(key, waiter.clone());
+    assert_eq!(futex_wake(key, 1), 1);
+    assert_eq!(waiter_count(key), 0);
+    // 唤醒没有把 initproc 再加入就绪队列，不需要 remove_task
+
+    // 超时的等待者自己离开队列
+    enqueue_waiter(key, waiter.clone());
+    assert!(remove_waiter(&waiter));
+    assert!(!FUTEX_QUEUES.lock().contains_key(&key));
+    assert!(!remove_waiter(&waiter));
+    info!("futex_timeout_test passed!");
+}
@@ -1,10 +1,13 @@
 //! Synchronization and interior mutability primitives
 
 mod condvar;
+pub mod futex;
 pub mod mutex;
-mod semaphore;
+pub mod semaphore;
+pub mod sp;
 mod up;
 
 // pub use condvar::Condvar;
 pub use semaphore::Semaphore;
+pub use sp::SpSafeCell;
 pub use up::UPSafeCell;
@@ -0,0 +1,151 @@
+//! 提供给用户程序的 mutex，通过 mutex_create 创建后用 id 访问
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+};
+
+use super::Mutex;
+use crate::{
+    sync::SpSafeCell,
+    task::{
+        block_current_and_run_next,
+        current_task,
+        suspend_current_and_run_next,
+        wakeup_task,
+        TaskControlBlock,
+    },
+};
+
+/// 拿不到锁时让出 CPU 后重试
+pub struct MutexSpin {
+    locked: SpSafeCell<bool>,
+}
+
+impl MutexSpin {
+    pub fn new() -> Self {
+        Self {
+            locked: SpSafeCell::new(false),
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) {
+        trace!("kernel: MutexSpin::lock");
+        loop {
+            let mut locked = self.locked.exclusive_access(file!(), line!());
+            if !*locked {
+                *locked = true;
+                return;
+            }
+            drop(locked);
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn unlock(&self) {
+        trace!("kernel: MutexSpin::unlock");
+        *self.locked.exclusive_access(file!(), line!()) = false;
+    }
+}
+
+/// 拿不到锁时阻塞，直到持有者解锁时把锁直接交给它
+pub struct MutexBlocking {
+    inner: SpSafeCell<MutexBlockingInner>,
+}
+
+struct MutexBlockingInner {
+    locked:     bool,
+    /// 等待者按到达顺序排队。等待的任务可能已经退出，所以只保存弱引用
+    wait_queue: VecDeque<Weak<TaskControlBlock>>,
+}
+
+impl MutexBlocking {
+    pub fn new() -> Self {
+        Self {
+            inner: SpSafeCell::new(MutexBlockingInner {
+                locked:     false,
+                wait_queue: VecDeque::new(),
+            }),
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.inner.exclusive_access(file!(), line!()).locked
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) {
+        trace!("kernel: MutexBlocking::lock");
+        let mut inner = self.inner.exclusive_access(file!(), line!());
+        if inner.locked {
+            inner
+                .wait_queue
+                .push_back(Arc::downgrade(&current_task().unwrap()));
+            drop(inner);
+            // 被唤醒时锁已经交给了自己
+            block_current_and_run_next();
+        } else {
+            inner.locked = true;
+        }
+    }
+
+    fn unlock(&self) {
+        trace!("kernel: MutexBlocking::unlock");
+        let mut inner = self.inner.exclusive_access(file!(), line!());
+        assert!(inner.locked);
+        // 锁直接交给第一个还活着的等待者，保持 locked 不变
+        while let Some(waiter) = inner.wait_queue.pop_front() {
+            if let Some(task) = waiter.upgrade() {
+                drop(inner);
+                wakeup_task(task);
+                return;
+            }
+        }
+        inner.locked = false;
+    }
+}
+
+/// 解锁时锁交给第一个等待者并唤醒它，已经退出的等待者被跳过。
+/// 等待者借用 initproc，调用时机与 [`crate::sync::futex::robust_futex_test`] 相同
+#[allow(unused)]
+pub fn mutex_blocking_test() {
+    use crate::task::{remove_task, TaskStatus, INITPROC};
+
+    let mutex = MutexBlocking::new();
+    mutex.lock();
+    assert!(mutex.is_locked());
+
+    let waiter = INITPROC.clone();
+    waiter.inner_exclusive_access(file!(), line!()).task_status = TaskStatus::Blocked;
+    {
+        let mut inner = mutex.inner.exclusive_access(file!(), line!());
+        inner.wait_queue.push_back(Weak::new());
+        inner.wait_queue.push_back(Arc::downgrade(&waiter));
+    }
+    mutex.unlock();
+    // 锁交给了 initproc
+    assert!(mutex.is_locked());
+    assert!(waiter.inner_exclusive_access(file!(), line!()).task_status == TaskStatus::Ready);
+    assert!(mutex
+        .inner
+        .exclusive_access(file!(), line!())
+        .wait_queue
+        .is_empty());
+    // initproc 创建时已经在就绪队列中，去掉唤醒时多加入的一次
+    remove_task(waiter);
+
+    mutex.unlock();
+    assert!(!mutex.is_locked());
+    mutex.lock();
+    mutex.unlock();
+
+    let spin = MutexSpin::new();
+    spin.lock();
+    spin.unlock();
+    spin.lock();
+    spin.unlock();
+    info!("mutex_blocking_test passed!");
+}
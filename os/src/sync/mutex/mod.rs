@@ -3,9 +3,13 @@
 use riscv::register::sstatus;
 use spin_mutex::SpinMutex;
 
+/// 用户程序使用的 mutex
+pub mod blocking;
 /// SpinMutex
 pub mod spin_mutex;
 
+pub use blocking::{MutexBlocking, MutexSpin};
+
 /// SpinLock
 pub type SpinLock<T> = SpinMutex<T, Spin>;
 /// SpinNoIrqLock(Cannot be interrupted)
@@ -1,21 +1,26 @@
 //! Semaphore
 
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+};
 
 use crate::{
-    sync::UPSafeCell,
+    sync::SpSafeCell,
     task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock},
 };
 
-/// semaphore structure
+/// 计数信号量，通过 semaphore_create 创建后用 id 访问
 pub struct Semaphore {
     /// semaphore inner
-    pub inner: UPSafeCell<SemaphoreInner>,
+    pub inner: SpSafeCell<SemaphoreInner>,
 }
 
 pub struct SemaphoreInner {
-    pub count:      isize,
-    pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// 可用的资源数，有等待者时一定为 0
+    pub count:      usize,
+    /// 等待者按到达顺序排队。等待的任务可能已经退出，所以只保存弱引用
+    pub wait_queue: VecDeque<Weak<TaskControlBlock>>,
 }
 
 impl Semaphore {
@@ -23,36 +28,90 @@ impl Semaphore {
     pub fn new(res_count: usize) -> Self {
         trace!("kernel: Semaphore::new");
         Self {
-            inner: unsafe {
-                UPSafeCell::new(SemaphoreInner {
-                    count:      res_count as isize,
-                    wait_queue: VecDeque::new(),
-                })
-            },
+            inner: SpSafeCell::new(SemaphoreInner {
+                count:      res_count,
+                wait_queue: VecDeque::new(),
+            }),
         }
     }
 
-    /// up operation of semaphore
+    /// up operation of semaphore，有等待者时资源直接交给最早等待的那个
     pub fn up(&self) {
         trace!("kernel: Semaphore::up");
         let mut inner = self.inner.exclusive_access(file!(), line!());
-        inner.count += 1;
-        if inner.count <= 0 {
-            if let Some(task) = inner.wait_queue.pop_front() {
+        while let Some(waiter) = inner.wait_queue.pop_front() {
+            if let Some(task) = waiter.upgrade() {
+                drop(inner);
                 wakeup_task(task);
+                return;
             }
         }
+        inner.count += 1;
     }
 
-    /// down operation of semaphore
+    /// down operation of semaphore，没有资源时阻塞到 up 把资源交给自己
     pub fn down(&self) {
         trace!("kernel: Semaphore::down");
         let mut inner = self.inner.exclusive_access(file!(), line!());
-        inner.count -= 1;
-        if inner.count < 0 {
-            inner.wait_queue.push_back(current_task().unwrap());
+        if inner.count > 0 {
+            inner.count -= 1;
+        } else {
+            inner
+                .wait_queue
+                .push_back(Arc::downgrade(&current_task().unwrap()));
             drop(inner);
             block_current_and_run_next();
         }
     }
+
+    fn count(&self) -> usize {
+        self.inner.exclusive_access(file!(), line!()).count
+    }
+}
+
+/// up 时资源按到达顺序交给第一个还活着的等待者，计数不变；没有等待者时计数加一。
+/// 等待者借用 initproc，调用时机与 [`crate::sync::futex::robust_futex_test`] 相同
+#[allow(unused)]
+pub fn semaphore_test() {
+    use crate::task::{remove_task, TaskStatus, INITPROC};
+
+    let sem = Semaphore::new(2);
+    sem.down();
+    sem.down();
+    assert_eq!(sem.count(), 0);
+
+    let waiter = INITPROC.clone();
+    waiter.inner_exclusive_access(file!(), line!()).task_status = TaskStatus::Blocked;
+    {
+        let mut inner = sem.inner.exclusive_access(file!(), line!());
+        inner.wait_queue.push_back(Weak::new());
+        inner.wait_queue.push_back(Arc::downgrade(&waiter));
+        inner.wait_queue.push_back(Weak::new());
+    }
+    sem.up();
+    // 资源交给了 initproc，排在它后面的等待者还在队列中
+    assert_eq!(sem.count(), 0);
+    assert!(waiter.inner_exclusive_access(file!(), line!()).task_status == TaskStatus::Ready);
+    assert_eq!(
+        sem.inner
+            .exclusive_access(file!(), line!())
+            .wait_queue
+            .len(),
+        1
+    );
+    // initproc 创建时已经在就绪队列中，去掉唤醒时多加入的一次
+    remove_task(waiter);
+
+    // 剩下的等待者已经退出，资源回到计数中
+    sem.up();
+    assert_eq!(sem.count(), 1);
+    assert!(sem
+        .inner
+        .exclusive_access(file!(), line!())
+        .wait_queue
+        .is_empty());
+    sem.up();
+    sem.down();
+    assert_eq!(sem.count(), 1);
+    info!("semaphore_test passed!");
 }
@@ -0,0 +1,141 @@
+//! Safe Cell for SMP (multiple harts)
+//!
+//! SpSafeCell 与 UPSafeCell 的接口相同，但内部用自旋锁代替 RefCell，
+//! 可以被多个 hart 同时访问：其他 hart 持有时原地等待，当前 hart 重复获取时 panic，
+//! 与 UPSafeCell 在单核上重复借用时 panic 的行为一致。
+//!
+//! 会被多个 hart 共享的结构都使用 SpSafeCell：
+//! - `TaskControlBlock::inner` (task/task.rs)：任务可能在任意 hart 上运行，也会被其他 hart 唤醒、发信号
+//! - `Pipe::buffer` (fs/pipe.rs)：读写两端可能在不同的 hart 上
+//! - `Ext4Inode::inner` (fs/ext4/inode.rs)：同一个 inode 可能被多个 hart 上的任务读写
+//! - `Semaphore::inner` (sync/semaphore.rs)
+//! - `MutexSpin::locked` 和 `MutexBlocking::inner` (sync/mutex/blocking.rs)：同一进程的线程共享 mutex_list
+//!
+//! 每个 hart 自己的 `Processor` 只会被所在的 hart 访问，继续使用 UPSafeCell。
+
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use spin::{Mutex, MutexGuard};
+
+use crate::task::hart_id;
+
+/// 没有 hart 持有时 owner 的取值
+const NO_OWNER: usize = usize::MAX;
+
+/// Wrap a data structure shared between harts, call `exclusive_access`
+/// to get mutable reference of inner data.
+pub struct SpSafeCell<T> {
+    /// inner data
+    data:  UnsafeCell<T>,
+    /// 保护 data 的自旋锁
+    lock:  Mutex<()>,
+    /// 持有锁的 hart 编号，用来发现同一个 hart 上的重复获取
+    owner: AtomicUsize,
+}
+
+unsafe impl<T: Send> Sync for SpSafeCell<T> {}
+
+/// `exclusive_access` 返回的引用，离开作用域时释放锁
+pub struct SpSafeCellGuard<'a, T> {
+    cell:   &'a SpSafeCell<T>,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl<T> SpSafeCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            data:  UnsafeCell::new(value),
+            lock:  Mutex::new(()),
+            owner: AtomicUsize::new(NO_OWNER),
+        }
+    }
+    /// Spin if the data is held by another hart, panic if it is held by the
+    /// current hart, and log the caller's location.
+    pub fn exclusive_access(&self, file: &'static str, line: u32) -> SpSafeCellGuard<'_, T> {
+        let hart = hart_id();
+        if self.owner.load(Ordering::Acquire) == hart {
+            panic!(
+                "exclusive_access called while data is borrowed at {}:{}",
+                file, line
+            );
+        }
+        let guard = self.lock.lock();
+        self.owner.store(hart, Ordering::Release);
+        SpSafeCellGuard {
+            cell:   self,
+            _guard: guard,
+        }
+    }
+    /// 是否有 hart 正持有
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+    /// 不经过锁的裸指针，调用者保证不与已有的访问冲突
+    pub fn as_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T> Deref for SpSafeCellGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpSafeCellGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.cell.data.get() }
+    }
+}
+
+impl<T> Drop for SpSafeCellGuard<'_, T> {
+    fn drop(&mut self) {
+        // 先清除 owner 再释放锁，下一个持有者写入的 owner 不会被覆盖
+        self.cell.owner.store(NO_OWNER, Ordering::Release);
+    }
+}
+
+/// 按 UPSafeCell 现有的几种用法访问 SpSafeCell，确认行为一致
+#[allow(unused)]
+pub fn sp_safe_cell_test() {
+    use alloc::vec::Vec;
+
+    use super::UPSafeCell;
+
+    let up = unsafe { UPSafeCell::new(Vec::new()) };
+    let sp = SpSafeCell::new(Vec::new());
+
+    // 临时借用，语句结束即释放
+    up.exclusive_access(file!(), line!()).push(1);
+    sp.exclusive_access(file!(), line!()).push(1);
+    assert!(!sp.is_locked());
+
+    // 持有一段时间后手动 drop，之后可以再次获取
+    let mut up_inner = up.exclusive_access(file!(), line!());
+    let mut sp_inner = sp.exclusive_access(file!(), line!());
+    up_inner.push(2);
+    sp_inner.push(2);
+    assert!(sp.is_locked());
+    drop(up_inner);
+    drop(sp_inner);
+    assert!(!sp.is_locked());
+
+    // 同时持有两个不同的 cell
+    let other = SpSafeCell::new(0usize);
+    {
+        let inner = sp.exclusive_access(file!(), line!());
+        *other.exclusive_access(file!(), line!()) = inner.len();
+    }
+    assert_eq!(*other.exclusive_access(file!(), line!()), 2);
+
+    assert_eq!(
+        *up.exclusive_access(file!(), line!()),
+        *sp.exclusive_access(file!(), line!())
+    );
+    info!("sp_safe_cell_test passed!");
+}
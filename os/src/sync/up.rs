@@ -28,6 +28,10 @@ impl<T> UPSafeCell<T> {
             inner: RefCell::new(value),
         }
     }
+    /// 不经过借用检查的裸指针，调用者保证不与已有的借用冲突
+    pub fn as_ptr(&self) -> *mut T {
+        self.inner.as_ptr()
+    }
     /// Panic if the data has been borrowed, and log the caller's location.
     pub fn exclusive_access(&self, file: &'static str, line: u32) -> RefMut<'_, T> {
         match self.inner.try_borrow_mut() {
@@ -419,6 +419,19 @@ pub enum Errno {
     EHWPOISON = -133,
 }
 
+/// 错误码取负后作为系统调用的返回值，数值必须和 Linux 的 errno 一致，
+/// 常量与 [`Errno`] 中的同名项也要相同
+#[allow(unused)]
+pub fn errno_test() {
+    assert_eq!((EPERM, ENOENT, EBADF, ENOSYS), (-1, -2, -9, -38));
+    assert_eq!((EINVAL, ERANGE, EHWPOISON), (-22, -34, -133));
+    for errno in [EPERM, ENOENT, EBADF, EINVAL, ERANGE, ENOSYS, EHWPOISON] {
+        assert_eq!(Errno::try_from(errno).unwrap() as isize, errno);
+    }
+    assert!(Errno::try_from(-41).is_err());
+    info!("errno_test passed!");
+}
+
 #[macro_export]
 ///
 macro_rules! set_errno {
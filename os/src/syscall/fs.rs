@@ -1,27 +1,87 @@
 use alloc::{
     borrow::ToOwned,
+    format,
     string::{String, ToString},
     sync::Arc,
     vec,
+    vec::Vec,
 };
-use core::{borrow::Borrow, cmp::min, mem::size_of, ops::Add, ptr};
+use core::{borrow::Borrow, cmp::min, mem::size_of, ptr};
 
 use riscv::register::sstatus;
 
 use crate::{
+    block::loop_dev::{loop_device, loop_setup, LOOP_MAJOR},
+    config::MAX_FD,
     fs::{
-        defs::OpenFlags,
-        file::{cast_file_to_inode, cast_inode_to_file},
-        inode::Stat,
+        absolute_path,
+        bind_mount,
+        chroot_path,
+        defs::{FdFlags, OpenFlags, FD_CLOEXEC, POSIX_FADV_NOREUSE, SEEK_CUR, SEEK_SET},
+        dentry::Dentry,
+        dev::{makedev, tty::is_tty},
+        file::{
+            cast_file_to_fs_context,
+            cast_file_to_inode,
+            cast_file_to_mount_fd,
+            cast_file_to_os_inode,
+            cast_inode_to_file,
+            File,
+        },
+        fscontext::{FsContext, MountFd},
+        inode::{Inode, InodeType, Stat, StatMode},
+        jailed_path,
+        lock::{
+            flock,
+            get_record_lock,
+            lock_key,
+            release_file_record_locks,
+            set_record_lock,
+            Flock,
+            F_RDLCK,
+            F_UNLCK,
+            F_WRLCK,
+        },
+        mount_overlay,
+        mount_vfat,
+        mounts,
         open_file,
+        os_inode::OSInode,
         pipe::make_pipe,
+        try_open_file,
         Iovec,
+        FS_MANAGER,
         ROOT_INODE,
     },
-    mm::{translated_byte_buffer, translated_refmut, translated_str},
+    mm::{
+        prefault_user,
+        translated_byte_buffer,
+        translated_ref,
+        translated_refmut,
+        translated_str,
+    },
     syscall::{
-        errno::{EACCES, EBADF, EBUSY, ENOENT, ENOTDIR, ENOTTY},
+        errno::{
+            EBADF,
+            EBUSY,
+            EEXIST,
+            EFAULT,
+            EINVAL,
+            EISDIR,
+            ENODEV,
+            ENOENT,
+            ENOTDIR,
+            ENOTTY,
+            ENXIO,
+            EOPNOTSUPP,
+            EPERM,
+            ERANGE,
+            ESPIPE,
+        },
         Dirent,
+        DT_DIR,
+        DT_REG,
+        DT_UNKNOWN,
     },
     task::{current_task, current_user_token},
     utils::string::c_ptr_to_string,
@@ -43,12 +103,12 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     }
     if let Some(file) = &inner.fd_table[fd] {
         if !file.writable() {
-            return EACCES;
+            return EBADF;
         }
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-
+        prefault_user(buf as usize, len, false);
         let buf = unsafe {
             sstatus::set_sum();
             let buf = core::slice::from_raw_parts(buf, len);
@@ -75,10 +135,11 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
     if let Some(file) = &inner.fd_table[fd] {
         let file = file.clone();
         if !file.readable() {
-            return EACCES;
+            return EBADF;
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
+        prefault_user(buf as usize, len, true);
         unsafe {
             sstatus::set_sum();
             let buf = core::slice::from_raw_parts_mut(buf, len);
@@ -96,6 +157,92 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
         EBADF
     }
 }
+/// lseek syscall，只有 open 得到的普通文件可以移动读写位置
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_lseek fd:{} offset:{} whence:{}",
+        current_task().unwrap().pid.0,
+        fd,
+        offset,
+        whence
+    );
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    let Some(os_inode) = cast_file_to_os_inode(file) else {
+        return ESPIPE;
+    };
+    match os_inode.lseek(offset, whence) {
+        Some(pos) => pos as isize,
+        None => EINVAL,
+    }
+}
+/// 取出 fd 对应的普通文件，用于 pread64/pwrite64
+fn positional_file(fd: usize, write: bool) -> Result<Arc<OSInode>, isize> {
+    let file = file_for_rw(fd, write)?;
+    if file.is_dir() {
+        return Err(EISDIR);
+    }
+    cast_file_to_os_inode(file).ok_or(ESPIPE)
+}
+
+/// 从 offset 处读取，不改变文件的读写位置
+pub fn sys_pread64(fd: usize, buf: *mut u8, count: usize, offset: isize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_pread64 fd:{} count:{} offset:{}",
+        current_task().unwrap().pid.0,
+        fd,
+        count,
+        offset
+    );
+    if offset < 0 {
+        return EINVAL;
+    }
+    let os_inode = match positional_file(fd, false) {
+        Ok(os_inode) => os_inode,
+        Err(err) => return err,
+    };
+    prefault_user(buf as usize, count, true);
+    let ret = unsafe {
+        sstatus::set_sum();
+        let buf = core::slice::from_raw_parts_mut(buf, count);
+        let ret = os_inode.read_at(offset as usize, buf);
+        sstatus::clear_sum();
+        ret
+    };
+    ret.map_or(ESPIPE, |size| size as isize)
+}
+
+/// 写入到 offset 处，不改变文件的读写位置
+pub fn sys_pwrite64(fd: usize, buf: *const u8, count: usize, offset: isize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_pwrite64 fd:{} count:{} offset:{}",
+        current_task().unwrap().pid.0,
+        fd,
+        count,
+        offset
+    );
+    if offset < 0 {
+        return EINVAL;
+    }
+    let os_inode = match positional_file(fd, true) {
+        Ok(os_inode) => os_inode,
+        Err(err) => return err,
+    };
+    prefault_user(buf as usize, count, false);
+    let ret = unsafe {
+        sstatus::set_sum();
+        let buf = core::slice::from_raw_parts(buf, count);
+        let ret = os_inode.write_at(offset as usize, buf);
+        sstatus::clear_sum();
+        ret
+    };
+    ret.map_or(ESPIPE, |size| size as isize)
+}
 /// openat sys
 pub fn sys_open(path: *const u8, flags: i32) -> isize {
     trace!("kernel:pid[{}] sys_open", current_task().unwrap().pid.0);
@@ -103,24 +250,41 @@ pub fn sys_open(path: *const u8, flags: i32) -> isize {
     let token = current_user_token();
     let path = translated_str(token, path);
     debug!("kernel: sys_open path: {}", path);
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return EINVAL,
+    };
     let curdir = task
         .inner_exclusive_access(file!(), line!())
         .work_dir
         .clone();
-    if let Some(dentry) = open_file(
-        curdir.inode(),
-        path.as_str(),
-        OpenFlags::from_bits(flags).unwrap(),
-    ) {
-        let inode = dentry.inode();
-        let mut inner = task.inner_exclusive_access(file!(), line!());
-        let fd = inner.alloc_fd();
-        let file = cast_inode_to_file(inode).unwrap();
-        inner.fd_table[fd] = Some(file);
-        trace!("kernel:pid[{}] sys_open success fd:{}", task.pid.0, fd);
-        fd as isize
-    } else {
-        ENOENT
+    // chroot 之后相对路径也换成绝对路径解析，".." 不会越过根目录
+    let Some(path) = jailed_path(&task.root_path(), curdir.name(), path) else {
+        return ENOENT;
+    };
+    let (readable, writable) = flags.read_write();
+    match try_open_file(curdir.inode(), path.as_str(), flags) {
+        Ok(dentry) if is_tty(dentry.inode()) => open_tty(),
+        Ok(dentry) => {
+            let dentry = named_by_path(dentry, curdir.name(), &path);
+            let file = Arc::new(OSInode::new(readable, writable, dentry));
+            file.set_append(flags.contains(OpenFlags::O_APPEND));
+            let mut inner = task.inner_exclusive_access(file!(), line!());
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(file);
+            inner.set_fd_flags(fd, FdFlags::from_open(flags));
+            trace!("kernel:pid[{}] sys_open success fd:{}", task.pid.0, fd);
+            fd as isize
+        }
+        Err(errno) => errno,
+    }
+}
+/// 用打开时的绝对路径给目录项命名，fchdir 到这个目录之后 getcwd 能得到完整路径；
+/// 算不出绝对路径时保留文件系统给的名字
+fn named_by_path(dentry: Arc<Dentry>, dir: &str, path: &str) -> Arc<Dentry> {
+    match absolute_path(dir, path) {
+        Some(path) => Arc::new(dentry.renamed(&path)),
+        None => dentry,
     }
 }
 pub fn sys_openat(dirfd: i32, path: *const u8, flags: i32) -> isize {
@@ -129,6 +293,8 @@ pub fn sys_openat(dirfd: i32, path: *const u8, flags: i32) -> isize {
         return sys_open(path, flags);
     }
     let dirfd = dirfd as usize;
+    // 读取路径时可能要处理缺页，需要在借用 inner 之前完成
+    let path = translated_str(current_user_token(), path);
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access(file!(), line!());
     if dirfd >= inner.fd_table.len() {
@@ -142,17 +308,47 @@ pub fn sys_openat(dirfd: i32, path: *const u8, flags: i32) -> isize {
     // if !dir.is_dir() {
     //     return -1;
     // }
-    let inode = cast_file_to_inode(dir).unwrap();
-    let token = inner.memory_set.token();
-    let path = translated_str(token, path);
-    if let Some(dentry) = open_file(inode, path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
+    let dir_name = cast_file_to_os_inode(dir.clone()).map(|dir| dir.dentry().name().to_string());
+    let inode = match cast_file_to_inode(dir) {
+        Some(inode) => inode,
+        None => return ENOTDIR,
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return EINVAL,
+    };
+    let dir_name = dir_name.as_deref().unwrap_or("");
+    let Some(path) = jailed_path(&task.root_path(), dir_name, path) else {
+        return ENOENT;
+    };
+    let (readable, writable) = flags.read_write();
+    match try_open_file(inode, path.as_str(), flags) {
+        Ok(dentry) if is_tty(dentry.inode()) => {
+            drop(inner);
+            open_tty()
+        }
+        Ok(dentry) => {
+            let fd = inner.alloc_fd();
+            let dentry = named_by_path(dentry, dir_name, &path);
+            let file = Arc::new(OSInode::new(readable, writable, dentry));
+            file.set_append(flags.contains(OpenFlags::O_APPEND));
+            inner.fd_table[fd] = Some(file);
+            inner.set_fd_flags(fd, FdFlags::from_open(flags));
+            fd as isize
+        }
+        Err(errno) => errno,
+    }
+}
+/// 打开 /dev/tty，即当前进程的控制终端，没有控制终端时返回 ENXIO
+fn open_tty() -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access(file!(), line!());
+    if let Some(tty) = inner.ctty.clone() {
         let fd = inner.alloc_fd();
-        let inode = dentry.inode();
-        let file = cast_inode_to_file(inode).unwrap();
-        inner.fd_table[fd] = Some(file);
+        inner.fd_table[fd] = Some(tty);
         fd as isize
     } else {
-        ENOENT
+        ENXIO
     }
 }
 /// close syscall
@@ -167,12 +363,24 @@ pub fn sys_close(fd: usize) -> isize {
     if fd >= inner.fd_table.len() {
         return EBADF;
     }
-    if inner.fd_table[fd].is_none() {
+    let Some(file) = inner.fd_table[fd].take() else {
         return EBADF;
+    };
+    // 写回设备时可能睡眠，先放开 inner
+    drop(inner);
+    release_closed_file_locks(file.clone(), task.pid.0);
+    if let Some(file) = cast_file_to_os_inode(file) {
+        file.close();
     }
-    inner.fd_table[fd].take();
     0
 }
+
+/// 关闭 fd 时释放当前进程在这个文件上的记录锁，即使还有其他 fd 指向它
+fn release_closed_file_locks(file: Arc<dyn File>, pid: usize) {
+    if let Some(inode) = cast_file_to_inode(file) {
+        release_file_record_locks(lock_key(&inode), pid);
+    }
+}
 /// pipe syscall
 pub fn sys_pipe(pipe: *mut u32) -> isize {
     trace!("kernel:pid[{}] sys_pipe", current_task().unwrap().pid.0);
@@ -183,6 +391,9 @@ pub fn sys_pipe(pipe: *mut u32) -> isize {
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
+    inner
+        .memory_set
+        .prefault(pipe as usize, 2 * size_of::<u32>(), true);
     unsafe {
         sstatus::set_sum();
         *pipe = read_fd as u32;
@@ -208,6 +419,15 @@ pub fn sys_dup(fd: usize) -> isize {
     }
     let new_fd = inner.alloc_fd();
     inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
+    // 新 fd 共享文件状态标志，但不继承 close-on-exec
+    let status = inner.fd_flags(fd).status;
+    inner.set_fd_flags(
+        new_fd,
+        FdFlags {
+            cloexec: false,
+            status,
+        },
+    );
     new_fd as isize
 }
 
@@ -222,13 +442,28 @@ pub fn sys_dup3(fd: usize, new_fd: usize) -> isize {
     if inner.fd_table[fd].is_none() {
         return EBADF;
     }
+    if fd == new_fd {
+        return EINVAL;
+    }
     while inner.fd_table.len() <= new_fd {
         inner.fd_table.push(None);
     }
-    if inner.fd_table[new_fd].is_some() {
-        inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
+    // new_fd 原来打开的文件被隐式关闭
+    let file = Arc::clone(inner.fd_table[fd].as_ref().unwrap());
+    let old = inner.fd_table[new_fd].replace(file);
+    let status = inner.fd_flags(fd).status;
+    inner.set_fd_flags(
+        new_fd,
+        FdFlags {
+            cloexec: false,
+            status,
+        },
+    );
+
+    drop(inner);
+    if let Some(old) = old {
+        release_closed_file_locks(old, task.pid.0);
     }
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
 
     debug!(
         "kernel:pid[{}] sys_dup3 fd:{} => new_fd:{}",
@@ -256,6 +491,8 @@ pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
             return EBADF;
         }
         let stat = stat.unwrap();
+        drop(inner);
+        prefault_user(st as usize, size_of::<Stat>(), true);
         unsafe {
             sstatus::set_sum();
             *st = stat;
@@ -276,11 +513,14 @@ pub fn sys_linkat(old_name: *const u8, new_name: *const u8) -> isize {
         .inner_exclusive_access(file!(), line!())
         .work_dir
         .clone();
-    let target = curdir.inode().lookup(old_name.as_str()).unwrap();
+    let target = match curdir.inode().lookup(old_name.as_str()) {
+        Some(target) => target,
+        None => return ENOENT,
+    };
     if curdir.inode().link(&new_name, target) {
         0
     } else {
-        ENOENT
+        EEXIST
     }
 }
 
@@ -294,27 +534,54 @@ pub fn sys_unlinkat(name: *const u8) -> isize {
         .inner_exclusive_access(file!(), line!())
         .work_dir
         .clone();
-    if curdir.inode().unlink(&name) {
+    // 挂载的文件系统只认识单个文件名，先经由挂载表找到所在的目录
+    let (dir, name) = match open_parent(curdir.inode(), &name) {
+        Ok(parent) => parent,
+        Err(errno) => return errno,
+    };
+    if dir.unlink(name) {
         0
     } else {
         ENOENT
     }
 }
 
+/// 把路径拆成所在目录和最后一级文件名，目录经由挂载表打开；`base` 是解析相对路径的起点
+fn open_parent(base: Arc<dyn Inode>, path: &str) -> Result<(Arc<dyn Inode>, &str), isize> {
+    let (parent, name) = match path.rsplit_once('/') {
+        None => return Ok((base, path)),
+        Some((".", name)) => return Ok((base, name)),
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+    };
+    match open_file(base, parent, OpenFlags::O_RDONLY | OpenFlags::O_DIRECTORY) {
+        Some(dentry) => Ok((dentry.inode(), name)),
+        None => Err(ENOENT),
+    }
+}
+
 pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
     trace!("kernel:pid[{}] sys_getcwd", current_task().unwrap().pid.0);
     let token = current_user_token();
-    if let path = current_task()
+    // 写入用户缓冲区时可能要处理缺页，不能持有 inner 的借用
+    let work_dir = current_task()
         .unwrap()
         .inner_exclusive_access(file!(), line!())
         .work_dir
-        .clone()
-        .name()
-    {
-        let len = core::cmp::min(len, path.len());
-        let mut v = translated_byte_buffer(token, buf, len);
+        .clone();
+    if let path = work_dir.name() {
+        if buf.is_null() {
+            return EFAULT;
+        }
+        // 需要为结尾的 '\0' 留出空间
+        if len < path.len() + 1 {
+            return ERANGE;
+        }
+        let mut path = path.as_bytes().to_vec();
+        path.push(0);
+        let mut v = translated_byte_buffer(token, buf, path.len());
         unsafe {
-            let mut p = path.as_bytes().as_ptr();
+            let mut p = path.as_ptr();
             for slice in v.iter_mut() {
                 let len = slice.len();
                 ptr::copy_nonoverlapping(p, slice.as_mut_ptr(), len);
@@ -334,195 +601,655 @@ pub fn sys_chdir(path: *const u8) -> isize {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access(file!(), line!());
     let dir = inner.work_dir.clone();
-    let inode = dir.inode();
-    let dir = open_file(inode, &path, OpenFlags::O_RDWR | OpenFlags::O_DIRECTORY);
-    inner.work_dir = dir.unwrap();
+    // 工作目录有绝对路径时把目标也换成绝对路径，".." 在根目录处停住，getcwd 也能得到完整路径
+    let path = absolute_path(dir.name(), &path).unwrap_or(path);
+    // 只需要能查找目录，以只读方式打开，没有写权限的目录也能进入
+    let dir = match try_open_file(
+        dir.inode(),
+        &path,
+        OpenFlags::O_RDONLY | OpenFlags::O_DIRECTORY,
+    ) {
+        Ok(dir) => dir,
+        Err(errno) => return errno,
+    };
+    if !cast_inode_to_file(dir.inode()).map_or(false, |file| file.is_dir()) {
+        return ENOTDIR;
+    }
+    inner.work_dir = Arc::new(dir.renamed(&path));
     0
 }
 
-pub fn sys_mkdirat64(dirfd: i32, path: *const u8, _mode: u32) -> isize {
-    trace!("kernel:pid[{}] sys_mkdirat", current_task().unwrap().pid.0);
+/// 把当前进程的根目录切换到 path，之后的绝对路径都从这里开始解析，".." 不会越过它。
+/// 只有 root 用户可以调用，不改变工作目录
+pub fn sys_chroot(path: *const u8) -> isize {
+    trace!("kernel:pid[{}] sys_chroot", current_task().unwrap().pid.0);
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if !inner.cred.is_privileged() {
+        return EPERM;
+    }
+    let Some(path) = absolute_path(inner.work_dir.name(), &path) else {
+        return ENOENT;
+    };
+    let Some(dir) = open_file(ROOT_INODE.clone(), &path, OpenFlags::O_RDONLY) else {
+        return ENOENT;
+    };
+    if !cast_inode_to_file(dir.inode()).map_or(false, |file| file.is_dir()) {
+        return ENOTDIR;
+    }
+    let global = chroot_path(&task.root_path(), &path);
+    *task.root.lock() = Arc::new(dir.renamed(&global));
+    0
+}
+
+/// 把工作目录切换到 fd 对应的目录
+pub fn sys_fchdir(fd: usize) -> isize {
+    trace!("kernel:pid[{}] sys_fchdir", current_task().unwrap().pid.0);
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access(file!(), line!());
-    let inode;
-    if dirfd == AT_FDCWD {
-        inode = ROOT_INODE.clone();
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    if !file.is_dir() {
+        return ENOTDIR;
+    }
+    match cast_file_to_os_inode(file) {
+        Some(os_inode) => {
+            inner.work_dir = os_inode.dentry();
+            0
+        }
+        None => ENOTDIR,
+    }
+}
+
+/// 修改 fd 对应文件的权限位
+pub fn sys_fchmod(fd: usize, mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_fchmod", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    match cast_file_to_inode(file) {
+        Some(inode) if inode.chmod(mode) => 0,
+        _ => EPERM,
+    }
+}
+
+/// 修改 dirfd 下 path 对应文件的权限位，flags 目前忽略 (没有符号链接)
+pub fn sys_fchmodat(dirfd: i32, path: *const u8, mode: u32, _flags: i32) -> isize {
+    trace!("kernel:pid[{}] sys_fchmodat", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    let inode = if dirfd == AT_FDCWD {
+        inner.work_dir.inode()
     } else {
         let dirfd = dirfd as usize;
-        if dirfd >= inner.fd_table.len() {
-            return EBADF;
-        }
-        if inner.fd_table[dirfd].is_none() {
+        if dirfd >= inner.fd_table.len() || inner.fd_table[dirfd].is_none() {
             return EBADF;
         }
         let dir = inner.fd_table[dirfd].as_ref().unwrap().clone();
         if !dir.is_dir() {
             return ENOTDIR;
         }
-        inode = cast_file_to_inode(dir).unwrap();
-    }
+        match cast_file_to_inode(dir) {
+            Some(inode) => inode,
+            None => return ENOTDIR,
+        }
+    };
+    drop(inner);
     let path = c_ptr_to_string(path);
-    if let Some(_) = open_file(inode.clone(), &path, OpenFlags::O_RDONLY) {
-        return -1;
+    let inode = match open_file(inode, &path, OpenFlags::O_RDONLY) {
+        Some(dentry) => dentry.inode(),
+        None => return ENOENT,
+    };
+    if inode.chmod(mode) {
+        0
+    } else {
+        EPERM
     }
-    if let Some(dentry) = open_file(
-        inode.clone(),
-        &path,
-        OpenFlags::O_DIRECTORY | OpenFlags::O_CREAT,
-    ) {
-        let fd = inner.alloc_fd();
-        let inode = dentry.inode();
-        let file = cast_inode_to_file(inode).unwrap();
-        inner.fd_table[fd] = Some(file);
-        fd as isize
+}
+
+/// 按路径设置文件长度，不需要先打开文件。文件系统不支持时 (如把 ext4 上的文件变长)
+/// 返回 EOPNOTSUPP
+pub fn sys_truncate(path: *const u8, length: isize) -> isize {
+    trace!("kernel:pid[{}] sys_truncate", current_task().unwrap().pid.0);
+    if length < 0 {
+        return EINVAL;
+    }
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let task = current_task().unwrap();
+    let cwd = task
+        .inner_exclusive_access(file!(), line!())
+        .work_dir
+        .clone();
+    let inode = match try_open_file(cwd.inode(), &path, OpenFlags::O_WRONLY) {
+        Ok(dentry) => dentry.inode(),
+        Err(errno) => return errno,
+    };
+    let file = cast_inode_to_file(inode.clone()).unwrap();
+    if file.is_dir() {
+        return EISDIR;
+    }
+    if inode.truncate(length as usize) {
+        0
     } else {
-        EACCES //TODO: to be confirmed
+        EOPNOTSUPP
     }
 }
 
-pub fn sys_getdents64(dirfd: i32, buf: *mut u8, len: usize) -> isize {
+/// 设置已打开文件的长度，fd 必须以可写方式打开。文件系统不支持时返回 EOPNOTSUPP
+pub fn sys_ftruncate(fd: usize, length: isize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_getdents64",
+        "kernel:pid[{}] sys_ftruncate",
         current_task().unwrap().pid.0
     );
+    if length < 0 {
+        return EINVAL;
+    }
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access(file!(), line!());
-    let inode;
-    if dirfd == AT_FDCWD {
-        inode = ROOT_INODE.clone();
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    if file.is_dir() {
+        return EISDIR;
+    }
+    if !file.writable() {
+        return EINVAL;
+    }
+    match cast_file_to_inode(file) {
+        Some(inode) if inode.truncate(length as usize) => 0,
+        Some(_) => EOPNOTSUPP,
+        None => EINVAL,
+    }
+}
+
+/// 在 dirfd (AT_FDCWD 时为当前工作目录) 下创建目录 path，不会创建缺少的中间目录
+pub fn sys_mkdirat(dirfd: i32, path: *const u8, _mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_mkdirat", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    let inode = if dirfd == AT_FDCWD {
+        inner.work_dir.inode()
     } else {
         let dirfd = dirfd as usize;
-        if dirfd >= inner.fd_table.len() {
-            return EBADF;
-        }
-        if inner.fd_table[dirfd].is_none() {
+        if dirfd >= inner.fd_table.len() || inner.fd_table[dirfd].is_none() {
             return EBADF;
         }
         let dir = inner.fd_table[dirfd].as_ref().unwrap().clone();
         if !dir.is_dir() {
             return ENOTDIR;
         }
-        inode = cast_file_to_inode(dir).unwrap();
+        match cast_file_to_inode(dir) {
+            Some(inode) => inode,
+            None => return ENOTDIR,
+        }
+    };
+    let token = inner.memory_set.token();
+    drop(inner);
+    let path = translated_str(token, path);
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        // "" 不存在，"/" 总是已经存在
+        return if path.is_empty() { ENOENT } else { EEXIST };
+    }
+    if open_file(inode.clone(), trimmed, OpenFlags::O_RDONLY).is_some() {
+        return EEXIST;
+    }
+    // 父目录必须已经存在，不像 mkdir -p 那样逐级创建
+    let (parent, name) = match trimmed.rsplit_once('/') {
+        Some(("", name)) => (ROOT_INODE.clone(), name),
+        Some((parent, name)) => match open_file(inode, parent, OpenFlags::O_RDONLY) {
+            Some(dentry) => (dentry.inode(), name),
+            None => return ENOENT,
+        },
+        None => (inode, trimmed),
+    };
+    match cast_inode_to_file(parent.clone()) {
+        Some(file) if file.is_dir() => {}
+        _ => return ENOTDIR,
+    }
+    match parent.create(name, InodeType::Directory) {
+        Some(_) => 0,
+        // 检查之后被别的任务抢先创建了
+        None => EEXIST,
     }
+}
+
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_getdents64",
+        current_task().unwrap().pid.0
+    );
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let dir = inner.fd_table[fd].as_ref().unwrap().clone();
     let token = inner.memory_set.token();
-    let mut v = translated_byte_buffer(token, buf, len);
-    let mut read_size = 0usize;
-    let mut offset_in_slice = 0usize;
-    let mut slice_index = 0usize;
-    let mut is_end = true;
-    for name in inode.ls() {
-        let dirent_len = 19 + name.len() + 1;
-        if read_size + dirent_len > len {
-            is_end = false;
+    drop(inner);
+    if !dir.is_dir() {
+        return ENOTDIR;
+    }
+    let Some(dir) = cast_file_to_os_inode(dir) else {
+        return ENOTDIR;
+    };
+    // 目录的读写位置是下一个要返回的目录项的序号
+    let start = dir.offset();
+    let entries = dir.inode().ls_typed();
+    let mut records = Vec::new();
+    let mut next = start;
+    for (name, type_) in entries.iter().skip(start) {
+        let type_ = match type_ {
+            InodeType::Directory => DT_DIR,
+            InodeType::Regular => DT_REG,
+            _ => DT_UNKNOWN,
+        };
+        // 没有真正的 inode 编号，用序号代替，0 会被部分 libc 当作已删除的目录项
+        let record = Dirent::encode((next + 1) as u64, (next + 1) as i64, type_, name);
+        if records.len() + record.len() > len {
             break;
         }
-        // TODO: 这里 vec 的长度不同会导致内核 LoadPageFault，先这样处理
-        let mut mbuf = [0u8; 35];
-        let mut p = mbuf.as_mut() as *mut [u8] as *mut u8;
-        let dirent = p as *mut Dirent;
-        unsafe {
-            *dirent = Dirent::new(read_size + dirent_len, dirent_len as u16, &name);
-        }
-        let mut copy_len = 0;
-        while copy_len < dirent_len {
-            let copy_size = min(
-                dirent_len - copy_len,
-                v[slice_index].len() - offset_in_slice,
-            );
-            unsafe {
-                ptr::copy_nonoverlapping(
-                    p,
-                    v[slice_index][offset_in_slice..].as_mut_ptr(),
-                    copy_size,
-                );
-                p = p.add(copy_size);
-            }
-            read_size += copy_size;
-            offset_in_slice += copy_size;
-            copy_len += copy_size;
-            if offset_in_slice == v[slice_index].len() {
-                offset_in_slice = 0;
-                slice_index += 1;
-                if slice_index == v.len() {
-                    break;
-                }
-            }
-        }
+        records.extend_from_slice(&record);
+        next += 1;
     }
-    if is_end {
-        0
-    } else {
-        read_size as isize
+    if next == start && start < entries.len() {
+        // 缓冲区连一条记录都放不下
+        return EINVAL;
+    }
+    dir.lseek(next as isize, SEEK_SET);
+    let mut copied = 0;
+    for chunk in translated_byte_buffer(token, buf, records.len()) {
+        chunk.copy_from_slice(&records[copied..copied + chunk.len()]);
+        copied += chunk.len();
     }
+    records.len() as isize
 }
 
-pub fn sys_umount2(_target: *const u8, _flags: i32) -> isize {
+/// umount2 的 flags：即使还有打开的文件也强制卸载
+const MNT_FORCE: i32 = 1;
+
+pub fn sys_umount2(target: *const u8, flags: i32) -> isize {
     trace!("kernel:pid[{}] sys_umount2", current_task().unwrap().pid.0);
+    let target = c_ptr_to_string(target);
+    // 根文件系统不允许卸载，target 不是挂载点时什么也不做
+    if target == "/" {
+        return 0;
+    }
+    let mounts = mounts();
+    let mut manager = mounts.lock();
+    // 还有打开的文件或工作目录在其中的挂载不能卸载，强制卸载后它们仍然可以访问原来的 inode
+    if flags & MNT_FORCE == 0 && manager.get(&target).is_some_and(|mount| mount.busy()) {
+        return EBUSY;
+    }
+    manager.unmount(&target);
     0
 }
 
+/// mount 的 flags：把已有的文件或目录绑定到另一个路径上，此时忽略文件系统类型
+const MS_BIND: u32 = 4096;
+
 pub fn sys_mount(
-    _source: *const u8, _target: *const u8, _fs: *const u8, _flags: u32, _data: *const u8,
+    source: *const u8, target: *const u8, fs: *const u8, flags: u32, data: *const u8,
 ) -> isize {
     trace!("kernel:pid[{}] sys_mount", current_task().unwrap().pid.0);
-    0
+    let source = c_ptr_to_string(source);
+    let target = c_ptr_to_string(target);
+    if flags & MS_BIND != 0 {
+        return sys_mount_bind(&source, &target);
+    }
+    let fs = c_ptr_to_string(fs);
+    if fs == "overlay" {
+        if data.is_null() {
+            return EINVAL;
+        }
+        return sys_mount_overlay(&target, &c_ptr_to_string(data));
+    }
+    // 目前只支持把 loop 设备上的 FAT32 镜像挂载到绝对路径上，其余情况仍直接返回成功
+    let Some(index) = source.strip_prefix("/dev/loop") else {
+        return 0;
+    };
+    if fs != "vfat" || !target.starts_with('/') {
+        return EINVAL;
+    }
+    let Some(bdev) = index.parse().ok().and_then(loop_device) else {
+        return ENXIO;
+    };
+    if mount_vfat(bdev, &source, &target) {
+        0
+    } else {
+        EINVAL
+    }
 }
 
-pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
-    trace!("kernel:pid[{}] sys_ioctl", current_task().unwrap().pid.0);
-    // TODO:
-    ENOTTY
-    // let task = current_task().unwrap();
-    // let mut inner = task.inner_exclusive_access(file!(), line!());
-    // if fd >= inner.fd_table.len() {
-    //     return EBADF;
-    // }
-    // if inner.fd_table[fd].is_none() {
-    //     return EBADF;
-    // }
-    // if let Some(file) = &inner.fd_table[fd] {
-    //     let file = file.clone();
-    //     file.ioctl(request, arg1, arg2, arg3, arg4)
-    // } else {
-    //     EBADF
-    // }
+/// bind mount：source 和 target 都必须是已经存在的绝对路径，target 不能是根目录
+fn sys_mount_bind(source: &str, target: &str) -> isize {
+    if !source.starts_with('/') || !target.starts_with('/') || target == "/" {
+        return EINVAL;
+    }
+    if open_file(ROOT_INODE.clone(), target, OpenFlags::O_RDONLY).is_none() {
+        return ENOENT;
+    }
+    if bind_mount(source, target.trim_end_matches('/')) {
+        0
+    } else {
+        ENOENT
+    }
 }
 
-pub fn sys_writev(fd: usize, iov: usize, iovcnt: usize) -> isize {
-    trace!("kernel:pid[{}] sys_writev", current_task().unwrap().pid.0);
+/// overlay 挂载：data 中以逗号分隔的 lowerdir= 和 upperdir= 给出下层和上层目录的绝对路径，
+/// workdir= 只是为了与 Linux 兼容而接受，不会用到
+fn sys_mount_overlay(target: &str, data: &str) -> isize {
+    let mut lower = None;
+    let mut upper = None;
+    for option in data.split(',') {
+        match option.split_once('=') {
+            Some(("lowerdir", path)) => lower = Some(path),
+            Some(("upperdir", path)) => upper = Some(path),
+            Some(("workdir", _)) => {}
+            _ => return EINVAL,
+        }
+    }
+    let (Some(lower), Some(upper)) = (lower, upper) else {
+        return EINVAL;
+    };
+    if ![lower, upper, target]
+        .iter()
+        .all(|path| path.starts_with('/'))
+        || target == "/"
+    {
+        return EINVAL;
+    }
+    if open_file(ROOT_INODE.clone(), target, OpenFlags::O_RDONLY).is_none() {
+        return ENOENT;
+    }
+    if mount_overlay(lower, upper, target.trim_end_matches('/')) {
+        0
+    } else {
+        ENOTDIR
+    }
+}
+
+/// fsopen 的 flags
+const FSOPEN_CLOEXEC: u32 = 0x1;
+/// fsmount 的 flags
+const FSMOUNT_CLOEXEC: u32 = 0x1;
+/// move_mount 的 flags：from_path 为空，直接移动 from_dfd 指向的挂载
+const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x4;
+
+/// fsconfig 的命令
+const FSCONFIG_SET_FLAG: u32 = 0;
+const FSCONFIG_SET_STRING: u32 = 1;
+const FSCONFIG_CMD_CREATE: u32 = 6;
+
+/// 把新建的 fd 放进 fd 表
+fn install_fd(file: Arc<dyn File>, cloexec: bool) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access(file!(), line!());
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(file);
+    inner.set_fd_flags(
+        fd,
+        FdFlags {
+            cloexec,
+            status: OpenFlags::empty(),
+        },
+    );
+    fd as isize
+}
+
+/// 取出 fd 对应的文件
+fn fd_file(fd: usize) -> Option<Arc<dyn File>> {
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access(file!(), line!());
-    if fd >= inner.fd_table.len() {
+    inner.fd_table.get(fd).cloned().flatten()
+}
+
+/// 为名为 fsname 的文件系统创建上下文，返回指向它的 fd。目前只支持 tmpfs
+pub fn sys_fsopen(fsname: *const u8, flags: u32) -> isize {
+    trace!("kernel:pid[{}] sys_fsopen", current_task().unwrap().pid.0);
+    if flags & !FSOPEN_CLOEXEC != 0 {
+        return EINVAL;
+    }
+    let fsname = translated_str(current_user_token(), fsname);
+    match FsContext::new(&fsname) {
+        Some(context) => install_fd(Arc::new(context), flags & FSOPEN_CLOEXEC != 0),
+        None => ENODEV,
+    }
+}
+
+/// 设置文件系统上下文的参数，或者按已经设置的参数创建文件系统
+pub fn sys_fsconfig(fd: usize, cmd: u32, key: *const u8, value: *const u8, aux: i32) -> isize {
+    trace!("kernel:pid[{}] sys_fsconfig", current_task().unwrap().pid.0);
+    let Some(file) = fd_file(fd) else {
         return EBADF;
+    };
+    let Some(context) = cast_file_to_fs_context(file) else {
+        return EINVAL;
+    };
+    let token = current_user_token();
+    let ok = match cmd {
+        FSCONFIG_SET_FLAG if !key.is_null() && value.is_null() && aux == 0 => {
+            context.set_param(&translated_str(token, key), None)
+        }
+        FSCONFIG_SET_STRING if !key.is_null() && !value.is_null() && aux == 0 => {
+            let value = translated_str(token, value);
+            context.set_param(&translated_str(token, key), Some(value))
+        }
+        FSCONFIG_CMD_CREATE if key.is_null() && value.is_null() && aux == 0 => context.create(),
+        // 二进制、路径和 fd 形式的参数以及重新配置暂不支持
+        2..=5 | 7 => return EOPNOTSUPP,
+        _ => false,
+    };
+    if ok {
+        0
+    } else {
+        EINVAL
     }
-    if inner.fd_table[fd].is_none() {
+}
+
+/// 把 fs_fd 上下文中创建好的文件系统包装成一个挂载，返回指向它的 fd。
+/// 挂载属性暂不支持，attr_flags 必须为 0
+pub fn sys_fsmount(fs_fd: usize, flags: u32, attr_flags: u32) -> isize {
+    trace!("kernel:pid[{}] sys_fsmount", current_task().unwrap().pid.0);
+    if flags & !FSMOUNT_CLOEXEC != 0 || attr_flags != 0 {
+        return EINVAL;
+    }
+    let Some(file) = fd_file(fs_fd) else {
         return EBADF;
+    };
+    let Some(context) = cast_file_to_fs_context(file) else {
+        return EINVAL;
+    };
+    match context.take_fs() {
+        Some(fs) => install_fd(Arc::new(MountFd::new(fs)), flags & FSMOUNT_CLOEXEC != 0),
+        None => EINVAL,
     }
-    if let Some(file) = &inner.fd_table[fd] {
-        if !file.writable() {
-            return EACCES;
+}
+
+/// 把 fsmount 得到的挂载挂到绝对路径 to_path 上。目前只支持 MOVE_MOUNT_F_EMPTY_PATH，
+/// 即 from_dfd 本身就是要移动的挂载，to_dfd 被忽略
+pub fn sys_move_mount(
+    from_dfd: usize, from_path: *const u8, _to_dfd: i32, to_path: *const u8, flags: u32,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_move_mount",
+        current_task().unwrap().pid.0
+    );
+    let token = current_user_token();
+    if flags != MOVE_MOUNT_F_EMPTY_PATH || !translated_str(token, from_path).is_empty() {
+        return EINVAL;
+    }
+    let target = translated_str(token, to_path);
+    if !target.starts_with('/') || target == "/" {
+        return EINVAL;
+    }
+    let Some(file) = fd_file(from_dfd) else {
+        return EBADF;
+    };
+    let Some(mount) = cast_file_to_mount_fd(file) else {
+        return EINVAL;
+    };
+    // 挂载点必须是已经存在的目录
+    let Some(dentry) = open_file(ROOT_INODE.clone(), &target, OpenFlags::O_RDONLY) else {
+        return ENOENT;
+    };
+    if !cast_inode_to_file(dentry.inode()).map_or(false, |file| file.is_dir()) {
+        return ENOTDIR;
+    }
+    // 同一个挂载只能挂一次
+    let Some(fs) = mount.attach() else {
+        return EBUSY;
+    };
+    let source = fs.fs_type().to_str();
+    mounts()
+        .lock()
+        .mount(fs, source, target.trim_end_matches('/'));
+    0
+}
+
+/// 把 fd 对应的文件绑定到一个新的 loop 设备上，返回设备编号 N，
+/// 之后可以通过 mount("/dev/loopN", target, "vfat", ...) 挂载文件中的镜像
+pub fn sys_loop_setup(fd: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_loop_setup",
+        current_task().unwrap().pid.0
+    );
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    if file.is_dir() {
+        return EISDIR;
+    }
+    let Some(inode) = cast_file_to_inode(file) else {
+        return EINVAL;
+    };
+    let index = loop_setup(inode);
+    // 在 devfs 中创建对应的块设备节点
+    if let Some((_, devfs, _)) = FS_MANAGER.lock().find_mount("/dev") {
+        devfs.root.mknod(
+            &format!("loop{}", index),
+            InodeType::BlockDevice,
+            makedev(LOOP_MAJOR, index as u64),
+        );
+    }
+    index as isize
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    trace!("kernel:pid[{}] sys_ioctl", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    // 目前只有 inode 实现了 ioctl，其余文件 (如管道) 一律返回 ENOTTY
+    match cast_file_to_inode(file) {
+        Some(inode) => inode.ioctl(request, arg),
+        None => ENOTTY,
+    }
+}
+
+/// 一次 readv/writev 最多的段数，与 Linux 的 IOV_MAX 相同
+const IOV_MAX: usize = 1024;
+
+/// 从用户空间读出 iovec 数组。段数超过 IOV_MAX 或总长度溢出时返回 EINVAL
+fn read_iovecs(token: usize, iov: usize, iovcnt: usize) -> Result<Vec<Iovec>, isize> {
+    if iovcnt > IOV_MAX {
+        return Err(EINVAL);
+    }
+    if iovcnt > 0 && iov == 0 {
+        return Err(EFAULT);
+    }
+    let mut total: usize = 0;
+    let mut iovecs = Vec::with_capacity(iovcnt);
+    for i in 0..iovcnt {
+        let iovec = *translated_ref(token, (iov as *const Iovec).wrapping_add(i));
+        total = match total.checked_add(iovec.iov_len) {
+            Some(total) if total <= isize::MAX as usize => total,
+            _ => return Err(EINVAL),
+        };
+        iovecs.push(iovec);
+    }
+    Ok(iovecs)
+}
+
+/// 取出 fd 对应的文件，并检查是否可读或可写
+fn file_for_rw(fd: usize, write: bool) -> Result<Arc<dyn File>, isize> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    match inner.fd_table.get(fd) {
+        Some(Some(file)) if (write && file.writable()) || (!write && file.readable()) => {
+            Ok(file.clone())
         }
-        let file = file.clone();
-        let mut total_len = 0;
-        let iovec_size: usize = core::mem::size_of::<Iovec>();
-        for i in 0..iovcnt {
-            unsafe {
-                sstatus::set_sum();
-            }
-            let current = iov.add(iovec_size * i);
-            let iov_base = unsafe { (*(current as *const Iovec)).iov_base };
-            let iov_len = unsafe { (*(current as *const Iovec)).iov_len };
-            let buf = unsafe { core::slice::from_raw_parts(iov_base as *const u8, iov_len) };
-            total_len += file.write(buf);
-            unsafe {
-                sstatus::clear_sum();
+        _ => Err(EBADF),
+    }
+}
+
+/// 依次把各段写入文件，返回写入的总字节数。某一段没有写完时停止
+pub fn sys_writev(fd: usize, iov: usize, iovcnt: usize) -> isize {
+    trace!("kernel:pid[{}] sys_writev", current_task().unwrap().pid.0);
+    let file = match file_for_rw(fd, true) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let token = current_user_token();
+    let iovecs = match read_iovecs(token, iov, iovcnt) {
+        Ok(iovecs) => iovecs,
+        Err(err) => return err,
+    };
+    let mut total = 0;
+    for iovec in iovecs.iter().filter(|iovec| iovec.iov_len > 0) {
+        for chunk in translated_byte_buffer(token, iovec.iov_base as *const u8, iovec.iov_len) {
+            let written = file.write(chunk);
+            total += written;
+            if written < chunk.len() {
+                return total as isize;
             }
         }
+    }
+    total as isize
+}
 
-        total_len as isize
-    } else {
-        EBADF
+/// 依次读入各段，返回读到的总字节数。某一段没有读满 (如到达文件末尾) 时停止
+pub fn sys_readv(fd: usize, iov: usize, iovcnt: usize) -> isize {
+    trace!("kernel:pid[{}] sys_readv", current_task().unwrap().pid.0);
+    let file = match file_for_rw(fd, false) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let token = current_user_token();
+    let iovecs = match read_iovecs(token, iov, iovcnt) {
+        Ok(iovecs) => iovecs,
+        Err(err) => return err,
+    };
+    let mut total = 0;
+    for iovec in iovecs.iter().filter(|iovec| iovec.iov_len > 0) {
+        for chunk in translated_byte_buffer(token, iovec.iov_base as *const u8, iovec.iov_len) {
+            let read = file.read(chunk);
+            total += read;
+            if read < chunk.len() {
+                return total as isize;
+            }
+        }
     }
+    total as isize
 }
 
 const F_DUPFD: i32 = 0;
@@ -531,6 +1258,9 @@ const F_GETFD: i32 = 1;
 const F_SETFD: i32 = 2;
 const F_GETFL: i32 = 3;
 const F_SETFL: i32 = 4;
+const F_GETLK: i32 = 5;
+const F_SETLK: i32 = 6;
+const F_SETLKW: i32 = 7;
 
 pub fn sys_fcntl(fd: usize, cmd: i32, arg: usize) -> isize {
     trace!("kernel:pid[{}] sys_fcntl", current_task().unwrap().pid.0);
@@ -543,69 +1273,436 @@ pub fn sys_fcntl(fd: usize, cmd: i32, arg: usize) -> isize {
         return EBADF;
     }
     match cmd {
-        F_DUPFD => {
-            let new_fd = inner.alloc_fd();
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= MAX_FD {
+                return EINVAL;
+            }
+            let new_fd = inner.alloc_fd_from(arg);
             inner.fd_table[new_fd] = inner.fd_table[fd].clone();
+            let status = inner.fd_flags(fd).status;
+            let cloexec = cmd == F_DUPFD_CLOEXEC;
+            inner.set_fd_flags(new_fd, FdFlags { cloexec, status });
             debug!(
                 "kernel:pid[{}] sys_fcntl F_DUPFD fd:{} => new_fd:{}",
                 task.pid.0, fd, new_fd
             );
             new_fd as isize
         }
-        F_DUPFD_CLOEXEC => {
-            let new_fd = inner.alloc_fd();
-            inner.fd_table[new_fd] = inner.fd_table[fd].clone();
-            // TODO: fix this
-            // inner.fd_table[new_fd].as_mut().unwrap().flags |= OpenFlags::CLOEXEC;
-            debug!(
-                "kernel:pid[{}] sys_fcntl F_DUPFD fd:{} => new_fd:{}",
-                task.pid.0, fd, new_fd
-            );
-            new_fd as isize
+        F_GETFD => {
+            if inner.fd_flags(fd).cloexec {
+                FD_CLOEXEC as isize
+            } else {
+                0
+            }
         }
         F_SETFD => {
-            // TODO: fix this
-            // let flags = OpenFlags::from_bits(arg as u32).ok_or(SyscallErr::EINVAL)?;
-            // inner.fd_table[fd].as_mut().unwrap().flags = flags;
+            let mut flags = inner.fd_flags(fd);
+            flags.cloexec = arg & FD_CLOEXEC != 0;
+            inner.set_fd_flags(fd, flags);
             0
         }
+        F_GETFL => {
+            // 访问模式来自打开的文件，其余是记录下来的状态标志
+            let file = inner.fd_table[fd].as_ref().unwrap();
+            let mode = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::O_RDWR,
+                (false, true) => OpenFlags::O_WRONLY,
+                _ => OpenFlags::O_RDONLY,
+            };
+            (mode | inner.fd_flags(fd).status).bits() as isize
+        }
         F_SETFL => {
-            // TODO: fix this
-            // let flags = OpenFlags::from_bits(arg as u32).ok_or(SyscallErr::EINVAL)?;
-            // inner.fd_table[fd].as_mut().unwrap().flags = flags;
+            // 只有 O_APPEND 和 O_NONBLOCK 可以修改，访问模式等其余的位被忽略
+            let mut flags = inner.fd_flags(fd);
+            flags.status = OpenFlags::from_bits_truncate(arg as i32).status_flags();
+            inner.set_fd_flags(fd, flags);
+            let file = inner.fd_table[fd].as_ref().unwrap().clone();
+            if let Some(file) = cast_file_to_os_inode(file) {
+                file.set_append(flags.status.contains(OpenFlags::O_APPEND));
+            }
             0
         }
-        F_GETFD | F_GETFL => {
-            todo!()
+        F_GETLK | F_SETLK | F_SETLKW => {
+            let file = inner.fd_table[fd].as_ref().unwrap().clone();
+            drop(inner);
+            fcntl_record_lock(file, cmd, arg as *mut Flock)
+        }
+        _ => EINVAL,
+    }
+}
+
+/// fcntl 的 F_GETLK/F_SETLK/F_SETLKW，锁的范围只支持相对文件开头 (SEEK_SET)
+fn fcntl_record_lock(file: Arc<dyn File>, cmd: i32, lock: *mut Flock) -> isize {
+    if lock.is_null() {
+        return EFAULT;
+    }
+    let Some(inode) = cast_file_to_inode(file.clone()) else {
+        return EINVAL;
+    };
+    // F_GETLK 之后把冲突的锁写回同一个结构体
+    prefault_user(lock as usize, size_of::<Flock>(), cmd == F_GETLK);
+    let mut flock = unsafe {
+        sstatus::set_sum();
+        let flock = ptr::read(lock);
+        sstatus::clear_sum();
+        flock
+    };
+    if flock.l_whence != 0 {
+        return EINVAL;
+    }
+    // l_len 为 0 表示一直锁到文件末尾，为负数表示 [l_start + l_len, l_start)
+    let (start, end) = match flock.l_len {
+        0 => (flock.l_start, i64::MAX),
+        len if len > 0 => (flock.l_start, flock.l_start.saturating_add(len)),
+        len => (flock.l_start + len, flock.l_start),
+    };
+    if start < 0 {
+        return EINVAL;
+    }
+    let (start, end) = (
+        start as usize,
+        if end == i64::MAX {
+            usize::MAX
+        } else {
+            end as usize
+        },
+    );
+    let pid = current_task().unwrap().pid.0;
+    match cmd {
+        F_GETLK => {
+            let exclusive = match flock.l_type {
+                F_RDLCK => false,
+                F_WRLCK => true,
+                _ => return EINVAL,
+            };
+            match get_record_lock(lock_key(&inode), pid, start, end, exclusive) {
+                Some(conflict) => {
+                    flock.l_type = if conflict.exclusive { F_WRLCK } else { F_RDLCK };
+                    flock.l_start = conflict.start as i64;
+                    flock.l_len = if conflict.end == usize::MAX {
+                        0
+                    } else {
+                        (conflict.end - conflict.start) as i64
+                    };
+                    flock.l_pid = conflict.pid as i32;
+                }
+                None => flock.l_type = F_UNLCK,
+            }
+            unsafe {
+                sstatus::set_sum();
+                ptr::write(lock, flock);
+                sstatus::clear_sum();
+            }
+            0
         }
         _ => {
-            todo!()
+            // 加读锁要求以可读方式打开，加写锁要求以可写方式打开
+            if (flock.l_type == F_RDLCK && !file.readable())
+                || (flock.l_type == F_WRLCK && !file.writable())
+            {
+                return EBADF;
+            }
+            set_record_lock(
+                lock_key(&inode),
+                pid,
+                start,
+                end,
+                flock.l_type,
+                cmd == F_SETLKW,
+            )
         }
     }
 }
 
-pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: usize, count: usize) -> isize {
+/// 对 fd 对应的文件加/解 advisory 锁
+pub fn sys_flock(fd: usize, operation: i32) -> isize {
+    trace!("kernel:pid[{}] sys_flock", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    // 锁属于打开的文件，dup 出来的 fd 共享同一把锁
+    let owner = Arc::as_ptr(&file) as *const () as usize;
+    let Some(inode) = cast_file_to_inode(file) else {
+        return EINVAL;
+    };
+    flock(lock_key(&inode), owner, operation)
+}
+
+/// 提示内核对 [offset, offset + len) 的访问模式，len 为 0 表示一直到文件末尾
+pub fn sys_fadvise64(fd: usize, offset: isize, len: isize, advice: usize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_sendfile in_fd:{} out_fd:{}",
+        "kernel:pid[{}] sys_fadvise64",
+        current_task().unwrap().pid.0
+    );
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return EBADF;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    drop(inner);
+    if offset < 0 || len < 0 || advice > POSIX_FADV_NOREUSE {
+        return EINVAL;
+    }
+    match cast_file_to_inode(file) {
+        Some(inode) => {
+            inode.fadvise(offset as usize, len as usize, advice);
+            0
+        }
+        None => ESPIPE,
+    }
+}
+
+/// sendfile 在内核栈上使用的中转缓冲区大小
+const SENDFILE_BUF_SIZE: usize = 1024;
+
+/// sendfile syscall
+///
+/// 从 in_fd 读取至多 count 字节写入 out_fd，数据不经过用户空间，out_fd 可以是管道。
+/// `offset` 非空时从 `*offset` 处读取，不移动 in_fd 的读写位置，
+/// 结束后把 `*offset` 更新到拷贝数据之后；为空时从 in_fd 当前位置读取。
+/// 返回实际写出的字节数
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut usize, count: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sendfile in_fd:{} out_fd:{} count:{}",
         current_task().unwrap().pid.0,
         in_fd,
-        out_fd
+        out_fd,
+        count
+    );
+    let in_file = match file_for_rw(in_fd, false) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let out_file = match file_for_rw(out_fd, true) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    if in_file.is_dir() {
+        return EINVAL;
+    }
+    let in_os_inode = cast_file_to_os_inode(in_file.clone());
+    let mut pos = if offset.is_null() {
+        None
+    } else {
+        // 按位置读取要求 in_fd 是普通文件
+        if in_os_inode.is_none() {
+            return ESPIPE;
+        }
+        // 结束后还要写回新的位置
+        prefault_user(offset as usize, size_of::<usize>(), true);
+        unsafe {
+            sstatus::set_sum();
+            let pos = *offset;
+            sstatus::clear_sum();
+            Some(pos)
+        }
+    };
+
+    let mut buf = [0u8; SENDFILE_BUF_SIZE];
+    let mut sent = 0;
+    while sent < count {
+        let chunk = min(buf.len(), count - sent);
+        let read_size = match (pos, in_os_inode.as_ref()) {
+            (Some(pos), Some(os_inode)) => match os_inode.read_at(pos, &mut buf[..chunk]) {
+                Some(size) => size,
+                None => return ESPIPE,
+            },
+            _ => in_file.read(&mut buf[..chunk]),
+        };
+        if read_size == 0 {
+            break;
+        }
+        let write_size = out_file.write(&buf[..read_size]);
+        sent += write_size;
+        if let Some(pos) = pos.as_mut() {
+            *pos += write_size;
+        }
+        if write_size < read_size {
+            // 读出但没有写出的数据退回给 in_fd
+            if let (None, Some(os_inode)) = (pos, in_os_inode.as_ref()) {
+                os_inode.lseek(write_size as isize - read_size as isize, SEEK_CUR);
+            }
+            break;
+        }
+    }
+
+    if let Some(pos) = pos {
+        unsafe {
+            sstatus::set_sum();
+            *offset = pos;
+            sstatus::clear_sum();
+        }
+    }
+    sent as isize
+}
+
+/// copy_file_range syscall
+///
+/// 在内核态完成两个文件之间的数据拷贝，不经过用户缓冲区。
+/// `off_in`/`off_out` 为空时使用文件自身的读写位置，否则从给定偏移处拷贝并回写新的偏移。
+/// TODO: 同一文件系统内可以通过共享簇 (COW) 避免真正的数据拷贝
+pub fn sys_copy_file_range(
+    fd_in: usize, off_in: *mut usize, fd_out: usize, off_out: *mut usize, len: usize, flags: u32,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_copy_file_range fd_in:{} fd_out:{} len:{}",
+        current_task().unwrap().pid.0,
+        fd_in,
+        fd_out,
+        len
     );
+    if flags != 0 {
+        return EINVAL;
+    }
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access(file!(), line!());
-    if out_fd >= inner.fd_table.len() || in_fd >= inner.fd_table.len() {
+    if fd_in >= inner.fd_table.len() || fd_out >= inner.fd_table.len() {
         return EBADF;
     }
-    if inner.fd_table[out_fd].is_none() || inner.fd_table[in_fd].is_none() {
+    if inner.fd_table[fd_in].is_none() || inner.fd_table[fd_out].is_none() {
         return EBADF;
     }
-    let out_file = inner.fd_table[out_fd].as_ref().unwrap().clone();
-    let in_file = inner.fd_table[in_fd].as_ref().unwrap().clone();
-    let mut buf = vec![0u8; 10000];
+    let in_file = inner.fd_table[fd_in].as_ref().unwrap().clone();
+    let out_file = inner.fd_table[fd_out].as_ref().unwrap().clone();
     drop(inner);
-    let read_size = in_file.read(&mut buf);
-    // warn!("buf: {:?}", buf,);
-    let ret = out_file.write(&buf[..read_size]) as isize;
-    error!("count: {}, write size: {}", count, ret);
-    ret
+    if !in_file.readable() || !out_file.writable() {
+        return EBADF;
+    }
+    // 管道等非 inode 文件没有 fstat，先排除掉再判断目录
+    let in_inode = cast_file_to_inode(in_file.clone());
+    let out_inode = cast_file_to_inode(out_file.clone());
+    if in_inode.is_none() || out_inode.is_none() {
+        return EINVAL;
+    }
+    let in_inode = in_inode.unwrap();
+    let out_inode = out_inode.unwrap();
+    if in_file.is_dir() || out_file.is_dir() {
+        return EISDIR;
+    }
+
+    let mut pos_in = if off_in.is_null() {
+        None
+    } else {
+        prefault_user(off_in as usize, size_of::<usize>(), true);
+        unsafe {
+            sstatus::set_sum();
+            let pos = *off_in;
+            sstatus::clear_sum();
+            Some(pos)
+        }
+    };
+    let mut pos_out = if off_out.is_null() {
+        None
+    } else {
+        prefault_user(off_out as usize, size_of::<usize>(), true);
+        unsafe {
+            sstatus::set_sum();
+            let pos = *off_out;
+            sstatus::clear_sum();
+            Some(pos)
+        }
+    };
+
+    let mut buf = vec![0u8; 4096];
+    let mut copied = 0;
+    while copied < len {
+        let chunk = min(buf.len(), len - copied);
+        let read_size = match pos_in {
+            Some(pos) => in_inode.read_at(pos, &mut buf[..chunk]),
+            None => in_file.read(&mut buf[..chunk]),
+        };
+        if read_size == 0 {
+            break;
+        }
+        let write_size = match pos_out {
+            Some(pos) => out_inode.write_at(pos, &buf[..read_size]),
+            None => out_file.write(&buf[..read_size]),
+        };
+        if let Some(pos) = pos_in.as_mut() {
+            *pos += write_size;
+        }
+        if let Some(pos) = pos_out.as_mut() {
+            *pos += write_size;
+        }
+        copied += write_size;
+        if write_size < read_size {
+            break;
+        }
+    }
+
+    unsafe {
+        sstatus::set_sum();
+        if let Some(pos) = pos_in {
+            *off_in = pos;
+        }
+        if let Some(pos) = pos_out {
+            *off_out = pos;
+        }
+        sstatus::clear_sum();
+    }
+    copied as isize
+}
+
+/// mknodat syscall
+///
+/// 目前只支持在 devfs 中创建字符设备和块设备节点，普通文件交给 open_file 创建
+pub fn sys_mknodat(dirfd: i32, path: *const u8, mode: u32, dev: u64) -> isize {
+    trace!("kernel:pid[{}] sys_mknodat", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    let inode = if dirfd == AT_FDCWD {
+        inner.work_dir.inode()
+    } else {
+        let dirfd = dirfd as usize;
+        if dirfd >= inner.fd_table.len() || inner.fd_table[dirfd].is_none() {
+            return EBADF;
+        }
+        let dir = inner.fd_table[dirfd].as_ref().unwrap().clone();
+        if !dir.is_dir() {
+            return ENOTDIR;
+        }
+        match cast_file_to_inode(dir) {
+            Some(inode) => inode,
+            None => return ENOTDIR,
+        }
+    };
+    let privileged = inner.cred.is_privileged();
+    drop(inner);
+    let path = c_ptr_to_string(path);
+    let type_ = match mode & StatMode::TYPE_MASK.bits() {
+        0 => InodeType::Regular,
+        m if m == StatMode::FILE.bits() => InodeType::Regular,
+        m if m == StatMode::CHAR.bits() => InodeType::CharDevice,
+        m if m == StatMode::BLOCK.bits() => InodeType::BlockDevice,
+        _ => return EINVAL,
+    };
+    if type_ == InodeType::Regular {
+        if open_file(inode.clone(), &path, OpenFlags::O_RDONLY).is_some() {
+            return EEXIST;
+        }
+        return match open_file(inode, &path, OpenFlags::O_CREAT) {
+            Some(_) => 0,
+            None => ENOENT,
+        };
+    }
+    // 创建设备节点需要 root 权限
+    if !privileged {
+        return EPERM;
+    }
+    let (parent, name) = match open_parent(inode, &path) {
+        Ok(parent) => parent,
+        Err(errno) => return errno,
+    };
+    if parent.clone().lookup(name).is_some() {
+        return EEXIST;
+    }
+    match parent.mknod(name, type_, dev) {
+        Some(_) => 0,
+        None => EPERM,
+    }
 }
@@ -17,25 +17,42 @@ pub const SYSCALL_DUP: usize = 23;
 pub const SYSCALL_DUP3: usize = 24;
 pub const SYSCALL_FCNTL: usize = 25;
 pub const SYSCALL_IOCTL: usize = 29;
+pub const SYSCALL_FLOCK: usize = 32;
+pub const SYSCALL_MKNODAT: usize = 33;
 pub const SYSCALL_MKDIRAT: usize = 34;
 pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
 pub const SYSCALL_UMOUNT2: usize = 39;
 pub const SYSCALL_MOUNT: usize = 40;
+pub const SYSCALL_TRUNCATE: usize = 45;
+pub const SYSCALL_FTRUNCATE: usize = 46;
 pub const SYSCALL_CHDIR: usize = 49;
+pub const SYSCALL_FCHDIR: usize = 50;
+pub const SYSCALL_CHROOT: usize = 51;
+pub const SYSCALL_FCHMOD: usize = 52;
+pub const SYSCALL_FCHMODAT: usize = 53;
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
 pub const SYSCALL_GETDENTS64: usize = 61;
+pub const SYSCALL_LSEEK: usize = 62;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_READV: usize = 65;
 pub const SYSCALL_WRITEV: usize = 66;
+pub const SYSCALL_PREAD64: usize = 67;
+pub const SYSCALL_PWRITE64: usize = 68;
 pub const SYSCALL_SENDFILE: usize = 71;
 pub const SYSCALL_PPOLL: usize = 73;
 pub const SYSCALL_FSTAT: usize = 80;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_EXIT_GROUP: usize = 94;
+pub const SYSCALL_WAITID: usize = 95;
 pub const SYSCALL_SETTID: usize = 96;
-pub const SYSCALL_SLEEP: usize = 101;
+pub const SYSCALL_UNSHARE: usize = 97;
+pub const SYSCALL_FUTEX: usize = 98;
+pub const SYSCALL_SET_ROBUST_LIST: usize = 99;
+pub const SYSCALL_GET_ROBUST_LIST: usize = 100;
+pub const SYSCALL_NANOSLEEP: usize = 101;
 pub const SYSCALL_CLOCK_GETTIME: usize = 113;
 pub const SYSCALL_YIELD: usize = 124;
 pub const SYSCALL_KILL: usize = 129;
@@ -43,8 +60,18 @@ pub const SYSCALL_SIGACTION: usize = 134;
 pub const SYSCALL_SIGPROCMASK: usize = 135;
 pub const SYSCALL_SIGTIMEDWAIT: usize = 137;
 pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETGID: usize = 144;
+pub const SYSCALL_SETUID: usize = 146;
+pub const SYSCALL_SETRESUID: usize = 147;
+pub const SYSCALL_SETRESGID: usize = 149;
 pub const SYSCALL_TIMES: usize = 153;
+pub const SYSCALL_SETSID: usize = 157;
+pub const SYSCALL_GETGROUPS: usize = 158;
+pub const SYSCALL_SETGROUPS: usize = 159;
+pub const SYSCALL_GETCPU: usize = 168;
 pub const SYSCALL_UNAME: usize = 160;
+pub const SYSCALL_SETHOSTNAME: usize = 161;
+pub const SYSCALL_SETDOMAINNAME: usize = 162;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETPPID: usize = 173;
@@ -57,10 +84,21 @@ pub const SYSCALL_CLONE: usize = 220;
 pub const SYSCALL_EXECVE: usize = 221;
 pub const SYSCALL_WAIT4: usize = 260;
 pub const SYSCALL_PRLIMIT64: usize = 261;
+pub const SYSCALL_GETRANDOM: usize = 278;
+pub const SYSCALL_COPY_FILE_RANGE: usize = 285;
+pub const SYSCALL_PIDFD_SEND_SIGNAL: usize = 424;
+pub const SYSCALL_MOVE_MOUNT: usize = 429;
+pub const SYSCALL_FSOPEN: usize = 430;
+pub const SYSCALL_FSCONFIG: usize = 431;
+pub const SYSCALL_FSMOUNT: usize = 432;
+pub const SYSCALL_PIDFD_OPEN: usize = 434;
+pub const SYSCALL_CLONE3: usize = 435;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
 pub const SYSCALL_BRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_FADVISE64: usize = 223;
+pub const SYSCALL_MPROTECT: usize = 226;
 pub const SYSCALL_SPAWN: usize = 400;
 /*
 pub const SYSCALL_MAIL_READ: usize = 401;
@@ -68,6 +106,9 @@ pub const SYSCALL_MAIL_WRITE: usize = 402;
 */
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_SYSCTL: usize = 411;
+pub const SYSCALL_LOOP_SETUP: usize = 412;
+pub const SYSCALL_HEAP_INFO: usize = 413;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -89,126 +130,328 @@ mod sync;
 mod thread;
 mod time;
 
+use alloc::vec::Vec;
+
+use errno::ENOSYS;
 use fs::*;
+use lazy_static::lazy_static;
 use ppoll::{sys_ppoll, PollFd};
 use process::*;
+pub use process::{clone3_args_test, getcpu_test, random_seed_test};
 use signal::{sys_sigaction, sys_sigprocmask, sys_sigtimedwait};
+use sync::{
+    sys_futex,
+    sys_mutex_create,
+    sys_mutex_lock,
+    sys_mutex_unlock,
+    sys_semaphore_create,
+    sys_semaphore_down,
+    sys_semaphore_up,
+};
 use thread::*;
-use time::sys_clock_gettime;
+use time::{sys_clock_gettime, sys_nanosleep};
 
 use crate::{
+    config::MAX_SYSCALL_NUM,
     fs::inode::Stat,
+    mm::HeapInfo,
     task::{current_task, sigaction::SignalAction, signal::SigInfo, SignalFlags},
     timer::TimeSpec,
+    trap::TrapContext,
 };
 
+/// 系统调用处理函数，统一接收 a0-a5 六个参数寄存器
+type SyscallHandler = fn([usize; 6]) -> isize;
+
+/// 系统调用表项
+struct SyscallEntry {
+    name:    &'static str,
+    /// 系统调用实际使用的参数个数，多余的参数寄存器在分发前会被清零
+    arity:   usize,
+    handler: SyscallHandler,
+}
+
+impl SyscallEntry {
+    /// 清零多余的参数寄存器后调用处理函数
+    fn dispatch(&self, mut args: [usize; 6]) -> isize {
+        args[self.arity..].fill(0);
+        trace!("syscall {} args: {:x?}", self.name, &args[..self.arity]);
+        (self.handler)(args)
+    }
+}
+
+/// 以系统调用号为下标构建系统调用表，同一调用号重复注册或参数个数超过 6 时直接 panic
+macro_rules! syscall_table {
+    ($($id:expr => ($name:literal, $arity:literal, $handler:expr)),* $(,)?) => {{
+        let mut table: Vec<Option<SyscallEntry>> = Vec::new();
+        table.resize_with(MAX_SYSCALL_NUM, || None);
+        $(
+            assert!($arity <= 6, "syscall {} takes at most 6 arguments", $name);
+            assert!(table[$id].is_none(), "duplicate syscall id {}", $id);
+            table[$id] = Some(SyscallEntry {
+                name:    $name,
+                arity:   $arity,
+                handler: $handler,
+            });
+        )*
+        table
+    }};
+}
+
+lazy_static! {
+    static ref SYSCALL_TABLE: Vec<Option<SyscallEntry>> = syscall_table! {
+        SYSCALL_GETCWD => ("getcwd", 2, |a| sys_getcwd(a[0] as *mut u8, a[1])),
+        SYSCALL_DUP => ("dup", 1, |a| sys_dup(a[0])),
+        SYSCALL_DUP3 => ("dup3", 2, |a| sys_dup3(a[0], a[1])),
+        SYSCALL_LINKAT => ("linkat", 4, |a| sys_linkat(a[1] as *const u8, a[3] as *const u8)),
+        SYSCALL_UNLINKAT => ("unlinkat", 2, |a| sys_unlinkat(a[1] as *const u8)),
+        SYSCALL_OPENAT => ("openat", 3, |a| {
+            sys_openat(a[0] as i32, a[1] as *const u8, a[2] as i32)
+        }),
+        SYSCALL_CLOSE => ("close", 1, |a| sys_close(a[0])),
+        SYSCALL_PIPE => ("pipe", 1, |a| sys_pipe(a[0] as *mut u32)),
+        SYSCALL_LSEEK => ("lseek", 3, |a| sys_lseek(a[0], a[1] as isize, a[2])),
+        SYSCALL_READ => ("read", 3, |a| sys_read(a[0], a[1] as *mut u8, a[2])),
+        SYSCALL_WRITE => ("write", 3, |a| sys_write(a[0], a[1] as *const u8, a[2])),
+        SYSCALL_READV => ("readv", 3, |a| sys_readv(a[0], a[1], a[2])),
+        SYSCALL_WRITEV => ("writev", 3, |a| sys_writev(a[0], a[1], a[2])),
+        SYSCALL_PREAD64 => ("pread64", 4, |a| {
+            sys_pread64(a[0], a[1] as *mut u8, a[2], a[3] as isize)
+        }),
+        SYSCALL_PWRITE64 => ("pwrite64", 4, |a| {
+            sys_pwrite64(a[0], a[1] as *const u8, a[2], a[3] as isize)
+        }),
+        SYSCALL_FSTAT => ("fstat", 2, |a| sys_fstat(a[0], a[1] as *mut Stat)),
+        SYSCALL_EXIT => ("exit", 1, |a| sys_exit(a[0] as i32)),
+        SYSCALL_EXIT_GROUP => ("exit_group", 1, |a| sys_exit_group(a[0] as i32)),
+        SYSCALL_SETTID => ("set_tid_address", 1, |a| sys_set_tid_address(a[0])),
+        SYSCALL_FUTEX => ("futex", 6, |a| sys_futex(a[0], a[1], a[2], a[3], a[4], a[5])),
+        SYSCALL_SET_ROBUST_LIST => ("set_robust_list", 2, |a| sys_set_robust_list(a[0], a[1])),
+        SYSCALL_GET_ROBUST_LIST => ("get_robust_list", 3, |a| {
+            sys_get_robust_list(a[0], a[1] as *mut usize, a[2] as *mut usize)
+        }),
+        SYSCALL_NANOSLEEP => ("nanosleep", 2, |a| {
+            sys_nanosleep(a[0] as *const TimeSpec, a[1] as *mut TimeSpec)
+        }),
+        SYSCALL_CLOCK_GETTIME => ("clock_gettime", 2, |a| {
+            sys_clock_gettime(a[0], a[1] as *mut TimeSpec)
+        }),
+        SYSCALL_YIELD => ("sched_yield", 0, |_| sys_yield()),
+        SYSCALL_TIMES => ("times", 1, |a| sys_times(a[0] as *mut Tms)),
+        SYSCALL_SETSID => ("setsid", 0, |_| sys_setsid()),
+        SYSCALL_UNAME => ("uname", 1, |a| sys_uname(a[0] as *mut Utsname)),
+        SYSCALL_SETHOSTNAME => ("sethostname", 2, |a| sys_sethostname(a[0] as *const u8, a[1])),
+        SYSCALL_UNSHARE => ("unshare", 1, |a| sys_unshare(a[0] as u32)),
+        SYSCALL_SETDOMAINNAME => ("setdomainname", 2, |a| {
+            sys_setdomainname(a[0] as *const u8, a[1])
+        }),
+        SYSCALL_GETCPU => ("getcpu", 3, |a| {
+            sys_getcpu(a[0] as *mut u32, a[1] as *mut u32, a[2] as *mut u8)
+        }),
+        SYSCALL_GETRANDOM => ("getrandom", 3, |a| {
+            sys_getrandom(a[0] as *mut u8, a[1], a[2] as u32)
+        }),
+        SYSCALL_GETPID => ("getpid", 0, |_| sys_getpid()),
+        SYSCALL_GETPPID => ("getppid", 0, |_| sys_getppid()),
+        SYSCALL_GETUID => ("getuid", 0, |_| sys_getuid()),
+        SYSCALL_GETEUID => ("geteuid", 0, |_| sys_geteuid()),
+        SYSCALL_GETGID => ("getgid", 0, |_| sys_getgid()),
+        SYSCALL_GETEGID => ("getegid", 0, |_| sys_getegid()),
+        SYSCALL_SETUID => ("setuid", 1, |a| sys_setuid(a[0] as u32)),
+        SYSCALL_SETGID => ("setgid", 1, |a| sys_setgid(a[0] as u32)),
+        SYSCALL_SETRESUID => ("setresuid", 3, |a| {
+            sys_setresuid(a[0] as u32, a[1] as u32, a[2] as u32)
+        }),
+        SYSCALL_SETRESGID => ("setresgid", 3, |a| {
+            sys_setresgid(a[0] as u32, a[1] as u32, a[2] as u32)
+        }),
+        SYSCALL_GETTID => ("gettid", 0, |_| sys_gettid()),
+        SYSCALL_GETGROUPS => ("getgroups", 2, |a| sys_getgroups(a[0], a[1] as *mut u32)),
+        SYSCALL_SETGROUPS => ("setgroups", 2, |a| sys_setgroups(a[0], a[1] as *const u32)),
+        SYSCALL_SIGACTION => ("rt_sigaction", 3, |a| {
+            sys_sigaction(a[0], a[1] as *const SignalAction, a[2] as *mut SignalAction)
+        }),
+        SYSCALL_SIGPROCMASK => ("rt_sigprocmask", 3, |a| {
+            sys_sigprocmask(a[0], a[1] as *mut usize, a[2] as *mut usize, false)
+        }),
+        SYSCALL_SIGTIMEDWAIT => ("rt_sigtimedwait", 4, |a| {
+            sys_sigtimedwait(
+                a[0] as *mut usize,
+                a[1] as *mut SigInfo,
+                a[2] as *const TimeSpec,
+                a[3],
+            )
+        }),
+        SYSCALL_CLONE => ("clone", 5, |a| {
+            sys_clone(a[0], a[1], a[2] as *mut usize, a[3], a[4] as *mut usize)
+        }),
+        SYSCALL_PIDFD_OPEN => ("pidfd_open", 2, |a| sys_pidfd_open(a[0], a[1] as u32)),
+        SYSCALL_PIDFD_SEND_SIGNAL => ("pidfd_send_signal", 4, |a| {
+            sys_pidfd_send_signal(a[0], a[1], a[2] as *const SigInfo, a[3] as u32)
+        }),
+        SYSCALL_CLONE3 => ("clone3", 2, |a| sys_clone3(a[0] as *const CloneArgs, a[1])),
+        SYSCALL_BRK => ("brk", 1, |a| sys_brk(a[0])),
+        SYSCALL_EXECVE => ("execve", 3, |a| {
+            sys_execve(a[0] as *const u8, a[1] as *const usize, a[2] as *const usize)
+        }),
+        SYSCALL_WAITID => ("waitid", 4, |a| {
+            sys_waitid(a[0] as u32, a[1], a[2] as *mut SigInfo, a[3] as u32)
+        }),
+        SYSCALL_WAIT4 => ("wait4", 4, |a| {
+            sys_wait4(a[0] as isize, a[1] as *mut i32, a[2] as u32, a[3])
+        }),
+        SYSCALL_GETTIMEOFDAY => ("gettimeofday", 2, |a| {
+            sys_gettimeofday(a[0] as *mut TimeVal, a[1])
+        }),
+        SYSCALL_MMAP => ("mmap", 6, |a| sys_mmap(a[0], a[1], a[2], a[3], a[4], a[5])),
+        SYSCALL_FADVISE64 => ("fadvise64", 4, |a| {
+            sys_fadvise64(a[0], a[1] as isize, a[2] as isize, a[3])
+        }),
+        SYSCALL_MUNMAP => ("munmap", 2, |a| sys_munmap(a[0], a[1])),
+        SYSCALL_MPROTECT => ("mprotect", 3, |a| sys_mprotect(a[0], a[1], a[2])),
+        SYSCALL_SET_PRIORITY => ("set_priority", 1, |a| sys_set_priority(a[0] as isize)),
+        SYSCALL_TASK_INFO => ("task_info", 1, |a| sys_task_info(a[0] as *mut TaskInfo)),
+        SYSCALL_SYSCTL => ("sysctl", 3, |a| {
+            sys_sysctl(a[0], a[1] as *const usize, a[2] as *mut usize)
+        }),
+        SYSCALL_FSOPEN => ("fsopen", 2, |a| sys_fsopen(a[0] as *const u8, a[1] as u32)),
+        SYSCALL_FSCONFIG => ("fsconfig", 5, |a| {
+            sys_fsconfig(
+                a[0],
+                a[1] as u32,
+                a[2] as *const u8,
+                a[3] as *const u8,
+                a[4] as i32,
+            )
+        }),
+        SYSCALL_FSMOUNT => ("fsmount", 3, |a| sys_fsmount(a[0], a[1] as u32, a[2] as u32)),
+        SYSCALL_MOVE_MOUNT => ("move_mount", 5, |a| {
+            sys_move_mount(
+                a[0],
+                a[1] as *const u8,
+                a[2] as i32,
+                a[3] as *const u8,
+                a[4] as u32,
+            )
+        }),
+        SYSCALL_LOOP_SETUP => ("loop_setup", 1, |a| sys_loop_setup(a[0])),
+        SYSCALL_HEAP_INFO => ("heap_info", 1, |a| sys_heap_info(a[0] as *mut HeapInfo)),
+        SYSCALL_SPAWN => ("spawn", 1, |a| sys_spawn(a[0] as *const u8)),
+        SYSCALL_THREAD_CREATE => ("thread_create", 2, |a| sys_thread_create(a[0], a[1])),
+        SYSCALL_WAITTID => ("waittid", 1, |a| sys_waittid(a[0]) as isize),
+        SYSCALL_MUTEX_CREATE => ("mutex_create", 1, |a| sys_mutex_create(a[0] == 1)),
+        SYSCALL_MUTEX_LOCK => ("mutex_lock", 1, |a| sys_mutex_lock(a[0])),
+        SYSCALL_MUTEX_UNLOCK => ("mutex_unlock", 1, |a| sys_mutex_unlock(a[0])),
+        SYSCALL_SEMAPHORE_CREATE => ("semaphore_create", 1, |a| sys_semaphore_create(a[0])),
+        SYSCALL_SEMAPHORE_UP => ("semaphore_up", 1, |a| sys_semaphore_up(a[0])),
+        SYSCALL_SEMAPHORE_DOWN => ("semaphore_down", 1, |a| sys_semaphore_down(a[0])),
+        // SYSCALL_CONDVAR_CREATE => ("condvar_create", 0, |_| sys_condvar_create()),
+        // SYSCALL_CONDVAR_SIGNAL => ("condvar_signal", 1, |a| sys_condvar_signal(a[0])),
+        // SYSCALL_CONDVAR_WAIT => ("condvar_wait", 2, |a| sys_condvar_wait(a[0], a[1])),
+        SYSCALL_KILL => ("kill", 2, |a| sys_kill(a[0], a[1] as u32)),
+        SYSCALL_TRUNCATE => ("truncate", 2, |a| sys_truncate(a[0] as *const u8, a[1] as isize)),
+        SYSCALL_FTRUNCATE => ("ftruncate", 2, |a| sys_ftruncate(a[0], a[1] as isize)),
+        SYSCALL_CHDIR => ("chdir", 1, |a| sys_chdir(a[0] as *const u8)),
+        SYSCALL_FCHDIR => ("fchdir", 1, |a| sys_fchdir(a[0])),
+        SYSCALL_CHROOT => ("chroot", 1, |a| sys_chroot(a[0] as *const u8)),
+        SYSCALL_FCHMOD => ("fchmod", 2, |a| sys_fchmod(a[0], a[1] as u32)),
+        SYSCALL_FCHMODAT => ("fchmodat", 4, |a| {
+            sys_fchmodat(a[0] as i32, a[1] as *const u8, a[2] as u32, a[3] as i32)
+        }),
+        SYSCALL_MKDIRAT => ("mkdirat", 3, |a| {
+            sys_mkdirat(a[0] as i32, a[1] as *const u8, a[2] as u32)
+        }),
+        SYSCALL_MKNODAT => ("mknodat", 4, |a| {
+            sys_mknodat(a[0] as i32, a[1] as *const u8, a[2] as u32, a[3] as u64)
+        }),
+        SYSCALL_GETDENTS64 => ("getdents64", 3, |a| {
+            sys_getdents64(a[0], a[1] as *mut u8, a[2])
+        }),
+        SYSCALL_UMOUNT2 => ("umount2", 2, |a| sys_umount2(a[0] as *const u8, a[1] as i32)),
+        SYSCALL_MOUNT => ("mount", 5, |a| {
+            sys_mount(
+                a[0] as *const u8,
+                a[1] as *const u8,
+                a[2] as *const u8,
+                a[3] as u32,
+                a[4] as *const u8,
+            )
+        }),
+        SYSCALL_IOCTL => ("ioctl", 3, |a| sys_ioctl(a[0], a[1], a[2])),
+        SYSCALL_FCNTL => ("fcntl", 3, |a| sys_fcntl(a[0], a[1] as i32, a[2])),
+        SYSCALL_FLOCK => ("flock", 2, |a| sys_flock(a[0], a[1] as i32)),
+        SYSCALL_PPOLL => ("ppoll", 4, |a| {
+            sys_ppoll(
+                a[0] as *mut PollFd,
+                a[1],
+                a[2] as *const TimeSpec,
+                a[3] as *const SignalFlags,
+            )
+        }),
+        SYSCALL_SENDFILE => ("sendfile", 4, |a| {
+            sys_sendfile(a[0], a[1], a[2] as *mut usize, a[3])
+        }),
+        SYSCALL_COPY_FILE_RANGE => ("copy_file_range", 6, |a| {
+            sys_copy_file_range(
+                a[0],
+                a[1] as *mut usize,
+                a[2],
+                a[3] as *mut usize,
+                a[4],
+                a[5] as u32,
+            )
+        }),
+        SYSCALL_PRLIMIT64 => ("prlimit64", 4, |_| 0),
+    };
+}
+
+/// handle syscall exception, fetching `syscall_id` (a7) and arguments (a0-a5) from the trap context
+pub fn syscall_from_cx(cx: &TrapContext) -> isize {
+    syscall(
+        cx.x[17],
+        [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+    )
+}
+
 /// handle syscall exception with `syscall_id` and other arguments
+///
+/// 未实现的系统调用号返回 ENOSYS
 pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    let entry = match SYSCALL_TABLE.get(syscall_id) {
+        Some(Some(entry)) => entry,
+        _ => {
+            warn!("Unsupported syscall_id: {}", syscall_id);
+            return ENOSYS;
+        }
+    };
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access(file!(), line!());
     inner.syscall_times[syscall_id] += 1;
     drop(inner);
     drop(task);
-    match syscall_id {
-        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
-        SYSCALL_DUP => sys_dup(args[0]),
-        SYSCALL_DUP3 => sys_dup3(args[0], args[1]),
-        SYSCALL_LINKAT => sys_linkat(args[1] as *const u8, args[3] as *const u8),
-        SYSCALL_UNLINKAT => sys_unlinkat(args[1] as *const u8),
-        SYSCALL_OPENAT => sys_openat(args[0] as i32, args[1] as *const u8, args[2] as i32),
-        SYSCALL_CLOSE => sys_close(args[0]),
-        SYSCALL_PIPE => sys_pipe(args[0] as *mut u32),
-        SYSCALL_READ => sys_read(args[0], args[1] as *mut u8, args[2]),
-        SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_WRITEV => sys_writev(args[0], args[1], args[2]),
-        SYSCALL_FSTAT => sys_fstat(args[0], args[1] as *mut Stat),
-        SYSCALL_EXIT => sys_exit(args[0] as i32),
-        SYSCALL_EXIT_GROUP => sys_exit_group(args[0] as i32),
-        SYSCALL_SETTID => sys_set_tid_address(args[0]),
-        // SYSCALL_SLEEP => sys_sleep(args[0] as *const u64, args[1] as *mut u64),
-        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut TimeSpec),
-        SYSCALL_YIELD => sys_yield(),
-        SYSCALL_TIMES => sys_times(args[0] as *mut Tms),
-        SYSCALL_UNAME => sys_uname(args[0] as *mut Utsname),
-        SYSCALL_GETPID => sys_getpid(),
-        SYSCALL_GETPPID => sys_getppid(),
-        SYSCALL_GETUID => sys_getuid(),
-        SYSCALL_GETEUID => sys_geteuid(),
-        SYSCALL_GETGID => sys_getgid(),
-        SYSCALL_GETEGID => sys_getegid(),
-        SYSCALL_GETTID => sys_gettid(),
-        SYSCALL_SIGACTION => sys_sigaction(
-            args[0],
-            args[1] as *const SignalAction,
-            args[2] as *mut SignalAction,
-        ),
-        SYSCALL_SIGPROCMASK => {
-            sys_sigprocmask(args[0], args[1] as *mut usize, args[2] as *mut usize, false)
+    entry.dispatch(args)
+}
+
+/// 未注册的调用号直接返回 ENOSYS，分发时超出参数个数的寄存器被清零
+#[allow(unused)]
+pub fn syscall_table_test() {
+    assert_eq!(syscall(MAX_SYSCALL_NUM - 1, [0; 6]), ENOSYS);
+    assert_eq!(syscall(MAX_SYSCALL_NUM + 1, [0; 6]), ENOSYS);
+    for (id, entry) in SYSCALL_TABLE.iter().enumerate() {
+        if let Some(entry) = entry {
+            assert!(entry.arity <= 6 && !entry.name.is_empty(), "syscall {}", id);
         }
-        SYSCALL_SIGTIMEDWAIT => sys_sigtimedwait(
-            args[0] as *mut usize,
-            args[1] as *mut SigInfo,
-            args[2] as *const TimeSpec,
-            args[3],
-        ),
-        SYSCALL_CLONE => sys_clone(
-            args[0],
-            args[1],
-            args[2] as *mut usize,
-            args[3],
-            args[4] as *mut usize,
-        ),
-        SYSCALL_BRK => sys_brk(args[0]),
-        SYSCALL_EXECVE => sys_execve(
-            args[0] as *const u8,
-            args[1] as *const usize,
-            args[2] as *const usize,
-        ),
-        SYSCALL_WAIT4 => sys_wait4(
-            args[0] as isize,
-            args[1] as *mut i32,
-            args[2] as u32,
-            args[3],
-        ),
-        SYSCALL_GETTIMEOFDAY => sys_gettimeofday(args[0] as *mut TimeVal, args[1]),
-        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
-        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
-        SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
-        SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
-        SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
-        SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
-        SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
-        // SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
-        // SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
-        // SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
-        // SYSCALL_SEMAPHORE_CREATE => sys_semaphore_create(args[0]),
-        // SYSCALL_SEMAPHORE_UP => sys_semaphore_up(args[0]),
-        // SYSCALL_SEMAPHORE_DOWN => sys_semaphore_down(args[0]),
-        // SYSCALL_CONDVAR_CREATE => sys_condvar_create(),
-        // SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
-        // SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
-        SYSCALL_KILL => sys_kill(args[0], args[1] as u32),
-        SYSCALL_CHDIR => sys_chdir(args[0] as *const u8),
-        SYSCALL_MKDIRAT => sys_mkdirat64(args[0] as i32, args[1] as *const u8, args[2] as u32),
-        SYSCALL_GETDENTS64 => sys_getdents64(args[0] as i32, args[1] as *mut u8, args[2]),
-        SYSCALL_UMOUNT2 => sys_umount2(args[0] as *const u8, args[1] as i32),
-        SYSCALL_MOUNT => sys_mount(
-            args[0] as *const u8,
-            args[1] as *const u8,
-            args[2] as *const u8,
-            args[3] as u32,
-            args[4] as *const u8,
-        ),
-        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
-        SYSCALL_FCNTL => sys_fcntl(args[0], args[1] as i32, args[2]),
-        SYSCALL_PPOLL => sys_ppoll(
-            args[0] as *mut PollFd,
-            args[1],
-            args[2] as *const TimeSpec,
-            args[3] as *const SignalFlags,
-        ),
-        SYSCALL_SENDFILE => sys_sendfile(args[0], args[1], args[2], args[3]),
-        SYSCALL_PRLIMIT64 => 0,
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
+    let arity = |id: usize| SYSCALL_TABLE[id].as_ref().unwrap().arity;
+    assert_eq!(arity(SYSCALL_GETPID), 0);
+    assert_eq!(arity(SYSCALL_SENDFILE), 4);
+    assert_eq!(arity(SYSCALL_COPY_FILE_RANGE), 6);
+
+    let probe = SyscallEntry {
+        name:    "probe",
+        arity:   2,
+        handler: |a| (a[0] + a[1] + a[2] + a[3] + a[4] + a[5]) as isize,
+    };
+    assert_eq!(probe.dispatch([1, 2, 3, 4, 5, 6]), 3);
+    info!("syscall_table_test passed!");
 }
@@ -1,29 +1,58 @@
-use alloc::{string::String, sync::Arc, vec::Vec};
-use core::{borrow::BorrowMut, mem::size_of, ptr};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    borrow::BorrowMut,
+    mem::{size_of, size_of_val},
+    ptr,
+};
 
+use lazy_static::lazy_static;
 use riscv::register::{satp, sstatus};
+use spin::Mutex;
 
 #[allow(unused)]
-use super::errno::{EINVAL, EPERM, SUCCESS};
+use super::errno::{E2BIG, EINVAL, EPERM, SUCCESS};
 use crate::{
     config::*,
-    fs::{defs::OpenFlags, dentry, open_file, ROOT_INODE},
-    mm::{translated_byte_buffer, translated_refmut, VirtAddr},
-    syscall::errno::{ECHILD, ENOENT, ESRCH},
+    fs::{
+        defs::{FdFlags, OpenFlags},
+        dentry,
+        file::cast_file_to_pidfd,
+        open_file,
+        pidfd::PidFd,
+        ROOT_INODE,
+    },
+    mm::{
+        heap_info,
+        prefault_user,
+        translated_byte_buffer,
+        translated_ref,
+        translated_refmut,
+        translated_str,
+        HeapInfo,
+        MapPermission,
+        VirtAddr,
+    },
+    syscall::errno::{EBADF, ECHILD, EFAULT, ENOENT, ESRCH},
+    sysctl::{self, SysctlParam},
     task::{
         current_task,
         current_user_token,
         exit_current_and_run_next,
+        hart_id,
         pid2process,
+        send_signal,
+        signal::{SigInfo, CLD_EXITED, CLD_KILLED},
         suspend_current_and_run_next,
         CloneFlags,
+        MmapProt,
         SignalFlags,
+        TaskControlBlock,
         TaskStatus,
         CSIGNAL,
     },
     timer::{get_time_ms, get_time_us},
     trap,
-    utils::string::c_ptr_to_string,
+    utils::random,
 };
 
 #[repr(C)]
@@ -41,7 +70,16 @@ pub struct Tms {
     tms_cstime: i64,
 }
 
+lazy_static! {
+    /// 内核全局主机名，可以通过 sethostname 修改，uname 的 nodename 字段返回该值
+    pub static ref HOSTNAME: Mutex<String> = Mutex::new(String::from(SYS_NODENAME));
+    /// 内核全局域名，可以通过 setdomainname 修改，uname 的 domainname 字段返回该值
+    pub static ref DOMAINNAME: Mutex<String> = Mutex::new(String::from(SYS_DOMAINNAME));
+}
+
+/// 与 Linux 的 `struct new_utsname` 布局一致
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Utsname {
     sysname:    [u8; 65],
     nodename:   [u8; 65],
@@ -61,6 +99,12 @@ pub struct TaskInfo {
     time:          usize,
 }
 
+/// getdents64 中目录项的类型
+pub const DT_UNKNOWN: u8 = 0;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+
+/// linux_dirent64 的头部，`name` 是变长的，以 0 结尾，整条记录按 8 字节对齐
 #[derive(Debug)]
 #[repr(C)]
 pub struct Dirent {
@@ -68,22 +112,21 @@ pub struct Dirent {
     off:   i64,
     len:   u16,
     type_: u8,
-    name:  [u8; 64],
+    name:  [u8; 0],
 }
 
 impl Dirent {
-    pub fn new(off: usize, len: u16, name: &String) -> Self {
-        let mut dirent = Self {
-            ino: 0,
-            off: off as i64,
-            len,
-            type_: 0,
-            name: [0; 64],
-        };
-        for (i, c) in name.chars().enumerate() {
-            dirent.name[i] = c.as_ascii().unwrap() as u8;
-        }
-        dirent
+    /// 编码一条完整的记录，`off` 是下一条记录在目录中的位置
+    pub fn encode(ino: u64, off: i64, type_: u8, name: &str) -> Vec<u8> {
+        let name_offset = core::mem::offset_of!(Dirent, name);
+        let len = (name_offset + name.len() + 1).next_multiple_of(8);
+        let mut record = vec![0u8; len];
+        record[..8].copy_from_slice(&ino.to_ne_bytes());
+        record[8..16].copy_from_slice(&off.to_ne_bytes());
+        record[16..18].copy_from_slice(&(len as u16).to_ne_bytes());
+        record[18] = type_;
+        record[name_offset..name_offset + name.len()].copy_from_slice(name.as_bytes());
+        record
     }
 }
 
@@ -132,16 +175,8 @@ pub fn sys_getpid() -> isize {
 /// getppid syscall
 pub fn sys_getppid() -> isize {
     trace!("kernel: sys_getppid pid:{}", current_task().unwrap().pid.0);
-    if let Some(parent) = &current_task()
-        .unwrap()
-        .inner_exclusive_access(file!(), line!())
-        .parent
-    {
-        parent.upgrade().unwrap().pid.0 as isize
-    } else {
-        warn!("kwenel: getppid NOT IMPLEMENTED YET!!");
-        ESRCH
-    }
+    // ppid 缓存在 TaskControlBlock 中，无需借用 inner
+    current_task().unwrap().getppid() as isize
 }
 /// fork child process syscall
 pub fn sys_clone(
@@ -155,10 +190,32 @@ pub fn sys_clone(
         tls,
         ctid
     );
-    let current_task = current_task().unwrap();
+    let exit_signal = match signal_from_num(flags & CSIGNAL) {
+        Some(signal) => signal,
+        None => return EINVAL,
+    };
+    let clone_signals = match CloneFlags::from_bits((flags & !CSIGNAL) as u32) {
+        Some(flags) => flags,
+        None => return EINVAL,
+    };
+    do_clone(exit_signal, clone_signals, stack_ptr, ptid, tls, ctid)
+}
+
+/// 把信号编号转换为 SignalFlags，0 表示不发送信号
+fn signal_from_num(signum: usize) -> Option<SignalFlags> {
+    match signum {
+        0 => Some(SignalFlags::empty()),
+        1..=64 => SignalFlags::from_bits(1 << (signum - 1)),
+        _ => None,
+    }
+}
 
-    let exit_signal = SignalFlags::from_bits(1 << ((flags & CSIGNAL) - 1)).unwrap();
-    let clone_signals = CloneFlags::from_bits((flags & !CSIGNAL) as u32).unwrap();
+/// clone 和 clone3 共用的创建逻辑，stack_ptr 是子任务的栈顶
+fn do_clone(
+    exit_signal: SignalFlags, clone_signals: CloneFlags, stack_ptr: usize, ptid: *mut usize,
+    tls: usize, ctid: *mut usize,
+) -> isize {
+    let current_task = current_task().unwrap();
 
     trace!(
         "[sys_clone] exit_signal = {:?}, clone_signals = {:?}, stack_ptr = {:#x}, ptid = {:#x}, \
@@ -170,13 +227,22 @@ pub fn sys_clone(
         tls,
         ctid as usize
     );
+    // 新建挂载命名空间需要 root 权限
+    if clone_signals.contains(CloneFlags::CLONE_NEWNS)
+        && !current_task
+            .inner_exclusive_access(file!(), line!())
+            .cred
+            .is_privileged()
+    {
+        return EPERM;
+    }
     if !clone_signals.contains(CloneFlags::CLONE_THREAD) {
         // assert!(stack_ptr == 0);
         if stack_ptr == 0 {
-            return current_task.fork() as isize;
+            return current_task.fork(clone_signals) as isize;
         } else {
             // return current_task.fork2(stack_ptr) as isize; //todo仅用于初赛
-            return current_task.fork() as isize; //todo
+            return current_task.fork(clone_signals) as isize; //todo
         }
     } else {
         println!("[sys_clone] create thread");
@@ -192,6 +258,7 @@ pub fn sys_clone(
         }
 
         if clone_signals.contains(CloneFlags::CLONE_PARENT_SETTID) && !ptid.is_null() {
+            prefault_user(ptid as usize, size_of_val(&new_thread_ttid), true);
             unsafe {
                 sstatus::set_sum();
                 *ptid = new_thread_ttid;
@@ -199,6 +266,7 @@ pub fn sys_clone(
             };
         }
         if clone_signals.contains(CloneFlags::CLONE_CHILD_SETTID) && !ctid.is_null() {
+            prefault_user(ctid as usize, size_of_val(&new_thread_ttid), true);
             unsafe {
                 sstatus::set_sum();
                 *ctid = new_thread_ttid;
@@ -213,21 +281,178 @@ pub fn sys_clone(
         new_thread_ttid as isize
     }
 }
+
+/// clone3 的参数，与 Linux 的 struct clone_args 前 8 个字段 (CLONE_ARGS_SIZE_VER0) 相同。
+/// 之后版本增加的 set_tid 和 cgroup 还不支持，用户传入时必须为 0
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CloneArgs {
+    pub flags:       u64,
+    pub pidfd:       u64,
+    pub child_tid:   u64,
+    pub parent_tid:  u64,
+    pub exit_signal: u64,
+    /// 栈的最低地址，与 stack_size 一起描述整个栈
+    pub stack:       u64,
+    pub stack_size:  u64,
+    pub tls:         u64,
+}
+
+const CLONE_ARGS_SIZE_VER0: usize = 64;
+
+impl CloneArgs {
+    /// 检查参数并换算成 clone 的形式：(退出信号, flags, 栈顶)
+    fn parse(&self) -> Result<(SignalFlags, CloneFlags, usize), isize> {
+        // clone3 的退出信号只能通过 exit_signal 指定
+        if self.flags & CSIGNAL as u64 != 0 || self.flags > u32::MAX as u64 {
+            return Err(EINVAL);
+        }
+        let clone_signals = CloneFlags::from_bits(self.flags as u32).ok_or(EINVAL)?;
+        let exit_signal = signal_from_num(self.exit_signal as usize).ok_or(EINVAL)?;
+        // 栈地址和大小要么都给出，要么都为 0 (沿用父任务的栈)
+        let stack_top = match (self.stack, self.stack_size) {
+            (0, 0) => 0,
+            (0, _) | (_, 0) => return Err(EINVAL),
+            (stack, size) => stack.checked_add(size).ok_or(EINVAL)? as usize,
+        };
+        Ok((exit_signal, clone_signals, stack_top))
+    }
+}
+
+/// 以 struct clone_args 传参的 clone。size 是用户结构体的大小，
+/// 比内核认识的更大时多出的部分必须全为 0
+pub fn sys_clone3(cl_args: *const CloneArgs, size: usize) -> isize {
+    trace!("[sys_clone3] cl_args {:x?} size {}", cl_args, size);
+    if size < CLONE_ARGS_SIZE_VER0 {
+        return EINVAL;
+    }
+    if size > PAGE_SIZE {
+        return E2BIG;
+    }
+    if cl_args.is_null() {
+        return EFAULT;
+    }
+    let mut args = CloneArgs::default();
+    let known = size.min(size_of::<CloneArgs>());
+    prefault_user(cl_args as usize, size, false);
+    let unknown_nonzero = unsafe {
+        sstatus::set_sum();
+        ptr::copy_nonoverlapping(cl_args as *const u8, &mut args as *mut _ as *mut u8, known);
+        let tail = core::slice::from_raw_parts((cl_args as *const u8).add(known), size - known);
+        let nonzero = tail.iter().any(|&b| b != 0);
+        sstatus::clear_sum();
+        nonzero
+    };
+    if unknown_nonzero {
+        return E2BIG;
+    }
+    let (exit_signal, clone_signals, stack_top) = match args.parse() {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+    do_clone(
+        exit_signal,
+        clone_signals,
+        stack_top,
+        args.parent_tid as *mut usize,
+        args.tls as usize,
+        args.child_tid as *mut usize,
+    )
+}
+
+/// clone3 的参数检查，只覆盖不会真正创建任务的路径
+#[allow(unused)]
+pub fn clone3_args_test() {
+    let thread = (CloneFlags::CLONE_VM | CloneFlags::CLONE_THREAD).bits() as u64;
+    let args = CloneArgs {
+        flags: thread,
+        exit_signal: 17,
+        stack: 0x8000,
+        stack_size: 0x2000,
+        ..Default::default()
+    };
+    let (signal, flags, stack_top) = args.parse().unwrap();
+    assert_eq!(signal, SignalFlags::SIGCHLD);
+    assert!(flags.contains(CloneFlags::CLONE_THREAD));
+    assert_eq!(stack_top, 0xa000);
+    // 只给出栈地址或栈大小
+    assert_eq!(
+        CloneArgs {
+            stack_size: 0,
+            ..args
+        }
+        .parse()
+        .unwrap_err(),
+        EINVAL
+    );
+    assert_eq!(CloneArgs { stack: 0, ..args }.parse().unwrap_err(), EINVAL);
+    assert_eq!(
+        CloneArgs {
+            stack: u64::MAX,
+            ..args
+        }
+        .parse()
+        .unwrap_err(),
+        EINVAL
+    );
+    // 退出信号不能放在 flags 里，也不能超出信号范围
+    assert_eq!(
+        CloneArgs {
+            flags: thread | 17,
+            ..args
+        }
+        .parse()
+        .unwrap_err(),
+        EINVAL
+    );
+    assert_eq!(
+        CloneArgs {
+            exit_signal: 65,
+            ..args
+        }
+        .parse()
+        .unwrap_err(),
+        EINVAL
+    );
+    assert_eq!(
+        CloneArgs {
+            flags: 1 << 32,
+            ..args
+        }
+        .parse()
+        .unwrap_err(),
+        EINVAL
+    );
+
+    // 结构体大小的检查在读取参数之前
+    assert_eq!(sys_clone3(&args, CLONE_ARGS_SIZE_VER0 - 8), EINVAL);
+    assert_eq!(sys_clone3(&args, PAGE_SIZE + 8), E2BIG);
+    assert_eq!(sys_clone3(ptr::null(), CLONE_ARGS_SIZE_VER0), EFAULT);
+    // 更大的结构体中内核不认识的部分不为 0
+    let mut bigger = [0u64; 11];
+    bigger[8] = 1;
+    assert_eq!(sys_clone3(bigger.as_ptr() as *const CloneArgs, 88), E2BIG);
+    // 内核不认识的部分全为 0 时按 VER0 解析，这里因为 stack_size 缺失而失败
+    bigger[8] = 0;
+    bigger[5] = 0x8000;
+    assert_eq!(sys_clone3(bigger.as_ptr() as *const CloneArgs, 88), EINVAL);
+    info!("clone3_args_test passed!");
+}
 /// exec syscall
 pub fn sys_execve(path: *const u8, mut args: *const usize, mut envp: *const usize) -> isize {
     trace!("kernel:pid[{}] sys_execve", current_task().unwrap().pid.0);
-    unsafe {
-        sstatus::set_sum();
-    }
-    let mut path = c_ptr_to_string(path);
+    // 参数和环境变量可能在还没有分配的页中，经由 translated_* 读取时先处理缺页
+    let token = current_user_token();
+    let mut path = translated_str(token, path);
     debug!("kernel: execve new app : {}", path);
     let mut args_vec: Vec<String> = Vec::new();
     let mut envp_vec: Vec<String> = Vec::new();
     loop {
-        if unsafe { *args == 0 } {
+        let arg = *translated_ref(token, args);
+        if arg == 0 {
             break;
         }
-        args_vec.push(c_ptr_to_string(unsafe { (*args) as *const u8 }));
+        args_vec.push(translated_str(token, arg as *const u8));
         debug!("exec get an arg {}", args_vec[args_vec.len() - 1]);
         unsafe {
             args = args.add(1);
@@ -237,10 +462,10 @@ pub fn sys_execve(path: *const u8, mut args: *const usize, mut envp: *const usiz
     if envp as usize != 0 {
         loop {
             let env_str_ptr = envp;
-            if unsafe { *env_str_ptr == 0 } {
+            if *translated_ref(token, env_str_ptr) == 0 {
                 break;
             }
-            envp_vec.push(c_ptr_to_string(env_str_ptr as *const u8));
+            envp_vec.push(translated_str(token, env_str_ptr as *const u8));
             unsafe {
                 envp = envp.add(1);
             }
@@ -252,9 +477,6 @@ pub fn sys_execve(path: *const u8, mut args: *const usize, mut envp: *const usiz
         path = String::from("./busybox");
     }
 
-    unsafe {
-        sstatus::clear_sum();
-    }
     let task = current_task().unwrap();
     let work_dir = task
         .inner_exclusive_access(file!(), line!())
@@ -277,11 +499,14 @@ pub fn sys_execve(path: *const u8, mut args: *const usize, mut envp: *const usiz
 
 /// waitpid syscall
 ///
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
+/// If there is not a child process whose pid is same as given, return ECHILD.
+/// Else if there is a child process but it is still running and WNOHANG is set, return 0.
 pub fn sys_wait4(pid: isize, exit_code_ptr: *mut i32, option: u32, _ru: usize) -> isize {
     trace!("kernel: sys_waitpid");
-    let option = WaitOption::from_bits(option).unwrap();
+    let option = match WaitOption::from_bits(option) {
+        Some(option) => option,
+        None => return EINVAL,
+    };
     loop {
         let task = current_task().unwrap();
         let mut inner = task.inner_exclusive_access(file!(), line!());
@@ -309,6 +534,9 @@ pub fn sys_wait4(pid: isize, exit_code_ptr: *mut i32, option: u32, _ru: usize) -
                 .unwrap();
             // ++++ release child PCB
             if !exit_code_ptr.is_null() {
+                inner
+                    .memory_set
+                    .prefault(exit_code_ptr as usize, size_of::<i32>(), true);
                 unsafe { sstatus::set_sum() };
                 debug!("kernel:sys_waitpid: exit_code_ptr is not null");
                 unsafe {
@@ -336,12 +564,135 @@ pub fn sys_wait4(pid: isize, exit_code_ptr: *mut i32, option: u32, _ru: usize) -
     // ---- release current PCB automatically
 }
 
+/// waitid 的 idtype
+const P_ALL: u32 = 0;
+const P_PID: u32 = 1;
+const P_PGID: u32 = 2;
+const P_PIDFD: u32 = 3;
+
+/// waitid 等待的子进程
+enum WaitTarget {
+    All,
+    Pid(usize),
+    Pgid(usize),
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &TaskControlBlock) -> bool {
+        match *self {
+            WaitTarget::All => true,
+            WaitTarget::Pid(pid) => child.pid.0 == pid,
+            WaitTarget::Pgid(pgid) => child.inner_exclusive_access(file!(), line!()).pgid == pgid,
+        }
+    }
+}
+
+/// 按 idtype 和 id 等待子进程状态改变，结果以 SIGCHLD 的 siginfo 形式写入 infop。
+///
+/// 目前只会报告子进程退出。负的退出码 -N (N 不超过 64) 是被信号 N 杀死的，报告为 CLD_KILLED。
+/// 设置 WNOWAIT 时不回收子进程，之后仍然可以 wait；设置 WNOHANG 且没有子进程退出时
+/// 返回 0，infop 清零
+pub fn sys_waitid(idtype: u32, id: usize, infop: *mut SigInfo, options: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_waitid idtype {} id {}",
+        current_task().unwrap().pid.0,
+        idtype,
+        id
+    );
+    let options = match WaitOption::from_bits(options) {
+        Some(options) => options,
+        None => return EINVAL,
+    };
+    if !options.intersects(WaitOption::WEXITED | WaitOption::WUNTRACED | WaitOption::WCONTINUED) {
+        return EINVAL;
+    }
+    let target = match idtype {
+        P_ALL => WaitTarget::All,
+        P_PID => WaitTarget::Pid(id),
+        // id 为 0 时等待与调用者同一进程组的子进程
+        P_PGID if id == 0 => WaitTarget::Pgid(
+            current_task()
+                .unwrap()
+                .inner_exclusive_access(file!(), line!())
+                .pgid,
+        ),
+        P_PGID => WaitTarget::Pgid(id),
+        P_PIDFD => {
+            let task = current_task().unwrap();
+            let inner = task.inner_exclusive_access(file!(), line!());
+            let Some(Some(file)) = inner.fd_table.get(id).cloned() else {
+                return EBADF;
+            };
+            drop(inner);
+            match cast_file_to_pidfd(file) {
+                Some(pidfd) => WaitTarget::Pid(pidfd.pid()),
+                None => return EBADF,
+            }
+        }
+        _ => return EINVAL,
+    };
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access(file!(), line!());
+        if !inner.children.iter().any(|child| target.matches(child)) {
+            return ECHILD;
+        }
+        // 只有 WEXITED 时才报告退出的子进程
+        let exited = options.contains(WaitOption::WEXITED).then(|| {
+            inner.children.iter().position(|child| {
+                target.matches(child) && child.inner_exclusive_access(file!(), line!()).is_zombie
+            })
+        });
+        if let Some(Some(idx)) = exited {
+            let child = if options.contains(WaitOption::WNOWAIT) {
+                Arc::clone(&inner.children[idx])
+            } else {
+                inner.children.remove(idx)
+            };
+            drop(inner);
+            let child_inner = child.inner_exclusive_access(file!(), line!());
+            let exit_code = child_inner.exit_code.unwrap();
+            let info = match exit_code {
+                -64..=-1 => {
+                    SigInfo::child(CLD_KILLED, child.pid.0, child_inner.cred.uid, -exit_code)
+                }
+                _ => SigInfo::child(CLD_EXITED, child.pid.0, child_inner.cred.uid, exit_code),
+            };
+            drop(child_inner);
+            if !infop.is_null() {
+                prefault_user(infop as usize, size_of::<SigInfo>(), true);
+                unsafe {
+                    sstatus::set_sum();
+                    *infop = info;
+                    sstatus::clear_sum();
+                }
+            }
+            return SUCCESS;
+        }
+        drop(inner);
+        drop(task);
+        if options.contains(WaitOption::WNOHANG) {
+            if !infop.is_null() {
+                prefault_user(infop as usize, size_of::<SigInfo>(), true);
+                unsafe {
+                    sstatus::set_sum();
+                    *infop = SigInfo::new(0, 0, 0);
+                    sstatus::clear_sum();
+                }
+            }
+            return SUCCESS;
+        }
+        suspend_current_and_run_next();
+        trap::wait_return();
+    }
+}
+
 /// kill syscall
 pub fn sys_kill(pid: usize, signal: u32) -> isize {
     trace!("kernel:pid[{}] sys_kill", current_task().unwrap().pid.0);
     if let Some(process) = pid2process(pid) {
         if let Some(flag) = SignalFlags::from_bits(signal as usize) {
-            process.inner_exclusive_access(file!(), line!()).signals |= flag;
+            send_signal(&process, flag);
             0
         } else {
             EINVAL
@@ -351,6 +702,65 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
     }
 }
 
+/// pidfd_open 唯一支持的标志，与 O_NONBLOCK 相同
+const PIDFD_NONBLOCK: u32 = OpenFlags::O_NONBLOCK.bits() as u32;
+
+/// 打开一个指向进程 pid 的 fd，进程退出后 fd 变为可读。pidfd 总是带有 close-on-exec
+pub fn sys_pidfd_open(pid: usize, flags: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_pidfd_open pid {}",
+        current_task().unwrap().pid.0,
+        pid
+    );
+    if flags & !PIDFD_NONBLOCK != 0 {
+        return EINVAL;
+    }
+    let Some(process) = pid2process(pid) else {
+        return ESRCH;
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access(file!(), line!());
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(PidFd::new(process)));
+    let status = OpenFlags::from_bits_truncate(flags as i32).status_flags();
+    inner.set_fd_flags(
+        fd,
+        FdFlags {
+            cloexec: true,
+            status,
+        },
+    );
+    fd as isize
+}
+
+/// 通过 pidfd 发送信号。sig 为 0 时只检查进程是否还在；暂不支持自定义 siginfo，info 被忽略
+pub fn sys_pidfd_send_signal(pidfd: usize, sig: usize, _info: *const SigInfo, flags: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_pidfd_send_signal",
+        current_task().unwrap().pid.0
+    );
+    if flags != 0 {
+        return EINVAL;
+    }
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    let Some(Some(file)) = inner.fd_table.get(pidfd).cloned() else {
+        return EBADF;
+    };
+    drop(inner);
+    let Some(pidfd) = cast_file_to_pidfd(file) else {
+        return EBADF;
+    };
+    let Some(signal) = signal_from_num(sig) else {
+        return EINVAL;
+    };
+    let Some(process) = pidfd.task() else {
+        return ESRCH;
+    };
+    send_signal(&process, signal);
+    SUCCESS
+}
+
 /// get_time syscall
 ///
 /// YOUR JOB: get time with second and microsecond
@@ -358,11 +768,15 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
 /// HINT: What if [`TimeVal`] is splitted by two pages ?
 pub fn sys_gettimeofday(ts: *mut TimeVal, _tz: usize) -> isize {
     trace!("kernel:pid[{}] sys_get_time", current_task().unwrap().pid.0);
+    if ts.is_null() {
+        return EFAULT;
+    }
     let us = get_time_us();
     let new_ts = TimeVal {
         sec:  us / 1_000_000,
         usec: us % 1_000_000,
     };
+    prefault_user(ts as usize, size_of::<TimeVal>(), true);
     unsafe {
         sstatus::set_sum();
         *ts = new_ts;
@@ -377,6 +791,9 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
         "kernel:pid[{}] sys_task_info",
         current_task().unwrap().pid.0
     );
+    if ti.is_null() {
+        return EFAULT;
+    }
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access(file!(), line!());
     let ti_new = TaskInfo {
@@ -384,6 +801,8 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
         syscall_times: inner.syscall_times,
         time:          get_time_ms() - inner.first_time.unwrap(),
     };
+    drop(inner);
+    prefault_user(ti as usize, size_of::<TaskInfo>(), true);
     unsafe {
         sstatus::set_sum();
         *ti = ti_new;
@@ -392,6 +811,25 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
     0
 }
 
+/// 调试用：把内核堆的使用情况复制到 info
+pub fn sys_heap_info(info: *mut HeapInfo) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_heap_info",
+        current_task().unwrap().pid.0
+    );
+    if info.is_null() {
+        return EFAULT;
+    }
+    let heap_info = heap_info();
+    prefault_user(info as usize, size_of::<HeapInfo>(), true);
+    unsafe {
+        sstatus::set_sum();
+        *info = heap_info;
+        sstatus::clear_sum();
+    }
+    0
+}
+
 /// mmap syscall
 ///
 /// YOUR JOB: Implement mmap.
@@ -426,6 +864,35 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
         .munmap(start, len)
 }
 
+/// 修改 [addr, addr + len) 的访问权限
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_mprotect addr:{:#x} len:{} prot:{}",
+        current_task().unwrap().pid.0,
+        addr,
+        len,
+        prot
+    );
+    let Some(prot) = MmapProt::from_bits(prot as u32) else {
+        return EINVAL;
+    };
+    let mut perm = MapPermission::U;
+    if prot.contains(MmapProt::PROT_READ) {
+        perm |= MapPermission::R;
+    }
+    if prot.contains(MmapProt::PROT_WRITE) {
+        perm |= MapPermission::W;
+    }
+    if prot.contains(MmapProt::PROT_EXEC) {
+        perm |= MapPermission::X;
+    }
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .memory_set
+        .mprotect(addr, len, perm)
+}
+
 /// change data segment size
 pub fn sys_brk(addr: usize) -> isize {
     trace!("kernel:pid[{}] sys_brk", current_task().unwrap().pid.0);
@@ -454,35 +921,41 @@ pub fn sys_brk(addr: usize) -> isize {
     }
 }
 
-/// spawn syscall
-/// YOUR JOB: Implement spawn.
-/// HINT: fork + exec =/= spawn
-pub fn sys_spawn(_path: *const u8) -> isize {
+/// spawn syscall，直接从可执行文件创建子进程并返回它的 pid。
+/// 与 fork + exec 不同，不会复制当前进程的地址空间
+pub fn sys_spawn(path: *const u8) -> isize {
     trace!("kernel:pid[{}] sys_spawn", current_task().unwrap().pid.0);
-    -1
-    // let token = current_user_token();
-    // let path = translated_str(token, path);
-    // if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-    //     let task = current_task().unwrap();
-    //     let all_data = app_inode.read_all();
-    //     let new_task = task.spawn(all_data.as_slice());
-    //     let new_pid = new_task.pid.0;
-    //     add_task(new_task);
-    //     new_pid as isize
-    // } else {
-    //     -1
-    // }
+    let path = translated_str(current_user_token(), path);
+    let task = current_task().unwrap();
+    let work_dir = task
+        .inner_exclusive_access(file!(), line!())
+        .work_dir
+        .clone();
+    match open_file(work_dir.inode(), path.as_str(), OpenFlags::O_RDONLY) {
+        Some(dentry) => {
+            let all_data = dentry.inode().read_all();
+            task.spawn(all_data.as_slice(), path).pid.0 as isize
+        }
+        None => ENOENT,
+    }
 }
 
 /// set priority syscall
 ///
-/// YOUR JOB: Set task priority
+/// 优先级至少为 2，成功时返回设置的优先级
 pub fn sys_set_priority(prio: isize) -> isize {
     trace!(
         "kernel:pid[{}] sys_set_priority",
         current_task().unwrap().pid.0
     );
-    0
+    if prio < 2 {
+        return EINVAL;
+    }
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .set_priority(prio as usize);
+    prio
 }
 
 /// get current process times
@@ -503,6 +976,7 @@ pub fn sys_times(tms: *mut Tms) -> isize {
         tms_cutime,
         tms_cstime,
     };
+    prefault_user(tms as usize, size_of::<Tms>(), true);
     unsafe {
         sstatus::set_sum();
         *tms = sys_tms;
@@ -514,6 +988,10 @@ pub fn sys_times(tms: *mut Tms) -> isize {
 ///get OS informations
 pub fn sys_uname(uts: *mut Utsname) -> isize {
     trace!("kernel:pid[{}] sys_uname", current_task().unwrap().pid.0);
+    if uts.is_null() {
+        return EFAULT;
+    }
+    prefault_user(uts as usize, size_of::<Utsname>(), true);
     unsafe { sstatus::set_sum() };
     let mut sys_uts = Utsname {
         sysname:    [0; 65],
@@ -525,11 +1003,13 @@ pub fn sys_uname(uts: *mut Utsname) -> isize {
     };
 
     let sysname_bytes = SYS_NAME.as_bytes();
-    let nodename_bytes = SYS_NODENAME.as_bytes();
+    let hostname = HOSTNAME.lock().clone();
+    let nodename_bytes = hostname.as_bytes();
     let release_bytes = SYS_RELEASE.as_bytes();
     let version_bytes = SYS_VERSION.as_bytes();
-    let machine_bytes = "Machine: riscv64".as_bytes();
-    let domainname_bytes = "None".as_bytes();
+    let machine_bytes = SYS_MACHINE.as_bytes();
+    let domainname = DOMAINNAME.lock().clone();
+    let domainname_bytes = domainname.as_bytes();
 
     sys_uts.sysname[..sysname_bytes.len()].copy_from_slice(sysname_bytes);
     sys_uts.nodename[..nodename_bytes.len()].copy_from_slice(nodename_bytes);
@@ -544,26 +1024,389 @@ pub fn sys_uname(uts: *mut Utsname) -> isize {
     0
 }
 
-/// 获取用户 id。在实现多用户权限前默认为最高权限。目前直接返回0。
+/// 获取用户 id。
 pub fn sys_getuid() -> isize {
     trace!("kernel:pid[{}] sys_getuid", current_task().unwrap().pid.0);
-    0
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .uid as isize
 }
 
-/// 获取有效用户 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限。目前直接返回0。
+/// 获取有效用户 id，即相当于哪个用户的权限。
 pub fn sys_geteuid() -> isize {
     trace!("kernel:pid[{}] sys_geteuid", current_task().unwrap().pid.0);
-    0
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .euid as isize
 }
 
-/// 获取用户组 id。在实现多用户权限前默认为最高权限。目前直接返回0。
+/// 获取用户组 id。
 pub fn sys_getgid() -> isize {
     trace!("kernel:pid[{}] sys_getgid", current_task().unwrap().pid.0);
-    0
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .gid as isize
 }
 
-/// 获取有效用户组 id，即相当于哪个用户组的权限。在实现多用户组权限前默认为最高权限。目前直接返回0。
+/// 获取有效用户组 id，即相当于哪个用户组的权限。
 pub fn sys_getegid() -> isize {
     trace!("kernel:pid[{}] sys_getegid", current_task().unwrap().pid.0);
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .egid as isize
+}
+
+/// 设置用户 id。目前没有完整的权限模型，只保存 id，root 可以切换到任意用户。
+pub fn sys_setuid(uid: u32) -> isize {
+    trace!("kernel:pid[{}] sys_setuid", current_task().unwrap().pid.0);
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .setuid(uid)
+}
+
+/// 设置用户组 id。
+pub fn sys_setgid(gid: u32) -> isize {
+    trace!("kernel:pid[{}] sys_setgid", current_task().unwrap().pid.0);
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .setgid(gid)
+}
+
+/// 同时设置 real/effective/saved 用户 id，参数为 -1 时对应项保持不变。
+pub fn sys_setresuid(ruid: u32, euid: u32, suid: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_setresuid",
+        current_task().unwrap().pid.0
+    );
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .setresuid(ruid, euid, suid)
+}
+
+/// 同时设置 real/effective/saved 用户组 id，参数为 -1 时对应项保持不变。
+pub fn sys_setresgid(rgid: u32, egid: u32, sgid: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_setresgid",
+        current_task().unwrap().pid.0
+    );
+    current_task()
+        .unwrap()
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .setresgid(rgid, egid, sgid)
+}
+
+/// 获取附属用户组列表。在实现多用户组权限前只有用户组 0。
+///
+/// `size` 为 0 时只返回附属用户组的个数
+pub fn sys_getgroups(size: usize, list: *mut u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_getgroups",
+        current_task().unwrap().pid.0
+    );
+    let groups: [u32; 1] = [0];
+    if size == 0 {
+        return groups.len() as isize;
+    }
+    if size < groups.len() {
+        return EINVAL;
+    }
+    if list.is_null() {
+        return EFAULT;
+    }
+    prefault_user(list as usize, size_of_val(&groups), true);
+    unsafe {
+        sstatus::set_sum();
+        ptr::copy_nonoverlapping(groups.as_ptr(), list, groups.len());
+        sstatus::clear_sum();
+    }
+    groups.len() as isize
+}
+
+/// 返回当前所在的 hart 编号，只有一个 NUMA 节点，节点号总是 0。
+/// 两个指针都可以为空，tcache 从 Linux 2.6.24 起已经不再使用
+pub fn sys_getcpu(cpu: *mut u32, node: *mut u32, _tcache: *mut u8) -> isize {
+    let hart = hart_id();
+    trace!("kernel: sys_getcpu hart {}", hart);
+    for ptr in [cpu, node] {
+        if !ptr.is_null() {
+            prefault_user(ptr as usize, size_of::<u32>(), true);
+        }
+    }
+    unsafe {
+        sstatus::set_sum();
+        if !cpu.is_null() {
+            *cpu = hart as u32;
+        }
+        if !node.is_null() {
+            *node = 0;
+        }
+        sstatus::clear_sum();
+    }
+    SUCCESS
+}
+
+/// 每个 hart 上调用 getcpu 得到的都是自己的编号
+#[allow(unused)]
+pub fn getcpu_test() {
+    let (mut cpu, mut node) = (u32::MAX, u32::MAX);
+    assert_eq!(sys_getcpu(&mut cpu, &mut node, ptr::null_mut()), SUCCESS);
+    assert_eq!(cpu as usize, hart_id());
+    assert!((cpu as usize) < MAX_HARTS);
+    assert_eq!(node, 0);
+    assert_eq!(
+        sys_getcpu(ptr::null_mut(), ptr::null_mut(), ptr::null_mut()),
+        SUCCESS
+    );
+    info!("getcpu_test passed on hart {}!", cpu);
+}
+
+/// getrandom 的 flags。伪随机数发生器从不阻塞，三种来源都由同一个发生器提供
+const GRND_NONBLOCK: u32 = 0x1;
+const GRND_RANDOM: u32 = 0x2;
+const GRND_INSECURE: u32 = 0x4;
+
+/// 用伪随机字节填充用户缓冲区，返回写入的字节数
+pub fn sys_getrandom(buf: *mut u8, len: usize, flags: u32) -> isize {
+    trace!("kernel: sys_getrandom len {} flags {:#x}", len, flags);
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return EINVAL;
+    }
+    if len == 0 {
+        return 0;
+    }
+    if buf.is_null() {
+        return EFAULT;
+    }
+    prefault_user(buf as usize, len, true);
+    unsafe {
+        sstatus::set_sum();
+        random::fill_bytes(core::slice::from_raw_parts_mut(buf, len));
+        sstatus::clear_sum();
+    }
+    len as isize
+}
+
+/// 设置相同的种子后两次 getrandom 得到相同的输出
+#[allow(unused)]
+pub fn random_seed_test() {
+    const SEED: u64 = 0x1234_5678_9abc_def0;
+    let (mut first, mut second) = ([0u8; 61], [0u8; 61]);
+    random::set_seed(SEED);
+    assert_eq!(sys_getrandom(first.as_mut_ptr(), first.len(), 0), 61);
+    random::set_seed(SEED);
+    assert_eq!(sys_getrandom(second.as_mut_ptr(), 30, GRND_NONBLOCK), 30);
+    assert_eq!(sys_getrandom(second[30..].as_mut_ptr(), 31, 0), 31);
+    // 按 8 字节块取数，分两次读取时第二次从新的块开始
+    assert_eq!(first[..30], second[..30]);
+    assert!(first.iter().any(|&b| b != 0));
+    random::set_seed(SEED);
+    assert_eq!(sys_getrandom(second.as_mut_ptr(), second.len(), 0), 61);
+    assert_eq!(first, second);
+    assert_eq!(
+        sys_getrandom(first.as_mut_ptr(), 1, GRND_RANDOM | GRND_INSECURE),
+        EINVAL
+    );
+    assert_eq!(sys_getrandom(first.as_mut_ptr(), 1, 0x8), EINVAL);
+    // 恢复启动时的种子设置，避免测试种子影响之后的用户程序
+    random::reset();
+    info!("random_seed_test passed!");
+}
+
+/// 设置附属用户组列表。在实现多用户组权限前只接受仅包含用户组 0 的列表，
+/// 长度超过 [`NGROUPS_MAX`] 时在读取用户内存前返回 EINVAL
+pub fn sys_setgroups(size: usize, list: *const u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_setgroups",
+        current_task().unwrap().pid.0
+    );
+    if size == 0 {
+        return 0;
+    }
+    if size > NGROUPS_MAX {
+        return EINVAL;
+    }
+    if list.is_null() {
+        return EFAULT;
+    }
+    prefault_user(list as usize, size * size_of::<u32>(), false);
+    let groups = unsafe {
+        sstatus::set_sum();
+        let groups = core::slice::from_raw_parts(list, size).to_vec();
+        sstatus::clear_sum();
+        groups
+    };
+    if groups.iter().all(|&gid| gid == 0) {
+        0
+    } else {
+        EINVAL
+    }
+}
+
+/// 设置主机名，只有 root 用户可以修改，长度不能超过 [`UTS_LEN`]
+pub fn sys_sethostname(name: *const u8, len: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sethostname",
+        current_task().unwrap().pid.0
+    );
+    let task = current_task().unwrap();
+    if !task
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .is_privileged()
+    {
+        return EPERM;
+    }
+    if len > UTS_LEN {
+        return EINVAL;
+    }
+    if name.is_null() {
+        return EFAULT;
+    }
+    prefault_user(name as usize, len, false);
+    let name = unsafe {
+        sstatus::set_sum();
+        let name = core::slice::from_raw_parts(name, len).to_vec();
+        sstatus::clear_sum();
+        name
+    };
+    match String::from_utf8(name) {
+        Ok(name) => {
+            *HOSTNAME.lock() = name;
+            0
+        }
+        Err(_) => EINVAL,
+    }
+}
+
+/// 不再和其他进程共享某些资源。目前只支持 CLONE_NEWNS：复制一份挂载表作为私有的挂载命名空间，
+/// 之后的挂载和卸载不影响其他进程。需要 root 权限
+pub fn sys_unshare(flags: u32) -> isize {
+    trace!("kernel:pid[{}] sys_unshare", current_task().unwrap().pid.0);
+    let Some(flags) = CloneFlags::from_bits(flags) else {
+        return EINVAL;
+    };
+    if !(flags - CloneFlags::CLONE_NEWNS).is_empty() {
+        return EINVAL;
+    }
+    if flags.is_empty() {
+        return 0;
+    }
+    let task = current_task().unwrap();
+    if !task
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .is_privileged()
+    {
+        return EPERM;
+    }
+    task.unshare_mounts();
     0
 }
+
+/// 设置域名，只有 root 用户可以修改，长度不能超过 [`UTS_LEN`]
+pub fn sys_setdomainname(name: *const u8, len: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_setdomainname",
+        current_task().unwrap().pid.0
+    );
+    let task = current_task().unwrap();
+    if !task
+        .inner_exclusive_access(file!(), line!())
+        .cred
+        .is_privileged()
+    {
+        return EPERM;
+    }
+    if len > UTS_LEN {
+        return EINVAL;
+    }
+    if name.is_null() {
+        return EFAULT;
+    }
+    prefault_user(name as usize, len, false);
+    let name = unsafe {
+        sstatus::set_sum();
+        let name = core::slice::from_raw_parts(name, len).to_vec();
+        sstatus::clear_sum();
+        name
+    };
+    match String::from_utf8(name) {
+        Ok(name) => {
+            *DOMAINNAME.lock() = name;
+            0
+        }
+        Err(_) => EINVAL,
+    }
+}
+
+/// 读写运行时内核参数
+///
+/// `old_value` 非空时写回参数原来的值，`new_value` 非空时把参数设置为新值
+pub fn sys_sysctl(param: usize, new_value: *const usize, old_value: *mut usize) -> isize {
+    trace!("kernel:pid[{}] sys_sysctl", current_task().unwrap().pid.0);
+    let param = match SysctlParam::try_from(param) {
+        Ok(param) => param,
+        Err(_) => return EINVAL,
+    };
+    let new_value = if new_value.is_null() {
+        None
+    } else {
+        if !current_task()
+            .unwrap()
+            .inner_exclusive_access(file!(), line!())
+            .cred
+            .is_privileged()
+        {
+            return EPERM;
+        }
+        prefault_user(new_value as usize, size_of::<usize>(), false);
+        unsafe {
+            sstatus::set_sum();
+            let value = *new_value;
+            sstatus::clear_sum();
+            Some(value)
+        }
+    };
+    if !old_value.is_null() {
+        let value = sysctl::get(param);
+        prefault_user(old_value as usize, size_of::<usize>(), true);
+        unsafe {
+            sstatus::set_sum();
+            *old_value = value;
+            sstatus::clear_sum();
+        }
+    }
+    match new_value {
+        Some(value) => sysctl::set(param, value),
+        None => 0,
+    }
+}
+
+/// 创建新会话，调用者成为会话首进程并脱离原来的控制终端
+///
+/// 目前还没有单独的会话 id，清除控制终端、成为新进程组的组长，返回 pid 作为新的会话 id
+pub fn sys_setsid() -> isize {
+    trace!("kernel:pid[{}] sys_setsid", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access(file!(), line!());
+    inner.ctty = None;
+    inner.pgid = task.pid.0;
+    task.pid.0 as isize
+}
@@ -1,3 +1,5 @@
+use core::mem::size_of;
+
 use riscv::register::{sscratch, sstatus};
 
 use crate::{
@@ -34,6 +36,16 @@ pub fn sys_sigprocmask(
     );
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access(file!(), line!());
+    if old_set as usize != 0 {
+        inner
+            .memory_set
+            .prefault(old_set as usize, size_of::<usize>(), true);
+    }
+    if set as usize != 0 {
+        inner
+            .memory_set
+            .prefault(set as usize, size_of::<usize>(), false);
+    }
 
     let mut mask = inner.signal_mask;
 
@@ -106,6 +118,16 @@ pub fn sys_sigaction(
         error!("[sys_sigaction] error signum");
         return EPERM;
     }
+    if old_action as usize != 0 {
+        inner
+            .memory_set
+            .prefault(old_action as usize, size_of::<SignalAction>(), true);
+    }
+    if action as usize != 0 {
+        inner
+            .memory_set
+            .prefault(action as usize, size_of::<SignalAction>(), false);
+    }
     if old_action as usize != 0 {
         unsafe { sstatus::set_sum() };
         unsafe { *old_action = inner.signal_actions.table[signum].clone() };
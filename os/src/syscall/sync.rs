@@ -1,266 +1,211 @@
-use riscv::register::sstatus;
+use alloc::sync::Arc;
 
 use crate::{
-    boards::CLOCK_FREQ,
-    task::{current_task, suspend_current_and_run_next},
-    timer::{get_time, NSEC_PER_SEC},
+    mm::translated_ref,
+    sync::{
+        futex::{
+            futex_key,
+            futex_requeue,
+            futex_wait,
+            futex_wake,
+            FUTEX_CMD_MASK,
+            FUTEX_CMP_REQUEUE,
+            FUTEX_REQUEUE,
+            FUTEX_WAIT,
+            FUTEX_WAKE,
+        },
+        mutex::{Mutex, MutexBlocking, MutexSpin},
+        Semaphore,
+    },
+    syscall::errno::{EAGAIN, EFAULT, EINVAL, ENOSYS},
+    task::{current_task, current_user_token},
+    timer::{TimeSpec, NSEC_PER_SEC},
 };
-/// sleep syscall
-pub fn sys_sleep(time_req: *const u64, time_remain: *mut u64) -> isize {
+
+/// futex syscall，目前支持 FUTEX_WAIT、FUTEX_WAKE 与 FUTEX_(CMP_)REQUEUE。
+/// FUTEX_WAIT 的 timeout 指向一个相对时间的 TimeSpec，为空时一直等待；
+/// REQUEUE 时 timeout 参数的位置传的是移动到 uaddr2 的任务数上限
+pub fn sys_futex(
+    uaddr: usize, op: usize, val: usize, timeout: usize, uaddr2: usize, val3: usize,
+) -> isize {
     trace!(
-        "kernel:pid[{}] tid[{}] sys_sleep",
+        "kernel:pid[{}] tid[{}] sys_futex op {}",
         current_task().unwrap().pid.0,
-        current_task().unwrap().tid
+        current_task().unwrap().tid,
+        op
     );
-    #[inline]
-    fn is_end(end_time: usize) -> bool {
-        let current_time = get_time();
-        current_time >= end_time
+    if uaddr % 4 != 0 {
+        return EINVAL;
     }
-    unsafe {
-        sstatus::set_sum();
-        let sec = *time_req;
-        let nano_sec = *time_req.add(1);
-        sstatus::clear_sum();
-        let end_time =
-            get_time() + sec as usize * CLOCK_FREQ + nano_sec as usize * CLOCK_FREQ / NSEC_PER_SEC;
-
-        loop {
-            if is_end(end_time) {
-                break;
+    let token = current_user_token();
+    let key = match futex_key(token, uaddr) {
+        Some(key) => key,
+        None => return EFAULT,
+    };
+    match op & FUTEX_CMD_MASK {
+        FUTEX_WAIT => {
+            let timeout = if timeout == 0 {
+                None
             } else {
-                debug!("kernel: sleep suspend_current_and_run_next");
-                suspend_current_and_run_next()
+                if futex_key(token, timeout).is_none() {
+                    return EFAULT;
+                }
+                let timeout = *translated_ref(token, timeout as *const TimeSpec);
+                if timeout.tv_nsec >= NSEC_PER_SEC {
+                    return EINVAL;
+                }
+                Some(timeout)
+            };
+            match futex_wait(token, uaddr, val as u32, timeout) {
+                Ok(()) => 0,
+                Err(errno) => errno,
             }
         }
-
-        sstatus::set_sum();
-        if time_remain as usize != 0 {
-            *time_remain = 0;
-            *time_remain.add(1) = 0;
+        FUTEX_WAKE => futex_wake(key, val) as isize,
+        FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+            if uaddr2 % 4 != 0 {
+                return EINVAL;
+            }
+            if futex_key(token, uaddr2).is_none() {
+                return EFAULT;
+            }
+            let cmd = op & FUTEX_CMD_MASK;
+            let expected = (cmd == FUTEX_CMP_REQUEUE).then_some(val3 as u32);
+            match futex_requeue(token, uaddr, uaddr2, val, timeout, expected) {
+                // FUTEX_REQUEUE 返回唤醒的个数，FUTEX_CMP_REQUEUE 返回唤醒和移动的总数
+                Some((woken, _)) if cmd == FUTEX_REQUEUE => woken as isize,
+                Some((woken, moved)) => (woken + moved) as isize,
+                None => EAGAIN,
+            }
+        }
+        _ => ENOSYS,
+    }
+}
+/// mutex create syscall，返回 mutex id。blocking 为真时拿不到锁的线程会阻塞，否则让出 CPU 后重试
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_mutex_create",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    let mutex: Arc<dyn Mutex> = if blocking {
+        Arc::new(MutexBlocking::new())
+    } else {
+        Arc::new(MutexSpin::new())
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access(file!(), line!());
+    match inner.mutex_list.iter().position(|item| item.is_none()) {
+        Some(id) => {
+            inner.mutex_list[id] = Some(mutex);
+            id as isize
+        }
+        None => {
+            inner.mutex_list.push(Some(mutex));
+            inner.mutex_list.len() as isize - 1
         }
-        sstatus::clear_sum();
     }
-    0
 }
 
-// /// mutex create syscall
-// pub fn sys_mutex_create(blocking: bool) -> isize {
-//     trace!(
-//         "kernel:pid[{}] tid[{}] sys_mutex_create",
-//         current_task().unwrap().process.upgrade().unwrap().getpid(),
-//         current_task()
-//             .unwrap()
-//             .inner_exclusive_access(file!(), line!())
-//             .res
-//             .as_ref()
-//             .unwrap()
-//             .tid
-//     );
-//     let process = current_process();
-//     let mutex: Option<Arc<dyn MutexSupport>> = if !blocking {
-//         Some(Arc::new(SpinNoIrqLock::new()))
-//     } else {
-//         Some(Arc::new(SpinNoIrqLock::new()))
-//     };
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     if let Some(id) = process_inner
-//         .mutex_list
-//         .iter()
-//         .enumerate()
-//         .find(|(_, item)| item.is_none())
-//         .map(|(id, _)| id)
-//     {
-//         process_inner.mutex_list[id] = mutex;
-//         process_inner.available[id] = 1;
-//         for task in &mut process_inner.allocation {
-//             task[id] = 0;
-//         }
-//         for task in &mut process_inner.need {
-//             task[id] = 0;
-//         }
-//         id as isize
-//     } else {
-//         process_inner.mutex_list.push(mutex);
-//         process_inner.available.push(1);
-//         for task in &mut process_inner.allocation {
-//             task.push(0);
-//         }
-//         for task in &mut process_inner.need {
-//             task.push(0);
-//         }
-//         process_inner.mutex_list.len() as isize - 1
-//     }
-// }
+/// 取出 mutex id 对应的 mutex
+fn get_mutex(mutex_id: usize) -> Option<Arc<dyn Mutex>> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    inner.mutex_list.get(mutex_id).cloned().flatten()
+}
 
-// /// mutex lock syscall
-// pub fn sys_mutex_lock(mutex_id: usize) -> isize {
-//     trace!(
-//         "kernel:pid[{}] tid[{}] sys_mutex_lock",
-//         current_task().unwrap().process.upgrade().unwrap().getpid(),
-//         current_task()
-//             .unwrap()
-//             .inner_exclusive_access(file!(), line!())
-//             .res
-//             .as_ref()
-//             .unwrap()
-//             .tid
-//     );
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
-//     let tid = current_task().unwrap().inner_exclusive_access(file!(), line!()).res.as_ref().unwrap().tid;
-//     process_inner.need[tid][mutex_id] += 1;
-//     let deadlock_detect = process_inner.deadlock_detect;
-//     drop(process_inner);
-//     drop(process);
-//     if deadlock_detect && detect_deadlock() {
-//         return -0xdead;
-//     }
-//     mutex.lock();
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     process_inner.available[mutex_id] -= 1;
-//     let tid = current_task().unwrap().inner_exclusive_access(file!(), line!()).res.as_ref().unwrap().tid;
-//     process_inner.allocation[tid][mutex_id] += 1;
-//     process_inner.need[tid][mutex_id] -= 1;
-//     0
-// }
+/// mutex lock syscall
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_mutex_lock",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    match get_mutex(mutex_id) {
+        Some(mutex) => {
+            mutex.lock();
+            0
+        }
+        None => EINVAL,
+    }
+}
 
-// /// mutex unlock syscall
-// pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
-//     trace!(
-//         "kernel:pid[{}] tid[{}] sys_mutex_unlock",
-//         current_task().unwrap().process.upgrade().unwrap().getpid(),
-//         current_task()
-//             .unwrap()
-//             .inner_exclusive_access(file!(), line!())
-//             .res
-//             .as_ref()
-//             .unwrap()
-//             .tid
-//     );
-//     let process = current_process();
-//     let process_inner = process.inner_exclusive_access(file!(), line!());
-//     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
-//     drop(process_inner);
-//     drop(process);
-//     mutex.unlock();
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     process_inner.available[mutex_id] += 1;
-//     let tid = current_task().unwrap().inner_exclusive_access(file!(), line!()).res.as_ref().unwrap().tid;
-//     process_inner.allocation[tid][mutex_id] -= 1;
-//     0
-// }
+/// mutex unlock syscall
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_mutex_unlock",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    match get_mutex(mutex_id) {
+        Some(mutex) => {
+            mutex.unlock();
+            0
+        }
+        None => EINVAL,
+    }
+}
 
-// /// semaphore create syscall
-// pub fn sys_semaphore_create(res_count: usize) -> isize {
-//     trace!(
-//         "kernel:pid[{}] tid[{}] sys_semaphore_create",
-//         current_task().unwrap().process.upgrade().unwrap().getpid(),
-//         current_task()
-//             .unwrap()
-//             .inner_exclusive_access(file!(), line!())
-//             .res
-//             .as_ref()
-//             .unwrap()
-//             .tid
-//     );
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     let id = if let Some(id) = process_inner
-//         .semaphore_list
-//         .iter()
-//         .enumerate()
-//         .find(|(_, item)| item.is_none())
-//         .map(|(id, _)| id)
-//     {
-//         process_inner.semaphore_list[id] = Some(Arc::new(Semaphore::new(res_count)));
-//         process_inner.available[id] = res_count as u32;
-//         for task in &mut process_inner.allocation {
-//             task[id] = 0;
-//         }
-//         for task in &mut process_inner.need {
-//             task[id] = 0;
-//         }
-//         id
-//     } else {
-//         process_inner
-//             .semaphore_list
-//             .push(Some(Arc::new(Semaphore::new(res_count))));
-//         process_inner.available.push(res_count as u32);
-//         for task in &mut process_inner.allocation {
-//             task.push(0);
-//         }
-//         for task in &mut process_inner.need {
-//             task.push(0);
-//         }
-//         process_inner.semaphore_list.len() - 1
-//     };
-//     id as isize
-// }
+/// semaphore create syscall，返回信号量 id，初始资源数为 res_count
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_semaphore_create",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    let semaphore = Arc::new(Semaphore::new(res_count));
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access(file!(), line!());
+    match inner.semaphore_list.iter().position(|item| item.is_none()) {
+        Some(id) => {
+            inner.semaphore_list[id] = Some(semaphore);
+            id as isize
+        }
+        None => {
+            inner.semaphore_list.push(Some(semaphore));
+            inner.semaphore_list.len() as isize - 1
+        }
+    }
+}
 
-// /// semaphore up syscall
-// pub fn sys_semaphore_up(sem_id: usize) -> isize {
-//     trace!(
-//         "kernel:pid[{}] tid[{}] sys_semaphore_up",
-//         current_task().unwrap().process.upgrade().unwrap().getpid(),
-//         current_task()
-//             .unwrap()
-//             .inner_exclusive_access(file!(), line!())
-//             .res
-//             .as_ref()
-//             .unwrap()
-//             .tid
-//     );
-//     let process = current_process();
-//     let process_inner = process.inner_exclusive_access(file!(), line!());
-//     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
-//     drop(process_inner);
-//     drop(process);
-//     sem.up();
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     process_inner.available[sem_id] += 1;
-//     let tid = current_task().unwrap().inner_exclusive_access(file!(), line!()).res.as_ref().unwrap().tid;
-//     process_inner.allocation[tid][sem_id] -= 1;
-//     0
-// }
+/// 取出信号量 id 对应的信号量
+fn get_semaphore(sem_id: usize) -> Option<Arc<Semaphore>> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access(file!(), line!());
+    inner.semaphore_list.get(sem_id).cloned().flatten()
+}
 
-// /// semaphore down syscall
-// pub fn sys_semaphore_down(sem_id: usize) -> isize {
-//     trace!(
-//         "kernel:pid[{}] tid[{}] sys_semaphore_down",
-//         current_task().unwrap().process.upgrade().unwrap().getpid(),
-//         current_task()
-//             .unwrap()
-//             .inner_exclusive_access(file!(), line!())
-//             .res
-//             .as_ref()
-//             .unwrap()
-//             .tid
-//     );
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
-//     let tid = current_task().unwrap().inner_exclusive_access(file!(), line!()).res.as_ref().unwrap().tid;
-//     process_inner.need[tid][sem_id] += 1;
-//     let deadlock_detect = process_inner.deadlock_detect;
-//     drop(process_inner);
-//     drop(process);
-//     if deadlock_detect && detect_deadlock() {
-//         return -0xdead;
-//     }
-//     sem.down();
-//     let process = current_process();
-//     let mut process_inner = process.inner_exclusive_access(file!(), line!());
-//     process_inner.available[sem_id] -= 1;
-//     let tid = current_task().unwrap().inner_exclusive_access(file!(), line!()).res.as_ref().unwrap().tid;
-//     process_inner.allocation[tid][sem_id] += 1;
-//     process_inner.need[tid][sem_id] -= 1;
-//     0
-// }
+/// semaphore up syscall
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_semaphore_up",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    match get_semaphore(sem_id) {
+        Some(semaphore) => {
+            semaphore.up();
+            0
+        }
+        None => EINVAL,
+    }
+}
+
+/// semaphore down syscall
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_semaphore_down",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    match get_semaphore(sem_id) {
+        Some(semaphore) => {
+            semaphore.down();
+            0
+        }
+        None => EINVAL,
+    }
+}
 
 // /// condvar create syscall
 // pub fn sys_condvar_create() -> isize {
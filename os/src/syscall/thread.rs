@@ -1,9 +1,14 @@
 use alloc::{sync::Arc, vec::Vec};
+use core::mem::size_of;
+
+use riscv::register::sstatus;
 
 use crate::{
     config::__breakpoint,
-    mm::kernel_token,
-    task::{add_task, current_task, kstack_alloc, TaskControlBlock},
+    mm::{kernel_token, prefault_user},
+    sync::futex::ROBUST_LIST_HEAD_SIZE,
+    syscall::errno::{EINVAL, ESRCH},
+    task::{add_task, current_task, kstack_alloc, pid2process, TaskControlBlock},
     trap::{trap_handler, TrapContext},
 };
 /// thread create syscall
@@ -61,8 +66,8 @@ pub fn sys_gettid() -> isize {
 
 /// wait for a thread to exit syscall
 ///
-/// thread does not exist, return -1
-/// thread has not exited yet, return -2
+/// thread does not exist, return ESRCH
+/// thread has not exited yet, return EAGAIN
 /// otherwise, return thread's exit code
 pub fn sys_waittid(tid: usize) -> i32 {
     trace!(
@@ -94,7 +99,7 @@ pub fn sys_waittid(tid: usize) -> i32 {
     //     // waited thread has not exited
     //     -2
     // }
-    -1
+    ESRCH as i32
 }
 
 pub fn sys_set_tid_address(tidptr: usize) -> isize {
@@ -108,3 +113,45 @@ pub fn sys_set_tid_address(tidptr: usize) -> isize {
     task_inner.clear_child_tid = tidptr;
     task.get_tid() as isize
 }
+
+/// 登记当前线程的 robust futex 链表，线程退出时由内核遍历
+pub fn sys_set_robust_list(head: usize, len: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_set_robust_list",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    if len != ROBUST_LIST_HEAD_SIZE {
+        return EINVAL;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access(file!(), line!()).robust_list = head;
+    0
+}
+
+/// 读取 pid 对应任务 (0 表示当前线程) 登记的 robust futex 链表
+pub fn sys_get_robust_list(pid: usize, head_ptr: *mut usize, len_ptr: *mut usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_get_robust_list",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    let task = if pid == 0 {
+        current_task().unwrap()
+    } else {
+        match pid2process(pid) {
+            Some(task) => task,
+            None => return ESRCH,
+        }
+    };
+    let head = task.inner_exclusive_access(file!(), line!()).robust_list;
+    prefault_user(head_ptr as usize, size_of::<usize>(), true);
+    prefault_user(len_ptr as usize, size_of::<usize>(), true);
+    unsafe {
+        sstatus::set_sum();
+        *head_ptr = head;
+        *len_ptr = ROBUST_LIST_HEAD_SIZE;
+        sstatus::clear_sum();
+    }
+    0
+}
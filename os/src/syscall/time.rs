@@ -1,38 +1,64 @@
-use riscv::register::sstatus;
-
 use crate::{
-    task::current_task,
-    timer::{ClockId, TimeSpec},
+    config::CLOCK_FREQ,
+    mm::{copy_to_user, translated_ref},
+    sync::futex::futex_key,
+    syscall::errno::{EFAULT, EINTR, EINVAL},
+    task::{current_task, current_user_token},
+    timer::{get_monotonic_time, get_time, sleep_until, ClockId, TimeSpec, NSEC_PER_SEC},
 };
 
+/// 读取 clock_id 对应的时钟。没有 RTC，CLOCK_REALTIME 与 CLOCK_MONOTONIC 都从开机时刻算起；
+/// CPU 时间类的时钟目前只支持 CLOCK_PROCESS_CPUTIME_ID，同样返回开机以来的时间
 pub fn sys_clock_gettime(clock_id: usize, timespec: *mut TimeSpec) -> isize {
     trace!(
         "kernel:pid[{}] tid[{}] sys_clock_gettime",
         current_task().unwrap().pid.0,
         current_task().unwrap().tid
     );
+    let time = match ClockId::from(clock_id) {
+        Some(
+            ClockId::Realtime
+            | ClockId::RealtimeCoarse
+            | ClockId::Monotonic
+            | ClockId::MonotonicRaw
+            | ClockId::MonotonicCoarse
+            | ClockId::Boottime
+            | ClockId::ProcessCputimeId,
+        ) => TimeSpec::from_tick(get_monotonic_time()),
+        _ => return EINVAL,
+    };
+    if timespec.is_null() {
+        return EFAULT;
+    }
+    // timespec 可能跨过页的边界，两页对应的物理页不一定相邻
+    copy_to_user(current_user_token(), timespec, &time);
+    0
+}
 
-    match ClockId::from(clock_id) {
-        ClockId::Monotonic | ClockId::Realtime | ClockId::ProcessCputimeId => {
-            let time = TimeSpec::now();
-            unsafe { *timespec = time };
-        }
-        _ => {
-            panic!("clock_get_time: clock_id {:?} not supported", clock_id);
-        }
+/// 睡眠 req 给出的相对时间。任务在定时器上阻塞；睡眠期间收到没有被屏蔽的信号时提前返回 EINTR，
+/// 并把没有睡够的时间写入 rem (为空时不写)
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_nanosleep",
+        current_task().unwrap().pid.0,
+        current_task().unwrap().tid
+    );
+    let token = current_user_token();
+    if req.is_null() || futex_key(token, req as usize).is_none() {
+        return EFAULT;
     }
-    let time = TimeSpec::now();
-    if timespec as usize != 0 {
-        unsafe {
-            sstatus::set_sum();
-        }
-        debug!("timespec: {:#x?}", timespec);
-        unsafe {
-            *timespec = time;
-        }
-        unsafe {
-            sstatus::clear_sum();
+    let req = *translated_ref(token, req);
+    if req.tv_nsec >= NSEC_PER_SEC {
+        return EINVAL;
+    }
+    let end_time = get_time() + req.tv_sec * CLOCK_FREQ + req.tv_nsec * CLOCK_FREQ / NSEC_PER_SEC;
+    match sleep_until(end_time) {
+        Ok(()) => 0,
+        Err(remain) => {
+            if !rem.is_null() {
+                copy_to_user(token, rem, &remain);
+            }
+            EINTR
         }
     }
-    0
 }
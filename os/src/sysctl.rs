@@ -0,0 +1,142 @@
+//! Runtime-tunable kernel parameters
+//!
+//! 统一管理可以在运行时调整的内核参数，通过 [`get`]/[`set`] 访问，
+//! 用户态通过 `sys_sysctl` 系统调用读写。各参数都用原子变量保存，读取时无需加锁。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use log::LevelFilter;
+use num_enum::TryFromPrimitive;
+
+use crate::syscall::errno::EINVAL;
+
+/// 可调整的内核参数编号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(usize)]
+pub enum SysctlParam {
+    /// 块缓存的最大块数
+    BlockCacheSize = 0,
+    /// 顺序读时的预读窗口大小 (块数)，0 表示关闭预读
+    ReadAheadWindow = 1,
+    /// 是否开启调度器对交互任务的优先提升，0 关闭，1 开启。
+    /// 开启后从阻塞中被唤醒的任务下一次调度就会被选中
+    SchedBoost = 2,
+    /// 日志等级，0 = OFF，1 = ERROR ... 5 = TRACE
+    LogLevel = 3,
+    /// 脏元数据定期写回的间隔 (毫秒)
+    DirtyWritebackMs = 4,
+    /// 关闭写过的文件时是否先把数据和元数据写回设备，0 关闭，1 开启
+    FsyncOnClose = 5,
+    /// 每个 FAT32 文件系统最多缓存的 inode 数，超过时淘汰最近最少使用的未打开 inode
+    InodeCacheSize = 6,
+}
+
+static BLOCK_CACHE_SIZE: AtomicUsize = AtomicUsize::new(16);
+static READ_AHEAD_WINDOW: AtomicUsize = AtomicUsize::new(0);
+static SCHED_BOOST: AtomicUsize = AtomicUsize::new(0);
+static DIRTY_WRITEBACK_MS: AtomicUsize = AtomicUsize::new(5000);
+static FSYNC_ON_CLOSE: AtomicUsize = AtomicUsize::new(0);
+static INODE_CACHE_SIZE: AtomicUsize = AtomicUsize::new(64);
+
+fn level_to_usize(level: LevelFilter) -> usize {
+    match level {
+        LevelFilter::Off => 0,
+        LevelFilter::Error => 1,
+        LevelFilter::Warn => 2,
+        LevelFilter::Info => 3,
+        LevelFilter::Debug => 4,
+        LevelFilter::Trace => 5,
+    }
+}
+
+fn usize_to_level(value: usize) -> Option<LevelFilter> {
+    match value {
+        0 => Some(LevelFilter::Off),
+        1 => Some(LevelFilter::Error),
+        2 => Some(LevelFilter::Warn),
+        3 => Some(LevelFilter::Info),
+        4 => Some(LevelFilter::Debug),
+        5 => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// 读取参数的当前值
+pub fn get(param: SysctlParam) -> usize {
+    match param {
+        SysctlParam::BlockCacheSize => BLOCK_CACHE_SIZE.load(Ordering::Relaxed),
+        SysctlParam::ReadAheadWindow => READ_AHEAD_WINDOW.load(Ordering::Relaxed),
+        SysctlParam::SchedBoost => SCHED_BOOST.load(Ordering::Relaxed),
+        SysctlParam::LogLevel => level_to_usize(log::max_level()),
+        SysctlParam::DirtyWritebackMs => DIRTY_WRITEBACK_MS.load(Ordering::Relaxed),
+        SysctlParam::FsyncOnClose => FSYNC_ON_CLOSE.load(Ordering::Relaxed),
+        SysctlParam::InodeCacheSize => INODE_CACHE_SIZE.load(Ordering::Relaxed),
+    }
+}
+
+/// 设置参数，取值非法时返回 EINVAL
+pub fn set(param: SysctlParam, value: usize) -> isize {
+    match param {
+        SysctlParam::BlockCacheSize => {
+            if value == 0 {
+                return EINVAL;
+            }
+            BLOCK_CACHE_SIZE.store(value, Ordering::Relaxed);
+        }
+        SysctlParam::ReadAheadWindow => READ_AHEAD_WINDOW.store(value, Ordering::Relaxed),
+        SysctlParam::SchedBoost => {
+            if value > 1 {
+                return EINVAL;
+            }
+            SCHED_BOOST.store(value, Ordering::Relaxed);
+        }
+        SysctlParam::LogLevel => match usize_to_level(value) {
+            Some(level) => log::set_max_level(level),
+            None => return EINVAL,
+        },
+        SysctlParam::DirtyWritebackMs => DIRTY_WRITEBACK_MS.store(value, Ordering::Relaxed),
+        SysctlParam::FsyncOnClose => {
+            if value > 1 {
+                return EINVAL;
+            }
+            FSYNC_ON_CLOSE.store(value, Ordering::Relaxed);
+        }
+        SysctlParam::InodeCacheSize => {
+            if value == 0 {
+                return EINVAL;
+            }
+            INODE_CACHE_SIZE.store(value, Ordering::Relaxed);
+        }
+    }
+    0
+}
+
+/// 块缓存的最大块数
+pub fn block_cache_size() -> usize {
+    get(SysctlParam::BlockCacheSize)
+}
+
+/// 预读窗口大小 (块数)
+pub fn read_ahead_window() -> usize {
+    get(SysctlParam::ReadAheadWindow)
+}
+
+/// 调度器优先提升是否开启
+pub fn sched_boost() -> bool {
+    get(SysctlParam::SchedBoost) != 0
+}
+
+/// 脏元数据定期写回的间隔 (毫秒)
+pub fn dirty_writeback_ms() -> usize {
+    get(SysctlParam::DirtyWritebackMs)
+}
+
+/// 关闭写过的文件时是否写回设备
+pub fn fsync_on_close() -> bool {
+    get(SysctlParam::FsyncOnClose) != 0
+}
+
+/// 每个 FAT32 文件系统最多缓存的 inode 数
+pub fn inode_cache_size() -> usize {
+    get(SysctlParam::InodeCacheSize)
+}
@@ -0,0 +1,126 @@
+//! 每个 hart 的 CPU 局部数据
+//!
+//! 内核态下 tp 中保存着当前 hart 的编号，以它为下标找到本 hart 的 [`Cpu`]，
+//! 其中的 [`Processor`] 记录当前运行的任务与 idle 控制流的上下文。
+//! `current_task` 等接口都经过这里，不同 hart 看到的是各自的当前任务。
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    arch::asm,
+    cell::RefMut,
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lazy_static::*;
+
+use super::{processor::Processor, TaskControlBlock};
+use crate::{config::MAX_HARTS, sync::UPSafeCell};
+
+/// 一个 hart 的局部数据
+pub struct Cpu {
+    /// hart 编号
+    pub id:    usize,
+    /// 只会被所在的 hart 访问，不需要跨 hart 的锁
+    processor: UPSafeCell<Processor>,
+}
+
+impl Cpu {
+    fn new(id: usize) -> Self {
+        Self {
+            id,
+            processor: unsafe { UPSafeCell::new(Processor::new()) },
+        }
+    }
+
+    /// 本 hart 的 Processor
+    pub fn processor(&self) -> RefMut<'_, Processor> {
+        self.processor.exclusive_access(file!(), line!())
+    }
+
+    /// 本 hart 上正在运行的任务
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.processor().current()
+    }
+}
+
+lazy_static! {
+    /// 每个 hart 一个 Cpu
+    static ref CPUS: Vec<Cpu> = (0..MAX_HARTS).map(Cpu::new).collect();
+}
+
+/// 当前 hart 的编号，启动时以及每次从用户态 trap 进内核时放在 tp 中
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+/// 当前 hart 的 Cpu
+pub fn this_cpu() -> &'static Cpu {
+    &CPUS[hart_id()]
+}
+
+/// 测试进行到的阶段
+#[allow(unused)]
+static TEST_STAGE: AtomicUsize = AtomicUsize::new(0);
+/// 参与测试的从 hart，usize::MAX 表示还没有
+#[allow(unused)]
+static TEST_HART: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 等待测试进入 `stage`，超时返回 false
+#[allow(unused)]
+fn wait_stage(stage: usize) -> bool {
+    let deadline = crate::timer::get_time_ms() + 1000;
+    while TEST_STAGE.load(Ordering::Acquire) < stage {
+        if crate::timer::get_time_ms() > deadline {
+            return false;
+        }
+        spin_loop();
+    }
+    true
+}
+
+/// 启动 hart 一侧：暂时把 initproc 设为本 hart 的当前任务，
+/// 确认本 hart 看到的是 initproc，而另一个 hart 看到的仍是自己的 (空的) 当前任务
+#[allow(unused)]
+pub fn cpu_local_test() {
+    use super::{current_task, take_current_task, INITPROC};
+
+    assert_eq!(this_cpu().id, hart_id());
+    assert!(current_task().is_none());
+    this_cpu().processor().current = Some(INITPROC.clone());
+    TEST_STAGE.store(1, Ordering::Release);
+
+    assert_eq!(current_task().unwrap().pid.0, INITPROC.pid.0);
+    if super::online_harts() > 1 {
+        assert!(
+            wait_stage(2),
+            "the other hart did not report its current task"
+        );
+    }
+    assert!(Arc::ptr_eq(&take_current_task().unwrap(), &INITPROC));
+    assert!(current_task().is_none());
+    info!("cpu_local_test passed!");
+}
+
+/// 从 hart 一侧：启动 hart 设置了自己的当前任务之后，本 hart 的当前任务仍然为空
+#[allow(unused)]
+pub fn cpu_local_test_secondary() {
+    use super::current_task;
+
+    if TEST_HART
+        .compare_exchange(usize::MAX, hart_id(), Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return;
+    }
+    if !wait_stage(1) {
+        return;
+    }
+    assert_eq!(this_cpu().id, hart_id());
+    assert!(current_task().is_none());
+    TEST_STAGE.store(2, Ordering::Release);
+}
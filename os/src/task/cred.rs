@@ -0,0 +1,106 @@
+//! Process credentials: real / effective / saved user and group ids.
+//!
+//! 目前还没有完整的权限模型，这里只负责保存 id 并做与 Linux 一致的基本合法性检查。
+
+use crate::syscall::errno::EPERM;
+
+/// `setresuid`/`setresgid` 中表示“保持不变”的参数值 (-1)
+pub const ID_UNCHANGED: u32 = u32::MAX;
+
+/// 进程凭证
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Credentials {
+    /// real user id
+    pub uid:  u32,
+    /// effective user id
+    pub euid: u32,
+    /// saved set-user-id
+    pub suid: u32,
+    /// real group id
+    pub gid:  u32,
+    /// effective group id
+    pub egid: u32,
+    /// saved set-group-id
+    pub sgid: u32,
+}
+
+impl Credentials {
+    /// 有效用户为 root 时拥有全部权限
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// setuid: root 同时修改 real/effective/saved uid，
+    /// 普通用户只能把 effective uid 设为自己的 real 或 saved uid
+    pub fn setuid(&mut self, uid: u32) -> isize {
+        if self.is_privileged() {
+            self.uid = uid;
+            self.euid = uid;
+            self.suid = uid;
+        } else if uid == self.uid || uid == self.suid {
+            self.euid = uid;
+        } else {
+            return EPERM;
+        }
+        0
+    }
+
+    /// setgid: 规则同 [`Credentials::setuid`]
+    pub fn setgid(&mut self, gid: u32) -> isize {
+        if self.is_privileged() {
+            self.gid = gid;
+            self.egid = gid;
+            self.sgid = gid;
+        } else if gid == self.gid || gid == self.sgid {
+            self.egid = gid;
+        } else {
+            return EPERM;
+        }
+        0
+    }
+
+    /// setresuid: 参数为 [`ID_UNCHANGED`] 的项保持不变，
+    /// 普通用户只能设为当前 real/effective/saved uid 之一
+    pub fn setresuid(&mut self, ruid: u32, euid: u32, suid: u32) -> isize {
+        let current = [self.uid, self.euid, self.suid];
+        if !self.is_privileged()
+            && [ruid, euid, suid]
+                .iter()
+                .any(|&id| id != ID_UNCHANGED && !current.contains(&id))
+        {
+            return EPERM;
+        }
+        if ruid != ID_UNCHANGED {
+            self.uid = ruid;
+        }
+        if euid != ID_UNCHANGED {
+            self.euid = euid;
+        }
+        if suid != ID_UNCHANGED {
+            self.suid = suid;
+        }
+        0
+    }
+
+    /// setresgid: 规则同 [`Credentials::setresuid`]
+    pub fn setresgid(&mut self, rgid: u32, egid: u32, sgid: u32) -> isize {
+        let current = [self.gid, self.egid, self.sgid];
+        if !self.is_privileged()
+            && [rgid, egid, sgid]
+                .iter()
+                .any(|&id| id != ID_UNCHANGED && !current.contains(&id))
+        {
+            return EPERM;
+        }
+        if rgid != ID_UNCHANGED {
+            self.gid = rgid;
+        }
+        if egid != ID_UNCHANGED {
+            self.egid = egid;
+        }
+        if sgid != ID_UNCHANGED {
+            self.sgid = sgid;
+        }
+        0
+    }
+}
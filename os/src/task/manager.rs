@@ -2,67 +2,129 @@
 //!
 //! It is only used to manage processes and schedule process based on ready queue.
 //! Other CPU process monitoring functions are in Processor.
+//!
+//! 每个 hart 有自己的就绪队列，任务优先放回当前 hart 的队列；
+//! 自己的队列空了就从最长的队列偷一个任务。
+//!
+//! 队列内按 stride 调度：每次取出 stride 最小的任务，并把它的 stride 加上 pass，
+//! 优先级越高 pass 越小，被选中的次数与优先级成正比。
+//! `min_stride` 记录最近一次被选中任务的 stride，重新入队的任务 stride 不低于它，
+//! 睡眠很久的任务不会因为 stride 落后而长时间独占 CPU。
+//! stride 超过 `STRIDE_LIMIT` 时把所有就绪任务的 stride 同时减去 `min_stride`，避免溢出。
+//!
+//! 开启 `SchedBoost` 时，从阻塞中被唤醒的任务 (通常在等待 I/O 的交互任务)
+//! stride 直接取 `min_stride` 并放到队首，下一次调度就能运行。
 
 use alloc::{
     collections::{btree_map::BTreeMap, vec_deque::VecDeque},
     sync::Arc,
+    vec::Vec,
 };
 
 use lazy_static::*;
+use spin::Mutex;
+
+use super::{cpu::hart_id, TaskControlBlock, TaskStatus};
+use crate::{
+    config::{BIG_STRIDE, MAX_HARTS},
+    sysctl,
+};
+
+/// stride 超过这个值时整体减去最小值
+const STRIDE_LIMIT: usize = usize::MAX / 2;
 
-use super::{TaskControlBlock, TaskStatus};
-use crate::sync::UPSafeCell;
 ///A array of `TaskControlBlock` that is thread-safe
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
-    block_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// 每个 hart 的就绪队列
+    ready_queues: Vec<VecDeque<Arc<TaskControlBlock>>>,
+    /// 最近一次被选中的任务在选中前的 stride
+    min_stride:   usize,
 
     /// The stopping task, leave a reference so that the kernel stack will not be recycled when switching tasks
     stop_task: Option<Arc<TaskControlBlock>>,
 }
 
-/// A simple FIFO scheduler.
+/// A stride scheduler.
 impl TaskManager {
     ///Creat an empty TaskManager
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
-            block_queue: VecDeque::new(),
-            stop_task:   None,
+            ready_queues: (0..MAX_HARTS).map(|_| VecDeque::new()).collect(),
+            min_stride:   0,
+            stop_task:    None,
         }
     }
-    /// Add process back to ready queue
+    /// Add process back to ready queue of current hart
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        {
+            // 整体减去最小值之前就不在队列中的任务，stride 可能比其他任务大出许多，
+            // 合法的领先量不会超过一个 pass
+            let mut inner = task.inner_exclusive_access(file!(), line!());
+            inner.stride = inner
+                .stride
+                .clamp(self.min_stride, self.min_stride + BIG_STRIDE);
+        }
+        self.ready_queues[hart_id()].push_back(task);
+    }
+    /// 把被唤醒的任务放回就绪队列，开启 `SchedBoost` 时让它最先被调度
+    pub fn add_woken(&mut self, task: Arc<TaskControlBlock>) {
+        if !sysctl::sched_boost() {
+            self.add(task);
+            return;
+        }
+        task.inner_exclusive_access(file!(), line!()).stride = self.min_stride;
+        self.ready_queues[hart_id()].push_front(task);
     }
-    /// add process back to block queue
-    pub fn add_block(&mut self, task: Arc<TaskControlBlock>) {
-        self.block_queue.push_back(task);
+    /// Whether all ready queues are empty
+    pub fn is_empty(&self) -> bool {
+        self.ready_queues.iter().all(|queue| queue.is_empty())
     }
     /// Take a process out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        if self.ready_queue.is_empty() {
-            return None;
+        let mut hart = hart_id();
+        if self.ready_queues[hart].is_empty() {
+            // 从最长的队列偷一个任务
+            hart = (0..MAX_HARTS).max_by_key(|&i| self.ready_queues[i].len())?;
         }
-        // let mut min_idx = 0;
-        // for (idx, _) in self.ready_queue.iter().enumerate() {
-        //     let stride_now = self.ready_queue[idx].inner_exclusive_access(file!(), line!()).stride;
-        //     let stride_min = self.ready_queue[min_idx].inner_exclusive_access(file!(), line!()).stride;
-        //     if stride_now < stride_min {
-        //         min_idx = idx;
-        //     }
-        // }
-        // self.ready_queue.swap(0, min_idx);
-        self.ready_queue.pop_front()
-    }
-    pub fn remove(&mut self, task: Arc<TaskControlBlock>) {
-        if let Some((id, _)) = self
-            .ready_queue
+        let queue = &mut self.ready_queues[hart];
+        // stride 相同时取靠前的，相同优先级的任务按 FIFO 轮转
+        let (idx, stride) = queue
             .iter()
+            .map(|task| task.inner_exclusive_access(file!(), line!()).stride)
             .enumerate()
-            .find(|(_, t)| Arc::as_ptr(t) == Arc::as_ptr(&task))
-        {
-            self.ready_queue.remove(id);
+            .min_by_key(|&(_, stride)| stride)?;
+        let task = queue.remove(idx)?;
+        self.min_stride = self.min_stride.max(stride);
+        let mut inner = task.inner_exclusive_access(file!(), line!());
+        inner.stride = stride + inner.pass;
+        if inner.stride > STRIDE_LIMIT {
+            inner.stride -= self.min_stride;
+            drop(inner);
+            self.rebase_strides();
+        } else {
+            drop(inner);
+        }
+        Some(task)
+    }
+    /// 所有就绪任务的 stride 减去 `min_stride`，相对大小不变
+    fn rebase_strides(&mut self) {
+        let min_stride = self.min_stride;
+        for task in self.ready_queues.iter().flatten() {
+            let mut inner = task.inner_exclusive_access(file!(), line!());
+            inner.stride = inner.stride.saturating_sub(min_stride);
+        }
+        self.min_stride = 0;
+    }
+    pub fn remove(&mut self, task: Arc<TaskControlBlock>) {
+        for queue in self.ready_queues.iter_mut() {
+            if let Some((id, _)) = queue
+                .iter()
+                .enumerate()
+                .find(|(_, t)| Arc::as_ptr(t) == Arc::as_ptr(&task))
+            {
+                queue.remove(id);
+                return;
+            }
         }
     }
     /// Add a task to stopping task
@@ -76,25 +138,16 @@ impl TaskManager {
 
 lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    pub static ref TASK_MANAGER: Mutex<TaskManager> = Mutex::new(TaskManager::new());
     /// PID2PCB instance (map of pid to pcb)
-    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Arc<TaskControlBlock>>> =
-        unsafe { UPSafeCell::new(BTreeMap::new()) };
+    pub static ref PID2PCB: Mutex<BTreeMap<usize, Arc<TaskControlBlock>>> =
+        Mutex::new(BTreeMap::new());
 }
 
 /// Add a task to ready queue
 pub fn add_task(task: Arc<TaskControlBlock>) {
     //trace!("kernel: TaskManager::add_task");
-    TASK_MANAGER.exclusive_access(file!(), line!()).add(task);
-}
-
-/// Add a task to block queue
-pub fn add_block_task(task: Arc<TaskControlBlock>) {
-    //trace!("kernel: TaskManager::add_block_task");
-    TASK_MANAGER
-        .exclusive_access(file!(), line!())
-        .add_block(task);
+    TASK_MANAGER.lock().add(task);
 }
 
 /// Wake up a task
@@ -103,59 +156,170 @@ pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     let mut task_inner = task.inner_exclusive_access(file!(), line!());
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
-    add_task(task);
+    TASK_MANAGER.lock().add_woken(task);
 }
 
 /// Remove a task from the ready queue
 pub fn remove_task(task: Arc<TaskControlBlock>) {
     //trace!("kernel: TaskManager::remove_task");
-    TASK_MANAGER.exclusive_access(file!(), line!()).remove(task);
+    TASK_MANAGER.lock().remove(task);
+}
+
+/// Whether there is any task in the ready queue
+pub fn has_ready_task() -> bool {
+    !TASK_MANAGER.lock().is_empty()
 }
 
 /// Fetch a task out of the ready queue
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     //trace!("kernel: TaskManager::fetch_task");
-    TASK_MANAGER.exclusive_access(file!(), line!()).fetch()
+    TASK_MANAGER.lock().fetch()
 }
 
 /// Set a task to stop-wait status, waiting for its kernel stack out of use.
 pub fn add_stopping_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER
-        .exclusive_access(file!(), line!())
-        .add_stop(task);
+    TASK_MANAGER.lock().add_stop(task);
 }
 
 /// Get process by pid
 pub fn pid2process(pid: usize) -> Option<Arc<TaskControlBlock>> {
-    let map = PID2PCB.exclusive_access(file!(), line!());
+    let map = PID2PCB.lock();
     map.get(&pid).map(Arc::clone)
 }
 
 /// Insert item(pid, pcb) into PID2PCB map (called by do_fork AND ProcessControlBlock::new)
 pub fn insert_into_pid2process(pid: usize, task: Arc<TaskControlBlock>) {
-    PID2PCB.exclusive_access(file!(), line!()).insert(pid, task);
+    PID2PCB.lock().insert(pid, task);
 }
 
 /// Remove item(pid, _some_pcb) from PDI2PCB map (called by exit_current_and_run_next)
 pub fn remove_from_pid2process(pid: usize) {
-    let mut map = PID2PCB.exclusive_access(file!(), line!());
+    let mut map = PID2PCB.lock();
     if map.remove(&pid).is_none() {
         panic!("cannot find pid {} in pid2task!", pid);
     }
 }
 
+/// 优先级 2 和 8 的两个任务轮流被调度，被选中的次数约为 1:4；
+/// 把 stride 推到接近 `STRIDE_LIMIT` 后再调度一轮，减去最小值后比例不变
 #[allow(unused)]
-pub fn unblock_task(task: Arc<TaskControlBlock>) {
-    // println!("[unblock_task] unblock thread");
-    let mut task_manager = TASK_MANAGER.exclusive_access(file!(), line!());
-    task.inner_exclusive_access(file!(), line!()).task_status = TaskStatus::Ready;
-    if let Some((idx, t)) = task_manager
-        .block_queue
-        .iter()
-        .enumerate()
-        .find(|(_, t)| Arc::ptr_eq(t, &task))
+pub fn stride_test() {
+    use super::{CloneFlags, INITPROC};
+
+    const ROUNDS: usize = 1000;
+    let low = INITPROC.clone();
+    let saved = {
+        let inner = low.inner_exclusive_access(file!(), line!());
+        (inner.priority, inner.stride, inner.pass)
+    };
+    let pid = low.fork(CloneFlags::empty());
+    let high = pid2process(pid).unwrap();
+    remove_task(high.clone());
+    low.inner_exclusive_access(file!(), line!()).set_priority(2);
+    high.inner_exclusive_access(file!(), line!())
+        .set_priority(8);
+
+    let mut manager = TaskManager::new();
+    let run = |manager: &mut TaskManager| {
+        let mut high_runs = 0;
+        for _ in 0..ROUNDS {
+            let task = manager.fetch().unwrap();
+            if Arc::ptr_eq(&task, &high) {
+                high_runs += 1;
+            }
+            manager.add(task);
+        }
+        // 队列中只剩这两个任务，留给下一轮
+        assert!(manager.fetch().is_some() && manager.fetch().is_some());
+        high_runs
+    };
+    for task in [&low, &high] {
+        task.inner_exclusive_access(file!(), line!()).stride = 0;
+        manager.add(task.clone());
+    }
+    let high_runs = run(&mut manager);
+    assert!(
+        (790..=810).contains(&high_runs),
+        "high_runs = {}",
+        high_runs
+    );
+
+    manager.min_stride = STRIDE_LIMIT - BIG_STRIDE;
+    for task in [&low, &high] {
+        task.inner_exclusive_access(file!(), line!()).stride = manager.min_stride;
+        manager.add(task.clone());
+    }
+    let wrapped_runs = run(&mut manager);
+    assert!(
+        (790..=810).contains(&wrapped_runs),
+        "wrapped_runs = {}",
+        wrapped_runs
+    );
+    assert!(manager.min_stride < STRIDE_LIMIT / 2);
+
+    {
+        let mut inner = low.inner_exclusive_access(file!(), line!());
+        (inner.priority, inner.stride, inner.pass) = saved;
+        inner.children.retain(|child| !Arc::ptr_eq(child, &high));
+    }
+    remove_from_pid2process(pid);
+    info!(
+        "stride_test passed! priority 8 ran {}/{} times",
+        high_runs, ROUNDS
+    );
+}
+
+/// 优先级 16 的任务一直就绪，优先级 2 的任务运行一次后阻塞再被唤醒：
+/// 关闭 `SchedBoost` 时唤醒的任务 stride 落后，不会马上被选中；开启后下一次调度就选中它
+#[allow(unused)]
+pub fn sched_boost_test() {
+    use super::{CloneFlags, INITPROC};
+    use crate::sysctl::SysctlParam;
+
+    let sleeper = INITPROC.clone();
+    let saved = {
+        let inner = sleeper.inner_exclusive_access(file!(), line!());
+        (inner.priority, inner.stride, inner.pass)
+    };
+    let saved_boost = sysctl::get(SysctlParam::SchedBoost);
+    let pid = sleeper.fork(CloneFlags::empty());
+    let busy = pid2process(pid).unwrap();
+    remove_task(busy.clone());
+    sleeper
+        .inner_exclusive_access(file!(), line!())
+        .set_priority(2);
+    busy.inner_exclusive_access(file!(), line!())
+        .set_priority(16);
+
+    // 返回唤醒之后下一个被选中的是否为 sleeper
+    let woken_first = |boost: usize| {
+        assert_eq!(sysctl::set(SysctlParam::SchedBoost, boost), 0);
+        let mut manager = TaskManager::new();
+        for task in [&sleeper, &busy] {
+            task.inner_exclusive_access(file!(), line!()).stride = 0;
+            manager.add(task.clone());
+        }
+        // sleeper 先运行一次，随后阻塞，不放回就绪队列
+        let task = manager.fetch().unwrap();
+        assert!(Arc::ptr_eq(&task, &sleeper));
+        let task = manager.fetch().unwrap();
+        assert!(Arc::ptr_eq(&task, &busy));
+        manager.add(task);
+        manager.add_woken(sleeper.clone());
+        let first = Arc::ptr_eq(&manager.fetch().unwrap(), &sleeper);
+        // 清空队列
+        while manager.fetch().is_some() {}
+        first
+    };
+    assert!(!woken_first(0));
+    assert!(woken_first(1));
+
+    sysctl::set(SysctlParam::SchedBoost, saved_boost);
     {
-        task_manager.block_queue.remove(idx);
-        task_manager.ready_queue.push_front(task);
+        let mut inner = sleeper.inner_exclusive_access(file!(), line!());
+        (inner.priority, inner.stride, inner.pass) = saved;
+        inner.children.retain(|child| !Arc::ptr_eq(child, &busy));
     }
+    remove_from_pid2process(pid);
+    info!("sched_boost_test passed!");
 }
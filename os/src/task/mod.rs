@@ -10,6 +10,8 @@
 //! might not be what you expect.
 
 mod context;
+pub mod cpu;
+pub mod cred;
 mod manager;
 pub mod process;
 mod processor;
@@ -21,12 +23,23 @@ mod switch;
 mod task;
 
 use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::Ordering;
 
 pub use context::TaskContext;
+pub use cpu::{hart_id, this_cpu};
 use lazy_static::*;
 use manager::{add_stopping_task, fetch_task};
-pub use manager::{add_task, pid2process, remove_from_pid2process, remove_task, wakeup_task};
-pub use process::{CloneFlags, CSIGNAL};
+pub use manager::{
+    add_task,
+    has_ready_task,
+    pid2process,
+    remove_from_pid2process,
+    remove_task,
+    sched_boost_test,
+    stride_test,
+    wakeup_task,
+};
+pub use process::{CloneFlags, MmapProt, CSIGNAL};
 pub use processor::{
     current_kstack_top,
     current_pid,
@@ -35,20 +48,27 @@ pub use processor::{
     current_trap_cx,
     current_trap_cx_user_va,
     current_user_token,
+    hart_online,
+    harts_ran_tasks,
+    idle_test,
+    online_harts,
     run_tasks,
     schedule,
+    smp_test,
     take_current_task,
 };
 pub use res::{kstack_alloc, pid_alloc, KernelStack, PidHandle, IDLE_PID};
+use sigaction::SignalAction;
 pub use signal::SignalFlags;
+use signal::{DefaultAction, SIG_DFL, SIG_IGN};
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
 
-use self::manager::add_block_task;
 use crate::{
-    fs::{defs::OpenFlags, open_file, ROOT_INODE},
+    fs::{defs::OpenFlags, lock::release_record_locks, open_file, ROOT_INODE},
     sbi::shutdown,
-    timer::remove_timer,
+    sync::futex::exit_robust_list,
+    timer::{interrupt_sleep, remove_timer},
 };
 
 /// Make current task suspended and switch to the next task
@@ -85,7 +105,7 @@ pub fn block_current_and_run_next() {
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     task_inner.task_status = TaskStatus::Blocked;
     drop(task_inner);
-    add_block_task(task);
+    // 阻塞的任务不放进任何队列，由等待它的一方持有，唤醒时经 wakeup_task 回到就绪队列
     schedule(task_cx_ptr);
 }
 
@@ -104,6 +124,12 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access(file!(), line!());
     let tid = task.tid;
+    // 释放这个线程仍持有的 robust futex，必须在回收地址空间之前
+    exit_robust_list(
+        task_inner.memory_set.token(),
+        task_inner.robust_list,
+        task.gettid(),
+    );
     // here we do not remove the thread since we are still using the kstack
     // it will be deallocated when sys_waittid is called
     // drop(task_inner);
@@ -153,6 +179,7 @@ pub fn exit_current_and_run_next(exit_code: i32) {
                 println!("kernel: move child process {} to initproc", child.pid.0);
                 child.inner_exclusive_access(file!(), line!()).parent =
                     Some(Arc::downgrade(&INITPROC));
+                child.ppid.store(INITPROC.pid.0, Ordering::Relaxed);
                 initproc_inner.children.push(child.clone());
             }
         }
@@ -188,10 +215,17 @@ pub fn exit_current_and_run_next(exit_code: i32) {
 
         let mut task_inner = task.inner_exclusive_access(file!(), line!());
         task_inner.children.clear();
+        // 没有 munmap 的共享文件页在退出时写回
+        task_inner.memory_set.writeback_shared_pages(..);
         // deallocate other data in user space i.e. program code/data section
         task_inner.memory_set.recycle_data_pages();
         // drop file descriptors
         task_inner.fd_table.clear();
+        // release record locks held by this process
+        release_record_locks(pid);
+        // 线程都已移除，不会再有任务等在这些 mutex 和信号量上
+        task_inner.mutex_list.clear();
+        task_inner.semaphore_list.clear();
         // remove all threads
         task_inner.threads.clear();
         drop(task_inner);
@@ -238,18 +272,59 @@ pub fn add_initproc() {
 //     debug!("PCB created: {}", file);
 // }
 
-/// Check if the current task has any signal to handle
-pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
+/// 返回用户态之前处理当前任务挂起的信号。没有被屏蔽、也没有用户处理函数的信号执行默认动作：
+/// 忽略的信号直接清除，终止进程的信号以 -signum 作为退出码结束当前任务并切换到下一个任务。
+/// SIGKILL 不能被屏蔽；有用户处理函数的信号目前还不能递送，保持挂起
+pub fn handle_signals() {
     let task = current_task().unwrap();
-    let task_inner = task.inner_exclusive_access(file!(), line!());
-    task_inner.signals.check_error()
+    let mut task_inner = task.inner_exclusive_access(file!(), line!());
+    let pending = task_inner.signals & !(task_inner.signal_mask - SignalFlags::SIGKILL);
+    let mut terminate = None;
+    for bit in 0..usize::BITS {
+        let Some(signal) = SignalFlags::from_bits(1 << bit).filter(|s| pending.contains(*s)) else {
+            continue;
+        };
+        let signum = signal.signum();
+        let handler = task_inner
+            .signal_actions
+            .table
+            .get(signum)
+            .map_or(SIG_DFL, |action| action.sa_handler);
+        match (handler, signal.default_action()) {
+            (SIG_IGN, _) | (SIG_DFL, DefaultAction::Ignore) => task_inner.signals.remove(signal),
+            (SIG_DFL, DefaultAction::Terminate) => {
+                terminate = Some(signum);
+                break;
+            }
+            _ => {}
+        }
+    }
+    drop(task_inner);
+    drop(task);
+    if let Some(signum) = terminate {
+        exit_current_and_run_next(-(signum as i32));
+    }
 }
 
-/// Add signal to the current task
+/// 给当前任务加上一个由异常同步产生的信号。返回用户态后会再次触发同一个异常，
+/// 所以这类信号不能被屏蔽或忽略，处理方式恢复为默认的终止进程
 pub fn current_add_signal(signal: SignalFlags) {
     let task = current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access(file!(), line!());
     task_inner.signals |= signal;
+    task_inner.signal_mask.remove(signal);
+    task_inner.signal_actions.table[signal.signum()] = SignalAction::default();
+}
+
+/// 给 task 加上信号。信号没有被屏蔽而 task 正在可被打断的睡眠中时提前唤醒它
+pub fn send_signal(task: &Arc<TaskControlBlock>, signal: SignalFlags) {
+    let mut task_inner = task.inner_exclusive_access(file!(), line!());
+    task_inner.signals |= signal;
+    let unmasked = !(signal & !task_inner.signal_mask).is_empty();
+    drop(task_inner);
+    if unmasked {
+        interrupt_sleep(task);
+    }
 }
 
 /// the inactive(blocked) tasks are removed when the PCB is deallocated.(called by exit_current_and_run_next)
@@ -114,6 +114,15 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// mmap/mprotect 的 prot 参数
+    pub struct MmapProt: u32 {
+        const PROT_READ = 0x1;
+        const PROT_WRITE = 0x2;
+        const PROT_EXEC = 0x4;
+    }
+}
+
 // /// Process Control Block
 // pub struct ProcessControlBlock {
 //     /// immutable
@@ -338,7 +347,7 @@ bitflags! {
 //         *trap_cx = TrapContext::app_init_context(
 //             entry_point,
 //             ustack_top,
-//             KERNEL_SPACE.exclusive_access(file!(), line!()).token(),
+//             KERNEL_SPACE.lock().token(),
 //             kstack_top,
 //             trap_handler as usize,
 //         );
@@ -419,7 +428,7 @@ bitflags! {
 //         *trap_cx = TrapContext::app_init_context(
 //             entry_point,
 //             ustack_top,
-//             KERNEL_SPACE.exclusive_access(file!(), line!()).token(),
+//             KERNEL_SPACE.lock().token(),
 //             kstack_top,
 //             trap_handler as usize,
 //         );
@@ -573,7 +582,7 @@ bitflags! {
 //         let mut trap_cx = TrapContext::app_init_context(
 //             entry_point,
 //             user_sp,
-//             KERNEL_SPACE.exclusive_access(file!(), line!()).token(),
+//             KERNEL_SPACE.lock().token(),
 //             task.kstack.get_top(),
 //             trap_handler as usize,
 //         );
@@ -4,26 +4,43 @@
 //! the current running state of CPU is recorded,
 //! and the replacement and transfer of control flow of different applications are executed.
 
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use core::{
+    arch::asm,
+    cell::RefMut,
+    hint::spin_loop,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use lazy_static::*;
-use riscv::register::{satp, sstatus};
+use riscv::register::{satp, sip, sstatus};
 
-use super::{__switch, fetch_task, switch::__schedule, TaskContext, TaskControlBlock, TaskStatus};
+use super::{
+    __switch,
+    cpu::{hart_id, this_cpu},
+    fetch_task,
+    has_ready_task,
+    switch::__schedule,
+    TaskContext,
+    TaskControlBlock,
+    TaskStatus,
+};
 use crate::{
-    config::__breakpoint,
+    config::{__breakpoint, MAX_HARTS},
     mm::{VirtAddr, KERNEL_SPACE},
-    sync::UPSafeCell,
-    timer::get_time_ms,
+    timer::{check_timer, get_time, get_time_ms, set_next_trigger},
     trap::TrapContext,
 };
 
 /// Processor management structure
 pub struct Processor {
-    current: Option<Arc<TaskControlBlock>>,
+    pub current: Option<Arc<TaskControlBlock>>,
 
     ///The basic control flow of each core, helping to select and switch process
     idle_task_cx: TaskContext,
+
+    /// 本 hart 的 FP 寄存器中最后加载的是哪个任务的状态
+    fp_owner: Weak<TaskControlBlock>,
 }
 
 impl Processor {
@@ -31,9 +48,17 @@ impl Processor {
         Self {
             current:      None,
             idle_task_cx: TaskContext::zero_init(),
+            fp_owner:     Weak::new(),
         }
     }
 
+    /// 本 hart 的 FP 寄存器将要加载 `task` 的状态，返回之前加载的是否已经是它
+    pub fn claim_fp(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let loaded = ptr::eq(self.fp_owner.as_ptr(), Arc::as_ptr(task));
+        self.fp_owner = Arc::downgrade(task);
+        loaded
+    }
+
     ///Get mutable reference to `idle_task_cx`
     fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
         &mut self.idle_task_cx as *mut _
@@ -50,17 +75,37 @@ impl Processor {
     }
 }
 
-lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+/// 当前 hart 的 Processor
+fn this_processor() -> RefMut<'static, Processor> {
+    this_cpu().processor()
 }
 
+/// 正在运行任务的 hart 数，所有 hart 都空闲且没有就绪任务时调度结束
+static BUSY_HARTS: AtomicUsize = AtomicUsize::new(0);
+/// 运行过任务的 hart 的位图
+static HARTS_RAN_TASKS: AtomicUsize = AtomicUsize::new(0);
+
 ///The main part of process execution and scheduling
 ///Loop `fetch_task` to get the process that needs to run, and switch the process through `__switch`
+///
+/// 每个 hart 各自运行这个循环，没有就绪任务但其他 hart 仍在运行任务时原地等待，
+/// 全部 hart 都空闲且没有就绪任务时返回
 pub fn run_tasks() {
     loop {
         debug!("start new turn of scheduling");
-        let mut processor = PROCESSOR.exclusive_access(file!(), line!());
+        // 两次切换之间没有当前任务，写回时的磁盘 I/O 不会阻塞到某个任务上
+        crate::fs::writeback::writeback_if_due();
+        // 先登记为忙再取任务，避免别的 hart 在取任务的间隙误以为调度已经结束
+        BUSY_HARTS.fetch_add(1, Ordering::AcqRel);
+        let mut processor = this_processor();
         if let Some(task) = fetch_task() {
+            // 任务刚在别的 hart 上让出时，要等那边的 __schedule 保存完上下文
+            while task.on_cpu.load(Ordering::Acquire) {
+                spin_loop();
+            }
+            task.on_cpu.store(true, Ordering::Release);
+            HARTS_RAN_TASKS.fetch_or(1 << hart_id(), Ordering::Relaxed);
+            let prev = task.clone();
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access(file!(), line!());
@@ -91,20 +136,97 @@ pub fn run_tasks() {
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // 回到 idle 时任务的上下文已经保存好，其他 hart 可以接手
+            prev.on_cpu.store(false, Ordering::Release);
+            BUSY_HARTS.fetch_sub(1, Ordering::AcqRel);
         } else {
-            return;
+            drop(processor);
+            crate::mm::tlb::handle_pending_flush();
+            if BUSY_HARTS.fetch_sub(1, Ordering::AcqRel) == 1 && !has_ready_task() {
+                return;
+            }
+            idle();
         }
     }
 }
 
+/// idle 中执行 wfi 的次数
+static IDLE_WFI_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 没有就绪任务时让 hart 停在 wfi 上，直到时钟中断、IPI 或外部中断到来。
+///
+/// 内核态全局中断是关闭的，中断只会让 wfi 返回而不会进入 trap，
+/// 所以这里按 sip 中挂起的中断直接处理，否则它们会一直挂起，wfi 也就不再等待
+fn idle() {
+    unsafe {
+        asm!("wfi");
+    }
+    IDLE_WFI_COUNT.fetch_add(1, Ordering::Relaxed);
+    let pending = sip::read();
+    if pending.stimer() {
+        set_next_trigger();
+        check_timer();
+    }
+    if pending.ssoft() {
+        crate::mm::tlb::handle_ipi();
+    }
+    if pending.sext() {
+        crate::boards::irq_handler();
+    }
+}
+
+/// 在 idle 中等待一次，确认 hart 执行了 wfi 并在下一次时钟中断后继续运行
+#[allow(unused)]
+pub fn idle_test() {
+    let count = IDLE_WFI_COUNT.load(Ordering::Relaxed);
+    let start = get_time();
+    idle();
+    assert_eq!(IDLE_WFI_COUNT.load(Ordering::Relaxed), count + 1);
+    assert!(get_time() > start);
+    // 时钟中断已经处理，不会一直挂起
+    assert!(!sip::read().stimer());
+    info!("idle_test passed!");
+}
+
+/// 运行过任务的 hart 的位图
+pub fn harts_ran_tasks() -> usize {
+    HARTS_RAN_TASKS.load(Ordering::Relaxed)
+}
+
+/// 已经完成初始化的 hart 数，启动 hart 算一个
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(1);
+
+/// 从 hart 初始化完成后登记上线
+pub fn hart_online() {
+    ONLINE_HARTS.fetch_add(1, Ordering::AcqRel);
+}
+
+/// 已经上线的 hart 数
+pub fn online_harts() -> usize {
+    ONLINE_HARTS.load(Ordering::Acquire)
+}
+
+/// 等待被唤醒的从 hart 全部上线，确认每个 hart 都拿到了自己的编号和 Processor
+#[allow(unused)]
+pub fn smp_test(expected: usize) {
+    let deadline = get_time_ms() + 1000;
+    while online_harts() < expected && get_time_ms() < deadline {
+        spin_loop();
+    }
+    assert_eq!(online_harts(), expected);
+    assert!(hart_id() < MAX_HARTS);
+    assert_eq!(this_cpu().id, hart_id());
+    info!("smp_test passed! {} hart(s) online", expected);
+}
+
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access(file!(), line!()).take_current()
+    this_processor().take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access(file!(), line!()).current()
+    this_processor().current()
 }
 
 /// get current pid
@@ -145,7 +267,7 @@ pub fn current_kstack_top() -> usize {
 
 /// Return to idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access(file!(), line!());
+    let mut processor = this_processor();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
@@ -7,6 +7,7 @@ use alloc::{
 
 use lazy_static::*;
 use riscv::register::satp;
+use spin::Mutex;
 
 use crate::{
     config::{
@@ -18,7 +19,6 @@ use crate::{
         USER_STACK_SIZE,
     },
     mm::{MapPermission, PTEFlags, PageTable, PhysPageNum, VirtAddr, KERNEL_SPACE},
-    sync::UPSafeCell,
     trap::TrapContext,
 };
 
@@ -59,11 +59,9 @@ impl RecycleAllocator {
 
 lazy_static! {
     /// Glocal allocator for pid
-    static ref PID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
-        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+    static ref PID_ALLOCATOR: Mutex<RecycleAllocator> = Mutex::new(RecycleAllocator::new());
     /// Global allocator for kernel stack
-    static ref KSTACK_ALLOCATOR: UPSafeCell<RecycleAllocator> =
-        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+    static ref KSTACK_ALLOCATOR: Mutex<RecycleAllocator> = Mutex::new(RecycleAllocator::new());
 
 }
 
@@ -75,15 +73,13 @@ pub struct PidHandle(pub usize);
 
 /// Allocate a pid for a process
 pub fn pid_alloc() -> PidHandle {
-    PidHandle(PID_ALLOCATOR.exclusive_access(file!(), line!()).alloc())
+    PidHandle(PID_ALLOCATOR.lock().alloc())
 }
 
 impl Drop for PidHandle {
     fn drop(&mut self) {
         trace!("drop pid {}", self.0);
-        PID_ALLOCATOR
-            .exclusive_access(file!(), line!())
-            .dealloc(self.0);
+        PID_ALLOCATOR.lock().dealloc(self.0);
     }
 }
 
@@ -101,16 +97,14 @@ pub struct KernelStack(pub usize);
 pub fn kstack_alloc() -> KernelStack {
     trace!("kstack_alloc");
 
-    let kstack_id = KSTACK_ALLOCATOR.exclusive_access(file!(), line!()).alloc();
+    let kstack_id = KSTACK_ALLOCATOR.lock().alloc();
     let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
 
-    KERNEL_SPACE
-        .exclusive_access(file!(), line!())
-        .insert_framed_area(
-            kstack_bottom.into(),
-            kstack_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
+    KERNEL_SPACE.lock().insert_framed_area(
+        kstack_bottom.into(),
+        kstack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
 
     KernelStack(kstack_id)
 }
@@ -125,11 +119,9 @@ impl Drop for KernelStack {
             kernel_stack_bottom + KERNEL_STACK_SIZE
         );
         KERNEL_SPACE
-            .exclusive_access(file!(), line!())
+            .lock()
             .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
-        KSTACK_ALLOCATOR
-            .exclusive_access(file!(), line!())
-            .dealloc(self.0);
+        KSTACK_ALLOCATOR.lock().dealloc(self.0);
     }
 }
 
@@ -279,7 +271,7 @@ fn ustack_top_from_id(ustack_top: usize, id: usize) -> usize {
 //             .translate(trap_cx_bottom_va.into())
 //             .unwrap()
 //             .ppn();
-//         let current_pagetable = &mut KERNEL_SPACE.exclusive_access(file!(), line!()).page_table;
+//         let current_pagetable = &mut KERNEL_SPACE.lock().page_table;
 //         debug!(
 //             "map trap_cx in current pagetable trap_cx_bottom: {:#x}, trap_cx_bottom_ppn: {:#x}, page_table: {:#x}",
 //             trap_cx_bottom_va.0, trap_cx_bottom_ppn.0, current_pagetable.token()
@@ -113,22 +113,35 @@ bitflags! {
     }
 }
 
+/// 没有用户处理函数时信号的默认动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// 终止进程
+    Terminate,
+    /// 忽略信号
+    Ignore,
+}
+
 impl SignalFlags {
-    /// convert signal flag to integer & string
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
-        } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
-        } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
-        } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
-        } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
+    /// 单个信号的编号，从 1 开始
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize + 1
+    }
+
+    /// 单个信号的默认动作。还不支持暂停和继续进程，SIGSTOP 等信号目前和 SIGCHLD 一样被忽略
+    pub fn default_action(&self) -> DefaultAction {
+        let ignored = Self::SIGCHLD
+            | Self::SIGURG
+            | Self::SIGWINCH
+            | Self::SIGCONT
+            | Self::SIGSTOP
+            | Self::SIGTSTP
+            | Self::SIGTTIN
+            | Self::SIGTTOU;
+        if ignored.contains(*self) {
+            DefaultAction::Ignore
         } else {
-            // warn!("[kernel] signalflags check_error  {:?}", self);
-            None
+            DefaultAction::Terminate
         }
     }
 }
@@ -174,4 +187,21 @@ impl SigInfo {
             _si_pad:  [0; 128 - 3 * core::mem::size_of::<u32>()],
         }
     }
+
+    /// waitid 返回的 SIGCHLD 信息。si_pid、si_uid 和 si_status 在联合体中，
+    /// 联合体按 8 字节对齐，从偏移 16 开始
+    pub fn child(si_code: usize, pid: usize, uid: u32, status: i32) -> Self {
+        let mut info = Self::new(SIGCHLD, 0, si_code);
+        info._si_pad[4..8].copy_from_slice(&(pid as i32).to_ne_bytes());
+        info._si_pad[8..12].copy_from_slice(&uid.to_ne_bytes());
+        info._si_pad[12..16].copy_from_slice(&status.to_ne_bytes());
+        info
+    }
 }
+
+/// SIGCHLD 的编号
+const SIGCHLD: usize = 17;
+/// waitid 的 si_code：子进程正常退出
+pub const CLD_EXITED: usize = 1;
+/// waitid 的 si_code：子进程被信号杀死
+pub const CLD_KILLED: usize = 2;
@@ -1,16 +1,20 @@
 //! Types related to task management & Functions for completely changing TCB
 
 use alloc::{
-    string::String,
+    string::{String, ToString},
     sync::{Arc, Weak},
     vec,
     vec::Vec,
 };
-use core::{cell::RefMut, slice};
+use core::{
+    slice,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 use riscv::register::sstatus;
 
 use super::{
+    cred::Credentials,
     kstack_alloc,
     process::Flags,
     sigaction::SignalActions,
@@ -21,16 +25,27 @@ use super::{
     TaskContext,
 };
 use crate::{
-    config::{MAX_SYSCALL_NUM, PAGE_SIZE, TRAP_CONTEXT_TRAMPOLINE, USER_STACK_SIZE},
+    config::{
+        BIG_STRIDE,
+        DEFAULT_PRIORITY,
+        MAX_SYSCALL_NUM,
+        PAGE_SIZE,
+        TRAP_CONTEXT_TRAMPOLINE,
+        USER_STACK_SIZE,
+    },
     fs::{
+        defs::FdFlags,
         dentry::Dentry,
+        dev::console::Console,
         file::{cast_file_to_inode, File},
         stdio::{Stdin, Stdout},
+        MountNamespace,
+        FS_MANAGER,
         ROOT_INODE,
     },
-    mm::{MapPermission, MemorySet, PTEFlags, PhysPageNum, VirtAddr, KERNEL_SPACE},
-    sync::UPSafeCell,
-    syscall::errno::EPERM,
+    mm::{MapPermission, MemorySet, PTEFlags, PhysPageNum, SharedFilePage, VirtAddr, KERNEL_SPACE},
+    sync::{mutex::Mutex, sp::SpSafeCellGuard, Semaphore, SpSafeCell},
+    syscall::errno::{EBADF, EINVAL, ENODEV},
     task::{add_task, manager::insert_into_pid2process, pid_alloc, res::trap_cx_bottom_from_tid},
     timer::get_time,
     trap::{trap_handler, TrapContext},
@@ -47,8 +62,18 @@ pub struct TaskControlBlock {
     pub pid: PidHandle,
     /// whether to send SIGCHLD when the task exits
     pub send_sigchld_when_exit: bool,
+    /// parent process id, cached here so that getppid does not need to borrow inner.
+    /// 只会在创建时以及被 initproc 收养时修改
+    pub ppid: AtomicUsize,
+    /// 任务的上下文是否还在某个 hart 上使用，被切走的 hart 保存完上下文后才清除
+    pub on_cpu: AtomicBool,
+    /// 根目录，绝对路径从这里开始解析，目录项的名字是它在全局目录树中的路径。
+    /// 不放在 inner 中，因为调用 open_file 解析路径时往往已经持有 inner
+    pub root: spin::Mutex<Arc<Dentry>>,
+    /// 所在的挂载命名空间，和 root 一样不放在 inner 中
+    mnt_ns: spin::Mutex<MountNamespace>,
     /// mutable
-    inner: UPSafeCell<TaskControlBlockInner>,
+    inner: SpSafeCell<TaskControlBlockInner>,
 }
 
 pub struct TaskControlBlockInner {
@@ -66,6 +91,8 @@ pub struct TaskControlBlockInner {
     pub first_time:       Option<usize>, // todo: 封装为一个单独的TaskTimer结构体
     ///
     pub clear_child_tid:  usize,
+    /// set_robust_list 登记的 robust futex 链表头，线程退出时遍历
+    pub robust_list:      usize,
     /// working directory
     pub work_dir:         Arc<Dentry>,
     /// father task control block
@@ -80,6 +107,8 @@ pub struct TaskControlBlockInner {
     pub exit_code:        Option<i32>,
     /// file descriptor table
     pub fd_table:         Vec<Option<Arc<dyn File>>>,
+    /// fd_table 中每一项的标志，比 fd_table 短时缺少的部分视为空
+    pub fd_flags:         Vec<FdFlags>,
     /// clock time stop watch
     pub clock_stop_watch: usize,
     /// user clock time
@@ -99,13 +128,29 @@ pub struct TaskControlBlockInner {
     pub signals_pending:  SignalFlags,
     // the signal to mask
     pub signal_mask:      SignalFlags,
+    /// user and group ids
+    pub cred:             Credentials,
+    /// controlling terminal, /dev/tty 指向它；脱离会话 (setsid) 后为 None
+    pub ctty:             Option<Arc<dyn File>>,
+    /// 进程组 id，fork 时继承，setsid 时成为自己的 pid
+    pub pgid:             usize,
+    /// mutex_create 创建的 mutex，下标就是 mutex id。线程创建时共享已有的 mutex
+    pub mutex_list:       Vec<Option<Arc<dyn Mutex>>>,
+    /// semaphore_create 创建的信号量，下标就是信号量 id，与 mutex_list 相同
+    pub semaphore_list:   Vec<Option<Arc<Semaphore>>>,
+    /// stride 调度的优先级，不小于 2，fork 和创建线程时继承
+    pub priority:         usize,
+    /// 累计的 stride，调度器总是选择就绪队列中 stride 最小的任务
+    pub stride:           usize,
+    /// 每次被调度时 stride 增加的步长，等于 BIG_STRIDE / priority
+    pub pass:             usize,
 }
 
 impl TaskControlBlock {
     /// Get the mutable reference of the inner TCB
     pub fn inner_exclusive_access(
         &self, file: &'static str, line: u32,
-    ) -> RefMut<'_, TaskControlBlockInner> {
+    ) -> SpSafeCellGuard<'_, TaskControlBlockInner> {
         self.inner.exclusive_access(file, line)
     }
     /// 使用闭包访问内部数据
@@ -126,6 +171,27 @@ impl TaskControlBlock {
     pub fn gettid(&self) -> usize {
         self.tid
     }
+
+    /// 获取父进程 pid，初始进程没有父进程，返回 0
+    pub fn getppid(&self) -> usize {
+        self.ppid.load(Ordering::Relaxed)
+    }
+
+    /// 根目录在全局目录树中的路径，没有 chroot 过时为 "/"
+    pub fn root_path(&self) -> String {
+        self.root.lock().name().to_string()
+    }
+
+    /// 所在的挂载命名空间
+    pub fn mounts(&self) -> MountNamespace {
+        self.mnt_ns.lock().clone()
+    }
+
+    /// 复制一份当前的挂载表作为私有的挂载命名空间，之后的挂载和卸载不再影响其他进程
+    pub fn unshare_mounts(&self) {
+        let copy = self.mounts().lock().copy();
+        *self.mnt_ns.lock() = Arc::new(spin::Mutex::new(copy));
+    }
 }
 
 impl TaskControlBlock {
@@ -175,11 +241,7 @@ impl TaskControlBlock {
             "alloc_user_res: ustack_bottom={:#x} ustack_top={:#x}",
             ustack_bottom, ustack_top
         );
-        memory_set.insert_framed_area(
-            ustack_bottom.into(),
-            ustack_top.into(),
-            MapPermission::R | MapPermission::W | MapPermission::U,
-        );
+        memory_set.insert_user_stack(ustack_bottom.into(), ustack_top.into());
         // alloc trap_cx
         let trap_cx_bottom = trap_cx_bottom_from_tid(pid_handle.0);
         let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
@@ -203,7 +265,7 @@ impl TaskControlBlock {
 
         {
             // 在一定区域中获取可变引用，保证离开时自动释放
-            let current_pagetable = &mut KERNEL_SPACE.exclusive_access(file!(), line!()).page_table;
+            let current_pagetable = &mut KERNEL_SPACE.lock().page_table;
             debug!(
                 "map trap_cx in current pagetable trap_cx_bottom: {:#x}, trap_cx_bottom_ppn: \
                  {:#x}, page_table: {:#x}",
@@ -241,41 +303,53 @@ impl TaskControlBlock {
             tid: tid,
             pid: pid_handle,
             send_sigchld_when_exit: false, //todo
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    is_zombie: false,
-                    memory_set,
-                    trap_cx_ppn,
-                    task_cx: TaskContext::goto_initproc_entry(kstack_top),
-                    task_status: TaskStatus::Ready,
-                    exit_code: None,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    first_time: None,
-                    clear_child_tid: 0,
-                    parent: None,
-                    children: Vec::new(),
-                    threads: Vec::new(),
-                    user_stack_top: ustack_top - 8, // todo
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                    signals: SignalFlags::empty(),
-                    clock_stop_watch: 0,
-                    user_clock: 0,
-                    kernel_clock: 0,
-                    heap_base: user_heap_base.into(),
-                    heap_end: user_heap_base.into(),
-                    work_dir,
-                    signal_actions: SignalActions::default(),
-                    signals_pending: SignalFlags::empty(),
-                    signal_mask: SignalFlags::empty(),
-                })
-            },
+            ppid: AtomicUsize::new(0),
+            on_cpu: AtomicBool::new(false),
+            root: spin::Mutex::new(work_dir.clone()),
+            mnt_ns: spin::Mutex::new(FS_MANAGER.clone()),
+            inner: SpSafeCell::new(TaskControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_initproc_entry(kstack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                first_time: None,
+                clear_child_tid: 0,
+                robust_list: 0,
+                parent: None,
+                children: Vec::new(),
+                threads: Vec::new(),
+                user_stack_top: ustack_top - 8, // todo
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stdout)),
+                ],
+                fd_flags: Vec::new(),
+                signals: SignalFlags::empty(),
+                clock_stop_watch: 0,
+                user_clock: 0,
+                kernel_clock: 0,
+                heap_base: user_heap_base.into(),
+                heap_end: user_heap_base.into(),
+                work_dir,
+                signal_actions: SignalActions::default(),
+                signals_pending: SignalFlags::empty(),
+                signal_mask: SignalFlags::empty(),
+                cred: Credentials::default(),
+                ctty: Some(Arc::new(Console)),
+                pgid: tid,
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                priority: DEFAULT_PRIORITY,
+                stride: 0,
+                pass: BIG_STRIDE / DEFAULT_PRIORITY,
+            }),
         });
         let task_inner = task.inner_exclusive_access(file!(), line!());
         let trap_cx = task.get_trap_cx();
@@ -287,7 +361,7 @@ impl TaskControlBlock {
         *trap_cx = TrapContext::app_init_context(
             entry_point,
             ustack_top,
-            KERNEL_SPACE.exclusive_access(file!(), line!()).token(),
+            KERNEL_SPACE.lock().token(),
             kstack_top,
             trap_handler as usize,
         );
@@ -307,11 +381,11 @@ impl TaskControlBlock {
             flag, sig, stack, ptid, tls, ctid
         );
         let pid = pid_alloc();
-        let task_inner = self.inner_exclusive_access(file!(), line!());
+        let mut task_inner = self.inner_exclusive_access(file!(), line!());
         let memory_set = if flag.contains(CloneFlags::CLONE_VM) {
-            MemorySet::from_existed_user(&task_inner.memory_set)
+            MemorySet::from_existed_user(&mut task_inner.memory_set)
         } else {
-            MemorySet::from_existed_user(&task_inner.memory_set) //todo: 改为Flag对应要求
+            MemorySet::from_existed_user(&mut task_inner.memory_set) //todo: 改为Flag对应要求
         };
 
         // copy fd table
@@ -365,8 +439,15 @@ impl TaskControlBlock {
         todo!("unfinished");
     }
 
-    pub fn fork(self: &Arc<Self>) -> usize {
+    /// 复制出一个子进程并加入调度，返回子进程的 pid。
+    /// flags 中目前只看 CLONE_NEWNS：设置时子进程得到一份私有的挂载表
+    pub fn fork(self: &Arc<Self>, flags: CloneFlags) -> usize {
         trace!("[kernel]: sys_fork");
+        let mnt_ns = if flags.contains(CloneFlags::CLONE_NEWNS) {
+            Arc::new(spin::Mutex::new(self.mounts().lock().copy()))
+        } else {
+            self.mounts()
+        };
         let pid = pid_alloc();
         warn!("fork: pid[{}]", pid.0);
         let trap_cx_ppn = self.trap_cx_ppn();
@@ -374,7 +455,7 @@ impl TaskControlBlock {
         let mut task_inner = self.inner_exclusive_access(file!(), line!());
         let kstack = kstack_alloc();
         let kstack_top = kstack.get_top();
-        let mut memory_set = MemorySet::from_existed_user(&task_inner.memory_set);
+        let mut memory_set = MemorySet::from_existed_user(&mut task_inner.memory_set);
 
         let tid = pid.0;
         let parent = Some(Arc::downgrade(self));
@@ -435,34 +516,46 @@ impl TaskControlBlock {
             tid,
             pid,
             send_sigchld_when_exit: false,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    is_zombie: false,
-                    memory_set,
-                    trap_cx_ppn,
-                    task_cx: TaskContext::goto_user_entry(kstack_top),
-                    task_status: TaskStatus::Ready,
-                    exit_code: None,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    first_time: None,
-                    clear_child_tid: 0,
-                    parent,
-                    children: Vec::new(),
-                    threads: Vec::new(),
-                    user_stack_top: task_inner.user_stack_top,
-                    fd_table: new_fd_table,
-                    signals: SignalFlags::empty(),
-                    clock_stop_watch: 0,
-                    user_clock: 0,
-                    kernel_clock: 0,
-                    heap_base: task_inner.heap_base.clone(),
-                    heap_end: task_inner.heap_end.clone(),
-                    work_dir: task_inner.work_dir.clone(),
-                    signal_actions: SignalActions::default(),
-                    signals_pending: task_inner.signals_pending,
-                    signal_mask: SignalFlags::empty(),
-                })
-            },
+            ppid: AtomicUsize::new(self.pid.0),
+            on_cpu: AtomicBool::new(false),
+            root: spin::Mutex::new(self.root.lock().clone()),
+            mnt_ns: spin::Mutex::new(mnt_ns),
+            inner: SpSafeCell::new(TaskControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_user_entry(kstack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                first_time: None,
+                clear_child_tid: 0,
+                robust_list: 0,
+                parent,
+                children: Vec::new(),
+                threads: Vec::new(),
+                user_stack_top: task_inner.user_stack_top,
+                fd_table: new_fd_table,
+                fd_flags: task_inner.fd_flags.clone(),
+                signals: SignalFlags::empty(),
+                clock_stop_watch: 0,
+                user_clock: 0,
+                kernel_clock: 0,
+                heap_base: task_inner.heap_base.clone(),
+                heap_end: task_inner.heap_end.clone(),
+                work_dir: task_inner.work_dir.clone(),
+                signal_actions: SignalActions::default(),
+                signals_pending: task_inner.signals_pending,
+                signal_mask: SignalFlags::empty(),
+                cred: task_inner.cred,
+                ctty: task_inner.ctty.clone(),
+                pgid: task_inner.pgid,
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                priority: task_inner.priority,
+                stride: task_inner.stride,
+                pass: task_inner.pass,
+            }),
         });
 
         task_inner.children.push(Arc::clone(&child_task));
@@ -496,6 +589,119 @@ impl TaskControlBlock {
         pid
     }
 
+    /// 直接从 ELF 创建一个子进程并加入调度，不复制当前进程的地址空间。
+    /// 子进程继承工作目录、用户和进程组，fd 表只有 stdin/stdout/stderr，argv 只有 `path`
+    pub fn spawn(self: &Arc<Self>, elf_data: &[u8], path: String) -> Arc<TaskControlBlock> {
+        trace!("[kernel]: spawn {}", path);
+        let pid = pid_alloc();
+        let kstack = kstack_alloc();
+        let kstack_top = kstack.get_top();
+        let (mut memory_set, user_heap_base, ustack_top, entry_point, auxv) =
+            MemorySet::from_elf(elf_data);
+
+        // 与 exec 相同地分配用户栈并放入参数
+        let ustack_top = ustack_top - 8;
+        memory_set.insert_user_stack((ustack_top - USER_STACK_SIZE + 8).into(), ustack_top.into());
+        let token = memory_set.page_table.token();
+        let (user_sp, argc, argv_base, envp_base, aux_base) =
+            memory_set.build_stack(ustack_top, vec![path], Vec::new(), auxv, token);
+        unsafe {
+            sstatus::clear_sum();
+        }
+
+        // 中断上下文直接写进新地址空间，不需要映射到当前页表
+        let mut trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
+            kstack_top,
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = argc;
+        trap_cx.x[11] = argv_base;
+        trap_cx.x[12] = envp_base;
+        trap_cx.x[13] = aux_base;
+        let trap_cx_bytes: &[u8] = unsafe {
+            slice::from_raw_parts(
+                &trap_cx as *const TrapContext as *const u8,
+                core::mem::size_of::<TrapContext>(),
+            )
+        };
+        let trap_cx_bottom = trap_cx_bottom_from_tid(pid.0);
+        memory_set.insert_framed_area_with_data(
+            trap_cx_bottom.into(),
+            (trap_cx_bottom + PAGE_SIZE).into(),
+            MapPermission::R | MapPermission::W,
+            trap_cx_bytes,
+        );
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(trap_cx_bottom).into())
+            .unwrap()
+            .ppn();
+
+        let mut task_inner = self.inner_exclusive_access(file!(), line!());
+        let tid = pid.0;
+        let child_task = Arc::new(TaskControlBlock {
+            kstack,
+            tid,
+            pid,
+            send_sigchld_when_exit: false,
+            ppid: AtomicUsize::new(self.pid.0),
+            on_cpu: AtomicBool::new(false),
+            root: spin::Mutex::new(self.root.lock().clone()),
+            mnt_ns: spin::Mutex::new(self.mounts()),
+            inner: SpSafeCell::new(TaskControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_user_entry(kstack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                first_time: None,
+                clear_child_tid: 0,
+                robust_list: 0,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                threads: Vec::new(),
+                user_stack_top: ustack_top,
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stdout)),
+                ],
+                fd_flags: Vec::new(),
+                signals: SignalFlags::empty(),
+                clock_stop_watch: 0,
+                user_clock: 0,
+                kernel_clock: 0,
+                heap_base: user_heap_base.into(),
+                heap_end: user_heap_base.into(),
+                work_dir: task_inner.work_dir.clone(),
+                signal_actions: SignalActions::default(),
+                signals_pending: SignalFlags::empty(),
+                signal_mask: SignalFlags::empty(),
+                cred: task_inner.cred,
+                ctty: task_inner.ctty.clone(),
+                pgid: task_inner.pgid,
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                priority: task_inner.priority,
+                stride: task_inner.stride,
+                pass: task_inner.pass,
+            }),
+        });
+        task_inner.children.push(Arc::clone(&child_task));
+        drop(task_inner);
+        insert_into_pid2process(tid, Arc::clone(&child_task));
+        add_task(Arc::clone(&child_task));
+        info!("spawn: child pid[{}] add to scheduler", tid);
+        child_task
+    }
+
     /// clone2
     pub fn clone2(
         self: &Arc<Self>, _exit_signals: SignalFlags, _clone_signals: CloneFlags, stack_ptr: usize,
@@ -533,47 +739,59 @@ impl TaskControlBlock {
             MapPermission::R | MapPermission::W,
         );
 
-        let memory_set = MemorySet::from_existed_user(&father_inner.memory_set);
+        let memory_set = MemorySet::from_existed_user(&mut father_inner.memory_set);
         let new_task = Arc::new(Self {
             kstack,
             tid: tid,
             pid: pid,
             send_sigchld_when_exit: false, //todo
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    is_zombie: false,
-                    memory_set,
-                    trap_cx_ppn,
-                    task_cx: TaskContext::goto_user_entry(kstack_top),
-                    task_status: TaskStatus::Ready,
-                    exit_code: None,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    first_time: None,
-                    clear_child_tid: 0,
-                    parent: None,
-                    children: Vec::new(),
-                    threads: Vec::new(),
-                    user_stack_top: thread_stack_top, // todo
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                    signals: SignalFlags::empty(),
-                    clock_stop_watch: 0,
-                    user_clock: 0,
-                    kernel_clock: 0,
-                    heap_base: father_inner.heap_base.clone(), //todo 这里存在一个疑问，即共享堆空间，子线程修改堆空间后如何及时更新线程组下其他
-                    heap_end: father_inner.heap_end.clone(), //todo  的线程包括主线程，以及地址空间的修改也需要同步，后续需要修改为线程组使用同一个对象，暂时先别用线程
-                    work_dir: father_inner.work_dir.clone(),
-                    signal_actions: SignalActions::default(),
-                    signals_pending: father_inner.signals_pending,
-                    signal_mask: SignalFlags::empty(),
-                })
-            },
+            ppid: AtomicUsize::new(self.getppid()),
+            on_cpu: AtomicBool::new(false),
+            root: spin::Mutex::new(self.root.lock().clone()),
+            mnt_ns: spin::Mutex::new(self.mounts()),
+            inner: SpSafeCell::new(TaskControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_user_entry(kstack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                first_time: None,
+                clear_child_tid: 0,
+                robust_list: 0,
+                parent: None,
+                children: Vec::new(),
+                threads: Vec::new(),
+                user_stack_top: thread_stack_top, // todo
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stdout)),
+                ],
+                fd_flags: Vec::new(),
+                signals: SignalFlags::empty(),
+                clock_stop_watch: 0,
+                user_clock: 0,
+                kernel_clock: 0,
+                heap_base: father_inner.heap_base.clone(), //todo 这里存在一个疑问，即共享堆空间，子线程修改堆空间后如何及时更新线程组下其他
+                heap_end: father_inner.heap_end.clone(), //todo  的线程包括主线程，以及地址空间的修改也需要同步，后续需要修改为线程组使用同一个对象，暂时先别用线程
+                work_dir: father_inner.work_dir.clone(),
+                signal_actions: SignalActions::default(),
+                signals_pending: father_inner.signals_pending,
+                signal_mask: SignalFlags::empty(),
+                cred: father_inner.cred,
+                ctty: father_inner.ctty.clone(),
+                pgid: father_inner.pgid,
+                mutex_list: father_inner.mutex_list.clone(),
+                semaphore_list: father_inner.semaphore_list.clone(),
+                priority: father_inner.priority,
+                stride: father_inner.stride,
+                pass: father_inner.pass,
+            }),
         });
 
         father_inner.threads.push(Some(Arc::clone(&new_task)));
@@ -616,6 +834,11 @@ impl TaskControlBlock {
         // set heap position
         task_inner.heap_base = user_heap_base.into();
         task_inner.heap_end = user_heap_base.into();
+        // 旧的 robust 链表在新的地址空间中已经没有意义
+        task_inner.robust_list = 0;
+        task_inner.close_on_exec();
+        task_inner.mutex_list.clear();
+        task_inner.semaphore_list.clear();
         // then we alloc user resource for main thread again
         // since memory_set has been changed
         trace!("[kernel: exec] .. alloc user resource for main thread again");
@@ -630,11 +853,7 @@ impl TaskControlBlock {
             "[kernel: exec] alloc user stack ustack_bottom={:#x} ustack_top={:#x}",
             ustack_bottom, ustack_top
         );
-        memory_set.insert_framed_area(
-            ustack_bottom.into(),
-            ustack_top.into(),
-            MapPermission::R | MapPermission::W | MapPermission::U,
-        );
+        memory_set.insert_user_stack(ustack_bottom.into(), ustack_top.into());
 
         // let user_trap_va: VirtAddr = trap_cx_bottom_from_tid(self.pid.0).into();
         // let user_trap_ppn = task_inner
@@ -697,6 +916,8 @@ impl TaskControlBlock {
 
         warn!("user_sp after push args: {:#x}", user_sp);
 
+        // 旧地址空间中共享的文件页在释放前写回
+        task_inner.memory_set.writeback_shared_pages(..);
         task_inner.memory_set = memory_set; // todo dealloc page here
 
         warn!("app entry: {:#x}", entry_point);
@@ -706,7 +927,7 @@ impl TaskControlBlock {
         let mut trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
-            KERNEL_SPACE.exclusive_access(file!(), line!()).token(),
+            KERNEL_SPACE.lock().token(),
             self.kstack.get_top(),
             trap_handler as usize,
         );
@@ -802,7 +1023,7 @@ impl TaskControlBlock {
     //             .translate(trap_cx_bottom_va.into())
     //             .unwrap()
     //             .ppn();
-    //         let current_pagetable = &mut KERNEL_SPACE.exclusive_access(file!(), line!()).page_table;
+    //         let current_pagetable = &mut KERNEL_SPACE.lock().page_table;
     //         debug!(
     //             "map trap_cx in current pagetable trap_cx_bottom: {:#x}, trap_cx_bottom_ppn: {:#x}, page_table: {:#x}",
     //             trap_cx_bottom_va.0, trap_cx_bottom_ppn.0, current_pagetable.token()
@@ -941,14 +1162,52 @@ impl TaskControlBlockInner {
     }
     /// allocate a new file descriptor
     pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+        self.alloc_fd_from(0)
+    }
+
+    /// 分配不小于 `min` 的最小空闲 fd，新 fd 的标志清空
+    pub fn alloc_fd_from(&mut self, min: usize) -> usize {
+        let fd = match (min..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            Some(fd) => fd,
+            None => {
+                let fd = self.fd_table.len().max(min);
+                self.fd_table.resize_with(fd + 1, || None);
+                fd
+            }
+        };
+        self.set_fd_flags(fd, FdFlags::empty());
+        fd
+    }
+
+    /// fd 的标志
+    pub fn fd_flags(&self, fd: usize) -> FdFlags {
+        self.fd_flags.get(fd).copied().unwrap_or(FdFlags::empty())
+    }
+
+    /// 设置 fd 的标志
+    pub fn set_fd_flags(&mut self, fd: usize, flags: FdFlags) {
+        if self.fd_flags.len() <= fd {
+            self.fd_flags.resize(fd + 1, FdFlags::empty());
+        }
+        self.fd_flags[fd] = flags;
+    }
+
+    /// exec 时关闭设置了 close-on-exec 的 fd
+    pub fn close_on_exec(&mut self) {
+        for fd in 0..self.fd_table.len() {
+            if self.fd_flags(fd).cloexec {
+                self.fd_table[fd] = None;
+                self.set_fd_flags(fd, FdFlags::empty());
+            }
         }
     }
 
+    /// 设置 stride 调度的优先级并重新计算步长，调用者保证 `priority >= 2`
+    pub fn set_priority(&mut self, priority: usize) {
+        self.priority = priority;
+        self.pass = BIG_STRIDE / priority;
+    }
+
     /// the count of tasks(threads) in this process
     pub fn thread_count(&self) -> usize {
         self.children.len() //todo 需要完善，目前只是计数子进程，没有维护线程计数，函数本身暂时没什么用
@@ -996,31 +1255,55 @@ impl TaskControlBlockInner {
         offset: usize,
     ) -> isize {
         let flags = Flags::from_bits(flags as u32).unwrap();
-        let file = self.fd_table[fd].clone().unwrap();
-        let inode = cast_file_to_inode(file).unwrap();
-        let (context, length) = if flags.contains(Flags::MAP_ANONYMOUS) {
-            (Vec::new(), len)
-        } else {
-            let context = inode.read_all();
-
-            let file_len = context.len();
-            let length = len.min(file_len - offset);
-            if file_len <= offset {
-                debug!(
-                    "mmap ERROR: offset exceeds file length context.len(): {}, offset: {}",
-                    file_len, offset
-                );
-                return EPERM;
-            };
-            (context, length)
+        if offset % PAGE_SIZE != 0 {
+            return EINVAL;
+        }
+        // 匿名映射不关联文件，新分配的页已经清零
+        if flags.contains(Flags::MAP_ANONYMOUS) || fd == usize::MAX {
+            return self.memory_set.mmap(start_addr, len, flags);
+        }
+        let file = match self.fd_table.get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return EBADF,
         };
-
-        self.memory_set
-            .mmap(start_addr, length, offset, context, flags)
+        // 设备文件直接映射设备提供的物理页
+        if let Some(ppns) = file.mmap_frames(offset, len) {
+            return self.memory_set.mmap_device(start_addr, len, ppns, flags);
+        }
+        let Some(inode) = cast_file_to_inode(file) else {
+            return ENODEV;
+        };
+        let start = self.memory_set.mmap(start_addr, len, flags) as usize;
+        // 逐页从文件的 offset 处读入，超出文件末尾的部分保持为 0
+        for (i, va) in (start..start + len).step_by(PAGE_SIZE).enumerate() {
+            let vpn = VirtAddr::from(va).floor();
+            let page = self.memory_set.page_bytes_mut(vpn).unwrap();
+            let file_offset = offset + i * PAGE_SIZE;
+            let read = inode.read_at(file_offset, page);
+            // 共享映射的修改在 munmap 和进程退出时写回文件，私有映射的修改只留在自己的页中
+            if flags.contains(Flags::MAP_SHARED) && read > 0 {
+                self.memory_set.add_shared_file_page(
+                    vpn,
+                    SharedFilePage {
+                        inode:  inode.clone(),
+                        offset: file_offset,
+                        len:    read,
+                    },
+                );
+            }
+            if read < PAGE_SIZE {
+                break;
+            }
+        }
+        start as isize
     }
 
     ///munmap
     pub fn munmap(&mut self, start_addr: usize, len: usize) -> isize {
+        // 与 MemorySet::munmap 相同的对齐方式，被改写过的共享文件页先写回再解除映射
+        let start = VirtAddr::from(start_addr).ceil();
+        let end = VirtAddr::from(start_addr + len).ceil();
+        self.memory_set.writeback_shared_pages(start..end);
         self.memory_set.munmap(start_addr, len)
     }
 }
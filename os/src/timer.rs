@@ -4,16 +4,24 @@ use alloc::{collections::BinaryHeap, sync::Arc};
 use core::{
     cmp::Ordering,
     ops::{Add, AddAssign, Sub},
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
 };
 
 use lazy_static::*;
 use riscv::register::time;
+use spin::Mutex;
 
 use crate::{
     config::CLOCK_FREQ,
     sbi::set_timer,
-    sync::UPSafeCell,
-    task::{current_task, suspend_current_and_run_next, wakeup_task, TaskControlBlock},
+    task::{
+        block_current_and_run_next,
+        current_task,
+        suspend_current_and_run_next,
+        wakeup_task,
+        SignalFlags,
+        TaskControlBlock,
+    },
 };
 ///纳秒转换关系
 pub const NSEC_PER_SEC: usize = 1_000_000_000;
@@ -37,6 +45,7 @@ pub const USEC_PER_MSEC: usize = 1_000;
 #[allow(dead_code)]
 const MICRO_PER_SEC: usize = 1_000_000;
 
+#[repr(C)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 /// Traditional UNIX timespec structures represent elapsed time, measured by the system clock
 /// # *CAUTION*
@@ -151,6 +160,18 @@ pub fn get_time() -> usize {
     time::read()
 }
 
+/// 单调时钟读到过的最大 tick。各个核的 time 寄存器之间可能有微小的偏差，
+/// 换到另一个核上读时不能比之前读到的小
+static MONOTONIC_TICK: AtomicUsize = AtomicUsize::new(0);
+
+/// 单调不减的当前时间 (tick)，CLOCK_MONOTONIC 使用
+pub fn get_monotonic_time() -> usize {
+    let now = get_time();
+    MONOTONIC_TICK
+        .fetch_max(now, AtomicOrdering::Relaxed)
+        .max(now)
+}
+
 /// Get the current time in milliseconds
 pub fn get_time_ms() -> usize {
     time::read() * MSEC_PER_SEC / CLOCK_FREQ
@@ -192,9 +213,11 @@ pub fn sleep_ms_until(ms: usize, mut f: impl FnMut() -> bool) {
 /// condvar for timer
 pub struct TimerCondVar {
     /// The time when the timer expires, in milliseconds
-    pub expire_ms: usize,
+    pub expire_ms:     usize,
     /// The task to be woken up when the timer expires
-    pub task:      Arc<TaskControlBlock>,
+    pub task:          Arc<TaskControlBlock>,
+    /// 是否可以被信号提前结束，只有 sleep 的定时器可以
+    pub interruptible: bool,
 }
 
 impl PartialEq for TimerCondVar {
@@ -219,22 +242,88 @@ impl Ord for TimerCondVar {
 
 lazy_static! {
     /// TIMERS: global instance: set of timer condvars
-    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
-        unsafe { UPSafeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+    static ref TIMERS: Mutex<BinaryHeap<TimerCondVar>> =
+        Mutex::new(BinaryHeap::<TimerCondVar>::new());
 }
 
 /// Add a timer
 pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
     trace!("kernel:pid[{}] add_timer", current_task().unwrap().pid.0);
-    let mut timers = TIMERS.exclusive_access(file!(), line!());
-    timers.push(TimerCondVar { expire_ms, task });
+    let mut timers = TIMERS.lock();
+    timers.push(TimerCondVar {
+        expire_ms,
+        task,
+        interruptible: false,
+    });
+}
+
+/// 添加一个可以被信号提前结束的定时器，见 [`interrupt_sleep`]
+pub fn add_interruptible_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.lock();
+    timers.push(TimerCondVar {
+        expire_ms,
+        task,
+        interruptible: true,
+    });
+}
+
+/// task 正在可被打断的睡眠中时取消它的定时器并唤醒它，返回是否唤醒了它
+pub fn interrupt_sleep(task: &Arc<TaskControlBlock>) -> bool {
+    let mut timers = TIMERS.lock();
+    let mut temp = BinaryHeap::<TimerCondVar>::new();
+    let mut found = false;
+    for condvar in timers.drain() {
+        if condvar.interruptible && Arc::ptr_eq(&condvar.task, task) {
+            found = true;
+        } else {
+            temp.push(condvar);
+        }
+    }
+    timers.append(&mut temp);
+    drop(timers);
+    if found {
+        wakeup_task(Arc::clone(task));
+    }
+    found
+}
+
+/// 阻塞当前任务直到 tick 数到达 end_tick。睡眠期间收到新的没有被屏蔽的信号时提前返回，
+/// Err 中是还没有睡够的时间。有用户处理函数的信号目前不会被递送和清除，所以进入睡眠之前就已经挂起的信号不会打断睡眠
+pub fn sleep_until(end_tick: usize) -> Result<(), TimeSpec> {
+    let task = current_task().unwrap();
+    let pending = || {
+        let inner = task.inner_exclusive_access(file!(), line!());
+        inner.signals & !inner.signal_mask
+    };
+    let before = pending();
+    let interrupted = |pending: SignalFlags| !(pending - before).is_empty();
+    loop {
+        let now = get_time();
+        if now >= end_tick {
+            return Ok(());
+        }
+        // 向上取整到毫秒，定时器到期时一定已经睡够
+        let ms = ((end_tick - now) * MSEC_PER_SEC + CLOCK_FREQ - 1) / CLOCK_FREQ;
+        add_interruptible_timer(get_time_ms() + ms, Arc::clone(&task));
+        // 在加入定时器之后再检查一次，之前到达的信号找不到定时器，不会唤醒这个任务
+        if interrupted(pending()) {
+            remove_timer(Arc::clone(&task));
+            return Err(TimeSpec::from_tick(end_tick - now));
+        }
+        block_current_and_run_next();
+        remove_timer(Arc::clone(&task));
+        let now = get_time();
+        if now < end_tick && interrupted(pending()) {
+            return Err(TimeSpec::from_tick(end_tick - now));
+        }
+    }
 }
 
 /// Remove a timer
 pub fn remove_timer(task: Arc<TaskControlBlock>) {
     //trace!("kernel:pid[{}] remove_timer", current_task().unwrap().process.upgrade().unwrap().getpid());
     trace!("kernel: remove_timer");
-    let mut timers = TIMERS.exclusive_access(file!(), line!());
+    let mut timers = TIMERS.lock();
     let mut temp = BinaryHeap::<TimerCondVar>::new();
     for condvar in timers.drain() {
         if Arc::as_ptr(&task) != Arc::as_ptr(&condvar.task) {
@@ -248,9 +337,10 @@ pub fn remove_timer(task: Arc<TaskControlBlock>) {
 
 /// Check if the timer has expired
 pub fn check_timer() {
-    trace!("kernel:pid[{}] check_timer", current_task().unwrap().pid.0);
+    // idle 中没有当前任务时也会调用
+    trace!("kernel: check_timer");
     let current_ms = get_time_ms();
-    let mut timers = TIMERS.exclusive_access(file!(), line!());
+    let mut timers = TIMERS.lock();
     while let Some(timer) = timers.peek() {
         if timer.expire_ms <= current_ms {
             wakeup_task(Arc::clone(&timer.task));
@@ -313,8 +403,9 @@ pub enum ClockId {
 }
 
 impl ClockId {
-    pub fn from(clock_id: usize) -> Self {
-        match clock_id {
+    /// 不认识的 clock_id 返回 None
+    pub fn from(clock_id: usize) -> Option<Self> {
+        let clock = match clock_id {
             CLOCK_REALTIME => ClockId::Realtime,
             CLOCK_MONOTONIC => ClockId::Monotonic,
             CLOCK_PROCESS_CPUTIME_ID => ClockId::ProcessCputimeId,
@@ -326,8 +417,9 @@ impl ClockId {
             CLOCK_REALTIME_ALARM => ClockId::RealtimeAlarm,
             CLOCK_BOOTTIME_ALARM => ClockId::BoottimeAlarm,
             CLOCK_TAI => ClockId::Tai,
-            _ => panic!("clock_id {:?} not supported", clock_id),
-        }
+            _ => return None,
+        };
+        Some(clock)
     }
 }
 
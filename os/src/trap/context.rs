@@ -1,5 +1,31 @@
 //! Implementation of [`TrapContext`]
-use riscv::register::sstatus::{self, Sstatus, SPP};
+//!
+//! FP 寄存器是惰性切换的：新程序的 FS 为 Off，只用整数指令的任务从不保存或恢复 FP 寄存器。
+//! 第一次执行 FP 指令时触发非法指令异常，由 [`TrapContext::enable_fp`] 打开 FP 后重新执行；
+//! 之后只在 FS 为 Dirty 时保存，在本 hart 的 FP 寄存器不是自己的状态时恢复。
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use riscv::register::sstatus::{self, Sstatus, FS, SPP};
+
+use crate::task::hart_id;
+
+/// sstatus 中的 FS 字段
+const SSTATUS_FS: usize = 3 << 13;
+/// FP 状态还没有被加载到任何 hart 上
+const FP_NOT_LOADED: usize = usize::MAX;
+
+extern "C" {
+    fn __save_fp(fp: *mut usize);
+    fn __restore_fp(fp: *const usize);
+}
+
+/// 保存 FP 寄存器的总次数
+static FP_SAVES: AtomicUsize = AtomicUsize::new(0);
+
+/// 到目前为止保存 FP 寄存器的次数
+pub fn fp_save_count() -> usize {
+    FP_SAVES.load(Ordering::Relaxed)
+}
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -17,6 +43,16 @@ pub struct TrapContext {
     pub kernel_sp:    usize,
     /// Virtual address of trap handler entry point in kernel
     pub trap_handler: usize,
+    /// 返回用户态时所在 hart 的编号，trap 进内核时恢复到 tp
+    pub kernel_tp:    usize,
+    /// f0-f31，离开用户态时 FS 为 Dirty 才保存
+    pub f:            [usize; 32],
+    /// fcsr，必须紧跟在 f 后面，由 __save_fp/__restore_fp 一起读写
+    pub fcsr:         usize,
+    /// 最后一次把 FP 状态加载到了哪个 hart 上
+    pub fp_hart:      usize,
+    /// 是否执行过 FP 指令，没有执行过的任务 FS 保持为 Off
+    pub fp_used:      bool,
 }
 
 impl TrapContext {
@@ -40,8 +76,106 @@ impl TrapContext {
             kernel_satp,  // addr of page table
             kernel_sp,    // kernel stack
             trap_handler, // addr of trap_handler function
+            kernel_tp: 0,
+            f: [0; 32],
+            fcsr: 0,
+            fp_hart: FP_NOT_LOADED,
+            fp_used: false,
         };
+        // 执行第一条 FP 指令时才打开 FP
+        cx.set_fs(FS::Off);
         cx.set_sp(sp); // app's user stack pointer
         cx // return initial Trap Context of app
     }
+
+    /// 修改保存的 sstatus 中的 FS 字段，__restore 时写回 sstatus
+    fn set_fs(&mut self, fs: FS) {
+        let bits = self.sstatus.bits() & !SSTATUS_FS | (fs as usize) << 13;
+        // Sstatus 只包装了一个 usize，没有提供按位构造的接口
+        self.sstatus = unsafe { core::mem::transmute::<usize, Sstatus>(bits) };
+    }
+
+    /// FS 为 Off 时的非法指令异常：第一次执行 FP 指令时打开 FP，FP 寄存器从全 0 开始，
+    /// 返回用户态后重新执行这条指令。已经打开过说明是真正的非法指令，返回 false
+    pub fn enable_fp(&mut self) -> bool {
+        if self.fp_used {
+            return false;
+        }
+        self.fp_used = true;
+        self.f = [0; 32];
+        self.fcsr = 0;
+        self.fp_hart = FP_NOT_LOADED;
+        self.set_fs(FS::Initial);
+        true
+    }
+
+    /// 从用户态 trap 进内核时调用：用户修改过 FP 寄存器 (FS 为 Dirty) 才保存，
+    /// 保存后标记为 Clean，之后没有再修改就不必重复保存
+    pub fn save_fp(&mut self) {
+        if !self.fp_used || self.sstatus.fs() != FS::Dirty {
+            return;
+        }
+        unsafe { __save_fp(self.f.as_mut_ptr()) };
+        FP_SAVES.fetch_add(1, Ordering::Relaxed);
+        self.set_fs(FS::Clean);
+    }
+
+    /// 返回用户态之前调用：`loaded` 表示本 hart 的 FP 寄存器里最后加载的就是这个任务的状态。
+    /// 只有状态没有加载过、最后加载在其他 hart 上或者本 hart 之后运行过别的任务时才恢复
+    pub fn restore_fp(&mut self, loaded: bool) {
+        let hart = hart_id();
+        if !self.fp_used
+            || self.sstatus.fs() == FS::Off
+            || (loaded && self.fp_hart == hart && self.sstatus.fs() != FS::Initial)
+        {
+            return;
+        }
+        unsafe {
+            sstatus::set_fs(FS::Clean);
+            __restore_fp(self.f.as_ptr());
+        }
+        self.fp_hart = hart;
+        self.set_fs(FS::Clean);
+    }
+}
+
+/// 只用整数指令的任务 trap 进出内核时不保存也不恢复 FP 寄存器；
+/// 用到 FP 的任务在第一条 FP 指令处打开 FP，之后修改过 FP 寄存器才保存
+#[allow(unused)]
+pub fn lazy_fp_test() {
+    let read_f1 = || {
+        let bits: usize;
+        unsafe { core::arch::asm!("fmv.x.d {}, f1", out(reg) bits) };
+        bits
+    };
+    let mut int_cx = TrapContext::app_init_context(0, 0, 0, 0, 0);
+    let mut fp_cx = TrapContext::app_init_context(0, 0, 0, 0, 0);
+    let before = fp_save_count();
+
+    for _ in 0..3 {
+        int_cx.save_fp();
+        int_cx.restore_fp(false);
+    }
+    assert_eq!(int_cx.sstatus.fs(), FS::Off);
+    assert_eq!(fp_save_count(), before);
+
+    // 第一次非法指令异常打开 FP，再次出现说明是真正的非法指令
+    assert!(fp_cx.enable_fp());
+    assert!(!fp_cx.enable_fp());
+    fp_cx.f[1] = 2.0f64.to_bits() as usize;
+    fp_cx.restore_fp(false);
+    assert_eq!(fp_cx.sstatus.fs(), FS::Clean);
+    assert_eq!(read_f1(), 2.0f64.to_bits() as usize);
+
+    // 模拟用户修改了 f1
+    unsafe { core::arch::asm!("fmv.d.x f1, {}", in(reg) 3.0f64.to_bits()) };
+    fp_cx.set_fs(FS::Dirty);
+    fp_cx.save_fp();
+    assert_eq!(fp_save_count(), before + 1);
+    assert_eq!(fp_cx.f[1], 3.0f64.to_bits() as usize);
+    assert_eq!(fp_cx.sstatus.fs(), FS::Clean);
+    // 没有再修改就不重复保存
+    fp_cx.save_fp();
+    assert_eq!(fp_save_count(), before + 1);
+    info!("lazy_fp_test passed!");
 }
@@ -13,7 +13,9 @@
 //! to [`syscall()`].
 
 mod context;
+pub mod softirq;
 
+use alloc::boxed::Box;
 use core::arch::{asm, global_asm};
 
 use riscv::register::{
@@ -22,22 +24,27 @@ use riscv::register::{
     scause::{self, Exception, Interrupt, Trap},
     sepc,
     sie,
+    sscratch,
+    sstatus,
     stval,
     stvec,
 };
 
+use self::softirq::{raise_softirq, run_softirqs};
 use crate::{
     config::__breakpoint,
-    syscall::{self, syscall},
+    mm::tlb,
+    syscall::{self, syscall_from_cx},
     task::{
-        check_signals_of_current,
         current_add_signal,
         current_task,
         current_trap_cx,
         current_trap_cx_user_va,
         current_user_token,
         exit_current_and_run_next,
+        handle_signals,
         suspend_current_and_run_next,
+        this_cpu,
         SignalFlags,
         INITPROC,
     },
@@ -60,12 +67,23 @@ fn set_kernel_trap_entry() {
         stvec::write(__trap_from_kernel as usize, TrapMode::Direct);
     }
 }
+/// 返回用户态之前，本 hart 的 FP 寄存器中不是当前任务的状态时从 TrapContext 恢复
+fn restore_user_fp() {
+    let task = current_task().unwrap();
+    let loaded = this_cpu().processor().claim_fp(&task);
+    current_trap_cx().restore_fp(loaded);
+}
+
 /// set trap entry for traps happen in user mode
 fn set_user_trap_entry() {
     extern "C" {
         fn __alltraps();
     }
+    // 从这里到真正返回用户态之间的 trap 也会进入 __alltraps，
+    // 它需要 sscratch 指向当前任务的 TrapContext 才能判断出是内核态的 trap
+    let trap_cx_user_va: usize = current_trap_cx_user_va().into();
     unsafe {
+        sscratch::write(trap_cx_user_va);
         stvec::write(__alltraps as usize, TrapMode::Direct);
     }
 }
@@ -77,10 +95,19 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// enable software interrupt (IPI) in supervisor mode
+pub fn enable_ipi() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
 /// trap handler
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    // 在可能切换到其他任务之前保存用户修改过的 FP 寄存器
+    current_trap_cx().save_fp();
     let scause = scause::read();
     let stval = stval::read();
     let sepc = sepc::read();
@@ -108,14 +135,41 @@ pub fn trap_handler() -> ! {
             syscall_num = cx.x[17] as i32;
             // get system call return value
             debug!("syscall_num = {}", syscall_num);
-            result = syscall(
-                cx.x[17],
-                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
-            );
+            result = syscall_from_cx(cx);
             // // cx is changed during sys_exec, so we have to call it again
             // cx = current_trap_cx();
             // cx.x[10] = result as usize;
         }
+        Trap::Exception(Exception::StorePageFault)
+            if current_task()
+                .unwrap()
+                .inner_exclusive_access(file!(), line!())
+                .memory_set
+                .handle_cow_fault(stval.into()) =>
+        {
+            // 写时复制的页已经复制，返回用户态重新执行这条写指令
+        }
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+            if current_task()
+                .unwrap()
+                .inner_exclusive_access(file!(), line!())
+                .memory_set
+                .handle_lazy_fault(stval.into()) =>
+        {
+            // 堆或匿名 mmap 中第一次访问的页已经分配，返回用户态重新执行这条指令；
+            // 不在懒分配区域中的访问落到下面，照常以 SIGSEGV 结束
+        }
+        Trap::Exception(Exception::StorePageFault) | Trap::Exception(Exception::LoadPageFault)
+            if current_task()
+                .unwrap()
+                .inner_exclusive_access(file!(), line!())
+                .memory_set
+                .handle_stack_fault(stval.into()) =>
+        {
+            // 用户栈已经向下扩展到缺页的地址；超过增长上限的访问落到下面以 SIGSEGV 结束
+        }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
@@ -132,16 +186,25 @@ pub fn trap_handler() -> ! {
             current_add_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            exit_current_and_run_next(-1);
-            current_add_signal(SignalFlags::SIGILL);
+            // FS 为 Off 时执行 FP 指令也是非法指令异常：打开 FP 后返回用户态重新执行
+            if !current_trap_cx().enable_fp() {
+                current_add_signal(SignalFlags::SIGILL);
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            check_timer();
+            // 唤醒睡眠的任务推迟到返回用户态之前
+            raise_softirq(Box::new(check_timer));
             debug!("Interrupt::SupervisorTimer suspend_current_and_run_next");
             suspend_current_and_run_next();
             debug!("back from timer interrupt");
         }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            crate::boards::irq_handler();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            tlb::handle_ipi();
+        }
         _ => {
             panic!(
                 "[kernel] trap_handler: unsupport trap {:?} , bad addr = {:#x}, bad instruction = \
@@ -152,11 +215,8 @@ pub fn trap_handler() -> ! {
             );
         }
     }
-    //check signals
-    if let Some((errno, msg)) = check_signals_of_current() {
-        trace!("[kernel] trap_handler: .. check signals {}", msg);
-        exit_current_and_run_next(errno);
-    }
+    // 返回用户态之前执行挂起信号的默认动作
+    handle_signals();
 
     let leave_trap_process_satp = satp::read().bits();
 
@@ -212,8 +272,12 @@ pub fn trap_handler() -> ! {
 #[no_mangle]
 pub fn trap_return() -> ! {
     info!("trap_return");
+    run_softirqs();
+    // trap_return 不切换页表，其他 hart 挂上的刷新请求在这里完成
+    tlb::handle_pending_flush();
     //disable_supervisor_interrupt();
     set_user_trap_entry();
+    restore_user_fp();
 
     //从内核态返回后，计算内核态运行时间
     current_task()
@@ -250,22 +314,78 @@ pub fn trap_return() -> ! {
     }
 }
 
+/// 通用寄存器的 ABI 名称
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
 /// handle trap from kernel
+///
+/// 内核态的 trap 都无法恢复：内核访问用户内存之前已经用 [`crate::mm::prefault_user`]
+/// 处理好懒分配、栈增长和写时复制，这里不再碰任务的数据。
+/// `regs` 是 `__trap_from_kernel` 在应急栈上保存的通用寄存器，连同相关的 CSR 一起打印出来之后 panic
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
-    error!(
-        "stval = {:#x}, sepc = {:#x}, satp = {:#x}",
+pub extern "C" fn trap_from_kernel(regs: &[usize; 32]) -> usize {
+    eprintln!(
+        "[kernel] trap from kernel on hart {}: scause = {:?}, stval = {:#x}, sepc = {:#x}",
+        crate::task::hart_id(),
+        scause::read().cause(),
         stval::read(),
-        sepc::read(),
+        sepc::read()
+    );
+    eprintln!(
+        "[kernel] sstatus = {:#x}, satp = {:#x}",
+        sstatus::read().bits(),
         satp::read().bits()
     );
+    for row in (0..32).step_by(4) {
+        eprintln!(
+            "[kernel] {:>4} = {:#018x} {:>4} = {:#018x} {:>4} = {:#018x} {:>4} = {:#018x}",
+            REG_NAMES[row],
+            regs[row],
+            REG_NAMES[row + 1],
+            regs[row + 1],
+            REG_NAMES[row + 2],
+            regs[row + 2],
+            REG_NAMES[row + 3],
+            regs[row + 3]
+        );
+    }
     panic!("a trap {:?} from kernel!", scause::read().cause());
 }
 
+/// 故意在内核态读一个没有映射的地址，应当看到 trap_from_kernel 打印的寄存器 (t3 为 0xdeadbeef)
+/// 之后干净地 panic。stvec 按返回用户态之前的样子指向 __alltraps，检查内核态的 trap 会被转到
+/// __trap_from_kernel 而不是当作用户 trap 处理。不会返回，只在开启 kernel-fault-test 时运行
+#[allow(unused)]
+pub fn kernel_fault_test() -> ! {
+    extern "C" {
+        fn __alltraps();
+    }
+    /// 代替 TrapContext，__alltraps 在其中暂存 t0
+    static mut SCRATCH: [usize; 38] = [0; 38];
+    info!("kernel_fault_test: expecting a clean panic with a register dump");
+    unsafe {
+        sscratch::write(core::ptr::addr_of_mut!(SCRATCH) as usize);
+        stvec::write(__alltraps as usize, TrapMode::Direct);
+        asm!(
+            "ld {tmp}, 0({addr})",
+            addr = in(reg) 0usize,
+            tmp = out(reg) _,
+            in("t3") 0xdead_beefusize,
+        );
+    }
+    unreachable!("kernel_fault_test: the kernel fault returned");
+}
+
 #[no_mangle]
 pub fn initproc_entry() -> ! {
     debug!("entering initproc");
+    run_softirqs();
     set_user_trap_entry();
+    restore_user_fp();
     let trap_cx_user_va: usize = current_trap_cx_user_va().into();
     let user_satp = INITPROC
         .inner_exclusive_access(file!(), line!())
@@ -280,6 +400,7 @@ pub fn initproc_entry() -> ! {
     }
     let restore_va = __init_entry as usize;
     warn!("init satp to {:#x}", user_satp);
+    tlb::set_active_token(user_satp);
     unsafe {
         asm!(
             "fence.i",
@@ -295,7 +416,9 @@ pub fn initproc_entry() -> ! {
 #[no_mangle]
 pub fn user_entry() -> ! {
     info!("entering user app");
+    run_softirqs();
     set_user_trap_entry();
+    restore_user_fp();
     let trap_cx_user_va: usize = current_trap_cx_user_va().into();
     let user_satp = current_user_token();
     debug!(
@@ -313,6 +436,7 @@ pub fn user_entry() -> ! {
     }
     let entry_va = __user_entry as usize;
     warn!("reset satp to {:#x}", user_satp);
+    tlb::set_active_token(user_satp);
     unsafe {
         asm!(
             "fence.i",
@@ -340,10 +464,11 @@ pub fn wait_return() {
     }
     let entry_va = __wait_return as usize;
     warn!("reset satp to {:#x}", user_satp);
+    tlb::set_active_token(user_satp);
     unsafe {
         satp::write(user_satp);
         asm!("sfence.vma");
     }
 }
 
-pub use context::TrapContext;
+pub use context::{fp_save_count, lazy_fp_test, TrapContext};
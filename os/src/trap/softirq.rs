@@ -0,0 +1,49 @@
+//! 软中断：中断处理函数里不直接做可能与用户上下文争锁的工作，
+//! 而是把工作挂到队列上，等到返回用户态前的安全点再统一执行
+
+use alloc::{boxed::Box, collections::VecDeque};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+/// 延迟执行的工作
+pub type SoftIrqWork = Box<dyn FnOnce() + Send>;
+
+/// 待执行的工作队列
+static SOFTIRQ_QUEUE: Mutex<VecDeque<SoftIrqWork>> = Mutex::new(VecDeque::new());
+
+/// 挂起一项延迟工作，在下一次返回用户态之前执行
+pub fn raise_softirq(work: SoftIrqWork) {
+    SOFTIRQ_QUEUE.lock().push_back(work);
+}
+
+/// 是否有尚未执行的工作
+pub fn softirq_pending() -> bool {
+    !SOFTIRQ_QUEUE.lock().is_empty()
+}
+
+/// 依次执行队列中的工作，执行时不持有队列的锁，工作本身可以继续挂起新的工作
+pub fn run_softirqs() {
+    loop {
+        let work = SOFTIRQ_QUEUE.lock().pop_front();
+        match work {
+            Some(work) => work(),
+            None => break,
+        }
+    }
+}
+
+/// 模拟时钟中断挂起一次唤醒，确认它在返回用户态的安全点之前被执行
+#[allow(unused)]
+pub fn softirq_test() {
+    static WOKEN: AtomicBool = AtomicBool::new(false);
+    raise_softirq(Box::new(|| WOKEN.store(true, Ordering::Release)));
+    // 中断处理函数只负责挂起，不会就地执行
+    assert!(softirq_pending());
+    assert!(!WOKEN.load(Ordering::Acquire));
+    // trap_return 等返回用户态的入口都会先调用 run_softirqs
+    run_softirqs();
+    assert!(WOKEN.load(Ordering::Acquire));
+    assert!(!softirq_pending());
+    info!("softirq_test passed!");
+}
@@ -1,3 +1,4 @@
 pub mod async_utils;
 pub mod platform_info;
+pub mod random;
 pub mod string;
@@ -41,6 +41,15 @@ pub struct MachineInfo {
     pub bootargs_len: usize,
 }
 
+impl MachineInfo {
+    /// Kernel command line as a string, if present and valid UTF-8
+    pub fn bootargs(&self) -> Option<&str> {
+        self.bootargs
+            .as_ref()
+            .and_then(|x| core::str::from_utf8(&x[..self.bootargs_len]).ok())
+    }
+}
+
 impl Debug for MachineInfo {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let index = self.model.iter().position(|&x| x == 0).unwrap_or(32);
@@ -66,11 +75,7 @@ impl Debug for MachineInfo {
         )
         .unwrap();
         write!(f, "Initrd: {:#x?}\n", self.initrd).unwrap();
-        let bootargs = self
-            .bootargs
-            .as_ref()
-            .map(|x| core::str::from_utf8(&x[..self.bootargs_len]).unwrap());
-        write!(f, "Bootargs: {:?}", bootargs).unwrap();
+        write!(f, "Bootargs: {:?}", self.bootargs()).unwrap();
         Ok(())
     }
 }
@@ -0,0 +1,72 @@
+//! 内核伪随机数发生器
+//!
+//! /dev/urandom 和 getrandom 共用同一个 xorshift64 状态，不能用于密码学用途。
+//! 默认在第一次使用时以当前时钟作为种子；为了让测试可以复现，可以用
+//! [`set_seed`] 固定种子，启用 `fixed-seed` feature 或在启动参数中传入
+//! `random.seed=<n>` 时启动后就使用固定的种子。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{config::FIXED_RANDOM_SEED, timer::get_time};
+
+/// 启动参数中指定种子的前缀
+const SEED_BOOTARG: &str = "random.seed=";
+
+/// 为 0 表示尚未设置种子
+static STATE: AtomicU64 = AtomicU64::new(0);
+/// 启动时决定的种子，为 0 表示使用时钟
+static BOOT_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// 设置种子，之后的输出序列只由种子决定。xorshift 的状态不能为 0，种子 0 按 1 处理
+pub fn set_seed(seed: u64) {
+    STATE.store(seed.max(1), Ordering::Relaxed);
+}
+
+/// 根据启动参数和 feature 决定初始种子，都没有指定时保持在第一次使用时取时钟
+pub fn init(bootargs: Option<&str>) {
+    let seed = bootargs
+        .and_then(|args| {
+            args.split_whitespace()
+                .find_map(|arg| arg.strip_prefix(SEED_BOOTARG))
+        })
+        .and_then(|seed| seed.parse::<u64>().ok());
+    let seed = match seed {
+        Some(seed) => {
+            info!("random: seed {} from bootargs", seed);
+            seed.max(1)
+        }
+        None if cfg!(feature = "fixed-seed") => {
+            info!("random: fixed seed {:#x}", FIXED_RANDOM_SEED);
+            FIXED_RANDOM_SEED
+        }
+        None => 0,
+    };
+    BOOT_SEED.store(seed, Ordering::Relaxed);
+    STATE.store(seed, Ordering::Relaxed);
+}
+
+/// 回到启动时的种子设置，测试改动种子后调用
+pub fn reset() {
+    STATE.store(BOOT_SEED.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+pub fn next_u64() -> u64 {
+    let mut x = STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        // 第一次使用时以当前时钟作为种子
+        x = get_time() as u64 | 1;
+    }
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    STATE.store(x, Ordering::Relaxed);
+    x
+}
+
+/// 用伪随机字节填满 buf。buf 可以是用户地址，由调用者负责设置 SUM
+pub fn fill_bytes(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
@@ -2,10 +2,18 @@ use alloc::string::String;
 
 use riscv::register::sstatus;
 
+use crate::{config::PAGE_SIZE, mm::prefault_user};
+
+/// 读取当前任务地址空间中以 '\0' 结尾的字符串，调用者不能持有当前任务 inner 的借用
 pub fn c_ptr_to_string(c_ptr: *const u8) -> String {
     let mut res = String::new();
     let mut i = 0;
     loop {
+        // 每进入一页先处理这一页的缺页
+        let va = c_ptr as usize + i;
+        if i == 0 || va % PAGE_SIZE == 0 {
+            prefault_user(va, 1, false);
+        }
         let c = unsafe { *c_ptr.add(i) };
         if c == 0 {
             break;
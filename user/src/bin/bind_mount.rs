@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fsconfig, fsmount, fsopen, mkdir, mount, move_mount, open, read, umount2, write,
+    OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH, MS_BIND,
+};
+
+const ENOENT: isize = -2;
+
+/// 读出 path 的全部内容 (不超过 buf 的长度)
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    assert_eq!(close(fd as usize), 0);
+}
+
+/// 在 tmpfs 中建一个目录，把它 bind mount 到另一个目录上，
+/// 两边各自创建的文件在对方的路径下都能看到，卸载 bind mount 后原目录不受影响
+#[no_mangle]
+pub fn main() -> i32 {
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    mkdir("/bind_src\0");
+    assert_eq!(
+        move_mount(mnt_fd, "\0", AT_FDCWD, "/bind_src\0", MOVE_MOUNT_F_EMPTY_PATH),
+        0
+    );
+    assert_eq!(mkdir("/bind_src/dir\0"), 0);
+    write_file("/bind_src/dir/from_src\0", b"source");
+
+    mkdir("/bind_dst\0");
+    assert_eq!(mount("/bind_src/nosuch\0", "/bind_dst\0", None, MS_BIND, None), ENOENT);
+    assert_eq!(mount("/bind_src/dir\0", "/bind_dst\0", None, MS_BIND, None), 0);
+
+    let mut buf = [0u8; 16];
+    assert_eq!(read_file("/bind_dst/from_src\0", &mut buf), 6);
+    assert_eq!(&buf[..6], b"source");
+    write_file("/bind_dst/from_dst\0", b"target");
+    assert_eq!(read_file("/bind_src/dir/from_dst\0", &mut buf), 6);
+    assert_eq!(&buf[..6], b"target");
+
+    assert_eq!(umount2("/bind_dst\0", 0), 0);
+    assert!(read_file("/bind_dst/from_src\0", &mut buf) < 0);
+    assert_eq!(read_file("/bind_src/dir/from_dst\0", &mut buf), 6);
+    assert_eq!(umount2("/bind_src\0", 0), 0);
+    close(mnt_fd);
+    close(fs_fd);
+    println!("bind_mount passed!");
+    0
+}
@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, chroot, close, exit, fork, mkdir, open, openat, read, setuid, waitpid, write, OpenFlags,
+};
+
+const EPERM: isize = -1;
+
+/// 新建文件 path 并写入 content
+fn create(path: &str, content: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, content), content.len() as isize);
+    close(fd as usize);
+}
+
+/// path 能打开并且内容是 content
+fn check(path: &str, content: &[u8]) {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd as usize, &mut buf), content.len() as isize);
+    assert_eq!(&buf[..content.len()], content);
+    close(fd as usize);
+}
+
+/// 在子进程中运行 f，等待它正常退出
+fn in_child(f: fn()) {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+/// chroot 到 /jail 之后 "/" 解析到 /jail，".." 在新的根目录处停住，访问不到外面的文件；
+/// 普通用户不能 chroot，chroot 只影响调用的进程
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/jail\0");
+    mkdir("/jail/sub\0");
+    create("/jail/inside\0", b"inside");
+    create("/outside\0", b"outside");
+
+    in_child(|| {
+        assert_eq!(chroot("/jail\0"), 0);
+        assert_eq!(chdir("/\0"), 0);
+        check("/inside\0", b"inside");
+        check("inside\0", b"inside");
+        check("/../inside\0", b"inside");
+        check("/sub/../../../inside\0", b"inside");
+        assert!(open("/outside\0", OpenFlags::RDONLY) < 0);
+        assert!(open("/../outside\0", OpenFlags::RDONLY) < 0);
+        assert!(open("/jail/inside\0", OpenFlags::RDONLY) < 0);
+        assert!(open("../outside\0", OpenFlags::RDONLY) < 0);
+        // 相对于目录 fd 的 ".." 同样停在根目录
+        let root = open("/\0", OpenFlags::RDONLY);
+        assert!(root > 0);
+        assert!(openat(root as usize, "../outside\0", OpenFlags::RDONLY) < 0);
+        let fd = openat(root as usize, "../inside\0", OpenFlags::RDONLY);
+        assert!(fd > 0);
+        close(fd as usize);
+        close(root as usize);
+        // 工作目录的 ".." 也停在根目录
+        assert_eq!(chdir("/sub\0"), 0);
+        assert_eq!(chdir("../..\0"), 0);
+        check("inside\0", b"inside");
+        assert!(open("outside\0", OpenFlags::RDONLY) < 0);
+        // 再次 chroot 的路径相对于当前的根目录
+        assert_eq!(chroot("/sub\0"), 0);
+        assert!(open("/inside\0", OpenFlags::RDONLY) < 0);
+        assert!(open("/../inside\0", OpenFlags::RDONLY) < 0);
+    });
+    in_child(|| {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(chroot("/jail\0"), EPERM);
+    });
+
+    check("/outside\0", b"outside");
+    check("/jail/inside\0", b"inside");
+    println!("chroot passed!");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, mmap, munmap, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME, MAP_ANONYMOUS,
+    MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EINVAL: isize = -22;
+const NSEC_PER_SEC: usize = 1_000_000_000;
+
+/// CLOCK_MONOTONIC 不会倒退，不支持的时钟返回 EINVAL，结果跨页时两页都能写对
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut last = TimeSpec::default();
+    for _ in 0..1000 {
+        let mut now = TimeSpec::default();
+        assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut now), 0);
+        assert!(now.tv_nsec < NSEC_PER_SEC);
+        assert!(now >= last);
+        last = now;
+    }
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_REALTIME, &mut now), 0);
+    assert!(now >= last);
+    assert_eq!(clock_gettime(100, &mut now), EINVAL);
+
+    // tv_sec 在第一页末尾，tv_nsec 在第二页开头
+    let addr = mmap(0, PAGE_SIZE * 2, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0);
+    assert!(addr > 0);
+    let tp = (addr as usize + PAGE_SIZE - 8) as *mut TimeSpec;
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, tp), 0);
+    let split = unsafe { tp.read_unaligned() };
+    assert!(split >= last);
+    assert!(split.tv_nsec < NSEC_PER_SEC);
+    assert_eq!(munmap(addr as usize, PAGE_SIZE * 2), 0);
+    println!("clock_gettime passed!");
+    0
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::{arch::asm, mem::size_of};
+
+use user_lib::{exit, sleep};
+
+const SYSCALL_CLONE3: usize = 435;
+const CLONE_VM: u64 = 0x100;
+const CLONE_THREAD: u64 = 0x10000;
+const CLONE_PARENT_SETTID: u64 = 0x100000;
+const EINVAL: isize = -22;
+const STACK_SIZE: usize = 8192;
+
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+    flags:       u64,
+    pidfd:       u64,
+    child_tid:   u64,
+    parent_tid:  u64,
+    exit_signal: u64,
+    stack:       u64,
+    stack_size:  u64,
+    tls:         u64,
+}
+
+#[repr(C, align(16))]
+struct Stack([u8; STACK_SIZE]);
+
+static mut STACK: Stack = Stack([0; STACK_SIZE]);
+
+/// 子线程的入口，参数是进入时的 sp，必须落在为它准备的栈里
+extern "C" fn child_main(sp: usize) -> ! {
+    let stack = unsafe { STACK.0.as_ptr() as usize };
+    if sp > stack && sp <= stack + STACK_SIZE {
+        println!("clone3 child running on its own stack");
+        exit(0);
+    }
+    println!("clone3 child has sp {:#x} outside {:#x}", sp, stack);
+    exit(-1);
+}
+
+/// 调用 clone3，子线程从新栈上直接跳到 child_main，不会回到这里
+fn clone3(args: &CloneArgs, size: usize) -> isize {
+    let ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            "bnez a0, 1f",
+            "mv a0, sp",
+            "jalr t0",
+            "1:",
+            inlateout("x10") args as *const CloneArgs as usize => ret,
+            in("x11") size,
+            in("x17") SYSCALL_CLONE3,
+            in("t0") child_main as usize,
+        );
+    }
+    ret
+}
+
+/// 用 clone3 在显式给出的栈上创建线程
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut tid: usize = 0;
+    let mut args = CloneArgs {
+        flags: CLONE_VM | CLONE_THREAD | CLONE_PARENT_SETTID,
+        parent_tid: &mut tid as *mut usize as u64,
+        stack: unsafe { STACK.0.as_ptr() as u64 },
+        stack_size: STACK_SIZE as u64,
+        ..Default::default()
+    };
+    // 结构体太小，或者只给出栈地址
+    assert_eq!(clone3(&args, 32), EINVAL);
+    args.stack_size = 0;
+    assert_eq!(clone3(&args, size_of::<CloneArgs>()), EINVAL);
+    args.stack_size = STACK_SIZE as u64;
+
+    let ret = clone3(&args, size_of::<CloneArgs>());
+    assert!(ret > 0);
+    let tid = unsafe { core::ptr::read_volatile(&tid) };
+    assert_eq!(tid, ret as usize);
+    // 等子线程打印
+    sleep(100);
+    println!("clone3 passed!");
+    0
+}
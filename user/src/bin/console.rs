@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, fstat, makedev, open, waitpid, write, OpenFlags, Stat, S_IFCHR, S_IFMT,
+};
+
+/// /dev/console 是设备号 (5, 1) 的字符设备，直接写串口，关掉标准输出后仍然可以使用
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/dev/console\0", OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.st_mode & S_IFMT, S_IFCHR);
+    assert_eq!(st.st_rdev, makedev(5, 1));
+    assert_eq!(write(fd, b"console\n"), 8);
+    assert_eq!(close(fd), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(close(1), 0);
+        let fd = open("/dev/console\0", OpenFlags::WRONLY);
+        assert!(fd >= 0);
+        assert_eq!(write(fd as usize, b"console without stdout\n"), 23);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("console passed!");
+    0
+}
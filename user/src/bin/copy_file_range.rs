@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, copy_file_range, fsconfig, fsmount, fsopen, lseek, mkdir, move_mount, open, pipe, pread,
+    umount2, unlink, write, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const EBADF: isize = -9;
+const EINVAL: isize = -22;
+
+/// 不给偏移时使用并移动两个文件自身的位置，给出偏移时只回写偏移；
+/// 源文件读完后停止，非法的 flags、只读的目标和管道都被拒绝
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/cfr_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/cfr_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let src = open("/cfr_mnt/src\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    let dst = open("/cfr_mnt/dst\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(src >= 0 && dst >= 0);
+    let (src, dst) = (src as usize, dst as usize);
+    assert_eq!(write(src, b"0123456789abcdef"), 16);
+    assert_eq!(lseek(src, 0, SEEK_SET), 0);
+
+    // 使用文件位置拷贝，两边的位置都向前移动
+    assert_eq!(copy_file_range(src, None, dst, None, 4, 0), 4);
+    assert_eq!(lseek(src, 0, SEEK_CUR), 4);
+    assert_eq!(lseek(dst, 0, SEEK_CUR), 4);
+
+    // 给出偏移时更新偏移，文件位置不变
+    let mut off_in = 10;
+    let mut off_out = 4;
+    assert_eq!(copy_file_range(src, Some(&mut off_in), dst, Some(&mut off_out), 6, 0), 6);
+    assert_eq!((off_in, off_out), (16, 10));
+    assert_eq!(lseek(src, 0, SEEK_CUR), 4);
+    assert_eq!(lseek(dst, 0, SEEK_CUR), 4);
+    let mut buf = [0u8; 16];
+    assert_eq!(pread(dst, &mut buf, 0), 10);
+    assert_eq!(&buf[..10], b"0123abcdef");
+
+    // 长度超过源文件剩余部分时只拷贝剩余的字节，读到末尾返回 0
+    let mut off_in = 14;
+    assert_eq!(copy_file_range(src, Some(&mut off_in), dst, None, 100, 0), 2);
+    assert_eq!(off_in, 16);
+    assert_eq!(copy_file_range(src, Some(&mut off_in), dst, None, 100, 0), 0);
+    assert_eq!(pread(dst, &mut buf, 0), 10);
+    assert_eq!(&buf[..10], b"0123efcdef");
+
+    assert_eq!(copy_file_range(src, None, dst, None, 1, 1), EINVAL);
+    let ro = open("/cfr_mnt/dst\0", OpenFlags::RDONLY) as usize;
+    assert_eq!(copy_file_range(src, None, ro, None, 1, 0), EBADF);
+    assert_eq!(close(ro), 0);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(copy_file_range(src, None, pipe_fd[1], None, 1, 0), EINVAL);
+    assert_eq!(close(pipe_fd[0]), 0);
+    assert_eq!(close(pipe_fd[1]), 0);
+
+    assert_eq!(close(src), 0);
+    assert_eq!(close(dst), 0);
+    assert_eq!(copy_file_range(src, None, dst, None, 1, 0), EBADF);
+    assert_eq!(unlink("/cfr_mnt/src\0"), 0);
+    assert_eq!(unlink("/cfr_mnt/dst\0"), 0);
+    assert_eq!(umount2("/cfr_mnt\0", 0), 0);
+    println!("copy_file_range passed!");
+    0
+}
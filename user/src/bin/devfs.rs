@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fstat, makedev, open, read, write, OpenFlags, Stat, S_IFCHR, S_IFDIR, S_IFMT};
+
+const ENOENT: isize = -2;
+
+/// 打开 /dev 下的设备，检查它是设备号为 `rdev` 的字符设备
+fn open_dev(path: &str, rdev: u64) -> usize {
+    let fd = open(path, OpenFlags::RDWR);
+    assert!(fd >= 0, "{}", path);
+    let fd = fd as usize;
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.st_mode & S_IFMT, S_IFCHR);
+    assert_eq!(st.st_rdev, rdev);
+    fd
+}
+
+/// /dev 是挂载在启动时的内存文件系统，里面的 null、zero、urandom 按 Linux 的设备号工作
+#[no_mangle]
+pub fn main() -> i32 {
+    let dir = open("/dev\0", OpenFlags::RDONLY);
+    assert!(dir >= 0);
+    let mut st = Stat::default();
+    assert_eq!(fstat(dir as usize, &mut st), 0);
+    assert_eq!(st.st_mode & S_IFMT, S_IFDIR);
+    assert_eq!(close(dir as usize), 0);
+
+    let mut buf = [0xffu8; 32];
+    let null = open_dev("/dev/null\0", makedev(1, 3));
+    assert_eq!(read(null, &mut buf), 0);
+    assert_eq!(write(null, b"discarded"), 9);
+    assert_eq!(close(null), 0);
+
+    let zero = open_dev("/dev/zero\0", makedev(1, 5));
+    assert_eq!(read(zero, &mut buf), 32);
+    assert_eq!(buf, [0; 32]);
+    assert_eq!(close(zero), 0);
+
+    // 连续两次读到相同的 32 字节几乎不可能
+    let urandom = open_dev("/dev/urandom\0", makedev(1, 9));
+    let mut other = [0u8; 32];
+    assert_eq!(read(urandom, &mut buf), 32);
+    assert_eq!(read(urandom, &mut other), 32);
+    assert_ne!(buf, other);
+    assert_eq!(close(urandom), 0);
+
+    assert_eq!(open("/dev/no_such_device\0", OpenFlags::RDONLY), ENOENT);
+    println!("devfs passed!");
+    0
+}
@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, fcntl, fsconfig, fsmount, fsopen, getcwd, mkdir, move_mount, open, pipe, read,
+    umount2, unlink, write, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const ENOENT: isize = -2;
+const EBADF: isize = -9;
+const ENOTDIR: isize = -20;
+const EINVAL: isize = -22;
+const ERANGE: isize = -34;
+
+/// 出错的系统调用返回取负的 errno，而不是统一的 -1 或者让内核 panic
+#[no_mangle]
+pub fn main() -> i32 {
+    // 管道读端不能写，写端不能读
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_end, write_end) = (pipe_fd[0], pipe_fd[1]);
+    let mut buf = [0u8; 64];
+    assert_eq!(write(read_end, b"x"), EBADF);
+    assert_eq!(read(write_end, &mut buf), EBADF);
+
+    // 未知的命令和打开标志
+    assert_eq!(fcntl(read_end, 1234, 0), EINVAL);
+    let bad_flags = unsafe { OpenFlags::from_bits_unchecked(0o40000000) };
+    assert_eq!(open("/\0", bad_flags), EINVAL);
+    assert_eq!(close(read_end), 0);
+    assert_eq!(close(write_end), 0);
+    assert_eq!(read(read_end, &mut buf), EBADF);
+
+    assert_eq!(chdir("/no_such_dir\0"), ENOENT);
+    // 挂载中打开失败的错误码原样返回，普通文件不能作为工作目录
+    mkdir("/errno_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/errno_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+    let file = open("/errno_mnt/file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(file >= 0);
+    assert_eq!(close(file as usize), 0);
+    assert_eq!(chdir("/errno_mnt/missing\0"), ENOENT);
+    assert_eq!(chdir("/errno_mnt/file\0"), ENOTDIR);
+    assert_eq!(unlink("/errno_mnt/file\0"), 0);
+    assert_eq!(umount2("/errno_mnt\0", 0), 0);
+
+    // 缓冲区放不下路径和结尾的 '\0'
+    assert_eq!(getcwd(&mut buf[..1]), ERANGE);
+    assert_eq!(getcwd(&mut buf), buf.as_ptr() as isize);
+    assert_eq!(buf[0], b'/');
+    assert!(buf.contains(&0));
+    println!("errno passed!");
+    0
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, fsconfig, fsmount, fsopen, mkdir, move_mount, open, umount2, unlink,
+    waitpid, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const ENOENT: isize = -2;
+const EEXIST: isize = -17;
+const RACERS: usize = 4;
+
+/// 几个子进程同时以 O_CREAT | O_EXCL 在新挂载的 tmpfs 中创建同一个文件，
+/// 只有一个成功，其余的得到 EEXIST
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/excl_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/excl_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let path = "/excl_mnt/race\0";
+    let mut pids = [0usize; RACERS];
+    for pid in pids.iter_mut() {
+        let child = fork();
+        if child == 0 {
+            let fd = open(path, OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY);
+            if fd >= 0 {
+                close(fd as usize);
+                exit(0);
+            }
+            assert_eq!(fd, EEXIST);
+            exit(1);
+        }
+        *pid = child as usize;
+    }
+    let mut winners = 0;
+    for &pid in pids.iter() {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+        if exit_code == 0 {
+            winners += 1;
+        } else {
+            assert_eq!(exit_code, 1);
+        }
+    }
+    assert_eq!(winners, 1);
+
+    // 文件已存在时不带 O_EXCL 的 O_CREAT 直接打开它，不存在且没有 O_CREAT 时返回 ENOENT
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY);
+    assert_eq!(fd, EEXIST);
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    assert_eq!(unlink(path), 0);
+    assert_eq!(open(path, OpenFlags::RDONLY), ENOENT);
+    assert_eq!(umount2("/excl_mnt\0", 0), 0);
+    println!("excl_create passed!");
+    0
+}
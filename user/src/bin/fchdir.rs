@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, fchdir, fsconfig, fsmount, fsopen, getcwd, mkdir, move_mount, open, umount2,
+    unlink, write, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const EBADF: isize = -9;
+const EBUSY: isize = -16;
+const ENOTDIR: isize = -20;
+
+/// 工作目录的路径，不含结尾的 '\0'
+fn cwd(buf: &mut [u8]) -> &[u8] {
+    assert_eq!(getcwd(buf), buf.as_ptr() as isize);
+    let len = buf.iter().position(|&b| b == 0).unwrap();
+    &buf[..len]
+}
+
+/// fchdir 切换到 fd 打开的目录，之后 getcwd 得到完整路径、相对路径从这里解析，
+/// 工作目录还会让挂载保持忙碌；普通文件返回 ENOTDIR，无效的 fd 返回 EBADF
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/fchdir_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/fchdir_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+    assert_eq!(mkdir("/fchdir_mnt/sub\0"), 0);
+    let file = open("/fchdir_mnt/sub/file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(file >= 0);
+    let file = file as usize;
+    assert_eq!(write(file, b"fchdir"), 6);
+
+    let dir = open("/fchdir_mnt/sub\0", OpenFlags::RDONLY);
+    assert!(dir >= 0);
+    let dir = dir as usize;
+    let mut buf = [0u8; 64];
+    assert_eq!(fchdir(dir), 0);
+    assert_eq!(cwd(&mut buf), b"/fchdir_mnt/sub");
+    let rel = open("file\0", OpenFlags::RDONLY);
+    assert!(rel >= 0);
+    assert_eq!(close(rel as usize), 0);
+    assert_eq!(chdir("..\0"), 0);
+    assert_eq!(cwd(&mut buf), b"/fchdir_mnt");
+
+    // 关掉 fd 之后，工作目录仍然让挂载保持忙碌
+    assert_eq!(fchdir(dir), 0);
+    assert_eq!(close(dir), 0);
+    assert_eq!(umount2("/fchdir_mnt\0", 0), EBUSY);
+
+    assert_eq!(fchdir(file), ENOTDIR);
+    assert_eq!(cwd(&mut buf), b"/fchdir_mnt/sub");
+    assert_eq!(fchdir(dir), EBADF);
+    assert_eq!(fchdir(1000), EBADF);
+
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(close(file), 0);
+    assert_eq!(unlink("/fchdir_mnt/sub/file\0"), 0);
+    assert_eq!(umount2("/fchdir_mnt\0", 0), 0);
+    println!("fchdir passed!");
+    0
+}
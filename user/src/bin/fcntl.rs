@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fcntl};
+
+const F_DUPFD: i32 = 0;
+const F_GETFD: i32 = 1;
+const F_SETFD: i32 = 2;
+const F_GETFL: i32 = 3;
+const F_SETFL: i32 = 4;
+const F_DUPFD_CLOEXEC: i32 = 1030;
+const FD_CLOEXEC: usize = 1;
+const O_WRONLY: isize = 0o1;
+const O_NONBLOCK: usize = 0o4000;
+const EBADF: isize = -9;
+
+/// 以 stdout 为例检查 fcntl 的 fd 复制、close-on-exec 和文件状态标志
+#[no_mangle]
+pub fn main() -> i32 {
+    // F_DUPFD 分配不小于 arg 的最小空闲 fd
+    let fd = fcntl(1, F_DUPFD, 10);
+    assert_eq!(fd, 10);
+    assert_eq!(fcntl(1, F_DUPFD, 10), 11);
+    assert_eq!(close(11), 0);
+
+    // 新 fd 不继承 close-on-exec
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), 0);
+    assert_eq!(fcntl(fd as usize, F_SETFD, FD_CLOEXEC), 0);
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), FD_CLOEXEC as isize);
+    let cloexec_fd = fcntl(fd as usize, F_DUPFD_CLOEXEC, 0);
+    assert!(cloexec_fd > 2);
+    assert_eq!(fcntl(cloexec_fd as usize, F_GETFD, 0), FD_CLOEXEC as isize);
+
+    // 访问模式来自打开的文件，F_SETFL 只能修改 O_APPEND 和 O_NONBLOCK
+    assert_eq!(fcntl(fd as usize, F_GETFL, 0), O_WRONLY);
+    assert_eq!(fcntl(fd as usize, F_SETFL, O_NONBLOCK | 0o2), 0);
+    assert_eq!(fcntl(fd as usize, F_GETFL, 0), O_WRONLY | O_NONBLOCK as isize);
+    assert_eq!(fcntl(1, F_GETFL, 0), O_WRONLY);
+
+    assert_eq!(close(fd as usize), 0);
+    assert_eq!(close(cloexec_fd as usize), 0);
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), EBADF);
+    assert_eq!(fcntl(1000, F_GETFD, 0), EBADF);
+    println!("fcntl passed!");
+    0
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, flock, fork, fsconfig, fsmount, fsopen, lseek, mkdir, move_mount, open, pipe,
+    pread, read, sleep, umount2, unlink, waitpid, write, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE,
+    MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+const LOCK_UN: i32 = 8;
+const EWOULDBLOCK: isize = -11;
+const PATH: &str = "/flock_mnt/file\0";
+
+/// 父进程持有排他锁，子进程另外打开同一个文件：LOCK_NB 返回 EWOULDBLOCK，
+/// 阻塞的 LOCK_EX 要等到父进程释放锁 (release 为 true 时 LOCK_UN，否则 close) 后才成功。
+/// 父进程释放锁之前在文件的 offset 处写入一个字节，子进程拿到锁时必须已经能读到它
+fn contend(offset: usize, release: bool) {
+    let fd = open(PATH, OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(flock(fd, LOCK_EX), 0);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        // 继承来的 fd 与父进程共享同一个打开的文件，不关掉的话父进程 close 时锁不会释放
+        close(fd);
+        close(pipe_fd[0]);
+        let own = open(PATH, OpenFlags::RDWR);
+        assert!(own >= 0);
+        let own = own as usize;
+        assert_eq!(flock(own, LOCK_EX | LOCK_NB), EWOULDBLOCK);
+        // 通知父进程即将阻塞
+        assert_eq!(write(pipe_fd[1], b"w"), 1);
+        assert_eq!(flock(own, LOCK_EX), 0);
+        let mut byte = [0u8; 1];
+        assert_eq!(pread(own, &mut byte, offset as isize), 1);
+        assert_eq!(byte[0], b'u');
+        close(own);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut byte = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut byte), 1);
+    close(pipe_fd[0]);
+    // 给子进程时间进入阻塞
+    sleep(50);
+    assert_eq!(lseek(fd, offset as isize, 0), offset as isize);
+    assert_eq!(write(fd, b"u"), 1);
+    if release {
+        assert_eq!(flock(fd, LOCK_UN), 0);
+    }
+    close(fd);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/flock_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/flock_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    contend(0, true);
+    contend(1, false);
+
+    assert_eq!(unlink(PATH), 0);
+    assert_eq!(umount2("/flock_mnt\0", 0), 0);
+    println!("flock passed!");
+    0
+}
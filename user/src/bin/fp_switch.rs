@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::hint::black_box;
+
+use user_lib::{exit, fork, waitpid, yield_};
+
+const ROUNDS: usize = 20000;
+
+/// 浮点运算的结果依赖之前的每一步，中途让出 CPU 时 FP 寄存器被其他进程改掉就会算错
+fn compute(seed: f64, yield_cpu: bool) -> f64 {
+    let mut x = black_box(seed);
+    let mut y = black_box(seed * 0.5);
+    for i in 0..ROUNDS {
+        x = x * 1.000_001 + y / 3.0;
+        y = y * 0.999_999 - x / 7.0;
+        if yield_cpu && i % 100 == 0 {
+            yield_();
+        }
+    }
+    x + y
+}
+
+/// 两个进程用不同的初值同时做浮点运算，与不让出 CPU 时的结果逐位比较
+fn check(seed: f64) -> bool {
+    let expected = compute(seed, false);
+    let got = compute(seed, true);
+    if got.to_bits() != expected.to_bits() {
+        println!("fp_switch: seed {} expected {} but got {}", seed, expected, got);
+        return false;
+    }
+    true
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(if check(2.0) { 0 } else { 1 });
+    }
+    let ok = check(3.0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(ok && exit_code == 0, "fp_switch: FP state leaked between processes");
+    println!("fp_switch passed!");
+    0
+}
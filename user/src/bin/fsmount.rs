@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fsconfig, fsmount, fsopen, mkdir, move_mount, open, read, umount2, write, OpenFlags,
+    AT_FDCWD, FSCONFIG_CMD_CREATE, FSCONFIG_SET_STRING, FSMOUNT_CLOEXEC, FSOPEN_CLOEXEC,
+    MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const EBUSY: isize = -16;
+const ENODEV: isize = -19;
+const EINVAL: isize = -22;
+
+/// 通过 fsopen / fsconfig / fsmount / move_mount 挂载一个 tmpfs，
+/// 挂载后可以在其中读写文件，卸载后文件随之消失
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(fsopen("nofs\0", 0), ENODEV);
+    let fs_fd = fsopen("tmpfs\0", FSOPEN_CLOEXEC);
+    assert!(fs_fd > 0);
+    let fs_fd = fs_fd as usize;
+    assert_eq!(
+        fsconfig(fs_fd, FSCONFIG_SET_STRING, Some("size\0"), Some("1m\0"), 0),
+        0
+    );
+    assert_eq!(
+        fsconfig(fs_fd, FSCONFIG_SET_STRING, Some("nosuch\0"), Some("1\0"), 0),
+        EINVAL
+    );
+    // 还没有创建文件系统
+    assert_eq!(fsmount(fs_fd, FSMOUNT_CLOEXEC, 0), EINVAL);
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, FSMOUNT_CLOEXEC, 0);
+    assert!(mnt_fd > 0);
+    let mnt_fd = mnt_fd as usize;
+    // 同一个上下文只能 fsmount 一次
+    assert_eq!(fsmount(fs_fd, FSMOUNT_CLOEXEC, 0), EINVAL);
+
+    mkdir("/tmpfs_mnt\0");
+    assert_eq!(
+        move_mount(mnt_fd, "\0", AT_FDCWD, "/tmpfs_mnt\0", MOVE_MOUNT_F_EMPTY_PATH),
+        0
+    );
+    assert_eq!(
+        move_mount(mnt_fd, "\0", AT_FDCWD, "/tmpfs_mnt\0", MOVE_MOUNT_F_EMPTY_PATH),
+        EBUSY
+    );
+
+    let fd = open("/tmpfs_mnt/hello\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"hello tmpfs"), 11);
+    assert_eq!(close(fd as usize), 0);
+    let fd = open("/tmpfs_mnt/hello\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(fd as usize, &mut buf), 11);
+    assert_eq!(&buf[..11], b"hello tmpfs");
+    assert_eq!(close(fd as usize), 0);
+
+    assert_eq!(umount2("/tmpfs_mnt\0", 0), 0);
+    assert!(open("/tmpfs_mnt/hello\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(close(mnt_fd), 0);
+    assert_eq!(close(fs_fd), 0);
+    println!("fsmount passed!");
+    0
+}
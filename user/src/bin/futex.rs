@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{futex_wait, futex_wake, TimeSpec};
+
+const EAGAIN: isize = -11;
+const EINVAL: isize = -22;
+const ETIMEDOUT: isize = -110;
+
+/// 检查 FUTEX_WAIT 的值比较和超时，以及没有等待者时 FUTEX_WAKE 返回 0
+#[no_mangle]
+pub fn main() -> i32 {
+    let word: u32 = 1;
+    // 值已经改变时立即返回
+    assert_eq!(futex_wait(&word, 0, None), EAGAIN);
+    let bad = TimeSpec {
+        tv_sec:  0,
+        tv_nsec: 1_000_000_000,
+    };
+    assert_eq!(futex_wait(&word, 1, Some(&bad)), EINVAL);
+
+    // 没有人唤醒，等到超时
+    let timeout = TimeSpec {
+        tv_sec:  0,
+        tv_nsec: 50_000_000,
+    };
+    assert_eq!(futex_wait(&word, 1, Some(&timeout)), ETIMEDOUT);
+
+    assert_eq!(futex_wake(&word, 1), 0);
+    println!("futex passed!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, getpid, getppid, pipe, read, waitpid, write};
+
+/// 子进程看到的父进程是 fork 它的进程；父进程退出后，孤儿进程被 initproc (pid 0) 收养
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut go = [0usize; 2];
+    let mut report = [0usize; 2];
+    assert_eq!(pipe(&mut go), 0);
+    assert_eq!(pipe(&mut report), 0);
+    let pid = getpid();
+    let child = fork();
+    if child == 0 {
+        assert_eq!(getppid(), pid);
+        let child_pid = getpid();
+        if fork() == 0 {
+            assert_eq!(getppid(), child_pid);
+            // 等中间的进程退出并被回收后再看自己的父进程
+            let mut byte = [0u8; 1];
+            assert_eq!(read(go[0], &mut byte), 1);
+            let ppid = getppid() as usize;
+            assert_eq!(write(report[1], &ppid.to_ne_bytes()), 8);
+            exit(0);
+        }
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+    assert_eq!(write(go[1], b"x"), 1);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(report[0], &mut buf), 8);
+    assert_eq!(usize::from_ne_bytes(buf), 0);
+    for fd in go.iter().chain(report.iter()) {
+        assert_eq!(close(*fd), 0);
+    }
+    println!("getppid passed!");
+    0
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getgroups, setgroups};
+
+const EINVAL: isize = -22;
+const NGROUPS_MAX: usize = 65536;
+
+/// 检查附属用户组列表：只有用户组 0，超长的列表在读取用户内存前就被拒绝
+#[no_mangle]
+pub fn main() -> i32 {
+    // 长度为 0 时只返回组的个数
+    assert_eq!(getgroups(&mut []), 1);
+    let mut groups = [u32::MAX; 2];
+    assert_eq!(getgroups(&mut groups), 1);
+    assert_eq!(groups, [0, u32::MAX]);
+
+    assert_eq!(setgroups(&[]), 0);
+    assert_eq!(setgroups(&[0, 0]), 0);
+    assert_eq!(setgroups(&[0, 100]), EINVAL);
+
+    // 超过 NGROUPS_MAX 的长度直接返回 EINVAL，内核不会越过 groups 读取后面的内存
+    let oversized = unsafe { core::slice::from_raw_parts(groups.as_ptr(), NGROUPS_MAX + 1) };
+    assert_eq!(setgroups(oversized), EINVAL);
+    println!("groups passed!");
+    0
+}
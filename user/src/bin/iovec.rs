@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, pipe, readv, writev, IoVec};
+
+const EBADF: isize = -9;
+const EINVAL: isize = -22;
+
+/// 通过管道检查 writev 按顺序写出各段、readv 按顺序填满各段
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_end, write_end) = (pipe_fd[0], pipe_fd[1]);
+
+    // 长度为 0 的段被跳过
+    let parts: [&[u8]; 4] = [b"hello", b"", b", ", b"iovec"];
+    let iov = parts.map(IoVec::new);
+    assert_eq!(writev(write_end, &iov), 12);
+
+    let (mut head, mut tail) = ([0u8; 4], [0u8; 8]);
+    let iov = [IoVec::new_mut(&mut head), IoVec::new_mut(&mut tail)];
+    assert_eq!(readv(read_end, &iov), 12);
+    assert_eq!(&head, b"hell");
+    assert_eq!(&tail, b"o, iovec");
+
+    // 没有段时什么也不做
+    assert_eq!(writev(write_end, &[]), 0);
+    // 段数超过 IOV_MAX
+    let one = [0u8; 1];
+    let too_many: [IoVec; 1025] = core::array::from_fn(|_| IoVec::new(&one));
+    assert_eq!(writev(write_end, &too_many), EINVAL);
+    // 总长度溢出
+    let overflow = [
+        IoVec { base: one.as_ptr() as usize, len: usize::MAX },
+        IoVec::new(&one),
+    ];
+    assert_eq!(writev(write_end, &overflow), EINVAL);
+    // 方向不对的 fd
+    let mut byte = [0u8; 1];
+    assert_eq!(readv(write_end, &[IoVec::new_mut(&mut byte)]), EBADF);
+    assert_eq!(writev(read_end, &[IoVec::new(&one)]), EBADF);
+
+    assert_eq!(close(read_end), 0);
+    assert_eq!(close(write_end), 0);
+    println!("iovec passed!");
+    0
+}
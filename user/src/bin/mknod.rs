@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, fstat, makedev, mknod, open, read, setuid, unlink, waitpid, write,
+    OpenFlags, Stat, S_IFBLK, S_IFCHR, S_IFDIR, S_IFMT,
+};
+
+const EPERM: isize = -1;
+const EEXIST: isize = -17;
+const EINVAL: isize = -22;
+
+/// 打开设备节点，返回 fd 和它的 stat
+fn open_node(path: &str) -> (usize, Stat) {
+    let fd = open(path, OpenFlags::RDWR);
+    assert!(fd >= 0, "{}", path);
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    (fd as usize, st)
+}
+
+/// 在 /dev 下用 mknod 创建设备节点，读写按设备号转发；重名、不支持的类型和普通用户都被拒绝
+#[no_mangle]
+pub fn main() -> i32 {
+    // 与 /dev/zero 设备号相同的节点读到的也是 0
+    assert_eq!(mknod("/dev/myzero\0", S_IFCHR | 0o666, makedev(1, 5)), 0);
+    let (fd, st) = open_node("/dev/myzero\0");
+    assert_eq!(st.st_mode & S_IFMT, S_IFCHR);
+    assert_eq!(st.st_rdev, makedev(1, 5));
+    let mut buf = [0xffu8; 16];
+    assert_eq!(read(fd, &mut buf), 16);
+    assert_eq!(buf, [0; 16]);
+    assert_eq!(close(fd), 0);
+    assert_eq!(mknod("/dev/myzero\0", S_IFCHR | 0o666, makedev(1, 3)), EEXIST);
+
+    // 没有驱动的设备号也能创建节点，读写什么都不做
+    assert_eq!(mknod("/dev/myblk\0", S_IFBLK | 0o600, makedev(240, 7)), 0);
+    let (fd, st) = open_node("/dev/myblk\0");
+    assert_eq!(st.st_mode & S_IFMT, S_IFBLK);
+    assert_eq!(st.st_rdev, makedev(240, 7));
+    assert_eq!(read(fd, &mut buf), 0);
+    assert_eq!(write(fd, b"x"), 0);
+    assert_eq!(close(fd), 0);
+
+    assert_eq!(mknod("/dev/mydir\0", S_IFDIR | 0o755, 0), EINVAL);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(mknod("/dev/mynull\0", S_IFCHR | 0o666, makedev(1, 3)), EPERM);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(unlink("/dev/myzero\0"), 0);
+    assert_eq!(unlink("/dev/myblk\0"), 0);
+    assert!(open("/dev/myzero\0", OpenFlags::RDONLY) < 0);
+    println!("mknod passed!");
+    0
+}
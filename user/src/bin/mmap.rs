@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close,
+    mmap,
+    munmap,
+    open,
+    write,
+    OpenFlags,
+    MAP_ANONYMOUS,
+    MAP_PRIVATE,
+    PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EBADF: isize = -9;
+const EINVAL: isize = -22;
+
+fn pattern(pos: usize) -> u8 {
+    (pos % 251) as u8
+}
+
+/// 映射文件后读到的是文件内容，匿名映射读到的全是 0
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("mmap_test\0", OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    // 两页多一点，最后一页只有一部分属于文件
+    let len = PAGE_SIZE * 2 + 100;
+    let mut data = [0u8; PAGE_SIZE * 2 + 100];
+    for (pos, byte) in data.iter_mut().enumerate() {
+        *byte = pattern(pos);
+    }
+    assert_eq!(write(fd, &data), len as isize);
+
+    let prot = PROT_READ | PROT_WRITE;
+    let addr = mmap(0, len, prot, MAP_PRIVATE, fd, 0);
+    assert!(addr > 0);
+    let mapped = unsafe { core::slice::from_raw_parts(addr as *const u8, PAGE_SIZE * 3) };
+    assert_eq!(&mapped[..len], &data[..]);
+    assert!(mapped[len..].iter().all(|&byte| byte == 0));
+    assert_eq!(munmap(addr as usize, len), 0);
+
+    // 从第二页开始映射
+    let addr = mmap(0, PAGE_SIZE, prot, MAP_PRIVATE, fd, PAGE_SIZE);
+    assert!(addr > 0);
+    let mapped = unsafe { core::slice::from_raw_parts(addr as *const u8, PAGE_SIZE) };
+    assert_eq!(mapped, &data[PAGE_SIZE..PAGE_SIZE * 2]);
+    assert_eq!(munmap(addr as usize, PAGE_SIZE), 0);
+
+    let anon = mmap(0, PAGE_SIZE, prot, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0);
+    assert!(anon > 0);
+    let mapped = unsafe { core::slice::from_raw_parts(anon as *const u8, PAGE_SIZE) };
+    assert!(mapped.iter().all(|&byte| byte == 0));
+    assert_eq!(munmap(anon as usize, PAGE_SIZE), 0);
+
+    assert_eq!(mmap(0, PAGE_SIZE, prot, MAP_PRIVATE, fd, 100), EINVAL);
+    assert_eq!(mmap(0, PAGE_SIZE, prot, MAP_PRIVATE, 1000, 0), EBADF);
+    assert_eq!(close(fd), 0);
+    assert_eq!(mmap(0, PAGE_SIZE, prot, MAP_PRIVATE, fd, 0), EBADF);
+    println!("mmap passed!");
+    0
+}
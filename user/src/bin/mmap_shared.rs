@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close,
+    exit,
+    fork,
+    mmap,
+    munmap,
+    open,
+    pread,
+    waitpid,
+    write,
+    OpenFlags,
+    MAP_PRIVATE,
+    MAP_SHARED,
+    PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const LEN: usize = PAGE_SIZE + 16;
+
+fn map(fd: usize, flags: usize) -> &'static mut [u8] {
+    let addr = mmap(0, LEN, PROT_READ | PROT_WRITE, flags, fd, 0);
+    assert!(addr > 0);
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, LEN) }
+}
+
+fn file_byte(fd: usize, pos: usize) -> u8 {
+    let mut byte = [0u8];
+    assert_eq!(pread(fd, &mut byte, pos as isize), 1);
+    byte[0]
+}
+
+/// MAP_SHARED 的修改在 munmap 或进程退出时写回文件，MAP_PRIVATE 的修改不写回
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("mmap_shared_test\0", OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, &[b'a'; LEN]), LEN as isize);
+
+    let private = map(fd, MAP_PRIVATE);
+    private[0] = b'p';
+    assert_eq!(munmap(private.as_ptr() as usize, LEN), 0);
+    assert_eq!(file_byte(fd, 0), b'a');
+
+    let shared = map(fd, MAP_SHARED);
+    shared[1] = b's';
+    shared[PAGE_SIZE + 15] = b'S';
+    assert_eq!(munmap(shared.as_ptr() as usize, LEN), 0);
+    assert_eq!(file_byte(fd, 1), b's');
+    assert_eq!(file_byte(fd, PAGE_SIZE + 15), b'S');
+
+    // 子进程不调用 munmap 直接退出
+    let pid = fork();
+    if pid == 0 {
+        let shared = map(fd, MAP_SHARED);
+        shared[2] = b'c';
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(file_byte(fd, 2), b'c');
+    assert_eq!(close(fd), 0);
+    println!("mmap_shared passed!");
+    0
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, fsconfig, fsmount, fsopen, mkdir, move_mount, open, pipe, read, setuid,
+    unshare, waitpid, write, OpenFlags, AT_FDCWD, CLONE_NEWNS, FSCONFIG_CMD_CREATE,
+    MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const EPERM: isize = -1;
+
+/// 新建一个 tmpfs 挂载到 /ns_mnt 上
+fn mount_tmpfs() {
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/ns_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+}
+
+/// /proc/self/mountinfo 中有没有挂载点为 /ns_mnt 的一行
+fn ns_mnt_listed() -> bool {
+    let fd = open("/proc/self/mountinfo\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 1024];
+    let mut len = 0;
+    loop {
+        let n = read(fd as usize, &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd as usize);
+    let info = core::str::from_utf8(&buf[..len]).unwrap();
+    info.lines().any(|line| line.split(' ').nth(4) == Some("/ns_mnt"))
+}
+
+/// 子进程 unshare 出私有的挂载命名空间后挂载的 tmpfs 只有自己看得到，父进程中 /ns_mnt
+/// 仍然是根文件系统上的空目录；普通用户不能 unshare
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/ns_mnt\0");
+    let mut mounted = [0usize; 2];
+    let mut checked = [0usize; 2];
+    assert_eq!(pipe(&mut mounted), 0);
+    assert_eq!(pipe(&mut checked), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(unshare(CLONE_NEWNS), 0);
+        mount_tmpfs();
+        let fd = open("/ns_mnt/private\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        close(fd as usize);
+        assert!(ns_mnt_listed());
+        assert_eq!(write(mounted[1], b"m"), 1);
+        // 等父进程检查完，期间挂载一直存在
+        let mut byte = [0u8; 1];
+        assert_eq!(read(checked[0], &mut byte), 1);
+        assert!(open("/ns_mnt/private\0", OpenFlags::RDONLY) > 0);
+        exit(0);
+    }
+    let mut byte = [0u8; 1];
+    assert_eq!(read(mounted[0], &mut byte), 1);
+    assert!(!ns_mnt_listed());
+    assert!(open("/ns_mnt/private\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(write(checked[1], b"c"), 1);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(unshare(CLONE_NEWNS), EPERM);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("mount_ns passed!");
+    0
+}
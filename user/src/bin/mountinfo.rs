@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fsconfig, fsmount, fsopen, mkdir, move_mount, open, read, umount2, OpenFlags,
+    AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+/// 读出整个 /proc/self/mountinfo，返回读到的长度。文件大小总是 0，要一直读到返回 0 为止
+fn read_mountinfo(buf: &mut [u8]) -> usize {
+    let fd = open("/proc/self/mountinfo\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut len = 0;
+    loop {
+        let n = read(fd as usize, &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd as usize);
+    len
+}
+
+/// 挂载点为 `mount_point` 的一行，没有时返回 None
+fn find_line<'a>(info: &'a str, mount_point: &str) -> Option<&'a str> {
+    info.lines().find(|line| line.split(' ').nth(4) == Some(mount_point))
+}
+
+/// 新挂载的 tmpfs 出现在 mountinfo 中，文件系统类型正确；卸载后这一行随之消失
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 1024];
+    let len = read_mountinfo(&mut buf);
+    let info = core::str::from_utf8(&buf[..len]).unwrap();
+    assert!(find_line(info, "/").is_some());
+    assert!(find_line(info, "/mi_mnt").is_none());
+
+    mkdir("/mi_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/mi_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let len = read_mountinfo(&mut buf);
+    let info = core::str::from_utf8(&buf[..len]).unwrap();
+    let line = find_line(info, "/mi_mnt").expect("tmpfs mount missing from mountinfo");
+    let (_, fs) = line.split_once(" - ").unwrap();
+    assert_eq!(fs.split(' ').next(), Some("tmpfs"));
+
+    assert_eq!(umount2("/mi_mnt\0", 0), 0);
+    let len = read_mountinfo(&mut buf);
+    let info = core::str::from_utf8(&buf[..len]).unwrap();
+    assert!(find_line(info, "/mi_mnt").is_none());
+    println!("mountinfo passed!");
+    0
+}
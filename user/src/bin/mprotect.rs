@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit,
+    fork,
+    mmap,
+    mprotect,
+    munmap,
+    waitpid,
+    MAP_ANONYMOUS,
+    MAP_PRIVATE,
+    PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EINVAL: isize = -22;
+const ENOMEM: isize = -12;
+
+/// 子进程写入只读页会被杀死
+fn write_faults(page: *mut u8) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        unsafe { page.write_volatile(0xff) };
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code != 0
+}
+
+/// 只读之后读取正常、写入出错，恢复写权限后可以再写；写时复制的页在父子之间仍然独立
+#[no_mangle]
+pub fn main() -> i32 {
+    let addr = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0);
+    assert!(addr > 0);
+    let addr = addr as usize;
+    let page = addr as *mut u8;
+    unsafe { page.write_volatile(1) };
+
+    assert_eq!(mprotect(addr, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(unsafe { page.read_volatile() }, 1);
+    assert!(write_faults(page));
+
+    let pid = fork();
+    if pid == 0 {
+        // fork 之后的页是写时复制的，打开写权限后写入只影响子进程
+        assert_eq!(mprotect(addr, PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+        unsafe { page.write_volatile(2) };
+        assert_eq!(unsafe { page.read_volatile() }, 2);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { page.read_volatile() }, 1);
+
+    assert_eq!(mprotect(addr, PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+    unsafe { page.write_volatile(3) };
+    assert!(!write_faults(page));
+
+    assert_eq!(mprotect(addr + 1, PAGE_SIZE, PROT_READ), EINVAL);
+    assert_eq!(mprotect(addr, PAGE_SIZE * 2, PROT_READ), ENOMEM);
+    assert_eq!(munmap(addr, PAGE_SIZE), 0);
+    assert_eq!(mprotect(addr, PAGE_SIZE, PROT_READ), ENOMEM);
+    println!("mprotect passed!");
+    0
+}
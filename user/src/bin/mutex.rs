@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock, waitpid};
+
+const EINVAL: isize = -22;
+const ROUNDS: usize = 10000;
+
+/// 在 mutex 保护下反复加一，两种 mutex 都要能反复加锁、解锁
+fn add_under(mutex_id: usize, counter: &mut usize) {
+    for _ in 0..ROUNDS {
+        assert_eq!(mutex_lock(mutex_id), 0);
+        *counter += 1;
+        assert_eq!(mutex_unlock(mutex_id), 0);
+    }
+}
+
+/// 检查 mutex 的创建、加锁和解锁，以及 fork 出的子进程不继承 mutex
+#[no_mangle]
+pub fn main() -> i32 {
+    let spin = mutex_create();
+    let blocking = mutex_blocking_create();
+    assert!(spin >= 0 && blocking >= 0);
+    assert_ne!(spin, blocking);
+    let mut counter = 0;
+    add_under(spin as usize, &mut counter);
+    add_under(blocking as usize, &mut counter);
+    assert_eq!(counter, 2 * ROUNDS);
+
+    let pid = fork();
+    if pid == 0 {
+        // 子进程有自己的 mutex 表
+        assert_eq!(mutex_lock(blocking as usize), EINVAL);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(mutex_lock(100), EINVAL);
+    println!("mutex passed!");
+    0
+}
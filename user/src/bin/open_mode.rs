@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fcntl, fsconfig, fsmount, fsopen, mkdir, move_mount, open, pread, pwrite, read, readv,
+    umount2, unlink, write, writev, IoVec, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE,
+    MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const EBADF: isize = -9;
+const F_GETFL: i32 = 3;
+const O_ACCMODE: isize = 0o3;
+
+const PATH: &str = "/mode_mnt/file\0";
+
+fn open_as(flags: OpenFlags) -> usize {
+    let fd = open(PATH, flags);
+    assert!(fd >= 0);
+    fd as usize
+}
+
+/// 打开文件时的访问模式决定能否读写：只读的 fd 各种写入都返回 EBADF，只写的 fd 各种读取也是
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/mode_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/mode_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let fd = open_as(OpenFlags::CREATE | OpenFlags::RDWR);
+    assert_eq!(fcntl(fd, F_GETFL, 0) & O_ACCMODE, OpenFlags::RDWR.bits() as isize);
+    assert_eq!(write(fd, b"data"), 4);
+    assert_eq!(close(fd), 0);
+
+    let mut buf = [0u8; 4];
+    let fd = open_as(OpenFlags::RDONLY);
+    assert_eq!(fcntl(fd, F_GETFL, 0) & O_ACCMODE, OpenFlags::RDONLY.bits() as isize);
+    assert_eq!(write(fd, b"x"), EBADF);
+    assert_eq!(pwrite(fd, b"x", 0), EBADF);
+    assert_eq!(writev(fd, &[IoVec::new(b"x")]), EBADF);
+    assert_eq!(read(fd, &mut buf), 4);
+    assert_eq!(&buf, b"data");
+    assert_eq!(close(fd), 0);
+
+    let fd = open_as(OpenFlags::WRONLY);
+    assert_eq!(fcntl(fd, F_GETFL, 0) & O_ACCMODE, OpenFlags::WRONLY.bits() as isize);
+    assert_eq!(read(fd, &mut buf), EBADF);
+    assert_eq!(pread(fd, &mut buf, 0), EBADF);
+    assert_eq!(readv(fd, &[IoVec::new_mut(&mut buf)]), EBADF);
+    assert_eq!(write(fd, b"DA"), 2);
+    assert_eq!(close(fd), 0);
+
+    // 只写的 fd 写入的内容经由另一个 fd 可以读到
+    let fd = open_as(OpenFlags::RDONLY);
+    assert_eq!(read(fd, &mut buf), 4);
+    assert_eq!(&buf, b"DAta");
+    assert_eq!(close(fd), 0);
+
+    assert_eq!(unlink(PATH), 0);
+    assert_eq!(umount2("/mode_mnt\0", 0), 0);
+    println!("open_mode passed!");
+    0
+}
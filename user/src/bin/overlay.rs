@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fsconfig, fsmount, fsopen, mkdir, mount, move_mount, open, read, umount2, write,
+    OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+/// 读出 path 的全部内容 (不超过 buf 的长度)
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    assert_eq!(close(fd as usize), 0);
+}
+
+/// 新建一个 tmpfs 挂载到 path 上，返回 (fs_fd, mnt_fd)
+fn mount_tmpfs(path: &str) -> (usize, usize) {
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    mkdir(path);
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, path, MOVE_MOUNT_F_EMPTY_PATH), 0);
+    (fs_fd, mnt_fd)
+}
+
+/// 合并视图中能读到下层的文件，修改后复制到上层，下层的内容不变。
+/// 为了能在用户态准备下层的内容，这里下层也用 tmpfs，FAT32 下层由内核中的 overlay_test 覆盖
+#[no_mangle]
+pub fn main() -> i32 {
+    let lower = mount_tmpfs("/ovl_lower\0");
+    let upper = mount_tmpfs("/ovl_upper\0");
+    assert_eq!(mkdir("/ovl_lower/dir\0"), 0);
+    write_file("/ovl_lower/dir/file\0", b"lower");
+
+    mkdir("/ovl\0");
+    assert!(mount("overlay\0", "/ovl\0", Some("overlay\0"), 0, Some("lowerdir=/ovl_lower\0")) < 0);
+    let data = "lowerdir=/ovl_lower,upperdir=/ovl_upper\0";
+    assert_eq!(mount("overlay\0", "/ovl\0", Some("overlay\0"), 0, Some(data)), 0);
+
+    let mut buf = [0u8; 16];
+    assert_eq!(read_file("/ovl/dir/file\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"lower");
+    // 修改下层的文件：copy-up 到上层，下层不变
+    let fd = open("/ovl/dir/file\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"UP"), 2);
+    assert_eq!(close(fd as usize), 0);
+    assert_eq!(read_file("/ovl/dir/file\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"UPwer");
+    assert_eq!(read_file("/ovl_upper/dir/file\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"UPwer");
+    assert_eq!(read_file("/ovl_lower/dir/file\0", &mut buf), 5);
+    assert_eq!(&buf[..5], b"lower");
+
+    assert_eq!(umount2("/ovl\0", 0), 0);
+    for (path, (fs_fd, mnt_fd)) in [("/ovl_lower\0", lower), ("/ovl_upper\0", upper)] {
+        assert_eq!(umount2(path, 0), 0);
+        close(mnt_fd);
+        close(fs_fd);
+    }
+    println!("overlay passed!");
+    0
+}
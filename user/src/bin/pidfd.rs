@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, pidfd_open, pidfd_send_signal, poll, sleep, waitpid, yield_, PollFd,
+    POLLIN,
+};
+
+const EBADF: isize = -9;
+const ESRCH: isize = -3;
+const EINVAL: isize = -22;
+
+/// pidfd 当前是否可读，即进程是否已经退出
+fn exited(pidfd: usize) -> bool {
+    let mut fds = [PollFd {
+        fd:      pidfd as u32,
+        events:  POLLIN,
+        revents: 0,
+    }];
+    poll(&mut fds);
+    fds[0].revents & POLLIN != 0
+}
+
+/// 为子进程打开 pidfd，子进程退出前 poll 不可读，退出后变为可读
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        exit(3);
+    }
+    let pidfd = pidfd_open(pid as usize, 0);
+    assert!(pidfd > 0);
+    let pidfd = pidfd as usize;
+    assert!(!exited(pidfd));
+    // 信号 0 只检查进程是否存在
+    assert_eq!(pidfd_send_signal(pidfd, 0), 0);
+    assert_eq!(pidfd_send_signal(pidfd, 65), EINVAL);
+    assert_eq!(pidfd_send_signal(1, 0), EBADF);
+
+    while !exited(pidfd) {
+        yield_();
+    }
+    assert_eq!(pidfd_send_signal(pidfd, 0), ESRCH);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 3);
+
+    assert_eq!(pidfd_open(pid as usize, 0), ESRCH);
+    assert_eq!(pidfd_open(0, 1), EINVAL);
+    assert_eq!(close(pidfd), 0);
+    println!("pidfd passed!");
+    0
+}
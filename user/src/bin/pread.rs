@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, lseek, open, pipe, pread, pwrite, read, write, OpenFlags};
+
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const EBADF: isize = -9;
+const EINVAL: isize = -22;
+const ESPIPE: isize = -29;
+
+/// pread/pwrite 与普通的 read 交替进行，普通 read 的位置不受影响
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("pread_test\0", OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello world"), 11);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+
+    let mut buf = [0u8; 5];
+    assert_eq!(read(fd, &mut buf[..2]), 2);
+    assert_eq!(&buf[..2], b"he");
+    assert_eq!(pread(fd, &mut buf, 6), 5);
+    assert_eq!(&buf, b"world");
+    assert_eq!(read(fd, &mut buf[..3]), 3);
+    assert_eq!(&buf[..3], b"llo");
+
+    // pwrite 也不移动位置
+    assert_eq!(pwrite(fd, b"HE", 0), 2);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 5);
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf, b" worl");
+    assert_eq!(pread(fd, &mut buf[..2], 0), 2);
+    assert_eq!(&buf[..2], b"HE");
+    // 超出文件末尾读不到数据
+    assert_eq!(pread(fd, &mut buf, 100), 0);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 10);
+
+    assert_eq!(pread(fd, &mut buf, -1), EINVAL);
+    assert_eq!(pwrite(fd, b"x", -1), EINVAL);
+    assert_eq!(close(fd), 0);
+    assert_eq!(pread(fd, &mut buf, 0), EBADF);
+
+    // 管道不能按位置读写
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(pwrite(pipe_fd[1], b"x", 0), ESPIPE);
+    assert_eq!(close(pipe_fd[0]), 0);
+    assert_eq!(close(pipe_fd[1]), 0);
+    println!("pread passed!");
+    0
+}
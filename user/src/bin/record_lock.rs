@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl, fork, fsconfig, fsmount, fsopen, getpid, mkdir, move_mount, open, sleep,
+    umount2, unlink, waitpid, OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const F_GETLK: i32 = 5;
+const F_SETLK: i32 = 6;
+const F_SETLKW: i32 = 7;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+const EAGAIN: isize = -11;
+
+#[repr(C)]
+struct Flock {
+    l_type:   i16,
+    l_whence: i16,
+    l_start:  i64,
+    l_len:    i64,
+    l_pid:    i32,
+}
+
+fn lock(fd: usize, cmd: i32, l_type: i16, start: i64, len: i64) -> (isize, Flock) {
+    let mut flock = Flock { l_type, l_whence: 0, l_start: start, l_len: len, l_pid: 0 };
+    let ret = fcntl(fd, cmd, &mut flock as *mut Flock as usize);
+    (ret, flock)
+}
+
+/// 运行子进程，返回它的退出码
+fn in_child(f: impl FnOnce() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// 不重叠的字节范围可以同时加写锁，重叠的范围冲突，F_GETLK 报告持有者；
+/// 进程关闭指向文件的任意一个 fd 都会释放它在这个文件上的所有记录锁
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/lock_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/lock_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let path = "/lock_mnt/file\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 10).0, 0);
+    assert_eq!(lock(fd, F_SETLK, F_WRLCK, 20, 10).0, 0);
+
+    let parent = getpid();
+    let code = in_child(|| {
+        // [10, 20) 与父进程的两段锁都不重叠
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 10, 10).0, 0);
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 5, 10).0, EAGAIN);
+        let (ret, conflict) = lock(fd, F_GETLK, F_WRLCK, 5, 10);
+        assert_eq!(ret, 0);
+        assert_eq!(conflict.l_type, F_WRLCK);
+        assert_eq!((conflict.l_start, conflict.l_len), (0, 10));
+        assert_eq!(conflict.l_pid as isize, parent);
+        0
+    });
+    assert_eq!(code, 0);
+
+    // 关闭另一个指向同一文件的 fd，父进程的锁全部释放
+    let other = open(path, OpenFlags::RDONLY);
+    assert!(other >= 0);
+    assert_eq!(close(other as usize), 0);
+    let code = in_child(|| {
+        let (ret, conflict) = lock(fd, F_GETLK, F_WRLCK, 0, 0);
+        assert_eq!(ret, 0);
+        assert_eq!(conflict.l_type, F_UNLCK);
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 30).0, 0);
+        0
+    });
+    assert_eq!(code, 0);
+
+    // F_SETLKW 等到持有者关闭 fd 之后拿到锁
+    assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 10).0, 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 10).0, EAGAIN);
+        assert_eq!(lock(fd, F_SETLKW, F_WRLCK, 0, 10).0, 0);
+        exit(0);
+    }
+    sleep(50);
+    assert_eq!(close(fd), 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(unlink(path), 0);
+    assert_eq!(umount2("/lock_mnt\0", 0), 0);
+    println!("record_lock passed!");
+    0
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, semaphore_create, semaphore_down, semaphore_up, waitpid};
+
+const EINVAL: isize = -22;
+
+/// 检查信号量的创建和计数，以及 fork 出的子进程不继承信号量
+#[no_mangle]
+pub fn main() -> i32 {
+    let sem = semaphore_create(2);
+    let empty = semaphore_create(0);
+    assert!(sem >= 0 && empty >= 0);
+    assert_ne!(sem, empty);
+    let (sem, empty) = (sem as usize, empty as usize);
+
+    // 还有资源时 down 不阻塞
+    assert_eq!(semaphore_down(sem), 0);
+    assert_eq!(semaphore_down(sem), 0);
+    assert_eq!(semaphore_up(sem), 0);
+    assert_eq!(semaphore_down(sem), 0);
+    // 先 up 再 down，不会阻塞
+    assert_eq!(semaphore_up(empty), 0);
+    assert_eq!(semaphore_down(empty), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        // 子进程有自己的信号量表
+        assert_eq!(semaphore_down(sem), EINVAL);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(semaphore_up(100), EINVAL);
+    assert_eq!(semaphore_down(100), EINVAL);
+    println!("semaphore passed!");
+    0
+}
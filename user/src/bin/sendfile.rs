@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, lseek, open, pipe, read, sendfile, write, OpenFlags};
+
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const EBADF: isize = -9;
+
+/// 把文件内容通过 sendfile 发送到管道，检查给出 offset 时文件自身的位置不变
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("sendfile_test\0", OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"0123456789abcdef"), 16);
+    assert_eq!(lseek(fd, 2, SEEK_SET), 2);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_end, write_end) = (pipe_fd[0], pipe_fd[1]);
+    let mut buf = [0u8; 16];
+
+    // 从给定位置发送，更新 offset，不移动文件位置
+    let mut offset = 10;
+    assert_eq!(sendfile(write_end, fd, Some(&mut offset), 4), 4);
+    assert_eq!(offset, 14);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 2);
+    assert_eq!(read(read_end, &mut buf[..4]), 4);
+    assert_eq!(&buf[..4], b"abcd");
+
+    // 没有 offset 时从文件位置发送并移动它，count 超过剩余长度时只发送剩余部分
+    assert_eq!(sendfile(write_end, fd, None, 100), 14);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 16);
+    assert_eq!(read(read_end, &mut buf[..14]), 14);
+    assert_eq!(&buf[..14], b"23456789abcdef");
+    assert_eq!(sendfile(write_end, fd, None, 100), 0);
+
+    // 管道的读端不能写
+    assert_eq!(sendfile(read_end, fd, None, 1), EBADF);
+    assert_eq!(close(fd), 0);
+    assert_eq!(close(read_end), 0);
+    assert_eq!(close(write_end), 0);
+    println!("sendfile passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+use alloc::string::String;
+
+use user_lib::{exit, fork, setdomainname, sethostname, setuid, uname, uts_field, waitpid, Utsname};
+
+const EPERM: isize = -1;
+const EINVAL: isize = -22;
+
+fn names() -> (String, String) {
+    let mut uts = Utsname::default();
+    assert_eq!(uname(&mut uts), 0);
+    (String::from(uts_field(&uts.nodename)), String::from(uts_field(&uts.domainname)))
+}
+
+/// setdomainname 修改 uname 报告的域名，不影响主机名；长度和权限检查与 sethostname 相同
+#[no_mangle]
+pub fn main() -> i32 {
+    let (hostname, old) = names();
+    assert_eq!(setdomainname(b"chaos.local"), 0);
+    assert_eq!(names(), (hostname.clone(), String::from("chaos.local")));
+    // 修改主机名也不影响域名
+    assert_eq!(sethostname(b"chaos-test"), 0);
+    assert_eq!(names().1, "chaos.local");
+    assert_eq!(sethostname(hostname.as_bytes()), 0);
+
+    assert_eq!(setdomainname(&[b'd'; 65]), EINVAL);
+    assert_eq!(names().1, "chaos.local");
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(setdomainname(b"user"), EPERM);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(names().1, "chaos.local");
+
+    assert_eq!(setdomainname(old.as_bytes()), 0);
+    assert_eq!(names(), (hostname, old));
+    println!("setdomainname passed!");
+    0
+}
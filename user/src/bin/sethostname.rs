@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+use alloc::string::String;
+
+use user_lib::{exit, fork, sethostname, setuid, uname, uts_field, waitpid, Utsname};
+
+const EPERM: isize = -1;
+const EINVAL: isize = -22;
+
+fn hostname() -> String {
+    let mut uts = Utsname::default();
+    assert_eq!(uname(&mut uts), 0);
+    String::from(uts_field(&uts.nodename))
+}
+
+/// sethostname 修改 uname 报告的主机名；超过 64 字节的名字和非 root 进程被拒绝
+#[no_mangle]
+pub fn main() -> i32 {
+    let old = hostname();
+    assert_eq!(sethostname(b"chaos-test"), 0);
+    assert_eq!(hostname(), "chaos-test");
+    // 64 字节是允许的最大长度
+    assert_eq!(sethostname(&[b'h'; 64]), 0);
+    assert_eq!(hostname().len(), 64);
+    assert_eq!(sethostname(&[b'h'; 65]), EINVAL);
+    assert_eq!(hostname().len(), 64);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(sethostname(b"user"), EPERM);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(hostname().len(), 64);
+
+    assert_eq!(sethostname(old.as_bytes()), 0);
+    assert_eq!(hostname(), old);
+    println!("sethostname passed!");
+    0
+}
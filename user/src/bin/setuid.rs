@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getegid, geteuid, getgid, getuid, setgid, setresgid, setresuid, setuid, waitpid,
+    ID_UNCHANGED,
+};
+
+const EPERM: isize = -1;
+
+/// 运行子进程，返回它的退出码
+fn in_child(f: impl FnOnce() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// root 可以任意设置 id；放弃 root 后只能在自己的 real/effective/saved id 之间切换，
+/// 凭证由 fork 继承，子进程的修改不影响父进程
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!((getuid(), geteuid(), getgid(), getegid()), (0, 0, 0, 0));
+    let code = in_child(|| {
+        assert_eq!(setgid(100), 0);
+        assert_eq!((getgid(), getegid()), (100, 100));
+        // 保留 saved uid 为 0，之后还能切回 root
+        assert_eq!(setresuid(1000, 1000, 0), 0);
+        assert_eq!((getuid(), geteuid()), (1000, 1000));
+        assert_eq!(setgid(0), EPERM);
+        assert_eq!(setresuid(ID_UNCHANGED, 2000, ID_UNCHANGED), EPERM);
+        assert_eq!(setuid(0), 0);
+        assert_eq!((getuid(), geteuid()), (1000, 0));
+
+        // 有效用户是 root 时 setuid 同时修改三个 id，之后无法再切回 root
+        assert_eq!(setuid(1000), 0);
+        assert_eq!((getuid(), geteuid()), (1000, 1000));
+        assert_eq!(setuid(0), EPERM);
+        assert_eq!(setresgid(ID_UNCHANGED, 100, ID_UNCHANGED), 0);
+        assert_eq!(setresgid(0, ID_UNCHANGED, ID_UNCHANGED), EPERM);
+        in_child(|| {
+            assert_eq!((getuid(), geteuid(), getgid()), (1000, 1000, 100));
+            0
+        })
+    });
+    assert_eq!(code, 0);
+    assert_eq!((getuid(), geteuid(), getgid(), getegid()), (0, 0, 0, 0));
+    println!("setuid passed!");
+    0
+}
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+
+use user_lib::{exit, fork, getpid, kill, sigaction, sleep, waitpid, SignalAction, SIG_IGN};
+
+/// 内核的 kill 以位掩码传信号，第 signum 个信号是 1 << (signum - 1)
+const fn sig(signum: usize) -> i32 {
+    1 << (signum - 1)
+}
+const SIGILL: usize = 4;
+const SIGKILL: usize = 9;
+const SIGUSR1: usize = 10;
+const SIGUSR2: usize = 12;
+const SIGCHLD: usize = 17;
+
+/// fork 一个运行 f 的子进程，返回它的 pid
+fn spawn(f: fn()) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    pid as usize
+}
+
+fn wait_exit_code(pid: usize) -> i32 {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+    exit_code
+}
+
+/// 没有处理函数的信号按默认动作处理：SIGKILL、SIGUSR1 和非法指令产生的 SIGILL 终止进程，
+/// 退出码为 -signum；
+/// SIGCHLD 默认被忽略，SIG_IGN 的信号也被忽略
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = spawn(|| loop {});
+    sleep(50);
+    assert_eq!(kill(pid, sig(SIGKILL)), 0);
+    assert_eq!(wait_exit_code(pid), -(SIGKILL as i32));
+
+    let pid = spawn(|| {
+        kill(getpid() as usize, sig(SIGUSR1));
+        // 从 kill 返回用户态之前就已经被终止
+        exit(1);
+    });
+    assert_eq!(wait_exit_code(pid), -(SIGUSR1 as i32));
+
+    let pid = spawn(|| unsafe {
+        asm!("unimp");
+    });
+    assert_eq!(wait_exit_code(pid), -(SIGILL as i32));
+
+    let pid = spawn(|| {
+        let ignore = SignalAction {
+            sa_handler: SIG_IGN,
+            ..Default::default()
+        };
+        assert_eq!(sigaction(SIGUSR2, Some(&ignore), None), 0);
+        assert_eq!(kill(getpid() as usize, sig(SIGUSR2)), 0);
+        assert_eq!(kill(getpid() as usize, sig(SIGCHLD)), 0);
+        exit(7);
+    });
+    assert_eq!(wait_exit_code(pid), 7);
+    println!("signal_default passed!");
+    0
+}
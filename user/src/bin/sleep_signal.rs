@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, exit, fork, kill, nanosleep, sigaction, sleep, waitpid, SignalAction, TimeSpec,
+    CLOCK_MONOTONIC,
+};
+
+const EINTR: isize = -4;
+const EINVAL: isize = -22;
+/// 内核的 kill 以位掩码传信号，第 signum 个信号是 1 << (signum - 1)
+const SIGUSR1: i32 = 1 << 9;
+
+/// 装上处理函数后 SIGUSR1 不再按默认动作终止进程。处理函数目前还不会被调用
+fn on_usr1(_signum: i32) {}
+
+fn now_ms() -> usize {
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut now), 0);
+    now.tv_sec * 1000 + now.tv_nsec / 1_000_000
+}
+
+/// 睡眠按时结束时返回 0；被信号打断时返回 EINTR，并给出没有睡够的时间
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut rem = TimeSpec { tv_sec: 1, tv_nsec: 1 };
+    let req = TimeSpec { tv_sec: 0, tv_nsec: 100_000_000 };
+    let start = now_ms();
+    assert_eq!(nanosleep(&req, Some(&mut rem)), 0);
+    assert!(now_ms() - start >= 100);
+    // 睡满时不写 rem
+    assert_eq!((rem.tv_sec, rem.tv_nsec), (1, 1));
+    let bad = TimeSpec { tv_sec: 0, tv_nsec: 1_000_000_000 };
+    assert_eq!(nanosleep(&bad, None), EINVAL);
+
+    let pid = fork();
+    if pid == 0 {
+        let action = SignalAction {
+            sa_handler: on_usr1 as usize,
+            ..Default::default()
+        };
+        assert_eq!(sigaction(10, Some(&action), None), 0);
+        let req = TimeSpec { tv_sec: 10, tv_nsec: 0 };
+        let mut rem = TimeSpec::default();
+        let start = now_ms();
+        assert_eq!(nanosleep(&req, Some(&mut rem)), EINTR);
+        // 没有睡满 10 秒，剩余的时间与已经睡过的时间大致相加为 10 秒
+        let slept = now_ms() - start;
+        assert!(slept < 10_000);
+        assert!(rem.tv_sec > 0 && rem.tv_sec <= 10);
+        assert!(rem.tv_nsec < 1_000_000_000);
+        exit(0);
+    }
+    sleep(200);
+    assert_eq!(kill(pid as usize, SIGUSR1), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("sleep_signal passed!");
+    0
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpid, spawn, waitpid};
+
+const ENOENT: isize = -2;
+
+/// spawn 出的子进程运行另一个程序，父进程可以等待它；路径不存在时返回 ENOENT
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = spawn("fcntl\0");
+    assert!(pid > 0);
+    assert_ne!(pid, getpid());
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(spawn("no_such_program\0"), ENOENT);
+    println!("spawn passed!");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, waitpid};
+
+/// 每层递归在栈上占用的字节数
+const FRAME_SIZE: usize = 1024;
+/// 初始只映射 80KB 的栈，递归 160 层超过它但没有超过增长上限
+const DEPTH: usize = 160;
+
+/// 每层在栈上放一个数组并写满，返回所有层数组内容的和
+fn recurse(depth: usize) -> usize {
+    let mut buf = [0u8; FRAME_SIZE];
+    for byte in buf.iter_mut() {
+        unsafe { (byte as *mut u8).write_volatile(depth as u8) };
+    }
+    let below = if depth == 0 { 0 } else { recurse(depth - 1) };
+    let last = unsafe { (&buf[FRAME_SIZE - 1] as *const u8).read_volatile() };
+    below + last as usize
+}
+
+/// 栈在缺页时向下增长；超过增长上限的递归仍然以段错误结束
+#[no_mangle]
+pub fn main() -> i32 {
+    let expected: usize = (0..=DEPTH).map(|depth| depth as u8 as usize).sum();
+    assert_eq!(recurse(DEPTH), expected);
+    // 栈已经增长，再次递归不会缺页
+    assert_eq!(recurse(DEPTH), expected);
+
+    let pid = fork();
+    if pid == 0 {
+        recurse(usize::MAX);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_ne!(exit_code, 0);
+    println!("stack_grow passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, setuid, sysctl, waitpid};
+
+const EPERM: isize = -1;
+const EINVAL: isize = -22;
+
+/// 内核参数的编号，与内核中的 SysctlParam 一致
+const READ_AHEAD_WINDOW: usize = 1;
+const SCHED_BOOST: usize = 2;
+
+/// sysctl 取回参数原来的值并设置新值；未知的参数和非法的取值返回 EINVAL，
+/// 非 root 进程可以读取但不能修改
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut old = 0;
+    assert_eq!(sysctl(READ_AHEAD_WINDOW, None, Some(&mut old)), 0);
+    let mut value = usize::MAX;
+    assert_eq!(sysctl(READ_AHEAD_WINDOW, Some(old + 4), Some(&mut value)), 0);
+    assert_eq!(value, old);
+    assert_eq!(sysctl(READ_AHEAD_WINDOW, None, Some(&mut value)), 0);
+    assert_eq!(value, old + 4);
+
+    assert_eq!(sysctl(1234, None, Some(&mut value)), EINVAL);
+    assert_eq!(sysctl(1234, Some(1), None), EINVAL);
+    assert_eq!(sysctl(SCHED_BOOST, Some(2), None), EINVAL);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(sysctl(READ_AHEAD_WINDOW, Some(0), None), EPERM);
+        let mut value = 0;
+        assert_eq!(sysctl(READ_AHEAD_WINDOW, None, Some(&mut value)), 0);
+        assert_eq!(value, old + 4);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(sysctl(READ_AHEAD_WINDOW, Some(old), None), 0);
+    assert_eq!(sysctl(READ_AHEAD_WINDOW, None, Some(&mut value)), 0);
+    assert_eq!(value, old);
+    println!("sysctl passed!");
+    0
+}
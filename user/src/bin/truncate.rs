@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fsconfig, fsmount, fsopen, mkdir, move_mount, open, read, truncate, umount2, write,
+    OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const ENOENT: isize = -2;
+const EISDIR: isize = -21;
+const EINVAL: isize = -22;
+const EOPNOTSUPP: isize = -95;
+
+/// 读出整个文件，返回长度
+fn read_file(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, buf);
+    assert!(len >= 0);
+    close(fd as usize);
+    len as usize
+}
+
+/// 按路径缩短和加长 tmpfs 上的文件，加长的部分读出来是 0；ext4 上不支持加长
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/trunc_mnt\0");
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/trunc_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+
+    let path = "/trunc_mnt/file\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"hello world"), 11);
+    close(fd as usize);
+
+    let mut buf = [0xffu8; 16];
+    assert_eq!(truncate(path, 5), 0);
+    assert_eq!(read_file(path, &mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(truncate(path, 8), 0);
+    assert_eq!(read_file(path, &mut buf), 8);
+    assert_eq!(&buf[..8], b"hello\0\0\0");
+
+    assert_eq!(truncate(path, -1), EINVAL);
+    assert_eq!(truncate("/trunc_mnt/missing\0", 0), ENOENT);
+    assert_eq!(truncate("/trunc_mnt\0", 0), EISDIR);
+    // ext4 根文件系统上的文件不能变长，这个测试程序本身就在根目录下
+    assert_eq!(truncate("/truncate\0", 1 << 30), EOPNOTSUPP);
+    assert_eq!(umount2("/trunc_mnt\0", 0), 0);
+    println!("truncate passed!");
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{chdir, close, exit, fork, open, setsid, waitpid, write, OpenFlags};
+
+const ENXIO: isize = -6;
+
+/// /dev/tty 按设备节点识别：经由相对路径打开也得到控制终端，脱离控制终端之后打开返回 ENXIO
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/dev/tty\0", OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"tty\n"), 4);
+    close(fd as usize);
+
+    assert_eq!(chdir("/dev\0"), 0);
+    let fd = open("tty\0", OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"tty\n"), 4);
+    close(fd as usize);
+    assert_eq!(chdir("/\0"), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setsid(), user_lib::getpid());
+        assert_eq!(open("/dev/tty\0", OpenFlags::RDWR), ENXIO);
+        // 其他设备节点不受影响
+        let fd = open("/dev/console\0", OpenFlags::WRONLY);
+        assert!(fd >= 0);
+        close(fd as usize);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("tty passed!");
+    0
+}
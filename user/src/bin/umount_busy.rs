@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, fsconfig, fsmount, fsopen, mkdir, move_mount, open, pread, umount2, write,
+    OpenFlags, AT_FDCWD, FSCONFIG_CMD_CREATE, MNT_FORCE, MOVE_MOUNT_F_EMPTY_PATH,
+};
+
+const EBUSY: isize = -16;
+
+/// 新建一个 tmpfs 挂载到 /busy_mnt 上
+fn mount_tmpfs() {
+    let fs_fd = fsopen("tmpfs\0", 0) as usize;
+    assert_eq!(fsconfig(fs_fd, FSCONFIG_CMD_CREATE, None, None, 0), 0);
+    let mnt_fd = fsmount(fs_fd, 0, 0) as usize;
+    assert_eq!(move_mount(mnt_fd, "\0", AT_FDCWD, "/busy_mnt\0", MOVE_MOUNT_F_EMPTY_PATH), 0);
+    close(mnt_fd);
+    close(fs_fd);
+}
+
+/// 挂载中还有打开的文件或工作目录时 umount 返回 EBUSY，关闭之后才能卸载；
+/// MNT_FORCE 不检查引用，已经打开的文件在卸载后仍然可以读
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/busy_mnt\0");
+    mount_tmpfs();
+    let fd = open("/busy_mnt/file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"busy"), 4);
+    assert_eq!(umount2("/busy_mnt\0", 0), EBUSY);
+    assert_eq!(close(fd as usize), 0);
+
+    assert_eq!(chdir("/busy_mnt\0"), 0);
+    assert_eq!(umount2("/busy_mnt\0", 0), EBUSY);
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(umount2("/busy_mnt\0", 0), 0);
+    assert!(open("/busy_mnt/file\0", OpenFlags::RDONLY) < 0);
+
+    mount_tmpfs();
+    let fd = open("/busy_mnt/file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"force"), 5);
+    assert_eq!(umount2("/busy_mnt\0", MNT_FORCE), 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(pread(fd as usize, &mut buf, 0), 5);
+    assert_eq!(&buf[..5], b"force");
+    assert_eq!(close(fd as usize), 0);
+    println!("umount_busy passed!");
+    0
+}
@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{uname, uts_field, Utsname};
+
+/// release 能按 Linux 的 `x.y.z` 版本号解析，machine 只有体系结构名
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut uts = Utsname::default();
+    assert_eq!(uname(&mut uts), 0);
+    assert!(!uts_field(&uts.sysname).is_empty());
+    assert!(!uts_field(&uts.version).is_empty());
+    assert_eq!(uts_field(&uts.machine), "riscv64");
+
+    let release = uts_field(&uts.release);
+    let mut parts = release.split('.').map(|part| part.parse::<u32>().unwrap());
+    let major = parts.next().unwrap();
+    assert!(parts.next().is_some() && parts.next().is_some() && parts.next().is_none());
+    // 常见的 libc 和 busybox 要求内核版本至少为 2.6
+    assert!(major >= 3, "release {}", release);
+    println!("uname passed! release {}", release);
+    0
+}
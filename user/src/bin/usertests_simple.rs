@@ -5,16 +5,61 @@
 extern crate user_lib;
 
 static TESTS: &[&str] = &[
+    "bind_mount\0",
+    "chroot\0",
+    "clock_gettime\0",
+    "clone3\0",
+    "console\0",
+    "copy_file_range\0",
+    "devfs\0",
+    "errno\0",
+    "excl_create\0",
     "exit\0",
     "fantastic_text\0",
+    "fchdir\0",
+    "fcntl\0",
+    "flock\0",
     "forktest\0",
     "forktest2\0",
     "forktest_simple\0",
+    "fp_switch\0",
+    "fsmount\0",
+    "futex\0",
+    "getppid\0",
+    "groups\0",
     "hello_world\0",
+    "iovec\0",
     "matrix\0",
+    "mknod\0",
+    "mmap\0",
+    "mmap_shared\0",
+    "mount_ns\0",
+    "mountinfo\0",
+    "mprotect\0",
+    "open_mode\0",
+    "overlay\0",
+    "mutex\0",
+    "pidfd\0",
+    "pread\0",
+    "record_lock\0",
+    "semaphore\0",
+    "sendfile\0",
+    "setdomainname\0",
+    "sethostname\0",
+    "setuid\0",
+    "signal_default\0",
     "sleep\0",
+    "sleep_signal\0",
     "sleep_simple\0",
+    "spawn\0",
+    "stack_grow\0",
+    "sysctl\0",
+    "truncate\0",
+    "tty\0",
+    "umount_busy\0",
+    "uname\0",
     "stack_overflow\0",
+    "waitid\0",
     "yield\0",
 ];
 
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, sleep, waitid, waitpid, SigInfo, CLD_EXITED, P_ALL, P_PGID, P_PID, WEXITED,
+    WNOHANG, WNOWAIT,
+};
+
+const SIGCHLD: i32 = 17;
+const ECHILD: isize = -10;
+const EINVAL: isize = -22;
+
+/// 用 WNOWAIT 查看退出的子进程，之后仍然可以用 wait 回收它
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        exit(7);
+    }
+    let mut info = SigInfo::default();
+    // 子进程还在运行
+    info.pid = -1;
+    assert_eq!(waitid(P_PID, pid as usize, &mut info, WEXITED | WNOHANG), 0);
+    assert_eq!(info.pid, 0);
+
+    assert_eq!(waitid(P_PID, pid as usize, &mut info, WEXITED | WNOWAIT), 0);
+    assert_eq!(info.signo, SIGCHLD);
+    assert_eq!(info.code, CLD_EXITED);
+    assert_eq!(info.pid, pid as i32);
+    assert_eq!(info.status, 7);
+    // 子进程与父进程在同一进程组，仍然可以再次看到它
+    let mut again = SigInfo::default();
+    assert_eq!(waitid(P_PGID, 0, &mut again, WEXITED | WNOWAIT), 0);
+    assert_eq!(again.pid, pid as i32);
+
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    assert_eq!(waitid(P_PID, pid as usize, &mut info, WEXITED), ECHILD);
+    assert_eq!(waitid(P_ALL, 0, &mut info, 0), EINVAL);
+    println!("waitid passed!");
+    0
+}
@@ -59,21 +59,38 @@ fn main(_argc: usize, _argv: &[&str]) -> i32 {
 }
 
 bitflags! {
+    /// 与内核 (Linux) 的 O_* 取值相同
     pub struct OpenFlags: u32 {
         const RDONLY = 0;
-        const WRONLY = 1 << 0;
-        const RDWR = 1 << 1;
-        const CREATE = 1 << 9;
-        const TRUNC = 1 << 10;
+        const WRONLY = 0o1;
+        const RDWR = 0o2;
+        const CREATE = 0o100;
+        const EXCL = 0o200;
+        const TRUNC = 0o1000;
+        const APPEND = 0o2000;
     }
 }
 
+/// 成功时返回 buf 的地址，路径以 '\0' 结尾
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+pub fn fcntl(fd: usize, cmd: i32, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+pub fn flock(fd: usize, operation: i32) -> isize {
+    sys_flock(fd, operation)
+}
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
+/// 相对路径从目录 dirfd 开始解析
+pub fn openat(dirfd: usize, path: &str, flags: OpenFlags) -> isize {
+    sys_openat(dirfd, path, flags.bits)
+}
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
@@ -86,6 +103,262 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+pub fn pread(fd: usize, buf: &mut [u8], offset: isize) -> isize {
+    sys_pread64(fd, buf, offset)
+}
+pub fn pwrite(fd: usize, buf: &[u8], offset: isize) -> isize {
+    sys_pwrite64(fd, buf, offset)
+}
+pub fn sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut usize>, count: usize) -> isize {
+    sys_sendfile(out_fd, in_fd, offset, count)
+}
+/// fstat 的结果，布局与 riscv64 上的 `struct stat` 相同
+#[repr(C)]
+#[derive(Default)]
+pub struct Stat {
+    pub st_dev:     u64,
+    pub st_ino:     u64,
+    pub st_mode:    u32,
+    pub st_nlink:   u32,
+    pub st_uid:     u32,
+    pub st_gid:     u32,
+    pub st_rdev:    u64,
+    _pad:           u64,
+    pub st_size:    i64,
+    pub st_blksize: u32,
+    _pad2:          i32,
+    pub st_blocks:  u64,
+    pub st_atime:   TimeSpec,
+    pub st_mtime:   TimeSpec,
+    pub st_ctime:   TimeSpec,
+    _unused:        u64,
+}
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFBLK: u32 = 0o060000;
+
+/// 把 (major, minor) 编码为 st_rdev
+pub const fn makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0xfff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0xff)
+}
+pub fn fstat(fd: usize, st: &mut Stat) -> isize {
+    sys_fstat(fd, st)
+}
+pub fn copy_file_range(
+    fd_in: usize,
+    off_in: Option<&mut usize>,
+    fd_out: usize,
+    off_out: Option<&mut usize>,
+    len: usize,
+    flags: u32,
+) -> isize {
+    sys_copy_file_range(fd_in, off_in, fd_out, off_out, len, flags)
+}
+/// waitid 返回的子进程信息，只列出 SIGCHLD 用到的字段
+#[repr(C)]
+pub struct SigInfo {
+    pub signo:  i32,
+    pub errno:  i32,
+    pub code:   i32,
+    _pad0:      i32,
+    pub pid:    i32,
+    pub uid:    u32,
+    pub status: i32,
+    _pad1:      [i32; 25],
+}
+
+impl Default for SigInfo {
+    fn default() -> Self {
+        Self {
+            signo:  0,
+            errno:  0,
+            code:   0,
+            _pad0:  0,
+            pid:    0,
+            uid:    0,
+            status: 0,
+            _pad1:  [0; 25],
+        }
+    }
+}
+
+pub const P_ALL: u32 = 0;
+pub const P_PID: u32 = 1;
+pub const P_PGID: u32 = 2;
+pub const WNOHANG: u32 = 1;
+pub const WEXITED: u32 = 4;
+pub const WNOWAIT: u32 = 0x1000000;
+pub const CLD_EXITED: i32 = 1;
+
+pub fn waitid(idtype: u32, id: usize, info: &mut SigInfo, options: u32) -> isize {
+    sys_waitid(idtype, id, info, options)
+}
+/// ppoll 的参数，与内核的 struct pollfd 布局相同
+#[repr(C)]
+pub struct PollFd {
+    pub fd:      u32,
+    pub events:  u16,
+    pub revents: u16,
+}
+
+pub const POLLIN: u16 = 0x001;
+
+/// 不等待，立即返回各个 fd 当前的状态
+pub fn poll(fds: &mut [PollFd]) -> isize {
+    sys_ppoll(fds)
+}
+pub fn pidfd_open(pid: usize, flags: u32) -> isize {
+    sys_pidfd_open(pid, flags)
+}
+pub fn pidfd_send_signal(pidfd: usize, sig: usize) -> isize {
+    sys_pidfd_send_signal(pidfd, sig)
+}
+/// futex 超时使用的相对时间，也是 clock_gettime 的结果
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeSpec {
+    pub tv_sec:  usize,
+    pub tv_nsec: usize,
+}
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// tp 可以指向任意地址 (例如跨页)，由内核检查
+pub fn clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> isize {
+    sys_clock_gettime(clock_id, tp)
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+/// `*uaddr` 仍等于 `val` 时等待，timeout 为 None 时一直等待
+pub fn futex_wait(uaddr: &u32, val: u32, timeout: Option<&TimeSpec>) -> isize {
+    sys_futex(uaddr, FUTEX_WAIT, val, timeout)
+}
+/// 唤醒最多 `count` 个在 `uaddr` 上等待的任务，返回唤醒的个数
+pub fn futex_wake(uaddr: &u32, count: u32) -> isize {
+    sys_futex(uaddr, FUTEX_WAKE, count, None)
+}
+/// readv/writev 的一段缓冲区
+#[repr(C)]
+pub struct IoVec {
+    pub base: usize,
+    pub len:  usize,
+}
+
+impl IoVec {
+    pub fn new(buf: &[u8]) -> Self {
+        Self {
+            base: buf.as_ptr() as usize,
+            len:  buf.len(),
+        }
+    }
+    /// readv 会写入这段缓冲区
+    pub fn new_mut(buf: &mut [u8]) -> Self {
+        Self {
+            base: buf.as_mut_ptr() as usize,
+            len:  buf.len(),
+        }
+    }
+}
+
+pub fn readv(fd: usize, iov: &[IoVec]) -> isize {
+    sys_readv(fd, iov)
+}
+pub fn writev(fd: usize, iov: &[IoVec]) -> isize {
+    sys_writev(fd, iov)
+}
+
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// 成功时返回映射的起始地址，失败时返回负的错误码
+pub fn mmap(start: usize, len: usize, prot: usize, flags: usize, fd: usize, off: usize) -> isize {
+    sys_mmap(start, len, prot, flags, fd, off)
+}
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(addr, len, prot)
+}
+pub const AT_FDCWD: isize = -100;
+
+/// 路径都需要以 '\0' 结尾
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD, path, 0o755)
+}
+/// mode 的类型位决定创建普通文件还是设备节点
+pub fn mknod(path: &str, mode: u32, dev: u64) -> isize {
+    sys_mknodat(AT_FDCWD, path, mode, dev)
+}
+pub fn unlink(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, 0)
+}
+pub fn truncate(path: &str, length: isize) -> isize {
+    sys_truncate(path, length)
+}
+pub const MS_BIND: u32 = 4096;
+
+/// MS_BIND 时 fstype 为 None；overlay 通过 data 给出 "lowerdir=...,upperdir=..."
+pub fn mount(source: &str, target: &str, fstype: Option<&str>, flags: u32, data: Option<&str>) -> isize {
+    sys_mount(source, target, fstype, flags, data)
+}
+pub fn umount2(target: &str, flags: u32) -> isize {
+    sys_umount2(target, flags)
+}
+/// umount2 的 flags：即使挂载中还有打开的文件也卸载
+pub const MNT_FORCE: u32 = 1;
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+/// 把工作目录切换到 fd 打开的目录
+pub fn fchdir(fd: usize) -> isize {
+    sys_fchdir(fd)
+}
+/// 切换当前进程的根目录，只有 root 用户可以调用
+pub fn chroot(path: &str) -> isize {
+    sys_chroot(path)
+}
+/// clone 和 unshare 的 flags：使用私有的挂载命名空间
+pub const CLONE_NEWNS: u32 = 0x20000;
+/// 不再和其他进程共享 flags 指定的资源，目前只支持 CLONE_NEWNS
+pub fn unshare(flags: u32) -> isize {
+    sys_unshare(flags)
+}
+
+pub const FSOPEN_CLOEXEC: u32 = 0x1;
+pub const FSMOUNT_CLOEXEC: u32 = 0x1;
+pub const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x4;
+pub const FSCONFIG_SET_FLAG: u32 = 0;
+pub const FSCONFIG_SET_STRING: u32 = 1;
+pub const FSCONFIG_CMD_CREATE: u32 = 6;
+
+pub fn fsopen(fsname: &str, flags: u32) -> isize {
+    sys_fsopen(fsname, flags)
+}
+pub fn fsconfig(fd: usize, cmd: u32, key: Option<&str>, value: Option<&str>, aux: i32) -> isize {
+    sys_fsconfig(fd, cmd, key, value, aux)
+}
+pub fn fsmount(fs_fd: usize, flags: u32, attr_flags: u32) -> isize {
+    sys_fsmount(fs_fd, flags, attr_flags)
+}
+pub fn move_mount(from_dfd: usize, from_path: &str, to_dfd: isize, to_path: &str, flags: u32) -> isize {
+    sys_move_mount(from_dfd, from_path, to_dfd, to_path, flags)
+}
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
@@ -98,12 +371,99 @@ pub fn get_time() -> isize {
 pub fn getpid() -> isize {
     sys_getpid()
 }
+pub fn getppid() -> isize {
+    sys_getppid()
+}
+/// uname 的结果，每个字段都是以 '\0' 结尾的字符串
+#[repr(C)]
+pub struct Utsname {
+    pub sysname:    [u8; 65],
+    pub nodename:   [u8; 65],
+    pub release:    [u8; 65],
+    pub version:    [u8; 65],
+    pub machine:    [u8; 65],
+    pub domainname: [u8; 65],
+}
+
+impl Default for Utsname {
+    fn default() -> Self {
+        Self {
+            sysname:    [0; 65],
+            nodename:   [0; 65],
+            release:    [0; 65],
+            version:    [0; 65],
+            machine:    [0; 65],
+            domainname: [0; 65],
+        }
+    }
+}
+
+/// 取出 utsname 字段中 '\0' 之前的部分
+pub fn uts_field(field: &[u8]) -> &str {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).unwrap()
+}
+pub fn uname(uts: &mut Utsname) -> isize {
+    sys_uname(uts)
+}
+/// 名字不需要以 '\0' 结尾，长度由切片决定
+pub fn sethostname(name: &[u8]) -> isize {
+    sys_sethostname(name)
+}
+pub fn setdomainname(name: &[u8]) -> isize {
+    sys_setdomainname(name)
+}
+/// 读写内核参数：old_value 为 Some 时取回原来的值，new_value 为 Some 时设置新值
+pub fn sysctl(param: usize, new_value: Option<usize>, old_value: Option<&mut usize>) -> isize {
+    sys_sysctl(param, new_value.as_ref(), old_value)
+}
+pub fn getuid() -> isize {
+    sys_getuid()
+}
+pub fn geteuid() -> isize {
+    sys_geteuid()
+}
+pub fn getgid() -> isize {
+    sys_getgid()
+}
+pub fn getegid() -> isize {
+    sys_getegid()
+}
+pub fn setuid(uid: u32) -> isize {
+    sys_setuid(uid)
+}
+pub fn setgid(gid: u32) -> isize {
+    sys_setgid(gid)
+}
+/// setresuid/setresgid 中表示保持不变的参数
+pub const ID_UNCHANGED: u32 = u32::MAX;
+pub fn setresuid(ruid: u32, euid: u32, suid: u32) -> isize {
+    sys_setresuid(ruid, euid, suid)
+}
+pub fn setresgid(rgid: u32, egid: u32, sgid: u32) -> isize {
+    sys_setresgid(rgid, egid, sgid)
+}
+/// 创建新会话并脱离控制终端，返回新的会话 id
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+/// 读取附属用户组列表，`list` 为空时只返回组的个数
+pub fn getgroups(list: &mut [u32]) -> isize {
+    sys_getgroups(list.len(), list.as_mut_ptr())
+}
+pub fn setgroups(list: &[u32]) -> isize {
+    sys_setgroups(list.len(), list.as_ptr())
+}
 pub fn fork() -> isize {
     sys_fork()
 }
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
+/// 直接创建运行 `path` 的子进程，返回子进程的 pid
+pub fn spawn(path: &str) -> isize {
+    sys_spawn(path)
+}
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
@@ -146,8 +506,42 @@ pub fn kill(pid: usize, signal: i32) -> isize {
     sys_kill(pid, signal)
 }
 
+/// sigaction 的 sa_handler：按默认动作处理
+pub const SIG_DFL: usize = 0;
+/// sigaction 的 sa_handler：忽略信号
+pub const SIG_IGN: usize = 1;
+
+/// 信号的处理方式，与内核的 SignalAction 布局相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalAction {
+    pub sa_handler:  usize,
+    pub sa_flags:    u32,
+    pub sa_restorer: usize,
+    pub mask:        usize,
+}
+
+/// 设置编号为 signum 的信号的处理方式
+pub fn sigaction(
+    signum: usize, action: Option<&SignalAction>, old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |action| action as *mut _),
+    )
+}
+
 pub fn sleep(sleep_ms: usize) {
-    sys_sleep(sleep_ms);
+    let req = TimeSpec {
+        tv_sec:  sleep_ms / 1000,
+        tv_nsec: sleep_ms % 1000 * 1_000_000,
+    };
+    sys_nanosleep(&req, None);
+}
+/// 被信号打断时返回 EINTR，rem 中是没有睡够的时间
+pub fn nanosleep(req: &TimeSpec, rem: Option<&mut TimeSpec>) -> isize {
+    sys_nanosleep(req, rem)
 }
 
 pub fn thread_create(entry: usize, arg: usize) -> isize {
@@ -173,20 +567,20 @@ pub fn mutex_create() -> isize {
 pub fn mutex_blocking_create() -> isize {
     sys_mutex_create(true)
 }
-pub fn mutex_lock(mutex_id: usize) {
-    sys_mutex_lock(mutex_id);
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
 }
-pub fn mutex_unlock(mutex_id: usize) {
-    sys_mutex_unlock(mutex_id);
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
 }
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
 }
-pub fn semaphore_up(sem_id: usize) {
-    sys_semaphore_up(sem_id);
+pub fn semaphore_up(sem_id: usize) -> isize {
+    sys_semaphore_up(sem_id)
 }
-pub fn semaphore_down(sem_id: usize) {
-    sys_semaphore_down(sem_id);
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
 }
 pub fn condvar_create() -> isize {
     sys_condvar_create()
@@ -1,29 +1,83 @@
 use core::arch::asm;
 
+use crate::{IoVec, PollFd, SigInfo, SignalAction, Stat, TimeSpec, Utsname};
+
+const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 24;
-const SYSCALL_OPEN: usize = 56;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_FLOCK: usize = 32;
+const SYSCALL_MKNODAT: usize = 33;
+const SYSCALL_MKDIRAT: usize = 34;
+const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_TRUNCATE: usize = 45;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_FCHDIR: usize = 50;
+const SYSCALL_CHROOT: usize = 51;
+const SYSCALL_OPENAT: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READV: usize = 65;
+const SYSCALL_WRITEV: usize = 66;
+const SYSCALL_PREAD64: usize = 67;
+const SYSCALL_PWRITE64: usize = 68;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_FSTAT: usize = 80;
 const SYSCALL_EXIT: usize = 93;
-const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_WAITID: usize = 95;
+const SYSCALL_UNSHARE: usize = 97;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SETGID: usize = 144;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_SETRESUID: usize = 147;
+const SYSCALL_SETRESGID: usize = 149;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_UNAME: usize = 160;
+const SYSCALL_SETHOSTNAME: usize = 161;
+const SYSCALL_SETDOMAINNAME: usize = 162;
+const SYSCALL_GETGROUPS: usize = 158;
+const SYSCALL_SETGROUPS: usize = 159;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_COPY_FILE_RANGE: usize = 285;
+const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_SYSCTL: usize = 411;
+const SYSCALL_PIDFD_SEND_SIGNAL: usize = 424;
+const SYSCALL_MOVE_MOUNT: usize = 429;
+const SYSCALL_FSOPEN: usize = 430;
+const SYSCALL_FSCONFIG: usize = 431;
+const SYSCALL_FSMOUNT: usize = 432;
+const SYSCALL_PIDFD_OPEN: usize = 434;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
-const SYSCALL_MUTEX_CREATE: usize = 1010;
-const SYSCALL_MUTEX_LOCK: usize = 1011;
-const SYSCALL_MUTEX_UNLOCK: usize = 1012;
-const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
-const SYSCALL_SEMAPHORE_UP: usize = 1021;
-const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_MUTEX_CREATE: usize = 463;
+const SYSCALL_MUTEX_LOCK: usize = 464;
+const SYSCALL_MUTEX_UNLOCK: usize = 466;
+const SYSCALL_SEMAPHORE_CREATE: usize = 467;
+const SYSCALL_SEMAPHORE_UP: usize = 468;
+const SYSCALL_SEMAPHORE_DOWN: usize = 470;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
@@ -42,12 +96,64 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall4(id: usize, args: [usize; 4]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x17") id
+        );
+    }
+    ret
+}
+
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
+pub fn sys_getcwd(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_fcntl(fd: usize, cmd: i32, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd as usize, arg])
+}
+
+pub fn sys_flock(fd: usize, operation: i32) -> isize {
+    syscall(SYSCALL_FLOCK, [fd, operation as usize, 0])
+}
+
+/// 内核只提供 openat，相对路径从当前工作目录开始解析
 pub fn sys_open(path: &str, flags: u32) -> isize {
-    syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0])
+    syscall(
+        SYSCALL_OPENAT,
+        [crate::AT_FDCWD as usize, path.as_ptr() as usize, flags as usize],
+    )
+}
+
+pub fn sys_openat(dirfd: usize, path: &str, flags: u32) -> isize {
+    syscall(SYSCALL_OPENAT, [dirfd, path.as_ptr() as usize, flags as usize])
 }
 
 pub fn sys_close(fd: usize) -> isize {
@@ -69,13 +175,177 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
+pub fn sys_pread64(fd: usize, buffer: &mut [u8], offset: isize) -> isize {
+    syscall4(
+        SYSCALL_PREAD64,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len(), offset as usize],
+    )
+}
+
+pub fn sys_pwrite64(fd: usize, buffer: &[u8], offset: isize) -> isize {
+    syscall4(
+        SYSCALL_PWRITE64,
+        [fd, buffer.as_ptr() as usize, buffer.len(), offset as usize],
+    )
+}
+
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut usize>, count: usize) -> isize {
+    let offset = offset.map_or(0, |offset| offset as *mut usize as usize);
+    syscall4(SYSCALL_SENDFILE, [out_fd, in_fd, offset, count])
+}
+
+pub fn sys_fstat(fd: usize, st: &mut Stat) -> isize {
+    syscall(SYSCALL_FSTAT, [fd, st as *mut Stat as usize, 0])
+}
+
+pub fn sys_copy_file_range(
+    fd_in: usize,
+    off_in: Option<&mut usize>,
+    fd_out: usize,
+    off_out: Option<&mut usize>,
+    len: usize,
+    flags: u32,
+) -> isize {
+    let off_in = off_in.map_or(0, |off| off as *mut usize as usize);
+    let off_out = off_out.map_or(0, |off| off as *mut usize as usize);
+    syscall6(
+        SYSCALL_COPY_FILE_RANGE,
+        [fd_in, off_in, fd_out, off_out, len, flags as usize],
+    )
+}
+
+pub fn sys_waitid(idtype: u32, id: usize, info: &mut SigInfo, options: u32) -> isize {
+    syscall4(
+        SYSCALL_WAITID,
+        [idtype as usize, id, info as *mut SigInfo as usize, options as usize],
+    )
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd]) -> isize {
+    syscall4(SYSCALL_PPOLL, [fds.as_mut_ptr() as usize, fds.len(), 0, 0])
+}
+
+pub fn sys_pidfd_open(pid: usize, flags: u32) -> isize {
+    syscall(SYSCALL_PIDFD_OPEN, [pid, flags as usize, 0])
+}
+
+pub fn sys_clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, tp as usize, 0])
+}
+
+pub fn sys_futex(uaddr: &u32, op: usize, val: u32, timeout: Option<&TimeSpec>) -> isize {
+    let timeout = timeout.map_or(0, |timeout| timeout as *const TimeSpec as usize);
+    syscall4(
+        SYSCALL_FUTEX,
+        [uaddr as *const u32 as usize, op, val as usize, timeout],
+    )
+}
+
+pub fn sys_pidfd_send_signal(pidfd: usize, sig: usize) -> isize {
+    syscall4(SYSCALL_PIDFD_SEND_SIGNAL, [pidfd, sig, 0, 0])
+}
+
+pub fn sys_readv(fd: usize, iov: &[IoVec]) -> isize {
+    syscall(SYSCALL_READV, [fd, iov.as_ptr() as usize, iov.len()])
+}
+
+pub fn sys_writev(fd: usize, iov: &[IoVec]) -> isize {
+    syscall(SYSCALL_WRITEV, [fd, iov.as_ptr() as usize, iov.len()])
+}
+
+pub fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize, fd: usize, off: usize) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags, fd, off])
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0])
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [addr, len, prot])
+}
+
+pub fn sys_mknodat(dirfd: isize, path: &str, mode: u32, dev: u64) -> isize {
+    syscall4(
+        SYSCALL_MKNODAT,
+        [dirfd as usize, path.as_ptr() as usize, mode as usize, dev as usize],
+    )
+}
+
+pub fn sys_mkdirat(dirfd: isize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd as usize, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_unlinkat(dirfd: isize, path: &str, flags: u32) -> isize {
+    syscall(SYSCALL_UNLINKAT, [dirfd as usize, path.as_ptr() as usize, flags as usize])
+}
+
+pub fn sys_truncate(path: &str, length: isize) -> isize {
+    syscall(SYSCALL_TRUNCATE, [path.as_ptr() as usize, length as usize, 0])
+}
+
+pub fn sys_umount2(target: &str, flags: u32) -> isize {
+    syscall(SYSCALL_UMOUNT2, [target.as_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_fchdir(fd: usize) -> isize {
+    syscall(SYSCALL_FCHDIR, [fd, 0, 0])
+}
+
+pub fn sys_chroot(path: &str) -> isize {
+    syscall(SYSCALL_CHROOT, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: Option<&str>, flags: u32, data: Option<&str>) -> isize {
+    let fstype = fstype.map_or(0, |fstype| fstype.as_ptr() as usize);
+    let data = data.map_or(0, |data| data.as_ptr() as usize);
+    syscall6(
+        SYSCALL_MOUNT,
+        [source.as_ptr() as usize, target.as_ptr() as usize, fstype, flags as usize, data, 0],
+    )
+}
+
+pub fn sys_fsopen(fsname: &str, flags: u32) -> isize {
+    syscall(SYSCALL_FSOPEN, [fsname.as_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_fsconfig(fd: usize, cmd: u32, key: Option<&str>, value: Option<&str>, aux: i32) -> isize {
+    let key = key.map_or(0, |key| key.as_ptr() as usize);
+    let value = value.map_or(0, |value| value.as_ptr() as usize);
+    syscall6(SYSCALL_FSCONFIG, [fd, cmd as usize, key, value, aux as usize, 0])
+}
+
+pub fn sys_fsmount(fs_fd: usize, flags: u32, attr_flags: u32) -> isize {
+    syscall(SYSCALL_FSMOUNT, [fs_fd, flags as usize, attr_flags as usize])
+}
+
+pub fn sys_move_mount(from_dfd: usize, from_path: &str, to_dfd: isize, to_path: &str, flags: u32) -> isize {
+    syscall6(
+        SYSCALL_MOVE_MOUNT,
+        [from_dfd, from_path.as_ptr() as usize, to_dfd as usize, to_path.as_ptr() as usize, flags as usize, 0],
+    )
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
 }
 
-pub fn sys_sleep(sleep_ms: usize) -> isize {
-    syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
+pub fn sys_unshare(flags: u32) -> isize {
+    syscall(SYSCALL_UNSHARE, [flags as usize, 0, 0])
+}
+
+pub fn sys_nanosleep(req: &TimeSpec, rem: Option<&mut TimeSpec>) -> isize {
+    let rem = rem.map_or(0, |rem| rem as *mut TimeSpec as usize);
+    syscall(SYSCALL_NANOSLEEP, [req as *const TimeSpec as usize, rem, 0])
 }
 
 pub fn sys_yield() -> isize {
@@ -86,6 +356,12 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0])
 }
 
+pub fn sys_sigaction(
+    signum: usize, action: *const SignalAction, old_action: *mut SignalAction,
+) -> isize {
+    syscall(SYSCALL_SIGACTION, [signum, action as usize, old_action as usize])
+}
+
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
@@ -94,10 +370,79 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
+pub fn sys_uname(uts: &mut Utsname) -> isize {
+    syscall(SYSCALL_UNAME, [uts as *mut Utsname as usize, 0, 0])
+}
+
+pub fn sys_sethostname(name: &[u8]) -> isize {
+    syscall(SYSCALL_SETHOSTNAME, [name.as_ptr() as usize, name.len(), 0])
+}
+
+pub fn sys_setdomainname(name: &[u8]) -> isize {
+    syscall(SYSCALL_SETDOMAINNAME, [name.as_ptr() as usize, name.len(), 0])
+}
+
+pub fn sys_sysctl(param: usize, new_value: Option<&usize>, old_value: Option<&mut usize>) -> isize {
+    let new_value = new_value.map_or(0, |value| value as *const usize as usize);
+    let old_value = old_value.map_or(0, |value| value as *mut usize as usize);
+    syscall(SYSCALL_SYSCTL, [param, new_value, old_value])
+}
+
+pub fn sys_getuid() -> isize {
+    syscall(SYSCALL_GETUID, [0, 0, 0])
+}
+
+pub fn sys_geteuid() -> isize {
+    syscall(SYSCALL_GETEUID, [0, 0, 0])
+}
+
+pub fn sys_getgid() -> isize {
+    syscall(SYSCALL_GETGID, [0, 0, 0])
+}
+
+pub fn sys_getegid() -> isize {
+    syscall(SYSCALL_GETEGID, [0, 0, 0])
+}
+
+pub fn sys_setuid(uid: u32) -> isize {
+    syscall(SYSCALL_SETUID, [uid as usize, 0, 0])
+}
+
+pub fn sys_setgid(gid: u32) -> isize {
+    syscall(SYSCALL_SETGID, [gid as usize, 0, 0])
+}
+
+pub fn sys_setresuid(ruid: u32, euid: u32, suid: u32) -> isize {
+    syscall(SYSCALL_SETRESUID, [ruid as usize, euid as usize, suid as usize])
+}
+
+pub fn sys_setresgid(rgid: u32, egid: u32, sgid: u32) -> isize {
+    syscall(SYSCALL_SETRESGID, [rgid as usize, egid as usize, sgid as usize])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
+pub fn sys_getgroups(size: usize, list: *mut u32) -> isize {
+    syscall(SYSCALL_GETGROUPS, [size, list as usize, 0])
+}
+
+pub fn sys_setgroups(size: usize, list: *const u32) -> isize {
+    syscall(SYSCALL_SETGROUPS, [size, list as usize, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
 
+pub fn sys_spawn(path: &str) -> isize {
+    syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
+}
 pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     syscall(
         SYSCALL_EXEC,